@@ -27,26 +27,24 @@ fn send_orders<C: ApiClient>(client: &C, symbol: &str, margin: TickUnit)
 
     // One can specify order prices and sizes either in tick units or with a string
     // numerical representation.
-    let bid_order = trade::api::Order::new(best_bid - margin, "1.00000000", Side::Bid)
+    let bid_order = trade::api::Order::new(best_bid.0 - margin, "1.00000000", Side::Bid)
         .with_order_id::<C>("my_bid_order");
-    let ask_order = trade::api::Order::new(best_ask + margin, "1.00000000", Side::Ask)
+    let ask_order = trade::api::Order::new(best_ask.0 + margin, "1.00000000", Side::Ask)
         .with_order_id::<C>("my_ask_order");
 
     // We need an event loop + scheduler in order to run our HTTP requests.
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
 
     // Try to send the buy order.
-    runtime.block_on(client.order(bid_order.with_symbol(symbol)))?;
+    let bid_ack = runtime.block_on(client.order(bid_order.with_symbol(symbol)))?;
 
     // Now try to send the sell order.
     if let Err(_) = runtime.block_on(client.order(ask_order.with_symbol(symbol))) {
         println!("we were not able to execute the sell order, better to cancel the buy one");
-        let cancel_order = trade::api::Cancel::new(
-            // Do not use the name "my_bid_order" directly, as it was only given
-            // as a hint and the actual order ID may be different.
-            // However we did provide an order ID, so `unwrap` is ok here.
-            bid_order.order_id().unwrap().to_owned()
-        );
+        // Do not use the name "my_bid_order" directly, as it was only given
+        // as a hint and the actual order ID may be different: the ack carries the
+        // order ID to cancel.
+        let cancel_order = bid_ack.to_cancel();
         runtime.block_on(client.cancel(cancel_order.with_symbol(symbol)))?;
     }
 
@@ -54,11 +52,6 @@ fn send_orders<C: ApiClient>(client: &C, symbol: &str, margin: TickUnit)
 }
 
 fn main() -> Result<(), failure::Error> {
-    let params = trade::api::Params {
-        streaming_endpoint: "wss://ws-feed-public.sandbox.pro.coinbase.com".to_owned(),
-        rest_endpoint: "https://api-public.sandbox.pro.coinbase.com".to_owned(),
-    };
-
     let key_pair = trade::api::gdax::KeyPair::new(
         "my_api_key".to_owned(),
         "my_secret_key".to_owned(),
@@ -66,7 +59,7 @@ fn main() -> Result<(), failure::Error> {
     );
 
     // Use a client to the Coinbase Pro sandbox as an example.
-    let client = trade::api::gdax::Client::new(params, Some(key_pair))?;
+    let client = trade::api::gdax::Client::new_testnet(Some(key_pair))?;
     send_orders(&client, "BTCUSD", 10)?;
 
     Ok(())
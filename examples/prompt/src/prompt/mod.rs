@@ -99,7 +99,7 @@ impl Prompt {
                         order_book::display::displayable_size(update.consumed_size)
                     );
 
-                    if order.size == 0 {
+                    if order.size.is_zero() {
                         self.orders.remove(&update.order_id).unwrap();
                     }
                 } else {
@@ -74,11 +74,17 @@ impl<S: Stream<Item = Notification, Error = ()>> OrderBookThread<S> {
     fn process_notif(&mut self, notif: Notification) -> Result<(), ()> {
         match notif {
             Notification::LimitUpdates(updates) => {
-                for update in updates {
-                    self.order_book.update(update.into_inner());
-                }
+                self.order_book.update_batch(updates.into_iter().map(|update| update.into_inner()));
                 self.pull.send(PullEvent::OrderBook(self.order_book.clone())).unwrap();
             },
+            Notification::BookSnapshot(updates) => {
+                self.order_book = OrderBook::new();
+                self.order_book.update_batch(updates.into_iter().map(|update| update.into_inner()));
+                self.pull.send(PullEvent::OrderBook(self.order_book.clone())).unwrap();
+            },
+            Notification::BookResync => {
+                self.order_book = OrderBook::new();
+            },
             Notification::OrderConfirmation(order) => {
                 self.pull.send(PullEvent::OrderConfirmation(order.into_inner())).unwrap();
             },
@@ -0,0 +1,40 @@
+use std::fs::File;
+use std::io::BufWriter;
+use futures::prelude::*;
+use trade::prelude::*;
+use trade::api::notification::csv;
+use failure::format_err;
+
+/// Record the trades and order book updates of `symbol` into `path`, in the CSV format
+/// written by `csv::Writer`. Recorded files can be replayed with
+/// `trade::api::replay::FileReplay`.
+///
+/// This function may work with any client implementing the `ApiClient` trait.
+fn record<C: ApiClient>(client: &C, symbol: &str, path: &str) -> Result<(), failure::Error> {
+    let symbol = client.find_symbol(symbol)
+        .ok_or_else(|| format_err!("cannot find requested symbol"))?;
+
+    let mut writer = csv::Writer::new(BufWriter::new(File::create(path)?), symbol);
+
+    // The stream only ends when the connection is lost, the writer is flushed on drop.
+    let stream = client.stream_with_flags(
+        symbol,
+        NotificationFlags::ORDER_BOOK | NotificationFlags::TRADES
+    );
+    for notif in stream.wait() {
+        match notif {
+            Ok(notif) => writer.write(&notif)?,
+            Err(()) => break,
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), failure::Error> {
+    // Market data does not require a key pair.
+    let client = trade::api::binance::Client::new(trade::api::Params::binance_mainnet(), None)?;
+    record(&client, "BTCUSDT", "btcusdt.csv")?;
+
+    Ok(())
+}
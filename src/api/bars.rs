@@ -0,0 +1,180 @@
+//! A module defining a stream combinator aggregating trades into OHLCV bars.
+
+use std::cmp;
+use futures::prelude::*;
+use futures::try_ready;
+use serde_derive::{Serialize, Deserialize};
+use crate::tick::{Price, Size};
+use crate::api::{Notification, Trade};
+use crate::api::timestamp::{Timestamp, Timestamped};
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// An OHLCV bar aggregating consecutive trades.
+pub struct Candle {
+    /// Timestamp of the beginning of the bar. For time windows, this is the start of
+    /// the window rather than the timestamp of the first trade.
+    pub open_time: Timestamp,
+
+    /// Timestamp of the last trade of the bar.
+    pub close_time: Timestamp,
+
+    /// Price of the first trade, in ticks.
+    pub open: Price,
+
+    /// Highest traded price, in ticks.
+    pub high: Price,
+
+    /// Lowest traded price, in ticks.
+    pub low: Price,
+
+    /// Price of the last trade, in ticks.
+    pub close: Price,
+
+    /// Total traded size, in ticks.
+    pub volume: Size,
+
+    /// Number of aggregated trades.
+    pub trades: usize,
+}
+
+impl Candle {
+    fn new(open_time: Timestamp, trade: &Timestamped<Trade>) -> Self {
+        Candle {
+            open_time,
+            close_time: trade.timestamp(),
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: trade.size,
+            trades: 1,
+        }
+    }
+
+    fn push(&mut self, trade: &Timestamped<Trade>) {
+        self.close_time = trade.timestamp();
+        self.high = cmp::max(self.high, trade.price);
+        self.low = cmp::min(self.low, trade.price);
+        self.close = trade.price;
+        self.volume = self.volume.saturating_add(trade.size);
+        self.trades += 1;
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// When to close a bar.
+pub enum Window {
+    /// Close a bar every given number of milliseconds. Windows are aligned on multiples of
+    /// the interval, and are based on the trade timestamps rather than on the wall clock:
+    /// a bar is emitted upon receiving the first trade outside of its window. Windows
+    /// without any trade do not produce a bar.
+    Time(Timestamp),
+
+    /// Close a bar every given number of trades.
+    Trades(usize),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// An item yielded by `BarBuilder`.
+pub enum BarEvent {
+    /// A bar was closed.
+    Candle(Candle),
+
+    /// A non-trade notification, forwarded unchanged.
+    Notification(Notification),
+}
+
+#[must_use = "streams do nothing unless polled"]
+/// A stream combinator aggregating the `Notification::Trade` of the underlying notification
+/// stream into `Candle`s, and forwarding the other notifications unchanged.
+///
+/// When the underlying stream ends, the last bar is emitted even if incomplete.
+pub struct BarBuilder<S> {
+    stream: S,
+    window: Window,
+    current: Option<Candle>,
+    done: bool,
+}
+
+impl<S> BarBuilder<S> {
+    /// Aggregate the trades of `stream` according to `window`.
+    ///
+    /// # Panics
+    /// Panic if the interval or the number of trades of `window` is `0`.
+    pub fn new(stream: S, window: Window) -> Self {
+        match window {
+            Window::Time(0) => panic!("time window cannot be empty"),
+            Window::Trades(0) => panic!("trade window cannot be empty"),
+            _ => (),
+        }
+
+        BarBuilder {
+            stream,
+            window,
+            current: None,
+            done: false,
+        }
+    }
+
+    /// Return the bar currently being built, if any.
+    pub fn current(&self) -> Option<&Candle> {
+        self.current.as_ref()
+    }
+
+    /// Aggregate `trade`, return the bar it closed if any.
+    fn push(&mut self, trade: &Timestamped<Trade>) -> Option<Candle> {
+        match self.window {
+            Window::Time(interval) => {
+                let open_time = trade.timestamp() - trade.timestamp() % interval;
+                match &mut self.current {
+                    Some(candle) if candle.open_time == open_time => {
+                        candle.push(trade);
+                        None
+                    }
+                    current => current.replace(Candle::new(open_time, trade)),
+                }
+            }
+
+            Window::Trades(n) => {
+                match &mut self.current {
+                    Some(candle) => candle.push(trade),
+                    None => self.current = Some(Candle::new(trade.timestamp(), trade)),
+                }
+
+                if self.current.map(|candle| candle.trades >= n).unwrap_or(false) {
+                    self.current.take()
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+impl<S> Stream for BarBuilder<S> where S: Stream<Item = Notification> {
+    type Item = BarEvent;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<BarEvent>, S::Error> {
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+
+        loop {
+            match try_ready!(self.stream.poll()) {
+                Some(Notification::Trade(trade)) => {
+                    if let Some(candle) = self.push(&trade) {
+                        return Ok(Async::Ready(Some(BarEvent::Candle(candle))));
+                    }
+                }
+
+                Some(notif) => return Ok(Async::Ready(Some(BarEvent::Notification(notif)))),
+
+                None => {
+                    self.done = true;
+                    return Ok(Async::Ready(self.current.take().map(BarEvent::Candle)));
+                }
+            }
+        }
+    }
+}
@@ -27,8 +27,11 @@ pub struct RestError {
 
 impl api::errors::ErrorKinded<!> for RestError {
     fn kind(&self) -> api::errors::RestErrorKind<!> {
+        if self.kind == RestErrorKind::AddressBanned {
+            return api::errors::RestErrorKind::AddressBanned;
+        }
+
         if self.kind == RestErrorKind::BrokeRateLimit ||
-            self.kind == RestErrorKind::AddressBanned ||
             self.error_code == Some(-1003) ||
             self.error_code == Some(-1015)
         {
@@ -100,10 +103,12 @@ impl api::errors::ErrorKinded<api::errors::OrderErrorKind> for RestError {
             );
         }
 
+        // Rejection of a `LIMIT_MAKER` order, binance is not consistent about the case and
+        // the trailing period of the message across its endpoints.
         if order_rejected &&
             self.error_msg
                 .as_ref()
-                .map(|msg| msg.starts_with("Order would immediately match and take"))
+                .map(|msg| msg.to_ascii_lowercase().starts_with("order would immediately match"))
                 .unwrap_or(false)
         {
             return api::errors::RestErrorKind::Specific(
@@ -3,11 +3,17 @@
 pub mod errors;
 mod wss;
 mod rest;
+mod test;
 
 use openssl::pkey::{PKey, Private};
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 use futures::prelude::*;
-use log::debug;
+use log::{debug, warn, error};
 use serde_derive::{Serialize, Deserialize};
 use crate::api::{
     self,
@@ -21,11 +27,19 @@ use crate::api::{
     Notification,
     NotificationFlags,
     Balances,
+    DepositAddress,
+    Ticker,
+    Trade,
+    WithdrawAck,
+    WithdrawClient,
 };
-use crate::api::symbol::{Symbol, WithSymbol};
-use crate::api::timestamp::Timestamped;
+use crate::tick::Tick;
+use crate::api::symbol::{self, Symbol, WithSymbol};
+use crate::api::rate_limit::RateLimiter;
+use crate::api::transport::{self, HttpTransport};
+use crate::api::timestamp::{ClockOffset, Timestamped};
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 /// A binance key pair: api key + secret key.
 pub struct KeyPair {
     api_key: String,
@@ -42,12 +56,95 @@ impl KeyPair {
     }
 }
 
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyPair")
+            .field("api_key", &self.api_key)
+            .field("secret_key", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Clone)]
 struct Keys {
     api_key: String,
     secret_key: PKey<Private>,
-    listen_key: String,
+    listen_key: Arc<ListenKey>,
+    /// Offset of the binance clock, applied to the `timestamp` of signed requests.
+    clock: ClockOffset,
 }
 
+/// Listen key of the user data stream, shared between the client, its notification
+/// streams and the keep-alive thread.
+struct ListenKey {
+    key: Mutex<String>,
+
+    /// Incremented each time a new listen key is received, so that notification streams
+    /// know when to reconnect.
+    generation: AtomicUsize,
+    valid: AtomicBool,
+}
+
+impl ListenKey {
+    fn new(key: String) -> Self {
+        ListenKey {
+            key: Mutex::new(key),
+            generation: AtomicUsize::new(0),
+            valid: AtomicBool::new(true),
+        }
+    }
+
+    /// Return the current listen key along with its generation.
+    crate fn get(&self) -> (String, usize) {
+        let key = self.key.lock().unwrap();
+        (key.clone(), self.generation.load(Ordering::SeqCst))
+    }
+
+    crate fn generation(&self) -> usize {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    fn renew(&self, key: String) {
+        let mut current = self.key.lock().unwrap();
+        *current = key;
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.valid.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Binance recommends extending the validity of the listen key every 30 minutes.
+const KEEP_ALIVE_PERIOD: Duration = Duration::from_secs(30 * 60);
+
+/// HTTP transport shared by the REST requests of a `Client` and by the order book snapshot
+/// requests of its notification streams. Cloning it shares the underlying connection pool.
+type HttpClient = Arc<dyn HttpTransport>;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// Number of price levels per side of the partial order book streamed by binance, see
+/// `Client::with_partial_depth`.
+pub enum PartialDepth {
+    /// 5 levels per side.
+    Five,
+
+    /// 10 levels per side.
+    Ten,
+
+    /// 20 levels per side.
+    Twenty,
+}
+
+impl PartialDepth {
+    /// Return the number of price levels per side.
+    pub fn levels(self) -> usize {
+        match self {
+            PartialDepth::Five => 5,
+            PartialDepth::Ten => 10,
+            PartialDepth::Twenty => 20,
+        }
+    }
+}
+
+#[derive(Clone)]
 /// A binance API client.
 /// 
 /// The notification stream accessed through `<Client as ApiClient>::stream` is only valid for
@@ -55,15 +152,24 @@ struct Keys {
 /// get a new one.
 /// 
 /// The listen key is only valid for 60 minutes after its creation (through `Client::new`).
-/// Each `<Client as ApiClient>::ping` request will extend its validity for 60 minutes. Binance
-/// recommends sending a ping every 30 minutes. If the listen key becomes invalid, this client
-/// will stop forwarding the user data stream. The only way to fix it will be to drop the client
-/// and create a new one.
+/// `Client::new` spawns a background thread extending its validity every 30 minutes, which
+/// lives as long as the client, one of its clones or one of its notification streams is alive.
+/// If binance rejects the listen key anyway (e.g. after a network outage), a new one is
+/// requested and the notification streams forwarding the user data stream transparently
+/// reconnect: since the order book is then requested again, these streams emit
+/// `Notification::BookResync` first. Use `Client::listen_key_valid` for checking whether the
+/// user data stream is currently being forwarded.
 pub struct Client {
     params: Params,
     keys: Option<Keys>,
-    symbols: HashMap<String, Symbol>,
-    http_client: hyper::Client<hyper_tls::HttpsConnector<hyper::client::HttpConnector>>,
+    symbols: Arc<HashMap<String, Symbol>>,
+    rate_limiter: Option<RateLimiter>,
+    log_raw: bool,
+    http_client: HttpClient,
+    /// Custom order id generation scheme, see `Client::with_order_id_generator`.
+    order_id_generator: Option<api::OrderIdGenerator>,
+    /// Partial order book stream to subscribe to, see `Client::with_partial_depth`.
+    partial_depth: Option<PartialDepth>,
 }
 
 impl Client {
@@ -72,69 +178,271 @@ impl Client {
     /// a listen key for the user data stream.
     ///
     /// # Note
-    /// The request may block the thread if requesting a listen key for the user data stream.
-    /// The method will also block when fetching the available symbols from binance.
+    /// The method may block the thread if synchronizing with the server time and requesting
+    /// a listen key for the user data stream, see `Client::sync_time`. It will also block
+    /// when fetching the available symbols from binance.
     pub fn new(params: Params, key_pair: Option<KeyPair>) -> Result<Self, failure::Error> {
-        use tokio::runtime::current_thread;
+        Self::with_transport(params, key_pair, transport::https()?)
+    }
 
-        let http_client = hyper::Client::builder().build::<_, hyper::Body>(
-            hyper_tls::HttpsConnector::new(2)?
-        );
+    /// Create a new binance API client to the spot testnet, see `Params::binance_testnet` and
+    /// `Client::new`.
+    pub fn new_testnet(key_pair: Option<KeyPair>) -> Result<Self, failure::Error> {
+        Self::new(Params::binance_testnet(), key_pair)
+    }
 
+    /// Create a new binance API client like `Client::new`, sending its REST requests
+    /// (including the ones made by `Client::new`) through `http_client`.
+    pub fn with_transport(
+        params: Params,
+        key_pair: Option<KeyPair>,
+        http_client: Arc<dyn HttpTransport>
+    ) -> Result<Self, failure::Error>
+    {
+        use tokio::runtime::current_thread;
+
+        let rate_limiter = params.rate_limiter();
         let mut client = match key_pair {
             Some(pair) => {
                 let secret_key = PKey::hmac(pair.secret_key.as_bytes())?;
 
-                let mut client = Client {
+                let client = Client {
                     params,
                     keys: Some(Keys {
                         api_key: pair.api_key,
                         secret_key,
-                        listen_key: String::new(),
+                        listen_key: Arc::new(ListenKey::new(String::new())),
+                        clock: ClockOffset::default(),
                     }),
-                    symbols: HashMap::new(),
+                    symbols: Arc::new(HashMap::new()),
+                    rate_limiter,
+                    log_raw: false,
                     http_client,
+                    order_id_generator: None,
+                    partial_depth: None,
                 };
 
+                debug!("synchronizing with server time");
+                let skew = current_thread::Runtime::new()?.block_on(client.sync_time())?;
+                debug!("server clock is {}ms ahead", skew);
+
                 debug!("requesting listen key");
                 let listen_key = current_thread::Runtime::new()?
                     .block_on(client.get_listen_key())?;
                 debug!("received listen key");
 
-                client.keys.as_mut().unwrap().listen_key = listen_key;
+                *client.keys.as_ref().unwrap().listen_key.key.lock().unwrap() = listen_key;
                 client
             }
             None => Client {
                 params,
                 keys: None,
-                symbols: HashMap::new(),
+                symbols: Arc::new(HashMap::new()),
+                rate_limiter,
+                log_raw: false,
                 http_client,
+                order_id_generator: None,
+                partial_depth: None,
             }
         };
 
         debug!("requesting symbols");
-        client.symbols = current_thread::Runtime::new()?
-            .block_on(client.get_symbols())?;
+        client.symbols = Arc::new(
+            current_thread::Runtime::new()?.block_on(client.get_symbols())?
+        );
         debug!("received symbols");
+
+        if client.keys.is_some() {
+            let keep_alive_client = client.clone();
+            thread::spawn(move || keep_alive(keep_alive_client));
+        }
         Ok(client)
     }
+
+    /// Log every raw inbound text frame of the notification streams, as well as every
+    /// outbound subscription payload, at `trace` level with `"binance"` as the log target.
+    /// Payloads carrying credentials are not logged verbatim. Disabled by default.
+    pub fn with_log_raw(self, log_raw: bool) -> Self {
+        Client {
+            log_raw,
+            ..self
+        }
+    }
+
+    /// Use `generator` instead of `GenerateOrderId::new_order_id` for generating order ids
+    /// from a hint, see `Order::with_order_id_from`.
+    pub fn with_order_id_generator<F>(self, generator: F) -> Self
+        where F: Fn(&str) -> String + Send + Sync + 'static
+    {
+        Client {
+            order_id_generator: Some(Arc::new(generator)),
+            ..self
+        }
+    }
+
+    /// Use `commission_tick` for the commissions of the orders on the symbol named `symbol`,
+    /// e.g. for `OrderUpdate::commission`, instead of the tick derived from the precision
+    /// of the quote asset. If binance does not provide this precision and no tick is
+    /// supplied, commissions are ticked with `Tick::new(1)`, i.e. truncated to whole units.
+    /// Ignored if `symbol` is unknown.
+    ///
+    /// # Note
+    /// Only the `Symbol` values returned by `find_symbol` afterwards carry the new tick.
+    pub fn with_commission_tick(mut self, symbol: &str, commission_tick: Tick) -> Self {
+        let symbols = Arc::make_mut(&mut self.symbols);
+        if let Some(symbol) = symbols.get_mut(&symbol::canonical_name(symbol)) {
+            *symbol = symbol.with_commission_tick(commission_tick);
+        }
+        self
+    }
+
+    /// Stream a partial order book limited to `depth` levels per side instead of the full
+    /// order book, if `depth` is not `None`. Each update of the partial order book is
+    /// pushed every 100ms and forwarded as a `Notification::BookSnapshot`, hence no order
+    /// book snapshot needs to be requested from the REST API. Disabled by default.
+    pub fn with_partial_depth(self, partial_depth: Option<PartialDepth>) -> Self {
+        Client {
+            partial_depth,
+            ..self
+        }
+    }
+
+    /// Return the rate limiter delaying the REST requests of this client (and of its
+    /// clones), if rate limiting is enabled in `Params`.
+    pub fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    /// Return `false` if binance rejected the listen key of the user data stream and a new
+    /// one could not be obtained yet, in which case order notifications are not forwarded.
+    /// Always return `true` if this client was created without a key pair.
+    pub fn listen_key_valid(&self) -> bool {
+        self.keys.as_ref()
+            .map(|keys| keys.listen_key.valid.load(Ordering::SeqCst))
+            .unwrap_or(true)
+    }
+
+    /// Fetch the binance server time and measure the offset of its clock relative to the
+    /// local clock, in ms. If this client has a key pair, the offset is then applied to
+    /// the timestamp of signed requests (for this client and its clones), so that they are
+    /// not rejected with `RestErrorKind::OutsideTimeWindow` because of a drifting local
+    /// clock. Done by `Client::new` when given a key pair, may be called again periodically.
+    pub fn sync_time(&self)
+        -> impl Future<Item = i64, Error = api::errors::Error> + Send + 'static
+    {
+        let clock = self.keys.as_ref().map(|keys| keys.clock.clone());
+        self.get_server_time().map(move |(server_time, sent, received)| {
+            let offset = clock.unwrap_or_default();
+            offset.update(server_time, sent, received)
+        })
+    }
+
+    /// Return the offset of the binance clock relative to the local clock, in ms, as last
+    /// measured by `Client::sync_time`. Always return `0` if this client was created
+    /// without a key pair.
+    pub fn clock_skew(&self) -> i64 {
+        self.keys.as_ref().map(|keys| keys.clock.get()).unwrap_or(0)
+    }
+}
+
+/// Extend the validity of the listen key of `client` every `KEEP_ALIVE_PERIOD`, requesting a
+/// new one if binance rejects it. Stop when `client` is the last object holding the listen key.
+fn keep_alive(client: Client) {
+    let listen_key = client.keys.as_ref().unwrap().listen_key.clone();
+    let mut last_ping = Instant::now();
+
+    loop {
+        thread::sleep(Duration::from_secs(1));
+
+        // One reference for `client` and one for `listen_key`.
+        if Arc::strong_count(&listen_key) <= 2 {
+            debug!("client was dropped, stopping listen key keep-alive");
+            return;
+        }
+
+        if last_ping.elapsed() < KEEP_ALIVE_PERIOD {
+            continue;
+        }
+        last_ping = Instant::now();
+        refresh_listen_key(&client);
+    }
+}
+
+/// Extend the validity of the listen key of `client` once, requesting a new one if binance
+/// rejects it. If the listen key is invalid, also retry requesting a new one.
+fn refresh_listen_key(client: &Client) {
+    use tokio::runtime::current_thread;
+
+    let listen_key = &client.keys.as_ref().unwrap().listen_key;
+
+    if listen_key.valid.load(Ordering::SeqCst) {
+        debug!("extending listen key validity");
+        match current_thread::block_on_all(client.ping_impl()) {
+            Ok(_) => return,
+
+            // The listen key may still be valid, try again at the next period.
+            Err(api::errors::ApiError::RequestError(err)) => {
+                error!("listen key keep-alive failed: `{}`", err);
+                return;
+            }
+
+            Err(api::errors::ApiError::RestError(err)) => {
+                warn!("listen key was rejected: `{}`", err);
+                listen_key.valid.store(false, Ordering::SeqCst);
+            }
+        }
+    }
+
+    debug!("requesting new listen key");
+    match current_thread::block_on_all(client.get_listen_key()) {
+        Ok(key) => {
+            debug!("received new listen key");
+            listen_key.renew(key);
+        }
+        Err(err) => error!("failed to request new listen key: `{}`", err),
+    }
 }
 
 impl ApiClient for Client {
-    type Stream = futures::sync::mpsc::UnboundedReceiver<Notification>;
+    type Stream = api::stream::StreamHandle;
 
     fn find_symbol(&self, symbol: &str) -> Option<Symbol> {
-        self.symbols.get(&symbol.to_lowercase()).cloned()
+        self.symbols.get(&symbol::canonical_name(symbol)).cloned()
+    }
+
+    fn generate_order_id(&self, hint: &str) -> String {
+        match &self.order_id_generator {
+            Some(generator) => generator(hint),
+            None => Self::new_order_id(hint),
+        }
+    }
+
+    fn ticker(&self, symbol: Symbol)
+        -> Box<dyn Future<Item = Ticker, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.ticker_impl(symbol))
+    }
+
+    fn recent_trades(&self, symbol: Symbol, limit: usize)
+        -> Box<dyn Future<Item = Vec<Timestamped<Trade>>, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.recent_trades_impl(symbol, limit))
     }
 
     fn stream_with_flags(&self, symbol: Symbol, flags: NotificationFlags) -> Self::Stream {
         self.new_stream(symbol, flags)
     }
 
+    fn stream_many(&self, symbols: &[Symbol], flags: NotificationFlags)
+        -> Box<dyn Stream<Item = WithSymbol<Notification>, Error = ()> + Send + 'static>
+    {
+        Box::new(self.new_combined_stream(symbols, flags))
+    }
+
     fn order(&self, order: WithSymbol<&Order>)
         -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
     {
-        Box::new(self.order_impl(order))
+        self.order_impl(order)
     }
 
     fn cancel(&self, cancel: WithSymbol<&Cancel>)
@@ -143,6 +451,12 @@ impl ApiClient for Client {
         Box::new(self.cancel_impl(cancel))
     }
 
+    fn replace(&self, replaced: WithSymbol<&Order>, new: &Order)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
+    {
+        api::replace_by_cancel(self, replaced, new)
+    }
+
     fn ping(&self)
         -> Box<dyn Future<Item = Timestamped<()>, Error = api::errors::Error> + Send + 'static>
     {
@@ -154,6 +468,20 @@ impl ApiClient for Client {
     {
         Box::new(self.balances_impl())
     }
+
+    fn deposit_address(&self, asset: &str)
+        -> Box<dyn Future<Item = DepositAddress, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.deposit_address_impl(asset))
+    }
+}
+
+impl WithdrawClient for Client {
+    fn withdraw(&self, asset: &str, amount: &str, address: &str, tag: Option<&str>)
+        -> Box<dyn Future<Item = WithdrawAck, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.withdraw_impl(asset, amount, address, tag))
+    }
 }
 
 impl GenerateOrderId for Client {
@@ -3,9 +3,9 @@ use futures::prelude::*;
 use std::collections::HashMap;
 use failure::Fail;
 use serde_derive::Deserialize;
-use log::error;
+use log::{warn, error};
 use crate::Side;
-use crate::tick::Tick;
+use crate::tick::{Tick, Price, Size};
 use crate::api::{
     self,
     OrderType,
@@ -16,11 +16,12 @@ use crate::api::{
     CancelAck,
 };
 use crate::api::query_string::QueryString;
+use crate::api::rate_limit;
 use crate::api::errors::ErrorKinded;
-use crate::api::symbol::{Symbol, WithSymbol};
+use crate::api::symbol::{self, Symbol, WithSymbol};
 use crate::api::binance::Client;
 use crate::api::binance::errors::RestError;
-use crate::api::timestamp::{timestamp_ms, Timestamped, IntoTimestamped};
+use crate::api::timestamp::{timestamp_ms, Timestamp, Timestamped, IntoTimestamped};
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
 #[allow(non_snake_case)]
@@ -42,6 +43,43 @@ struct BinanceAccountInformation<'a> {
     balances: Vec<BinanceBalance<'a>>,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct BinanceBookTicker<'a> {
+    bidPrice: &'a str,
+    askPrice: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct Binance24hrTicker<'a> {
+    lastPrice: &'a str,
+    volume: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct BinanceTrade<'a> {
+    price: &'a str,
+    qty: &'a str,
+    time: Timestamp,
+    isBuyerMaker: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct BinanceDepositAddress<'a> {
+    address: &'a str,
+    success: bool,
+    addressTag: Option<&'a str>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct BinanceWithdrawAck<'a> {
+    success: bool,
+    id: Option<&'a str>,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
 #[allow(non_snake_case)]
 struct BinanceListenKey<'a> {
@@ -55,18 +93,28 @@ struct BinanceListenKey<'a> {
 enum BinanceFilter<'a> {
     PRICE_FILTER { tickSize: &'a str },
     LOT_SIZE { stepSize: &'a str },
-    MIN_NOTIONAL,
+    MIN_NOTIONAL { minNotional: &'a str },
     ICEBERG_PARTS,
     MAX_NUM_ALGO_ORDERS,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
 struct BinanceSymbol<'a> {
     symbol: &'a str,
+    baseAsset: &'a str,
+    quoteAsset: &'a str,
+    quotePrecision: Option<u32>,
     #[serde(borrow)]
     filters: Vec<BinanceFilter<'a>>,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct BinanceServerTime {
+    serverTime: Timestamp,
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
 struct BinanceExchangeInfo<'a> {
     #[serde(borrow)]
@@ -91,6 +139,9 @@ impl AsStr for OrderType {
         match self {
             OrderType::Limit => "LIMIT",
             OrderType::LimitMaker => "LIMIT_MAKER",
+            OrderType::Market => "MARKET",
+            OrderType::StopLoss => "STOP_LOSS",
+            OrderType::StopLossLimit => "STOP_LOSS_LIMIT",
         }
     }
 }
@@ -105,6 +156,17 @@ impl AsStr for TimeInForce {
     }
 }
 
+/// Weight of a request to `path`, as accounted by binance. Requests not listed here have
+/// a weight of `1`. Depth snapshots are always requested with `limit=1000`.
+crate fn request_weight(path: &str) -> u32 {
+    match path {
+        "api/v3/exchangeInfo" => 10,
+        "api/v3/depth" => 10,
+        "api/v3/account" => 5,
+        _ => 1,
+    }
+}
+
 impl Client {
     fn request<K: api::errors::ErrorKind>(
         &self,
@@ -116,36 +178,39 @@ impl Client {
     {
         use hyper::Request;
 
-        let mut request = Request::builder();
-
-        let query = match self.keys.as_ref() {
-            None => query.into_string(),
-            Some(keys) => {
-                request.header("X-MBX-APIKEY", keys.api_key.as_bytes());
-                query.into_string_with_signature(&keys.secret_key)
-            }
-        };
-
         let address = format!(
             "{}/{}",
             self.params.rest_endpoint,
             path,
         );
 
-        request.method(method)
-            .header("User-Agent", &b"hyper"[..])
-            .header("Content-Type", &b"application/x-www-form-urlencoded"[..])
-            .uri(&address);
-
-        // Unwrap because it is a bug if this fails (header failed to parse or something)
-        let request = request.body(query.into()).unwrap();
-        self.http_client.request(request).and_then(|res| {
-            let status = res.status();
-            res.into_body().concat2().and_then(move |body| {
-                Ok((status, body))
-            })
+        let http_client = self.http_client.clone();
+        let keys = self.keys.clone();
+        let weight = request_weight(path);
+
+        // Sign the query only once the request is allowed to be sent, since the signed
+        // query may carry a timestamp.
+        rate_limit::acquire(self.rate_limiter.as_ref(), weight).then(move |_| {
+            let mut request = Request::builder();
+
+            let query = match keys.as_ref() {
+                None => query.into_string(),
+                Some(keys) => {
+                    request.header("X-MBX-APIKEY", keys.api_key.as_bytes());
+                    query.into_string_with_signature(&keys.secret_key, &keys.clock)
+                }
+            };
+
+            request.method(method)
+                .header("User-Agent", &b"hyper"[..])
+                .header("Content-Type", &b"application/x-www-form-urlencoded"[..])
+                .uri(&address);
+
+            // Unwrap because it is a bug if this fails (header failed to parse or something)
+            let request = request.body(query.into()).unwrap();
+            http_client.request(request)
         })
-        .map_err(api::errors::RequestError::new)
+        .map_err(|err| api::errors::RequestError::new(err.compat()))
         .map_err(api::errors::ApiError::RequestError)
         .and_then(|(status, body)| {
             if status != hyper::StatusCode::OK {
@@ -161,33 +226,52 @@ impl Client {
     }
 
     crate fn order_impl(&self, order: WithSymbol<&Order>)
-        -> impl Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
     {
         use std::borrow::Borrow;
 
+        if let Err(err) = order.validate(&order.symbol()) {
+            return Box::new(Err(err.into()).into_future());
+        }
+
         let mut query = QueryString::new();
         let symbol = order.symbol();
         query.push_str("symbol", symbol.name());
         query.push_str("side", order.side.as_str());
         query.push_str("type", order.type_.as_str());
-        if order.type_ == OrderType::Limit {
+        if order.type_ == OrderType::Limit || order.type_ == OrderType::StopLossLimit {
             query.push("timeInForce", order.time_in_force.as_str());
         }
-        query.push_str(
-            "quantity",
-            order.size.unticked(symbol.size_tick()).borrow() as &str
-        );
-        query.push_str(
-            "price",
-            order.price.unticked(symbol.price_tick()).borrow() as &str
-        );
+        match &order.funds {
+            // Funds are expressed in the quote asset, with `quotePrecision` decimals.
+            Some(funds) => query.push_str(
+                "quoteOrderQty",
+                funds.unticked(symbol.funds_tick()).borrow() as &str
+            ),
+            None => query.push_str(
+                "quantity",
+                order.size.unticked(symbol.size_tick()).borrow() as &str
+            ),
+        }
+        if !order.type_.is_market() {
+            query.push_str(
+                "price",
+                order.price.unticked(symbol.price_tick()).borrow() as &str
+            );
+        }
+        if let (true, Some(stop_price)) = (order.type_.is_stop(), &order.stop_price) {
+            query.push_str(
+                "stopPrice",
+                stop_price.unticked(symbol.price_tick()).borrow() as &str
+            );
+        }
         if let Some(order_id) = &order.order_id {
             query.push_str("newClientOrderId", order_id);
         }
         query.push("recvWindow", order.time_window);
-        query.push("timestamp", timestamp_ms());
+        query.push_timestamp();
 
-        self.request("api/v3/order", Method::POST, query)
+        let fut = self.request("api/v3/order", Method::POST, query)
             .and_then(|body|
         {
             let ack: BinanceOrderAck<'_> = serde_json::from_slice(&body)
@@ -196,7 +280,8 @@ impl Client {
             Ok(OrderAck {
                 order_id: ack.clientOrderId.to_owned(),
             }.with_timestamp(ack.transactTime))
-        })
+        });
+        Box::new(fut)
     }
 
     crate fn cancel_impl(&self, cancel: WithSymbol<&Cancel>)
@@ -207,7 +292,7 @@ impl Client {
         query.push_str("symbol", symbol.name());
         query.push_str("origClientOrderId", &cancel.order_id);
         query.push("recvWindow", cancel.time_window);
-        query.push("timestamp", timestamp_ms());
+        query.push_timestamp();
 
         self.request("api/v3/order", Method::DELETE, query).and_then(|_| {
             Ok(CancelAck.timestamped())
@@ -219,7 +304,7 @@ impl Client {
     {
         let query = QueryString::new();
 
-        self.request("api/v1/userDataStream", Method::POST, query).and_then(|body| {
+        self.request("api/v3/userDataStream", Method::POST, query).and_then(|body| {
             let key: BinanceListenKey<'_> = serde_json::from_slice(&body)
                 .map_err(api::errors::RequestError::new)
                 .map_err(api::errors::ApiError::RequestError)?;
@@ -230,11 +315,11 @@ impl Client {
     crate fn ping_impl(&self)
         -> Box<dyn Future<Item = Timestamped<()>, Error = api::errors::Error> + Send + 'static>
     {
-        if let Some(listen_key) = self.keys.as_ref().map(|keys| &keys.listen_key) {
+        if let Some(listen_key) = self.keys.as_ref().map(|keys| keys.listen_key.get().0) {
             let mut query = QueryString::new();
-            query.push_str("listenKey", listen_key);
+            query.push_str("listenKey", &listen_key);
 
-            let fut = self.request("api/v1/userDataStream", Method::PUT, query)
+            let fut = self.request("api/v3/userDataStream", Method::PUT, query)
                 .and_then(|_| Ok(().timestamped()));
             Box::new(fut)
         } else {
@@ -247,7 +332,7 @@ impl Client {
     {
         let mut query = QueryString::new();
         query.push("recvWindow", 5000);
-        query.push("timestamp", timestamp_ms());
+        query.push_timestamp();
 
         self.request("api/v3/account", Method::GET, query).and_then(|body| {
             let info: BinanceAccountInformation<'_> = serde_json::from_slice(&body)
@@ -264,20 +349,170 @@ impl Client {
         })
     }
 
+    crate fn ticker_impl(&self, symbol: Symbol)
+        -> impl Future<Item = api::Ticker, Error = api::errors::Error> + Send + 'static
+    {
+        let mut query = QueryString::new();
+        query.push_str("symbol", symbol.name());
+        let book_ticker = self.request("api/v3/ticker/bookTicker", Method::GET, query);
+
+        let mut query = QueryString::new();
+        query.push_str("symbol", symbol.name());
+        let ticker_24hr = self.request("api/v3/ticker/24hr", Method::GET, query);
+
+        book_ticker.join(ticker_24hr).and_then(move |(body_book, body_24hr)| {
+            let book: BinanceBookTicker<'_> = serde_json::from_slice(&body_book)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            let ticker: Binance24hrTicker<'_> = serde_json::from_slice(&body_24hr)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            let convert = |tick: Tick, value: &str| {
+                tick.ticked(value)
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)
+            };
+
+            Ok(api::Ticker {
+                best_bid: Price(convert(symbol.price_tick(), book.bidPrice)?),
+                best_ask: Price(convert(symbol.price_tick(), book.askPrice)?),
+                last: Price(convert(symbol.price_tick(), ticker.lastPrice)?),
+                volume_24h: Size(convert(symbol.size_tick(), ticker.volume)?),
+            })
+        })
+    }
+
+    crate fn recent_trades_impl(&self, symbol: Symbol, limit: usize)
+        -> impl Future<Item = Vec<Timestamped<api::Trade>>, Error = api::errors::Error> + Send + 'static
+    {
+        let mut query = QueryString::new();
+        query.push_str("symbol", symbol.name());
+        query.push("limit", limit);
+
+        self.request("api/v3/trades", Method::GET, query).and_then(move |body| {
+            let trades: Vec<BinanceTrade<'_>> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            let convert = |tick: Tick, value: &str| {
+                tick.ticked(value)
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)
+            };
+
+            // Trades are sent oldest first.
+            let skip = trades.len().saturating_sub(limit);
+            trades.into_iter().skip(skip).map(|trade| {
+                Ok(api::Trade {
+                    price: Price(convert(symbol.price_tick(), trade.price)?),
+                    size: Size(convert(symbol.size_tick(), trade.qty)?),
+                    maker_side: if trade.isBuyerMaker { Side::Bid } else { Side::Ask },
+                }.with_timestamp(trade.time))
+            }).collect::<Result<_, api::errors::Error>>()
+        })
+    }
+
+    crate fn deposit_address_impl(&self, asset: &str)
+        -> impl Future<Item = api::DepositAddress, Error = api::errors::Error> + Send + 'static
+    {
+        let mut query = QueryString::new();
+        query.push_str("asset", asset);
+        query.push("recvWindow", 5000);
+        query.push_timestamp();
+
+        self.request("wapi/v3/depositAddress.html", Method::GET, query).and_then(|body| {
+            let address: BinanceDepositAddress<'_> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            // The withdraw API answers with a `200` status code even on failure.
+            if !address.success {
+                Err(
+                    api::errors::ApiError::RestError(
+                        api::errors::RestErrorKind::InvalidRequest.into()
+                    )
+                )?;
+            }
+
+            Ok(api::DepositAddress {
+                address: address.address.to_owned(),
+                tag: address.addressTag
+                    .filter(|tag| !tag.is_empty())
+                    .map(|tag| tag.to_owned()),
+            })
+        })
+    }
+
+    crate fn withdraw_impl(&self, asset: &str, amount: &str, address: &str, tag: Option<&str>)
+        -> impl Future<Item = api::WithdrawAck, Error = api::errors::Error> + Send + 'static
+    {
+        let mut query = QueryString::new();
+        query.push_str("asset", asset);
+        query.push_str("address", address);
+        if let Some(tag) = tag {
+            query.push_str("addressTag", tag);
+        }
+        query.push_str("amount", amount);
+        query.push("recvWindow", 5000);
+        query.push_timestamp();
+
+        self.request("wapi/v3/withdraw.html", Method::POST, query).and_then(|body| {
+            let ack: BinanceWithdrawAck<'_> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            // The withdraw API answers with a `200` status code even on failure.
+            match ack.id {
+                Some(id) if ack.success => Ok(api::WithdrawAck {
+                    id: id.to_owned(),
+                }),
+                _ => Err(
+                    api::errors::ApiError::RestError(
+                        api::errors::RestErrorKind::InvalidRequest.into()
+                    )
+                ),
+            }
+        })
+    }
+
+    /// Return the server time along with the local times at which the request was sent and
+    /// at which the response was received.
+    crate fn get_server_time(&self)
+        -> impl Future<Item = (Timestamp, Timestamp, Timestamp), Error = api::errors::Error>
+            + Send + 'static
+    {
+        let sent = timestamp_ms();
+        self.request("api/v3/time", Method::GET, QueryString::new()).and_then(move |body| {
+            let received = timestamp_ms();
+            let time: BinanceServerTime = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+            Ok((time.serverTime, sent, received))
+        })
+    }
+
     crate fn get_symbols(&self)
         -> impl Future<Item = HashMap<String, Symbol>, Error = api::errors::Error> + Send + 'static
     {
         let query = QueryString::new();
 
-        self.request("api/v1/exchangeInfo", Method::GET, query).and_then(|body| {
+        self.request("api/v3/exchangeInfo", Method::GET, query).and_then(|body| {
             let info: BinanceExchangeInfo<'_> = serde_json::from_slice(&body)
                 .map_err(api::errors::RequestError::new)
                 .map_err(api::errors::ApiError::RequestError)?;
 
             let mut symbols = HashMap::new();
             for symbol in info.symbols.into_iter() {
+                let base_asset = symbol.baseAsset;
+                let quote_asset = symbol.quoteAsset;
+                let commission_tick = symbol.quotePrecision
+                    .and_then(|precision| 10u64.checked_pow(precision))
+                    .map(Tick::new);
                 let mut price_tick = None;
                 let mut size_tick = None;
+                let mut min_notional = None;
 
                 for filter in symbol.filters {
                     #[allow(non_snake_case)]
@@ -288,6 +523,9 @@ impl Client {
                         BinanceFilter::LOT_SIZE { stepSize } => {
                             size_tick = Tick::tick_size(stepSize);
                         }
+                        BinanceFilter::MIN_NOTIONAL { minNotional } => {
+                            min_notional = Some(minNotional);
+                        }
                         _ => (),
                     }
                 }
@@ -308,7 +546,28 @@ impl Client {
                     size_tick.unwrap()
                 )
                 {
-                    symbols.insert(symbol.name().to_lowercase(), symbol);
+                    let mut symbol = symbol.with_base_asset(base_asset)
+                        .with_quote_asset(quote_asset);
+                    if let Some(min_notional) = min_notional {
+                        // The notional is expressed in the quote asset, i.e. with the price tick.
+                        match symbol.price_tick().ticked(min_notional) {
+                            Ok(min_notional) => symbol = symbol.with_min_notional(min_notional),
+                            Err(err) => warn!(
+                                "cannot read min notional for symbol `{}`: `{}`",
+                                symbol.name(),
+                                err
+                            ),
+                        }
+                    }
+                    // Both the commission and the funds of a market order are expressed in the
+                    // quote asset, with `quotePrecision` decimals.
+                    match commission_tick {
+                        Some(tick) => {
+                            symbol = symbol.with_commission_tick(tick).with_funds_tick(tick)
+                        }
+                        None => warn!("cannot read commission tick for symbol `{}`", symbol.name()),
+                    }
+                    symbols.insert(symbol::canonical_name(symbol.name()), symbol);
                 } else {
                     error!("symbol name too long: `{}`", symbol.symbol);
                 }
@@ -0,0 +1,564 @@
+#![cfg(test)]
+
+use std::sync::Arc;
+use futures::prelude::*;
+use hyper::StatusCode;
+use crate::Side;
+use crate::tick::{Tick, Price, Size};
+use crate::api::{
+    ApiClient,
+    Exchange,
+    Notification,
+    NotificationFlags,
+    Params,
+    Order,
+    Cancel,
+    Trade,
+};
+use crate::api::timestamp::{timestamp_ms, IntoTimestamped};
+use crate::api::harness::{self, MockTransport};
+use crate::api::symbol::{Symbol, IntoWithSymbol};
+use crate::api::errors::{ApiError, ErrorKinded, RestErrorKind, OrderErrorKind};
+use crate::api::binance::errors::{RestError, RestErrorKind as BinanceErrorKind};
+use crate::api::binance::{Client, HttpClient, KeyPair, PartialDepth};
+use crate::api::binance::wss::HandlerImpl;
+
+fn http_client() -> HttpClient {
+    crate::api::transport::https().unwrap()
+}
+
+fn order_error(code: i32, msg: &str) -> RestErrorKind<OrderErrorKind> {
+    let err = RestError {
+        kind: BinanceErrorKind::MalformedRequest,
+        error_code: Some(code),
+        error_msg: Some(msg.to_owned()),
+    };
+    err.kind()
+}
+
+#[test]
+fn order_error_kind() {
+    assert_eq!(
+        order_error(-2010, "Account has insufficient balance for requested action."),
+        RestErrorKind::Specific(OrderErrorKind::InsufficientBalance)
+    );
+
+    assert_eq!(
+        order_error(-2010, "Duplicate order sent."),
+        RestErrorKind::Specific(OrderErrorKind::DuplicateOrder)
+    );
+
+    assert_eq!(
+        order_error(-2010, "Order would immediately match and take."),
+        RestErrorKind::Specific(OrderErrorKind::WouldTakeLiquidity)
+    );
+}
+
+#[test]
+fn limit_maker_rejection() {
+    let payloads = [
+        r#"{"code":-2010,"msg":"Order would immediately match and take."}"#,
+        r#"{"code":-2010,"msg":"Order would immediately match and take"}"#,
+        r#"{"code":-1010,"msg":"Order would immediately match and take."}"#,
+        r#"{"code":-2010,"msg":"order would immediately match and take."}"#,
+    ];
+
+    for payload in &payloads {
+        let binance_error = serde_json::from_str(payload).unwrap();
+        let err = RestError::from_binance_error(StatusCode::BAD_REQUEST, Some(binance_error));
+        assert_eq!(
+            ErrorKinded::<OrderErrorKind>::kind(&err),
+            RestErrorKind::Specific(OrderErrorKind::WouldTakeLiquidity),
+            "{}",
+            payload
+        );
+    }
+
+    // Other rejections of a `LIMIT_MAKER` order are not mistaken for post-only ones.
+    assert_eq!(
+        order_error(-2010, "Order would trigger immediately."),
+        RestErrorKind::InvalidRequest
+    );
+    assert_eq!(order_error(-1013, "Filter failure: PRICE_FILTER"), RestErrorKind::InvalidRequest);
+}
+
+fn depth_update(first_update_id: u64, last_update_id: u64) -> String {
+    format!(
+        r#"{{"e":"depthUpdate","E":0,"s":"BNBBTC","U":{},"u":{},"b":[],"a":[]}}"#,
+        first_update_id,
+        last_update_id
+    )
+}
+
+#[test]
+fn depth_update_gap() {
+    let symbol = Symbol::new("BNBBTC", Tick::new(100_000_000), Tick::new(100)).unwrap();
+    let params = harness::params();
+    let mut handler = HandlerImpl::new(symbol, NotificationFlags::ALL, params, http_client());
+
+    assert_eq!(handler.process_message(&depth_update(1, 5)).unwrap(), None);
+    assert_eq!(handler.process_message(&depth_update(6, 8)).unwrap(), None);
+
+    // Gap between `9` and `12`.
+    assert_eq!(
+        handler.process_message(&depth_update(12, 15)).unwrap(),
+        Some(Notification::BookResync)
+    );
+
+    // Sequence starts over.
+    assert_eq!(handler.process_message(&depth_update(20, 25)).unwrap(), None);
+    assert_eq!(handler.process_message(&depth_update(26, 30)).unwrap(), None);
+}
+
+#[test]
+fn book_snapshot_rate_limit() {
+    use crate::api::rate_limit::RateLimiter;
+
+    let symbol = Symbol::new("BNBBTC", Tick::new(10_000), Tick::new(1)).unwrap();
+    let params = harness::params();
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"{"lastUpdateId":5,"bids":[["0.0010","1"]],"asks":[]}"#);
+
+    let limiter = RateLimiter::new(100);
+    let mut handler = HandlerImpl::new(symbol, NotificationFlags::ALL, params, transport.clone())
+        .with_rate_limiter(Some(limiter.clone()));
+
+    let update = |id| format!(
+        r#"{{"e":"depthUpdate","E":0,"s":"BNBBTC","U":{},"u":{},"b":[["0.0011","1"]],"a":[]}}"#,
+        id,
+        id
+    );
+
+    // The first depth update triggers the snapshot request, which weighs `10`.
+    assert_eq!(handler.process_message(&update(5)).unwrap(), None);
+    let mut last_update_id = 5;
+    let snapshot = loop {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        last_update_id += 1;
+        if let Some(notif) = handler.process_message(&update(last_update_id)).unwrap() {
+            break notif;
+        }
+        assert!(last_update_id < 500, "no snapshot received");
+    };
+    match snapshot {
+        Notification::BookSnapshot(..) => (),
+        other => panic!("expected a book snapshot, found `{:?}`", other),
+    }
+
+    assert_eq!(transport.paths(), vec!["/api/v3/depth"]);
+    assert!(limiter.budget() <= 91, "unexpected budget: {}", limiter.budget());
+}
+
+#[test]
+fn book_snapshot_then_updates() {
+    let symbol = Symbol::new("BNBBTC", Tick::new(10_000), Tick::new(1)).unwrap();
+    let params = harness::params();
+    let snapshot = r#"{
+        "lastUpdateId": 100,
+        "bids": [["0.0024", "10", []], ["0.0023", "20", []]],
+        "asks": [["0.0026", "100", []], ["0.0027", "1", []]]
+    }"#;
+    let mut handler = HandlerImpl::new(symbol, NotificationFlags::ALL, params, http_client())
+        .with_book_snapshot(snapshot);
+
+    let frames = [
+        // Already included in the snapshot.
+        r#"{"e":"depthUpdate","E":1,"s":"BNBBTC","U":95,"u":100,"b":[["0.0023","99",[]]],"a":[]}"#,
+        r#"{"e":"depthUpdate","E":2,"s":"BNBBTC","U":101,"u":102,"b":[["0.0024","5",[]]],"a":[["0.0026","0",[]]]}"#,
+        r#"{"e":"trade","E":3,"s":"BNBBTC","t":1,"p":"0.0025","q":"2","b":1,"a":2,"T":3,"m":true,"M":true}"#,
+        r#"{"e":"depthUpdate","E":4,"s":"BNBBTC","U":103,"u":103,"b":[],"a":[["0.0027","3",[]]]}"#,
+    ];
+
+    let expected = harness::order_book(&[(24, 5), (23, 20)], &[(27, 3)]);
+    let notifs = harness::assert_book(&mut handler, &frames, &expected);
+    assert_eq!(notifs.len(), 4);
+    assert!(if let Notification::BookSnapshot(..) = notifs[0] { true } else { false });
+}
+
+#[test]
+fn partial_depth() {
+    let symbol = Symbol::new("BNBBTC", Tick::new(10_000), Tick::new(1)).unwrap();
+    let params = harness::params();
+    let mut handler = HandlerImpl::new(symbol, NotificationFlags::ALL, params, http_client())
+        .with_partial_depth(Some(PartialDepth::Five));
+
+    // Each message carries the whole partial order book, without any sequence number
+    // to keep track of.
+    let frames = [
+        r#"{"lastUpdateId":160,"bids":[["0.0024","10"],["0.0023","20"]],"asks":[["0.0026","100"]]}"#,
+        r#"{"e":"trade","E":3,"s":"BNBBTC","t":1,"p":"0.0025","q":"2","b":1,"a":2,"T":3,"m":true,"M":true}"#,
+        r#"{"lastUpdateId":170,"bids":[["0.0023","15"]],"asks":[["0.0025","3"],["0.0026","50"]]}"#,
+    ];
+
+    let expected = harness::order_book(&[(23, 15)], &[(25, 3), (26, 50)]);
+    let notifs = harness::assert_book(&mut handler, &frames, &expected);
+    assert_eq!(notifs.len(), 3);
+    assert!(if let Notification::BookSnapshot(..) = notifs[0] { true } else { false });
+    assert!(if let Notification::BookSnapshot(..) = notifs[2] { true } else { false });
+}
+
+#[test]
+fn balance_update() {
+    let symbol = Symbol::new("BNBBTC", Tick::new(10_000), Tick::new(1)).unwrap();
+    let params = harness::params();
+    let frame = r#"{"e":"outboundAccountPosition","E":1564034571105,"u":1564034571073,"B":[{"a":"ETH","f":"10000.000000","l":"0.000000"}]}"#;
+
+    // Balance updates are opt-in.
+    let mut handler = HandlerImpl::new(
+        symbol,
+        NotificationFlags::ALL,
+        params.clone(),
+        http_client()
+    );
+    assert!(harness::replay(&mut handler, &[frame]).is_empty());
+
+    let mut handler = HandlerImpl::new(
+        symbol,
+        NotificationFlags::BALANCES,
+        params,
+        http_client()
+    );
+    let notifs = harness::replay(&mut handler, &[frame]);
+    assert_eq!(notifs.len(), 1);
+    match &notifs[0] {
+        Notification::BalanceUpdate(balances) => {
+            assert_eq!(balances.timestamp(), 1564034571105);
+            assert_eq!(balances.len(), 1);
+            assert_eq!(balances["ETH"].free, "10000.000000");
+            assert_eq!(balances["ETH"].locked, "0.000000");
+        }
+        other => panic!("expected a balance update, found `{:?}`", other),
+    }
+}
+
+#[test]
+fn mock_transport() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"{"symbols":[{"symbol":"BNBBTC","baseAsset":"BNB","quoteAsset":"BTC","quotePrecision":8,"filters":[{"filterType":"PRICE_FILTER","tickSize":"0.00000010"},{"filterType":"LOT_SIZE","stepSize":"0.01000000"}]}]}"#);
+    transport.respond(StatusCode::OK, r#"{"symbol":"BNBBTC","orderId":28,"clientOrderId":"6gCrw2kRUAF9CvJDGP16IP","transactTime":1507725176595}"#);
+    transport.respond(StatusCode::BAD_REQUEST, r#"{"code":-2010,"msg":"Account has insufficient balance for requested action."}"#);
+
+    let client = Client::with_transport(harness::params(), None, transport.clone()).unwrap();
+    let symbol = client.find_symbol("BNBBTC").unwrap();
+    assert_eq!(symbol.price_tick(), Tick::new(10_000_000));
+    assert_eq!(symbol.size_tick(), Tick::new(100));
+
+    let order = Order::new("0.0025", "1.5", Side::Bid);
+    let ack = client.order(order.with_symbol(symbol)).wait().unwrap();
+    assert_eq!(ack.timestamp(), 1507725176595);
+    assert_eq!(ack.order_id, "6gCrw2kRUAF9CvJDGP16IP");
+
+    match client.order(order.with_symbol(symbol)).wait() {
+        Err(ApiError::RestError(err)) => {
+            assert_eq!(err.kind(), RestErrorKind::Specific(OrderErrorKind::InsufficientBalance));
+        }
+        other => panic!("expected a REST error, found `{:?}`", other),
+    }
+
+    assert_eq!(transport.paths(), vec![
+        "/api/v3/exchangeInfo".to_owned(),
+        "/api/v3/order".to_owned(),
+        "/api/v3/order".to_owned(),
+    ]);
+}
+
+#[test]
+fn market_with_funds() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"{"symbols":[{"symbol":"BTCUSDT","baseAsset":"BTC","quoteAsset":"USDT","quotePrecision":8,"filters":[{"filterType":"PRICE_FILTER","tickSize":"0.01000000"},{"filterType":"LOT_SIZE","stepSize":"0.00000100"}]}]}"#);
+    transport.respond(StatusCode::OK, r#"{"symbol":"BTCUSDT","orderId":30,"clientOrderId":"funds","transactTime":1507725176595}"#);
+
+    let client = Client::with_transport(harness::params(), None, transport.clone()).unwrap();
+    let symbol = client.find_symbol("BTCUSDT").unwrap();
+
+    // Funds are on the `quotePrecision` grid, finer than the price tick.
+    assert_eq!(symbol.funds_tick(), Tick::new(100_000_000));
+    let order = Order::market_with_funds("10.12345678").with_order_id::<Client>("funds");
+    client.order(order.with_symbol(symbol)).wait().unwrap();
+
+    let requests = transport.requests();
+    let query = requests[1].uri().query().unwrap_or("").to_owned()
+        + std::str::from_utf8(requests[1].body()).unwrap();
+    assert!(query.contains("quoteOrderQty=10.12345678"), "{}", query);
+    assert!(!query.contains("quantity="), "{}", query);
+}
+
+#[test]
+fn replace() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"{"symbols":[{"symbol":"BNBBTC","baseAsset":"BNB","quoteAsset":"BTC","quotePrecision":8,"filters":[{"filterType":"PRICE_FILTER","tickSize":"0.00000010"},{"filterType":"LOT_SIZE","stepSize":"0.01000000"}]}]}"#);
+    transport.respond(StatusCode::OK, r#"{}"#);
+    transport.respond(StatusCode::OK, r#"{"symbol":"BNBBTC","orderId":29,"clientOrderId":"new-order","transactTime":1507725176595}"#);
+    transport.respond(StatusCode::OK, r#"{}"#);
+    transport.respond(StatusCode::BAD_REQUEST, r#"{"code":-2010,"msg":"Account has insufficient balance for requested action."}"#);
+
+    let client = Client::with_transport(harness::params(), None, transport.clone()).unwrap();
+    let symbol = client.find_symbol("BNBBTC").unwrap();
+
+    let replaced = Order::new("0.0025", "1.5", Side::Bid).with_order_id::<Client>("old-order");
+    let new = Order::new("0.0026", "1.5", Side::Bid).with_order_id::<Client>("new-order");
+    let ack = client.replace(replaced.with_symbol(symbol), &new).wait().unwrap();
+    assert_eq!(ack.order_id, "new-order");
+
+    // The replaced order is canceled but the new one fails: the replaced order is not sent
+    // again, but is returned along with the error.
+    match client.replace(replaced.with_symbol(symbol), &new).wait() {
+        Err(err) => {
+            assert_eq!(err.replaced_order(), Some(&replaced));
+            match err {
+                ApiError::RestError(err) => assert_eq!(
+                    err.kind(),
+                    RestErrorKind::Specific(OrderErrorKind::InsufficientBalance)
+                ),
+                other => panic!("expected a REST error, found `{:?}`", other),
+            }
+        }
+        other => panic!("expected an error, found `{:?}`", other),
+    }
+
+    // Replacing an order without an id is rejected without sending anything.
+    let order = Order::new("0.0025", "1.5", Side::Bid);
+    match client.replace(order.with_symbol(symbol), &new).wait() {
+        Err(ApiError::RestError(err)) => assert_eq!(err.kind(), RestErrorKind::InvalidRequest),
+        other => panic!("expected a REST error, found `{:?}`", other),
+    }
+
+    assert_eq!(transport.paths(), vec![
+        "/api/v3/exchangeInfo".to_owned(),
+        "/api/v3/order".to_owned(),
+        "/api/v3/order".to_owned(),
+        "/api/v3/order".to_owned(),
+        "/api/v3/order".to_owned(),
+    ]);
+}
+
+#[test]
+fn sync_time() {
+    let time_response = format!(r#"{{"serverTime":{}}}"#, timestamp_ms() + 3_600_000);
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, &time_response);
+    transport.respond(StatusCode::OK, r#"{"listenKey":"pqia91ma19a5s61cv6a81va65sdf19v8a65a1a5s61cv6a81va65sdf19v8a65a1"}"#);
+    transport.respond(StatusCode::OK, r#"{"symbols":[]}"#);
+
+    let params = harness::params();
+    let key_pair = KeyPair::new("api_key".to_owned(), "secret_key".to_owned());
+    let client = Client::with_transport(params, Some(key_pair), transport.clone()).unwrap();
+
+    // The server clock is one hour ahead, up to the latency of the mock request.
+    let skew = client.clock_skew();
+    assert!(skew > 3_590_000 && skew <= 3_600_000);
+
+    assert_eq!(transport.paths(), vec![
+        "/api/v3/time".to_owned(),
+        "/api/v3/userDataStream".to_owned(),
+        "/api/v3/exchangeInfo".to_owned(),
+    ]);
+}
+
+#[test]
+fn testnet() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, &format!(r#"{{"serverTime":{}}}"#, timestamp_ms()));
+    transport.respond(StatusCode::OK, r#"{"listenKey":"pqia91ma19a5s61cv6a81va65sdf19v8a65a1a5s61cv6a81va65sdf19v8a65a1"}"#);
+    transport.respond(StatusCode::OK, r#"{"symbols":[]}"#);
+
+    let params = Params::testnet(Exchange::Binance).unwrap();
+    assert_eq!(params.streaming_endpoint, "wss://testnet.binance.vision");
+
+    // The testnet only serves the v3 REST API.
+    let key_pair = KeyPair::new("api_key".to_owned(), "secret_key".to_owned());
+    Client::with_transport(params, Some(key_pair), transport.clone()).unwrap();
+    let uris = transport.requests()
+        .iter()
+        .map(|request| request.uri().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(uris[1..], [
+        "https://testnet.binance.vision/api/v3/userDataStream".to_owned(),
+        "https://testnet.binance.vision/api/v3/exchangeInfo".to_owned(),
+    ]);
+    assert!(uris[0].starts_with("https://testnet.binance.vision/api/v3/time"));
+}
+
+#[test]
+fn flatten() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, &format!(r#"{{"serverTime":{}}}"#, timestamp_ms()));
+    transport.respond(StatusCode::OK, r#"{"listenKey":"pqia91ma19a5s61cv6a81va65sdf19v8a65a1a5s61cv6a81va65sdf19v8a65a1"}"#);
+    transport.respond(StatusCode::OK, r#"{"symbols":[{"symbol":"BNBBTC","baseAsset":"BNB","quoteAsset":"BTC","quotePrecision":8,"filters":[{"filterType":"PRICE_FILTER","tickSize":"0.00000010"},{"filterType":"LOT_SIZE","stepSize":"0.01000000"}]}]}"#);
+
+    // The second order was already filled, the cancel is ignored.
+    transport.respond(StatusCode::OK, r#"{}"#);
+    transport.respond(StatusCode::BAD_REQUEST, r#"{"code":-2011,"msg":"UNKNOWN_ORDER"}"#);
+    transport.respond(StatusCode::OK, r#"{"balances":[{"asset":"BNB","free":"1.23456","locked":"0.00000"},{"asset":"BTC","free":"0.5","locked":"0.0"}]}"#);
+    transport.respond(StatusCode::OK, r#"{"symbol":"BNBBTC","orderId":28,"clientOrderId":"6gCrw2kRUAF9CvJDGP16IP","transactTime":1507725176595}"#);
+
+    // Nothing left to sell.
+    transport.respond(StatusCode::OK, r#"{"balances":[{"asset":"BNB","free":"0.00456","locked":"0.00000"}]}"#);
+
+    let params = harness::params();
+    let key_pair = KeyPair::new("api_key".to_owned(), "secret_key".to_owned());
+    let client = Client::with_transport(params, Some(key_pair), transport.clone()).unwrap();
+    let symbol = client.find_symbol("BNBBTC").unwrap();
+    assert_eq!(symbol.base_asset(), Some("BNB"));
+
+    let open_orders = [Cancel::new("order-1".to_owned()), Cancel::new("order-2".to_owned())];
+    client.flatten(symbol, &open_orders).wait().unwrap();
+    client.flatten(symbol, &[]).wait().unwrap();
+
+    assert_eq!(transport.paths()[3..], [
+        "/api/v3/order".to_owned(),
+        "/api/v3/order".to_owned(),
+        "/api/v3/account".to_owned(),
+        "/api/v3/order".to_owned(),
+        "/api/v3/account".to_owned(),
+    ]);
+}
+
+#[test]
+fn recent_trades() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"{"symbols":[{"symbol":"BNBBTC","baseAsset":"BNB","quoteAsset":"BTC","quotePrecision":8,"filters":[{"filterType":"PRICE_FILTER","tickSize":"0.00000010"},{"filterType":"LOT_SIZE","stepSize":"0.01000000"}]}]}"#);
+    transport.respond(StatusCode::OK, r#"[{"id":28457,"price":"0.00250000","qty":"12.00000000","quoteQty":"0.03000000","time":1499865549590,"isBuyerMaker":true,"isBestMatch":true},{"id":28458,"price":"0.00250010","qty":"1.50000000","quoteQty":"0.00375015","time":1499865549600,"isBuyerMaker":false,"isBestMatch":true}]"#);
+
+    let client = Client::with_transport(harness::params(), None, transport.clone()).unwrap();
+    let symbol = client.find_symbol("BNBBTC").unwrap();
+
+    let trades = client.recent_trades(symbol, 2).wait().unwrap();
+    assert_eq!(trades.len(), 2);
+    assert_eq!(trades[0].timestamp(), 1499865549590);
+    assert_eq!(trades[0].into_inner(), Trade {
+        price: Price(25_000),
+        size: Size(1200),
+        maker_side: Side::Bid,
+    });
+    assert_eq!(trades[1].timestamp(), 1499865549600);
+    assert_eq!(trades[1].into_inner(), Trade {
+        price: Price(25_001),
+        size: Size(150),
+        maker_side: Side::Ask,
+    });
+
+    assert_eq!(transport.paths(), vec![
+        "/api/v3/exchangeInfo".to_owned(),
+        "/api/v3/trades".to_owned(),
+    ]);
+}
+
+#[test]
+fn retry_rate_limited() {
+    use std::time::Duration;
+    use crate::api::retry::{RetryClient, Backoff};
+
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"{"symbols":[{"symbol":"BNBBTC","baseAsset":"BNB","quoteAsset":"BTC","quotePrecision":8,"filters":[{"filterType":"PRICE_FILTER","tickSize":"0.00000010"},{"filterType":"LOT_SIZE","stepSize":"0.01000000"}]}]}"#);
+    transport.respond(StatusCode::TOO_MANY_REQUESTS, r#"{"code":-1003,"msg":"Too many requests."}"#);
+    transport.respond(StatusCode::TOO_MANY_REQUESTS, r#"{"code":-1003,"msg":"Too many requests."}"#);
+    transport.respond(StatusCode::OK, r#"{"balances":[{"asset":"BNB","free":"1.00000000","locked":"0.00000000"}]}"#);
+    transport.respond(StatusCode::TOO_MANY_REQUESTS, r#"{"code":-1003,"msg":"Too many requests."}"#);
+    transport.respond(StatusCode::TOO_MANY_REQUESTS, r#"{"code":-1003,"msg":"Too many requests."}"#);
+    transport.respond(StatusCode::TOO_MANY_REQUESTS, r#"{"code":-1003,"msg":"Too many requests."}"#);
+    transport.respond(StatusCode::IM_A_TEAPOT, r#"{"code":-1003,"msg":"Way too many requests; IP banned."}"#);
+
+    let client = Client::with_transport(harness::params(), None, transport.clone()).unwrap();
+    let backoff = Backoff {
+        initial: Duration::from_millis(1),
+        max: Duration::from_millis(2),
+    };
+    let client = RetryClient::new(client, backoff, 2);
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+    // Rate limited twice, then successful.
+    let balances = runtime.block_on(client.balances()).unwrap();
+    assert_eq!(balances["BNB"].free, "1.00000000");
+    assert_eq!(transport.paths().len(), 4);
+
+    // Still rate limited after `max_retries` retries.
+    match runtime.block_on(client.balances()) {
+        Err(ApiError::RestError(err)) => assert_eq!(err.kind(), RestErrorKind::TooManyRequests),
+        other => panic!("expected a REST error, found `{:?}`", other),
+    }
+    assert_eq!(transport.paths().len(), 7);
+
+    // Bans are not retried.
+    match runtime.block_on(client.balances()) {
+        Err(ApiError::RestError(err)) => assert_eq!(err.kind(), RestErrorKind::AddressBanned),
+        other => panic!("expected a REST error, found `{:?}`", other),
+    }
+    assert_eq!(transport.paths().len(), 8);
+}
+
+#[test]
+fn listen_key_renewal() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, &format!(r#"{{"serverTime":{}}}"#, timestamp_ms()));
+    transport.respond(StatusCode::OK, r#"{"listenKey":"key0"}"#);
+    transport.respond(StatusCode::OK, r#"{"symbols":[]}"#);
+
+    let params = harness::params();
+    let key_pair = KeyPair::new("api_key".to_owned(), "secret_key".to_owned());
+    let client = Client::with_transport(params, Some(key_pair), transport.clone()).unwrap();
+    let listen_key = client.keys.as_ref().unwrap().listen_key.clone();
+    assert_eq!(listen_key.get(), ("key0".to_owned(), 0));
+
+    // The listen key is still valid.
+    transport.respond(StatusCode::OK, r#"{}"#);
+    super::refresh_listen_key(&client);
+    assert_eq!(listen_key.get(), ("key0".to_owned(), 0));
+    assert!(client.listen_key_valid());
+
+    // The listen key is rejected, and no new one can be obtained yet.
+    transport.respond(StatusCode::BAD_REQUEST, r#"{"code":-1125,"msg":"This listenKey does not exist."}"#);
+    transport.respond(StatusCode::SERVICE_UNAVAILABLE, r#"{}"#);
+    super::refresh_listen_key(&client);
+    assert!(!client.listen_key_valid());
+
+    // A new listen key is requested right away, with a new generation so that the
+    // notification streams reconnect.
+    transport.respond(StatusCode::OK, r#"{"listenKey":"key1"}"#);
+    super::refresh_listen_key(&client);
+    assert_eq!(listen_key.get(), ("key1".to_owned(), 1));
+    assert!(client.listen_key_valid());
+
+    let requests = transport.requests();
+    let methods: Vec<_> = requests[3..].iter().map(|request| request.method().as_str()).collect();
+    assert_eq!(methods, vec!["PUT", "PUT", "POST", "POST"]);
+    let query = requests[3].uri().query().unwrap_or("").to_owned()
+        + std::str::from_utf8(requests[3].body()).unwrap();
+    assert!(query.contains("listenKey=key0"), "{}", query);
+}
+
+#[test]
+fn commission_tick() {
+    use crate::api::OrderUpdate;
+
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"{"symbols":[{"symbol":"BNBBTC","baseAsset":"BNB","quoteAsset":"BTC","filters":[{"filterType":"PRICE_FILTER","tickSize":"0.00000010"},{"filterType":"LOT_SIZE","stepSize":"0.01000000"}]}]}"#);
+
+    let params = harness::params();
+    let client = Client::with_transport(params.clone(), None, transport.clone()).unwrap();
+
+    // Without `quotePrecision`, commissions are ticked in whole units.
+    let symbol = client.find_symbol("BNBBTC").unwrap();
+    assert_eq!(symbol.commission_tick(), Tick::new(1));
+
+    let client = client.with_commission_tick("BNBBTC", Tick::new(100_000_000))
+        .with_commission_tick("ETHBTC", Tick::new(100));
+    let symbol = client.find_symbol("BNBBTC").unwrap();
+    assert_eq!(symbol.commission_tick(), Tick::new(100_000_000));
+    assert!(client.find_symbol("ETHBTC").is_none());
+
+    let mut handler = HandlerImpl::new(symbol, NotificationFlags::ORDERS, params, transport);
+    let report = r#"{"e":"executionReport","E":1507725176595,"s":"BNBBTC","c":"order1","C":"","S":"BUY","q":"1.00000000","p":"0.00250000","x":"TRADE","l":"0.50000000","z":"0.50000000","L":"0.00250000","n":"0.00150000","N":"BNB","T":1507725176595}"#;
+    assert_eq!(
+        handler.process_message(report).unwrap(),
+        Some(Notification::OrderUpdate(OrderUpdate {
+            order_id: "order1".to_owned(),
+            consumed_size: Size(50),
+            remaining_size: Size(50),
+            consumed_price: Price(25_000),
+            commission: 150_000,
+            commission_asset: Some("BNB".to_owned()),
+            is_fill: true,
+        }.with_timestamp(1507725176595)))
+    );
+}
@@ -1,14 +1,18 @@
 use std::{mem, thread};
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
+use std::collections::HashMap;
 use std::borrow::Cow;
 use futures::prelude::*;
-use futures::sync::mpsc::{unbounded, UnboundedReceiver};
-use log::{error, debug};
+use futures::sync::mpsc::UnboundedSender;
+use log::{error, warn, debug};
 use failure::bail;
 use serde_derive::Deserialize;
 use crate::{tick, Side};
+use crate::tick::{Price, Size};
 use crate::order_book::LimitUpdate;
 use crate::api::{
+    Balance,
+    Balances,
     Notification,
     NotificationFlags,
     Params,
@@ -17,47 +21,186 @@ use crate::api::{
     OrderUpdate,
     OrderExpiration,
 };
-use crate::api::symbol::Symbol;
+use crate::api::symbol::{Symbol, WithSymbol};
 use crate::api::wss;
+use crate::api::stream::StreamHandle;
+use crate::api::rate_limit::{self, RateLimiter};
 use crate::api::timestamp::{Timestamped, IntoTimestamped};
-use crate::api::binance::Client;
+use crate::api::binance::{Client, ListenKey, HttpClient, PartialDepth};
 use crate::api::binance::errors::RestError;
+use crate::api::binance::rest;
 
+/// Return the name of the stream carrying the order book of the symbol named `name`.
+fn depth_stream(name: &str, partial_depth: Option<PartialDepth>) -> String {
+    match partial_depth {
+        Some(depth) => format!("{}@depth{}@100ms", name, depth.levels()),
+        None => format!("{}@depth", name),
+    }
+}
 
 impl Client {
     crate fn new_stream(&self, symbol: Symbol, flags: NotificationFlags)
-        -> UnboundedReceiver<Notification>
+        -> StreamHandle
     {
         let params = self.params.clone();
-        let listen_key = self.keys.as_ref().map(|keys| keys.listen_key.clone());
-        let (snd, rcv) = unbounded();
-        thread::spawn(move || {
-            let mut address = format!(
-               "{0}/ws/{1}@trade/{1}@depth",
+        let http_client = self.http_client.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let partial_depth = self.partial_depth;
+        let raw_log = wss::RawLog::new(self.log_raw, "binance");
+        let listen_key = self.keys.as_ref()
+            .map(|keys| keys.listen_key.clone())
+            .filter(|_| flags.contains_orders() || flags.contains_balances());
+        StreamHandle::spawn(move |snd, shutdown| loop {
+            let name = symbol.name().to_lowercase();
+
+            // Only subscribe to the streams we are going to forward: in particular, the
+            // order book snapshot will never be requested if `@depth` is not subscribed to,
+            // nor if a partial order book is streamed instead.
+            let mut streams = Vec::new();
+            if flags.contains_trades() {
+                streams.push(format!("{}@trade", name));
+            }
+            if flags.contains_book() {
+                streams.push(depth_stream(&name, partial_depth));
+            }
+            let watch = listen_key.as_ref().map(|listen_key| {
+                let (key, generation) = listen_key.get();
+                streams.push(key);
+                ListenKeyWatch {
+                    listen_key: listen_key.clone(),
+                    generation,
+                }
+            });
+
+            if streams.is_empty() {
+                debug!("no stream to subscribe to, closing notification stream");
+                return;
+            }
+
+            let address = format!(
+               "{}/ws/{}",
                 params.streaming_endpoint,
-                symbol.name().to_lowercase(),
+                streams.join("/"),
             );
-            if let Some(listen_key) = listen_key {
-                address += &format!("/{}", listen_key);
-            }
 
             debug!("initiating WebSocket connection at {}", address);
+            raw_log.outbound(&address);
 
             if let Err(err) = ws::connect(address, |out| {
-                wss::Handler::new(out, snd.clone(), wss::KeepAlive::True, HandlerImpl{
-                    flags,
+                shutdown.register(&out);
+                let mut handler = HandlerImpl::new(
                     symbol,
-                    params: params.clone(),
-                    book_snapshot_state: BookSnapshotState::None,
-                    previous_u: None,
+                    flags,
+                    params.clone(),
+                    http_client.clone()
+                ).with_partial_depth(partial_depth).with_rate_limiter(rate_limiter.clone());
+                handler.listen_key = watch.clone();
+                wss::Handler::new(out, snd.clone(), wss::KeepAlive::True, raw_log, handler)
+            })
+            {
+                error!("WebSocket connection terminated with error: `{}`", err);
+            }
+
+            if shutdown.requested() {
+                return;
+            }
+
+            match &watch {
+                Some(watch) if watch.renewed() => {
+                    let resync = Notification::BookResync;
+                    if flags.contains_book() && snd.unbounded_send(resync).is_err() {
+                        return;
+                    }
+                }
+                _ => return,
+            }
+        })
+    }
+
+    crate fn new_combined_stream(&self, symbols: &[Symbol], flags: NotificationFlags)
+        -> StreamHandle<WithSymbol<Notification>>
+    {
+        let params = self.params.clone();
+        let http_client = self.http_client.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let partial_depth = self.partial_depth;
+        let raw_log = wss::RawLog::new(self.log_raw, "binance");
+        let listen_key = self.keys.as_ref()
+            .map(|keys| keys.listen_key.clone())
+            .filter(|_| flags.contains_orders() || flags.contains_balances());
+        let symbols = symbols.to_vec();
+        StreamHandle::spawn(move |snd, shutdown| loop {
+            let mut streams = Vec::new();
+            for symbol in &symbols {
+                let name = symbol.name().to_lowercase();
+                if flags.contains_trades() {
+                    streams.push(format!("{}@trade", name));
+                }
+                if flags.contains_book() {
+                    streams.push(depth_stream(&name, partial_depth));
+                }
+            }
+
+            let user_data = listen_key.as_ref().map(|listen_key| {
+                let (key, generation) = listen_key.get();
+                streams.push(key.clone());
+                (key, ListenKeyWatch {
+                    listen_key: listen_key.clone(),
+                    generation,
                 })
+            });
+
+            if streams.is_empty() {
+                debug!("no stream to subscribe to, closing notification stream");
+                return;
+            }
+
+            let address = format!(
+               "{}/stream?streams={}",
+                params.streaming_endpoint,
+                streams.join("/"),
+            );
+
+            debug!("initiating WebSocket connection at {}", address);
+            raw_log.outbound(&address);
+
+            if let Err(err) = ws::connect(address, |out| {
+                shutdown.register(&out);
+                let handlers = symbols.iter().map(|symbol| (
+                    symbol.name().to_lowercase(),
+                    HandlerImpl::new(*symbol, flags, params.clone(), http_client.clone())
+                        .with_partial_depth(partial_depth)
+                        .with_rate_limiter(rate_limiter.clone())
+                )).collect();
+
+                let handler = CombinedHandlerImpl {
+                    handlers,
+                    listen_key: user_data.clone(),
+                };
+                wss::Handler::new(out, snd.clone(), wss::KeepAlive::True, raw_log, handler)
             })
             {
                 error!("WebSocket connection terminated with error: `{}`", err);
             }
-        });
 
-        rcv
+            if shutdown.requested() {
+                return;
+            }
+
+            match &user_data {
+                Some((_, watch)) if watch.renewed() => {
+                    if flags.contains_book() {
+                        for symbol in &symbols {
+                            let resync = WithSymbol::new(Notification::BookResync, *symbol);
+                            if snd.unbounded_send(resync).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+                _ => return,
+            }
+        })
     }
 }
 
@@ -89,15 +232,50 @@ enum BookSnapshotState {
     Ok,
 }
 
-struct HandlerImpl {
+pub(super) struct HandlerImpl {
     symbol: Symbol,
     flags: NotificationFlags,
     params: Params,
     book_snapshot_state: BookSnapshotState,
 
+    /// Client of the REST API, used for requesting the order book snapshot.
+    http_client: HttpClient,
+
+    /// Rate limiter of the client, the order book snapshot request counts against it.
+    rate_limiter: Option<RateLimiter>,
+
     /// Keep track of the `u` indicator sent by binance, this is used for checking
     /// the of the ordering of the limit updates.
     previous_u: Option<u64>,
+
+    /// Listen key this connection subscribed to, if any.
+    listen_key: Option<ListenKeyWatch>,
+
+    /// Whether this connection subscribed to a partial order book stream instead of
+    /// the diff depth stream.
+    partial_depth: Option<PartialDepth>,
+}
+
+#[derive(Clone)]
+/// Listen key along with the generation used when connecting, so that the connection
+/// can be closed when a new listen key is received.
+struct ListenKeyWatch {
+    listen_key: Arc<ListenKey>,
+    generation: usize,
+}
+
+impl ListenKeyWatch {
+    fn renewed(&self) -> bool {
+        self.listen_key.generation() != self.generation
+    }
+
+    fn on_ping(&self, out: &ws::Sender) -> ws::Result<()> {
+        if self.renewed() {
+            debug!("listen key was renewed, closing WebSocket connection");
+            return out.close(ws::CloseCode::Normal);
+        }
+        Ok(())
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
@@ -115,6 +293,7 @@ struct BinanceLimitUpdate<'a> {
     price: Cow<'a, str>,
     #[serde(borrow)]
     size: Cow<'a, str>,
+    #[serde(default)]
     _ignore: Vec<i32>,
 }
 
@@ -173,23 +352,109 @@ struct BinanceExecutionReport<'a> {
     z: &'a str,
     L: &'a str,
     n: &'a str,
+    N: Option<&'a str>,
     T: u64,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct BinanceAssetBalance<'a> {
+    a: &'a str,
+    f: &'a str,
+    l: &'a str,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct BinanceAccountPosition<'a> {
+    E: u64,
+    #[serde(borrow)]
+    B: Vec<BinanceAssetBalance<'a>>,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
 struct EventType<'a> {
-    e: &'a str,
+    /// Partial order book events do not carry an event type.
+    e: Option<&'a str>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+struct BinanceCombinedEvent<'a> {
+    stream: &'a str,
+    data: serde_json::Value,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct EventSymbol<'a> {
+    s: Option<&'a str>,
+}
+
+/// Handler for a combined stream, dispatching each event to the `HandlerImpl` of
+/// the corresponding symbol.
+struct CombinedHandlerImpl {
+    /// Handlers indexed by lower case symbol name.
+    handlers: HashMap<String, HandlerImpl>,
+    listen_key: Option<(String, ListenKeyWatch)>,
 }
 
 impl HandlerImpl {
+    pub(super) fn new(
+        symbol: Symbol,
+        flags: NotificationFlags,
+        params: Params,
+        http_client: HttpClient,
+    ) -> Self {
+        HandlerImpl {
+            flags,
+            symbol,
+            params,
+            book_snapshot_state: BookSnapshotState::None,
+            http_client,
+            rate_limiter: None,
+            previous_u: None,
+            listen_key: None,
+            partial_depth: None,
+        }
+    }
+
+    /// Expect partial order book events instead of depth updates if `partial_depth`
+    /// is not `None`.
+    pub(super) fn with_partial_depth(self, partial_depth: Option<PartialDepth>) -> Self {
+        HandlerImpl {
+            partial_depth,
+            ..self
+        }
+    }
+
+    /// Account the order book snapshot request against `rate_limiter`, if any.
+    pub(super) fn with_rate_limiter(self, rate_limiter: Option<RateLimiter>) -> Self {
+        HandlerImpl {
+            rate_limiter,
+            ..self
+        }
+    }
+
+    #[cfg(test)]
+    /// Provide the order book snapshot upfront instead of requesting it from the REST API
+    /// upon the first depth update.
+    pub(super) fn with_book_snapshot(mut self, snapshot: &str) -> Self {
+        let snapshot: BinanceBookSnapshot<'_> = serde_json::from_str(snapshot).unwrap();
+        let (snd, rcv) = mpsc::sync_channel(1);
+        snd.send(Ok(snapshot.owned())).unwrap();
+        self.book_snapshot_state = BookSnapshotState::Waiting(BookWaitingState {
+            rcv,
+            events: vec![],
+        });
+        self
+    }
+
     fn convert_binance_update(&self, l: &BinanceLimitUpdate, side: Side)
         -> Result<LimitUpdate, tick::ConversionError>
     {
         Ok(
             LimitUpdate {
                 side,
-                price: self.symbol.price_tick().ticked(&l.price)?,
-                size: self.symbol.size_tick().ticked(&l.size)?,
+                price: Price(self.symbol.price_tick().ticked(&l.price)?),
+                size: Size(self.symbol.size_tick().ticked(&l.size)?),
             }
         )
     }
@@ -198,24 +463,29 @@ impl HandlerImpl {
         let event_type: EventType<'_> = serde_json::from_str(json)?;
 
         let notif = match event_type.e {
-            "trade" if self.flags.contains(NotificationFlags::TRADES) => {
+            Some("trade") if self.flags.contains_trades() => {
                 let trade: BinanceTrade<'_> = serde_json::from_str(json)?;
                 Some(
                     Notification::Trade(Trade {
-                        size: self.symbol.size_tick().ticked(trade.q)?,
-                        price: self.symbol.price_tick().ticked(trade.p)?,
+                        size: Size(self.symbol.size_tick().ticked(trade.q)?),
+                        price: Price(self.symbol.price_tick().ticked(trade.p)?),
                         maker_side: if trade.m { Side::Bid } else { Side::Ask },
                     }.with_timestamp(trade.T))
                 )
             },
 
-            "depthUpdate" if self.flags.contains(NotificationFlags::ORDER_BOOK) => {
+            Some("depthUpdate") if self.flags.contains_book() => {
                 let depth_update: BinanceDepthUpdate<'_> = serde_json::from_str(json)?;
 
                 // The order book is consistent if the previous `u + 1` is equal to current `U`.
+                // Otherwise, we drop the current state and request a new snapshot of the
+                // order book on the next depth update.
                 if let Some(previous_u) = self.previous_u {
                     if previous_u + 1 != depth_update.U {
-                        panic!("previous `u + 1` and current `U` do not match");
+                        warn!("previous `u + 1` and current `U` do not match, resyncing LOB");
+                        self.previous_u = None;
+                        self.book_snapshot_state = BookSnapshotState::None;
+                        return Ok(Some(Notification::BookResync));
                     }
                 }
                 self.previous_u = Some(depth_update.u);
@@ -237,15 +507,35 @@ impl HandlerImpl {
                 }
             },
 
-            "executionReport" if self.flags.contains(NotificationFlags::ORDERS) => {
+            // A partial order book replaces the whole order book, no sequence tracking
+            // is needed.
+            None if self.partial_depth.is_some() && self.flags.contains_book() => {
+                let snapshot: BinanceBookSnapshot<'_> = serde_json::from_str(json)?;
+                Some(self.process_book_snapshot(Ok(snapshot), vec![])?)
+            },
+
+            // Only carries the balances which have changed.
+            Some("outboundAccountPosition") if self.flags.contains_balances() => {
+                let position: BinanceAccountPosition<'_> = serde_json::from_str(json)?;
+                let balances: Balances = position.B
+                    .into_iter()
+                    .map(|balance| (balance.a.to_owned(), Balance {
+                        free: balance.f.to_owned(),
+                        locked: balance.l.to_owned(),
+                    }))
+                    .collect();
+                Some(Notification::BalanceUpdate(balances.with_timestamp(position.E)))
+            }
+
+            Some("executionReport") if self.flags.contains_orders() => {
                 let report: BinanceExecutionReport<'_> = serde_json::from_str(json)?;
 
                 match report.x {
                     "NEW" => Some(
                         Notification::OrderConfirmation(OrderConfirmation {
                             order_id: report.c.to_owned(),
-                            size: self.symbol.size_tick().ticked(report.q)?,
-                            price: self.symbol.price_tick().ticked(report.p)?,
+                            size: Size(self.symbol.size_tick().ticked(report.q)?),
+                            price: Price(self.symbol.price_tick().ticked(report.p)?),
                             side: match report.S {
                                 "BUY" => Side::Bid,
                                 "SELL" => Side::Ask,
@@ -257,13 +547,17 @@ impl HandlerImpl {
                     "TRADE" => Some(
                         Notification::OrderUpdate(OrderUpdate {
                             order_id: report.c.to_owned(),
-                            consumed_size: self.symbol.size_tick().ticked(report.l)?,
+                            consumed_size: Size(self.symbol.size_tick().ticked(report.l)?),
 
-                            remaining_size: self.symbol.size_tick().ticked(report.q)?
-                                - self.symbol.size_tick().ticked(report.z)?,
+                            remaining_size: Size(
+                                self.symbol.size_tick().ticked(report.q)?
+                                    - self.symbol.size_tick().ticked(report.z)?
+                            ),
 
-                            consumed_price: self.symbol.price_tick().ticked(report.L)?,
+                            consumed_price: Price(self.symbol.price_tick().ticked(report.L)?),
                             commission: self.symbol.commission_tick().ticked(report.n)?,
+                            commission_asset: report.N.map(|asset| asset.to_owned()),
+                            is_fill: true,
                         }.with_timestamp(report.T))
                     ),
 
@@ -313,7 +607,7 @@ impl HandlerImpl {
             .flat_map(|update| update.updates)
             .map(Ok);
 
-        let notif = Notification::LimitUpdates(
+        let notif = Notification::BookSnapshot(
             bid.chain(ask).chain(buffered).collect::<Result<Vec<_>, tick::ConversionError>>()?
         );
 
@@ -370,30 +664,27 @@ impl HandlerImpl {
             }
         );
 
+        let path = "api/v3/depth";
         let address = format!(
-            "{}/api/v1/depth?symbol={}&limit=1000",
+            "{}/{}?symbol={}&limit=1000",
             self.params.rest_endpoint,
+            path,
             self.symbol.name()
-        ).parse().expect("invalid address");
+        );
 
         debug!("initiating LOB request at `{}`", address);
 
-        thread::spawn(move || {
-            let https = match hyper_tls::HttpsConnector::new(2) {
-                Ok(https) => https,
-                Err(err) => {
-                    let _ = snd.send(Err(err).map_err(From::from));
-                    return;
-                }
-            };
+        // Unwrap because it is a bug if this fails (address failed to parse or something)
+        let request = hyper::Request::get(address).body(hyper::Body::empty()).unwrap();
 
-            let client = hyper::Client::builder().build::<_, hyper::Body>(https);
-            let fut = client.get(address).and_then(|res| {
-                let status = res.status();
-                res.into_body().concat2().and_then(move |body| {
-                    Ok((status, body))
-                })
-            }).map_err(From::from).and_then(move |(status, body)| {
+        // The connection pool of the client is shared with its REST requests, the background
+        // tasks of the connections are run by the runtime below.
+        let client = self.http_client.clone();
+        let acquire = rate_limit::acquire(self.rate_limiter.as_ref(), rest::request_weight(path));
+        thread::spawn(move || {
+            let fut = acquire.then(move |_| {
+                client.request(request)
+            }).and_then(move |(status, body)| {
                 if status != hyper::StatusCode::OK {
                     let binance_error = serde_json::from_slice(&body);
                     Err(
@@ -417,18 +708,19 @@ impl HandlerImpl {
     }
 }
 
-impl wss::HandlerImpl for HandlerImpl {
-    fn on_open(&mut self, out: &ws::Sender) -> ws::Result<()> {
-        out.ping(vec![])
-    }
-
-    fn on_message(&mut self, text: &str, out: &wss::NotifSender) -> Result<(), failure::Error> {
-        match self.parse_message(text)? {
+impl HandlerImpl {
+    /// Process an incoming message, return the notification to be forwarded to the
+    /// consumer if any.
+    pub(super) fn process_message(&mut self, text: &str) -> Result<Option<Notification>, failure::Error> {
+        let notif = match self.parse_message(text)? {
             // Depth update notif: behavior depends on the status of the order book snapshot.
             Some(Notification::LimitUpdates(updates)) => {
                 match mem::replace(&mut self.book_snapshot_state, BookSnapshotState::Ok) {
                     // Very first limit update event received: time to ask for the book snapshot.
-                    BookSnapshotState::None => self.request_book_snapshot(updates),
+                    BookSnapshotState::None => {
+                        self.request_book_snapshot(updates);
+                        None
+                    }
 
                     // Still waiting: buffer incoming events.
                     BookSnapshotState::Waiting(mut state) => {
@@ -437,24 +729,94 @@ impl wss::HandlerImpl for HandlerImpl {
                             updates,
                         });
 
-                        if let Some(notif) = self.maybe_recv_book(state) {
-                            out.unbounded_send(notif).unwrap();
-                        }
+                        self.maybe_recv_book(state)
                     }
 
                     // We already received the book snapshot and notified the final consumer,
                     // we can now notify further notifications to them.
-                    BookSnapshotState::Ok => out.unbounded_send(
-                        Notification::LimitUpdates(updates)
-                    ).unwrap(),
+                    BookSnapshotState::Ok => Some(Notification::LimitUpdates(updates)),
                 }
             },
 
             // Other notif: just forward to the consumer.
-            Some(notif) => out.unbounded_send(notif).unwrap(),
+            notif => notif,
+        };
+        Ok(notif)
+    }
+}
+
+impl wss::HandlerImpl for HandlerImpl {
+    fn on_open(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        out.ping(vec![])
+    }
+
+    fn on_message(&mut self, text: &str, out: &wss::NotifSender) -> Result<(), failure::Error> {
+        if let Some(notif) = self.process_message(text)? {
+            out.unbounded_send(notif).unwrap();
+        }
+        Ok(())
+    }
+
+    fn on_ping(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        match &self.listen_key {
+            Some(watch) => watch.on_ping(out),
+            None => Ok(()),
+        }
+    }
+}
 
-            None => (),
+impl wss::HandlerImpl<WithSymbol<Notification>> for CombinedHandlerImpl {
+    fn on_open(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        out.ping(vec![])
+    }
+
+    fn on_message(&mut self, text: &str, out: &UnboundedSender<WithSymbol<Notification>>)
+        -> Result<(), failure::Error>
+    {
+        let event: BinanceCombinedEvent<'_> = serde_json::from_str(text)?;
+        let data = event.data.to_string();
+
+        // User data events are not tied to a stream name, but execution reports carry
+        // their symbol.
+        let name = if Some(event.stream) == self.listen_key.as_ref().map(|(k, _)| k.as_str()) {
+            let event_symbol: EventSymbol<'_> = serde_json::from_str(&data)?;
+            match event_symbol.s {
+                Some(name) => name.to_lowercase(),
+
+                // Account wide events, e.g. balance updates, are forwarded for each symbol.
+                None => {
+                    for handler in self.handlers.values_mut() {
+                        if let Some(notif) = handler.process_message(&data)? {
+                            out.unbounded_send(WithSymbol::new(notif, handler.symbol)).unwrap();
+                        }
+                    }
+                    return Ok(());
+                }
+            }
+        } else {
+            match event.stream.split('@').next() {
+                Some(name) => name.to_owned(),
+                None => bail!("wrong stream name `{}`", event.stream),
+            }
+        };
+
+        let handler = match self.handlers.get_mut(&name) {
+            Some(handler) => handler,
+
+            // Order updates for symbols we did not subscribe to.
+            None => return Ok(()),
+        };
+
+        if let Some(notif) = handler.process_message(&data)? {
+            out.unbounded_send(WithSymbol::new(notif, handler.symbol)).unwrap();
         }
         Ok(())
     }
+
+    fn on_ping(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        match &self.listen_key {
+            Some((_, watch)) => watch.on_ping(out),
+            None => Ok(()),
+        }
+    }
 }
@@ -0,0 +1,159 @@
+//! A module defining error types specific to Bitfinex.
+
+use failure_derive::Fail;
+use hyper::StatusCode;
+use std::fmt;
+use crate::api;
+
+/// The `["error", code, message]` array sent by Bitfinex REST API.
+pub(super) type BitfinexRestError = (String, i32, String);
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Fail)]
+/// An error returned by Bitfinex, either by the REST API or as a notification of the
+/// authenticated WebSocket input channel.
+pub struct RestError {
+    /// Error kind.
+    pub kind: RestErrorKind,
+
+    /// Internal Bitfinex error code: see API documentation.
+    pub error_code: i32,
+
+    /// Error message.
+    pub error_msg: String,
+}
+
+impl api::errors::ErrorKinded<!> for RestError {
+    fn kind(&self) -> api::errors::RestErrorKind<!> {
+        if self.kind == RestErrorKind::TooManyRequests {
+            return api::errors::RestErrorKind::TooManyRequests;
+        }
+
+        if self.kind == RestErrorKind::Timeout {
+            return api::errors::RestErrorKind::UnknownStatus;
+        }
+
+        // Bitfinex answers most invalid requests with a 500 status code, hence
+        // `InternalError` is not mapped to `OtherSide`.
+        if self.kind == RestErrorKind::ServiceUnavailable {
+            return api::errors::RestErrorKind::OtherSide;
+        }
+
+        if self.error_msg.contains("nonce: small") {
+            return api::errors::RestErrorKind::OutsideTimeWindow;
+        }
+
+        api::errors::RestErrorKind::InvalidRequest
+    }
+}
+
+impl api::errors::ErrorKinded<api::errors::CancelErrorKind> for RestError {
+    fn kind(&self) -> api::errors::RestErrorKind<api::errors::CancelErrorKind> {
+        if self.kind == RestErrorKind::Rejected && self.error_msg.contains("not found") {
+            return api::errors::RestErrorKind::Specific(
+                api::errors::CancelErrorKind::UnknownOrder
+            );
+        }
+        <Self as api::errors::ErrorKinded<!>>::kind(self).into()
+    }
+}
+
+impl api::errors::ErrorKinded<api::errors::OrderErrorKind> for RestError {
+    fn kind(&self) -> api::errors::RestErrorKind<api::errors::OrderErrorKind> {
+        if self.kind == RestErrorKind::Rejected && self.error_msg.contains("not enough") {
+            return api::errors::RestErrorKind::Specific(
+                api::errors::OrderErrorKind::InsufficientBalance
+            );
+        }
+
+        if self.kind == RestErrorKind::Rejected && self.error_msg.contains("duplicate") {
+            return api::errors::RestErrorKind::Specific(
+                api::errors::OrderErrorKind::DuplicateOrder
+            );
+        }
+
+        <Self as api::errors::ErrorKinded<!>>::kind(self).into()
+    }
+}
+
+impl fmt::Display for RestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: `{}` [error_code = {}]", self.kind, self.error_msg, self.error_code)
+    }
+}
+
+impl RestError {
+    pub(super) fn from_bitfinex_error(
+        status: StatusCode,
+        bitfinex_error: Option<BitfinexRestError>
+    ) -> Self {
+        RestError {
+            kind: RestErrorKind::from_status_code(status),
+            error_code: bitfinex_error.as_ref().map(|(_, code, _)| *code).unwrap_or(-1),
+            error_msg: bitfinex_error.map(|(_, _, msg)| msg)
+                .unwrap_or_else(|| "<empty>".to_owned()),
+        }
+    }
+
+    /// Build an error from a request rejected through the authenticated WebSocket
+    /// input channel.
+    pub(super) fn rejected(error_code: Option<i32>, error_msg: &str) -> Self {
+        RestError {
+            kind: RestErrorKind::Rejected,
+            error_code: error_code.unwrap_or(-1),
+            error_msg: error_msg.to_owned(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Fail)]
+/// Translate an HTTP error code to a Bitfinex error category.
+pub enum RestErrorKind {
+    #[fail(display = "bad request")]
+    /// Malformed request, issue on the lib side or consumer side.
+    BadRequest,
+
+    #[fail(display = "unauthorized")]
+    /// Authorisation required or failed.
+    Unauthorized,
+
+    #[fail(display = "too many requests")]
+    /// The client broke the request rate limit set by Bitfinex.
+    TooManyRequests,
+
+    #[fail(display = "internal server error")]
+    /// Issue on Bitfinex side. Bitfinex also uses this status for most invalid requests,
+    /// see `error_msg`.
+    InternalError,
+
+    #[fail(display = "service unavailable")]
+    /// Service is down for maintenance.
+    ServiceUnavailable,
+
+    #[fail(display = "timeout")]
+    /// The server did not respond in time. The order may have been executed or may have not.
+    Timeout,
+
+    #[fail(display = "rejected")]
+    /// An order or cancel request sent through the WebSocket input channel was rejected.
+    Rejected,
+
+    #[fail(display = "unknown error, HTTP status code = {}", _0)]
+    /// Unknown error.
+    Unknown(StatusCode),
+}
+
+impl RestErrorKind {
+    fn from_status_code(code: StatusCode) -> Self {
+        use self::RestErrorKind::*;
+        match code {
+            StatusCode::OK => panic!("`RestErrorKind::from_status_code` with `StatusCode::Ok`"),
+            StatusCode::BAD_REQUEST => BadRequest,
+            StatusCode::UNAUTHORIZED => Unauthorized,
+            StatusCode::TOO_MANY_REQUESTS => TooManyRequests,
+            StatusCode::INTERNAL_SERVER_ERROR => InternalError,
+            StatusCode::SERVICE_UNAVAILABLE => ServiceUnavailable,
+            StatusCode::GATEWAY_TIMEOUT => Timeout,
+            other => Unknown(other),
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Implementation of `ApiClient` for the Bitfinex API.
+
+pub mod errors;
+mod rest;
+mod wss;
+mod test;
+
+use openssl::pkey::{PKey, Private};
+use openssl::{sign::Signer, hash::MessageDigest};
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use futures::prelude::*;
+use serde_derive::{Serialize, Deserialize};
+use log::debug;
+use crate::api::{
+    self,
+    Params,
+    ApiClient,
+    GenerateOrderId,
+    NotificationFlags,
+    Order,
+    OrderAck,
+    Cancel,
+    CancelAck,
+    Balances,
+    DepositAddress,
+    Ticker,
+    Trade,
+    WithdrawAck,
+    WithdrawClient,
+};
+use crate::api::symbol::{self, Symbol, WithSymbol};
+use crate::api::rate_limit::RateLimiter;
+use crate::api::transport::{self, HttpTransport};
+use crate::api::timestamp::{timestamp_ms, Timestamped};
+
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// A Bitfinex key pair: api key + secret key.
+pub struct KeyPair {
+    api_key: String,
+    secret_key: String,
+}
+
+impl KeyPair {
+    /// Return a new key pair.
+    pub fn new(api_key: String, secret_key: String) -> Self {
+        KeyPair {
+            api_key,
+            secret_key,
+        }
+    }
+}
+
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyPair")
+            .field("api_key", &self.api_key)
+            .field("secret_key", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+struct Keys {
+    api_key: String,
+    secret_key: Arc<PKey<Private>>,
+}
+
+impl Keys {
+    /// Return the hex encoded HMAC-SHA384 signature of `payload`.
+    fn sign(&self, payload: &str) -> String {
+        let mut signer = Signer::new(MessageDigest::sha384(), &self.secret_key).unwrap();
+        signer.update(payload.as_bytes()).unwrap();
+        hex::encode(&signer.sign_to_vec().unwrap())
+    }
+}
+
+/// Return `max(now, last + 1)` and store it in `last`, so that successive calls return
+/// strictly increasing values.
+fn next_increasing(last: &AtomicUsize, now: usize) -> usize {
+    let mut current = last.load(Ordering::SeqCst);
+    loop {
+        let next = cmp::max(now, current + 1);
+        match last.compare_exchange(current, next, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => return next,
+            Err(actual) => current = actual,
+        }
+    }
+}
+
+/// Return a nonce for authenticating a request. Bitfinex requires the nonces sent with
+/// a given api key to be strictly increasing.
+fn nonce() -> usize {
+    static LAST_NONCE: AtomicUsize = AtomicUsize::new(0);
+    next_increasing(&LAST_NONCE, timestamp_ms() as usize * 1000)
+}
+
+#[derive(Clone)]
+/// A Bitfinex API client.
+///
+/// Orders and cancels are sent through the authenticated WebSocket input channel. The
+/// corresponding connection is opened upon the first request and kept open until the
+/// client is dropped.
+pub struct Client {
+    params: Params,
+    keys: Option<Keys>,
+    symbols: Arc<HashMap<String, Symbol>>,
+    rate_limiter: Option<RateLimiter>,
+    log_raw: bool,
+    http_client: Arc<dyn HttpTransport>,
+
+    /// Input channel for orders and cancels, only available with a key pair.
+    trading: Option<Arc<wss::Trading>>,
+    /// Custom order id generation scheme, see `Client::with_order_id_generator`.
+    order_id_generator: Option<api::OrderIdGenerator>,
+}
+
+impl Client {
+    /// Create a new Bitfinex API client with given `params`. If `key_pair` is not
+    /// `None`, this will enable performing requests to the REST API, sending orders
+    /// through the WebSocket input channel and will forward the user data stream.
+    ///
+    /// # Note
+    /// This method will block, fetching the available symbols from Bitfinex.
+    pub fn new(params: Params, key_pair: Option<KeyPair>) -> Result<Self, failure::Error> {
+        Self::with_transport(params, key_pair, transport::https()?)
+    }
+
+    /// Create a new Bitfinex API client like `Client::new`, sending its REST requests
+    /// (including the ones made by `Client::new`) through `http_client`.
+    pub fn with_transport(
+        params: Params,
+        key_pair: Option<KeyPair>,
+        http_client: Arc<dyn HttpTransport>
+    ) -> Result<Self, failure::Error>
+    {
+        let keys = match key_pair {
+            Some(pair) => {
+                let secret_key = PKey::hmac(pair.secret_key.as_bytes())?;
+
+                Some(Keys {
+                    api_key: pair.api_key,
+                    secret_key: Arc::new(secret_key),
+                })
+            },
+            None => None,
+        };
+
+        let rate_limiter = params.rate_limiter();
+        let trading = keys.clone().map(|keys| {
+            Arc::new(wss::Trading::new(params.streaming_endpoint.clone(), keys))
+        });
+        let mut client = Client {
+            params,
+            keys,
+            symbols: Arc::new(HashMap::new()),
+            rate_limiter,
+            log_raw: false,
+            http_client,
+            order_id_generator: None,
+            trading,
+        };
+
+        use tokio::runtime::current_thread;
+        debug!("requesting symbols");
+        client.symbols = Arc::new(
+            current_thread::Runtime::new()?.block_on(client.get_symbols())?
+        );
+        debug!("received symbols");
+
+        Ok(client)
+    }
+
+    /// Log every raw inbound text frame of the notification streams and of the input
+    /// channel, as well as every outbound payload, at `trace` level with `"bitfinex"` as
+    /// the log target. Payloads carrying credentials are not logged verbatim. Disabled
+    /// by default.
+    pub fn with_log_raw(self, log_raw: bool) -> Self {
+        Client {
+            log_raw,
+            ..self
+        }
+    }
+
+    /// Use `generator` instead of `GenerateOrderId::new_order_id` for generating order ids
+    /// from a hint, see `Order::with_order_id_from`.
+    ///
+    /// # Note
+    /// Bitfinex only accepts integers as client order ids, orders carrying another
+    /// id are rejected with `RestErrorKind::InvalidRequest` before being sent.
+    pub fn with_order_id_generator<F>(self, generator: F) -> Self
+        where F: Fn(&str) -> String + Send + Sync + 'static
+    {
+        Client {
+            order_id_generator: Some(Arc::new(generator)),
+            ..self
+        }
+    }
+
+    /// Return the rate limiter delaying the REST requests of this client, if rate limiting
+    /// is enabled in `Params`.
+    pub fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+}
+
+impl ApiClient for Client {
+    type Stream = api::stream::StreamHandle;
+
+    fn find_symbol(&self, symbol: &str) -> Option<Symbol> {
+        self.symbols.get(&symbol::canonical_name(symbol)).cloned()
+    }
+
+    fn generate_order_id(&self, hint: &str) -> String {
+        match &self.order_id_generator {
+            Some(generator) => generator(hint),
+            None => Self::new_order_id(hint),
+        }
+    }
+
+    fn ticker(&self, symbol: Symbol)
+        -> Box<dyn Future<Item = Ticker, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.ticker_impl(symbol))
+    }
+
+    fn recent_trades(&self, symbol: Symbol, limit: usize)
+        -> Box<dyn Future<Item = Vec<Timestamped<Trade>>, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.recent_trades_impl(symbol, limit))
+    }
+
+    fn stream_with_flags(&self, symbol: Symbol, flags: NotificationFlags) -> Self::Stream {
+        self.new_stream(symbol, flags)
+    }
+
+    fn order(&self, order: WithSymbol<&Order>)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
+    {
+        self.order_impl(order)
+    }
+
+    fn cancel(&self, cancel: WithSymbol<&Cancel>)
+        -> Box<dyn Future<Item = Timestamped<CancelAck>, Error = api::errors::CancelError> + Send + 'static>
+    {
+        self.cancel_impl(cancel)
+    }
+
+    fn replace(&self, replaced: WithSymbol<&Order>, new: &Order)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
+    {
+        api::replace_by_cancel(self, replaced, new)
+    }
+
+    fn ping(&self)
+        -> Box<dyn Future<Item = Timestamped<()>, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.ping_impl())
+    }
+
+    fn balances(&self)
+        -> Box<dyn Future<Item = Balances, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.balances_impl())
+    }
+
+    fn deposit_address(&self, _: &str)
+        -> Box<dyn Future<Item = DepositAddress, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(
+            Err(api::errors::RestErrorKind::Unsupported.into())
+                .map_err(api::errors::ApiError::RestError)
+                .into_future()
+        )
+    }
+}
+
+impl WithdrawClient for Client {
+    fn withdraw(&self, _: &str, _: &str, _: &str, _: Option<&str>)
+        -> Box<dyn Future<Item = WithdrawAck, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(
+            Err(api::errors::RestErrorKind::Unsupported.into())
+                .map_err(api::errors::ApiError::RestError)
+                .into_future()
+        )
+    }
+}
+
+impl GenerateOrderId for Client {
+    /// Bitfinex client order ids are integers, `hint` is ignored and the id is
+    /// derived from the current timestamp.
+    fn new_order_id(_: &str) -> String {
+        static LAST_ORDER_ID: AtomicUsize = AtomicUsize::new(0);
+        next_increasing(&LAST_ORDER_ID, timestamp_ms() as usize).to_string()
+    }
+}
@@ -0,0 +1,256 @@
+use futures::prelude::*;
+use failure::Fail;
+use std::collections::HashMap;
+use hyper::{Method, Request};
+use log::error;
+use serde_json::value::RawValue;
+use crate::Side;
+use crate::tick::{Tick, TickUnit, Price, Size, RoundingMode};
+use crate::api;
+use crate::api::timestamp::{Timestamped, IntoTimestamped};
+use crate::api::errors::ErrorKinded;
+use crate::api::rate_limit;
+use crate::api::symbol::{self, Symbol};
+use crate::api::bitfinex::{Keys, Client, nonce};
+use crate::api::bitfinex::errors::{RestError, BitfinexRestError};
+
+/// Bitfinex prices have up to 5 significant digits and up to 8 decimals, while sizes have
+/// up to 8 decimals. Since ticks are fixed, prices and sizes are expressed in units of
+/// `10^-8`.
+const DECIMALS: u32 = 8;
+
+/// `(WALLET_TYPE, CURRENCY, BALANCE, UNSETTLED_INTEREST, AVAILABLE_BALANCE, ...)`
+type BitfinexWallet<'a> = Vec<&'a RawValue>;
+
+impl Client {
+    fn request<K: api::errors::ErrorKind>(
+        &self,
+        endpoint: &str,
+        method: Method,
+        keys: Option<Keys>,
+    ) -> impl Future<Item = hyper::Chunk, Error = api::errors::ApiError<K>> + Send + 'static
+            where RestError: ErrorKinded<K>
+    {
+        let address = format!(
+            "{}/{}",
+            self.params.rest_endpoint,
+            endpoint,
+        );
+        let path = format!("/api/{}", endpoint);
+
+        let http_client = self.http_client.clone();
+        rate_limit::acquire(self.rate_limiter.as_ref(), 1).then(move |_| {
+            let mut request = Request::builder();
+            request.method(method)
+                .header("User-Agent", &b"hyper"[..])
+                .uri(&address);
+
+            // Authenticated endpoints only accept `POST` requests with a JSON body.
+            let body = match keys {
+                Some(keys) => {
+                    let body = "{}";
+                    let nonce = nonce();
+                    let signature = keys.sign(&format!("{}{}{}", path, nonce, body));
+                    request.header("Content-Type", &b"application/json"[..])
+                        .header("bfx-nonce", nonce.to_string().as_bytes())
+                        .header("bfx-apikey", keys.api_key.as_bytes())
+                        .header("bfx-signature", signature.as_bytes());
+                    body
+                }
+                None => "",
+            };
+
+            // Unwrap because it is a bug if this fails (header failed to parse or something)
+            let request = request.body(body.into()).unwrap();
+            http_client.request(request)
+        })
+        .map_err(|err| api::errors::RequestError::new(err.compat()))
+        .map_err(api::errors::ApiError::RequestError)
+        .and_then(|(status, body)| {
+            if status != hyper::StatusCode::OK {
+                let bitfinex_error: Option<BitfinexRestError> = serde_json::from_slice(&body).ok();
+                let error = RestError::from_bitfinex_error(status, bitfinex_error);
+                let kind = error.kind();
+                Err(
+                    api::errors::ApiError::RestError(error.context(kind).into())
+                )?;
+            }
+            Ok(body)
+        })
+    }
+
+    crate fn ping_impl(&self)
+        -> impl Future<Item = Timestamped<()>, Error = api::errors::Error> + Send + 'static
+    {
+        self.request("v2/platform/status", Method::GET, None).and_then(|body| {
+            let status: Vec<u32> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            // `[1]` if the platform is operative, `[0]` during maintenance.
+            if status.first() != Some(&1) {
+                Err(api::errors::RestErrorKind::OtherSide.into())
+                    .map_err(api::errors::ApiError::RestError)?;
+            }
+            Ok(().timestamped())
+        })
+    }
+
+    crate fn balances_impl(&self)
+        -> impl Future<Item = api::Balances, Error = api::errors::Error> + Send + 'static
+    {
+        let keys = self.keys.clone();
+        self.request("v2/auth/r/wallets", Method::POST, keys).and_then(|body| {
+            let wallets: Vec<BitfinexWallet<'_>> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            let tick = Tick::new((10 as TickUnit).pow(DECIMALS));
+            let convert = |amount: &str| {
+                tick.ticked(amount)
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)
+            };
+
+            let mut balances = HashMap::new();
+            for wallet in wallets {
+                let string = |index: usize| {
+                    let value = wallet.get(index)?;
+                    serde_json::from_str::<&str>(value.get()).ok()
+                };
+
+                // Amounts are kept as sent by Bitfinex rather than formatted from an `f64`.
+                let amount = |index: usize| {
+                    wallet.get(index).map(|value| value.get()).filter(|value| *value != "null")
+                };
+
+                // Only the exchange wallet is used for trading.
+                if string(0) != Some("exchange") {
+                    continue;
+                }
+
+                let currency = match string(1) {
+                    Some(currency) => currency,
+                    None => continue,
+                };
+
+                let total = amount(2).unwrap_or("0");
+
+                // Bitfinex sends a `null` available balance until it has been computed.
+                let free = amount(4).unwrap_or(total);
+                let locked = convert(total)?.saturating_sub(convert(free)?);
+
+                balances.insert(currency.to_owned(), api::Balance {
+                    free: free.to_owned(),
+                    locked: tick.unticked(locked)
+                        .map_err(api::errors::RequestError::new)
+                        .map_err(api::errors::ApiError::RequestError)?,
+                });
+            }
+            Ok(balances)
+        })
+    }
+
+    crate fn ticker_impl(&self, symbol: Symbol)
+        -> impl Future<Item = api::Ticker, Error = api::errors::Error> + Send + 'static
+    {
+        let endpoint = format!("v2/ticker/t{}", symbol.name());
+
+        self.request(&endpoint, Method::GET, None).and_then(move |body| {
+            // `[BID, BID_SIZE, ASK, ASK_SIZE, DAILY_CHANGE, DAILY_CHANGE_RELATIVE,
+            //   LAST_PRICE, VOLUME, HIGH, LOW]`
+            let ticker: Vec<f64> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            if ticker.len() < 8 {
+                Err(api::errors::RestErrorKind::OtherSide.into())
+                    .map_err(api::errors::ApiError::RestError)?;
+            }
+
+            let convert = |tick: Tick, value: f64| {
+                tick.from_f64(value, RoundingMode::Nearest)
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)
+            };
+
+            Ok(api::Ticker {
+                best_bid: Price(convert(symbol.price_tick(), ticker[0])?),
+                best_ask: Price(convert(symbol.price_tick(), ticker[2])?),
+                last: Price(convert(symbol.price_tick(), ticker[6])?),
+                volume_24h: Size(convert(symbol.size_tick(), ticker[7])?),
+            })
+        })
+    }
+
+    crate fn recent_trades_impl(&self, symbol: Symbol, limit: usize)
+        -> impl Future<Item = Vec<Timestamped<api::Trade>>, Error = api::errors::Error> + Send + 'static
+    {
+        let endpoint = format!("v2/trades/t{}/hist?limit={}", symbol.name(), limit);
+
+        self.request(&endpoint, Method::GET, None).and_then(move |body| {
+            // `[[ID, MTS, AMOUNT, PRICE], ...]`, newest first.
+            let trades: Vec<(u64, u64, f64, f64)> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            let convert = |tick: Tick, value: f64| {
+                tick.from_f64(value, RoundingMode::Nearest)
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)
+            };
+
+            // A positive amount means that the taker bought, i.e. the maker was on the ask side.
+            let mut trades = trades.into_iter().take(limit).map(|(_, timestamp, amount, price)| {
+                Ok(api::Trade {
+                    price: Price(convert(symbol.price_tick(), price)?),
+                    size: Size(convert(symbol.size_tick(), amount.abs())?),
+                    maker_side: if amount > 0. { Side::Ask } else { Side::Bid },
+                }.with_timestamp(timestamp))
+            }).collect::<Result<Vec<_>, _>>()?;
+
+            trades.reverse();
+            Ok(trades)
+        })
+    }
+
+    crate fn get_symbols(&self)
+        -> impl Future<Item = HashMap<String, Symbol>, Error = api::errors::Error> + Send + 'static
+    {
+        self.request("v2/conf/pub:info:pair", Method::GET, None).and_then(|body| {
+            // `[[[PAIR, [_, _, _, MIN_ORDER_SIZE, MAX_ORDER_SIZE, ...]], ...]]`
+            let conf: Vec<Vec<(String, serde_json::Value)>> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            let tick = Tick::new((10 as TickUnit).pow(DECIMALS));
+
+            let mut symbols = HashMap::new();
+            for (pair, _) in conf.into_iter().flatten() {
+                let symbol = match Symbol::new(&pair, tick, tick) {
+                    Some(symbol) => symbol.with_commission_tick(tick),
+                    None => {
+                        error!("symbol name too long: `{}`", pair);
+                        continue;
+                    }
+                };
+
+                // Pairs are either of the form `BTCUSD` or `TESTBTC:TESTUSD`.
+                let assets = match pair.find(':') {
+                    Some(index) => Some((&pair[..index], &pair[index + 1..])),
+                    None if pair.len() == 6 => Some((&pair[..3], &pair[3..])),
+                    None => None,
+                };
+
+                let symbol = match assets {
+                    Some((base_asset, quote_asset)) => {
+                        symbol.with_base_asset(base_asset).with_quote_asset(quote_asset)
+                    }
+                    None => symbol,
+                };
+                symbols.insert(symbol::canonical_name(symbol.name()), symbol);
+            }
+            Ok(symbols)
+        })
+    }
+}
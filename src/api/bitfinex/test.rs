@@ -0,0 +1,315 @@
+#![cfg(test)]
+
+use std::sync::Arc;
+use futures::prelude::*;
+use futures::sync::{mpsc, oneshot};
+use hyper::StatusCode;
+use openssl::pkey::PKey;
+use crate::Side;
+use crate::tick::{Tick, Price, Size};
+use crate::order_book::LimitUpdate;
+use crate::api::{
+    ApiClient,
+    Balance,
+    Notification,
+    NotificationFlags,
+    Params,
+    Trade,
+    OrderConfirmation,
+    OrderExpiration,
+    OrderUpdate,
+};
+use crate::api::harness::{self, MockTransport};
+use crate::api::wss::{self, RawLog};
+use crate::api::symbol::Symbol;
+use crate::api::bitfinex::{Client, Keys, KeyPair};
+use crate::api::bitfinex::errors::RestErrorKind;
+use crate::api::bitfinex::wss::{HandlerImpl, RequestKind, Trading, TradingHandlerImpl};
+
+fn process_messages(messages: &[&str]) -> Vec<Notification> {
+    let symbol = Symbol::new("BTCUSD", Tick::new(100_000_000), Tick::new(100_000_000)).unwrap();
+    let mut handler = HandlerImpl::new(
+        symbol,
+        NotificationFlags::ALL,
+        None,
+        RawLog::new(false, "bitfinex")
+    );
+
+    harness::replay(&mut handler, messages)
+}
+
+fn limit_updates(notif: &Notification) -> Vec<LimitUpdate> {
+    match notif {
+        Notification::LimitUpdates(updates) => {
+            updates.iter().map(|update| update.into_inner()).collect()
+        }
+        other => panic!("expected limit updates, found `{:?}`", other),
+    }
+}
+
+fn book_snapshot(notif: &Notification) -> Vec<LimitUpdate> {
+    match notif {
+        Notification::BookSnapshot(updates) => {
+            updates.iter().map(|update| update.into_inner()).collect()
+        }
+        other => panic!("expected a book snapshot, found `{:?}`", other),
+    }
+}
+
+#[test]
+fn book_channel() {
+    let notifs = process_messages(&[
+        r#"{"event":"info","version":2}"#,
+        r#"{"event":"subscribed","channel":"book","chanId":17,"symbol":"tBTCUSD","prec":"P0"}"#,
+        r#"{"event":"subscribed","channel":"trades","chanId":42,"symbol":"tBTCUSD"}"#,
+        r#"[17,[[7254.5,1,0.5],[7255,2,-1.25]]]"#,
+        r#"[17,"hb"]"#,
+        r#"[17,[7254.5,0,1]]"#,
+        r#"[17,[7255,0,-1]]"#,
+        r#"[42,[[1,1574694475039,0.1,7254.5]]]"#,
+        r#"[42,"te",[2,1574694478808,-0.25,7254.4]]"#,
+        r#"[42,"tu",[2,1574694478808,-0.25,7254.4]]"#,
+        r#"[99,[7000,1,1]]"#,
+    ]);
+
+    assert_eq!(notifs.len(), 4);
+
+    assert_eq!(book_snapshot(&notifs[0]), vec![
+        LimitUpdate::new(Price(725_450_000_000), Size(50_000_000), Side::Bid),
+        LimitUpdate::new(Price(725_500_000_000), Size(125_000_000), Side::Ask),
+    ]);
+
+    // A count of 0 removes the price level, the side is given by the sign of the amount.
+    assert_eq!(
+        limit_updates(&notifs[1]),
+        vec![LimitUpdate::new(Price(725_450_000_000), Size(0), Side::Bid)]
+    );
+    assert_eq!(
+        limit_updates(&notifs[2]),
+        vec![LimitUpdate::new(Price(725_500_000_000), Size(0), Side::Ask)]
+    );
+    harness::assert_same_book(
+        &harness::rebuild_order_book(&notifs),
+        &harness::order_book(&[], &[])
+    );
+
+    // Only the `te` message is forwarded, the taker was selling.
+    match &notifs[3] {
+        Notification::Trade(trade) => {
+            assert_eq!(trade.timestamp(), 1574694478808);
+            assert_eq!(trade.into_inner(), Trade {
+                price: Price(725_440_000_000),
+                size: Size(25_000_000),
+                maker_side: Side::Bid,
+            });
+        }
+        other => panic!("expected a trade, found `{:?}`", other),
+    }
+}
+
+#[test]
+fn recent_trades() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"[[["BTCUSD",[null,null,null,"0.0006","2000.0",null,null,null,null,null]]]]"#);
+    transport.respond(StatusCode::OK, r#"[[401597395,1574694478808,-0.005,7245.3],[401597393,1574694475039,0.005,7245.2]]"#);
+
+    let params = Params {
+        streaming_endpoint: "wss://localhost".to_owned(),
+        rest_endpoint: "https://localhost".to_owned(),
+        weight_per_minute: None,
+    };
+    let client = Client::with_transport(params, None, transport.clone()).unwrap();
+    let symbol = client.find_symbol("BTCUSD").unwrap();
+
+    // Trades are sent newest first, a negative amount meaning that the taker sold.
+    let trades = client.recent_trades(symbol, 1).wait().unwrap();
+    assert_eq!(trades.len(), 1);
+    assert_eq!(trades[0].timestamp(), 1574694478808);
+    assert_eq!(trades[0].into_inner(), Trade {
+        price: Price(724_530_000_000),
+        size: Size(500_000),
+        maker_side: Side::Bid,
+    });
+
+    let requests = transport.requests();
+    assert_eq!(requests[1].uri().path_and_query().unwrap(), "/v2/trades/tBTCUSD/hist?limit=1");
+}
+
+/// Return a `[0, KIND, [ID, GID, CID, SYMBOL, MTS_CREATE, MTS_UPDATE, AMOUNT, AMOUNT_ORIG,
+/// TYPE, TYPE_PREV, MTS_TIF, _, FLAGS, STATUS, _, _, PRICE, ...]]` order message.
+fn order_message(kind: &str, id: u64, cid: i64, amount: f64, amount_orig: f64, status: &str)
+    -> String
+{
+    format!(
+        r#"[0,"{}",[{},null,{},"tBTCUSD",1574694400000,1574694403000,{},{},"EXCHANGE LIMIT",null,null,null,0,"{}",null,null,7000,0,0,0,null,null,null,0,0,null,null,null,"API>BFX",null,null,null]]"#,
+        kind,
+        id,
+        cid,
+        amount,
+        amount_orig,
+        status
+    )
+}
+
+#[test]
+fn order_messages() {
+    let symbol = Symbol::new("BTCUSD", Tick::new(100_000_000), Tick::new(100_000_000))
+        .unwrap()
+        .with_commission_tick(Tick::new(100_000_000));
+    let mut handler = HandlerImpl::new(
+        symbol,
+        NotificationFlags::ORDERS,
+        None,
+        RawLog::new(false, "bitfinex")
+    );
+
+    let messages = [
+        // Open orders when authenticating, including one for another symbol.
+        r#"[0,"os",[[1001,null,1,"tBTCUSD",1574694400000,1574694400000,0.5,0.5,"EXCHANGE LIMIT",null,null,null,0,"ACTIVE",null,null,7000,0,0,0,null,null,null,0,0,null,null,null,"API>BFX",null,null,null],[1003,null,3,"tETHUSD",1574694400000,1574694400000,1,1,"EXCHANGE LIMIT",null,null,null,0,"ACTIVE",null,null,150,0,0,0,null,null,null,0,0,null,null,null,"API>BFX",null,null,null]]]"#.to_owned(),
+        order_message("on", 1002, 2, -0.3, -0.3, "ACTIVE"),
+
+        // `[ID, SYMBOL, MTS_CREATE, ORDER_ID, EXEC_AMOUNT, EXEC_PRICE, ORDER_TYPE,
+        //   ORDER_PRICE, MAKER, FEE, FEE_CURRENCY]`: a fee, then a maker rebate.
+        r#"[0,"tu",[401597395,"tBTCUSD",1574694401000,1001,0.2,7000,"EXCHANGE LIMIT",7000,1,-0.0004,"USD"]]"#.to_owned(),
+        r#"[0,"tu",[401597396,"tBTCUSD",1574694402000,1002,-0.1,7000,"EXCHANGE LIMIT",7000,1,0.00014,"USD"]]"#.to_owned(),
+
+        // Unknown orders are ignored.
+        r#"[0,"tu",[401597397,"tBTCUSD",1574694402000,1003,1,150,"EXCHANGE LIMIT",150,1,-0.3,"USD"]]"#.to_owned(),
+        order_message("oc", 1003, 3, 1., 1., "CANCELED"),
+
+        // Fills were already notified through `tu` messages.
+        order_message("oc", 1001, 1, 0., 0.5, "EXECUTED @ 7000.0(0.5)"),
+        order_message("oc", 1002, 2, -0.2, -0.3, "CANCELED"),
+    ];
+    let messages = messages.iter().map(|message| message.as_str()).collect::<Vec<_>>();
+    let notifs = harness::replay(&mut handler, &messages);
+    assert_eq!(notifs.len(), 4);
+
+    match &notifs[0] {
+        Notification::OrderConfirmation(confirmation) => {
+            assert_eq!(confirmation.timestamp(), 1574694400000);
+            assert_eq!(confirmation.clone().into_inner(), OrderConfirmation {
+                order_id: "2".to_owned(),
+                price: Price(700_000_000_000),
+                size: Size(30_000_000),
+                side: Side::Ask,
+            });
+        }
+        other => panic!("expected an order confirmation, found `{:?}`", other),
+    }
+
+    match &notifs[1] {
+        Notification::OrderUpdate(update) => {
+            assert_eq!(update.timestamp(), 1574694401000);
+            assert_eq!(update.clone().into_inner(), OrderUpdate {
+                order_id: "1".to_owned(),
+                consumed_size: Size(20_000_000),
+                remaining_size: Size(30_000_000),
+                consumed_price: Price(700_000_000_000),
+                commission: 40_000,
+                commission_asset: Some("USD".to_owned()),
+                is_fill: true,
+            });
+        }
+        other => panic!("expected an order update, found `{:?}`", other),
+    }
+
+    match &notifs[2] {
+        Notification::OrderUpdate(update) => {
+            assert_eq!(update.clone().into_inner(), OrderUpdate {
+                order_id: "2".to_owned(),
+                consumed_size: Size(10_000_000),
+                remaining_size: Size(20_000_000),
+                consumed_price: Price(700_000_000_000),
+                commission: 0,
+                commission_asset: Some("USD".to_owned()),
+                is_fill: true,
+            });
+        }
+        other => panic!("expected an order update, found `{:?}`", other),
+    }
+
+    match &notifs[3] {
+        Notification::OrderExpiration(expiration) => {
+            assert_eq!(expiration.timestamp(), 1574694403000);
+            assert_eq!(expiration.clone().into_inner(), OrderExpiration {
+                order_id: "2".to_owned(),
+            });
+        }
+        other => panic!("expected an order expiration, found `{:?}`", other),
+    }
+}
+
+#[test]
+fn trading_notifications() {
+    let keys = Keys {
+        api_key: "api_key".to_owned(),
+        secret_key: Arc::new(PKey::hmac(b"secret_key").unwrap()),
+    };
+    let trading = Arc::new(Trading::new("wss://localhost".to_owned(), keys));
+    let mut handler = TradingHandlerImpl::new(trading.clone(), RawLog::new(false, "bitfinex"));
+    let (snd, _) = mpsc::unbounded::<!>();
+
+    let (order_snd, order_rcv) = oneshot::channel();
+    let (cancel_snd, cancel_rcv) = oneshot::channel();
+    {
+        let mut state = trading.state.lock().unwrap();
+        state.pending.insert((RequestKind::Order, 1), order_snd);
+        state.pending.insert((RequestKind::Cancel, 1), cancel_snd);
+        state.order_dates.insert(1, "2019-11-25".to_owned());
+    }
+
+    // `[0, "n", [MTS, TYPE, MESSAGE_ID, _, NOTIFY_INFO, CODE, STATUS, TEXT]]`, the
+    // requests are matched by type and client order id.
+    let messages = [
+        r#"[0,"n",[1574694401000,"oc-req",null,null,[null,null,1,"tBTCUSD",null,null,0.5,0.5,"EXCHANGE LIMIT",null,null,null,0,"ACTIVE",null,null,7000,0,0,0,null,null,null,0,0,null,null,null,null,null,null,null],10001,"ERROR","Order not found."]]"#,
+        r#"[0,"n",[1574694400000,"on-req",null,null,[null,null,2,"tBTCUSD",null,null,0.5,0.5,"EXCHANGE LIMIT",null,null,null,0,"ACTIVE",null,null,7000,0,0,0,null,null,null,0,0,null,null,null,null,null,null,null],null,"SUCCESS","Submitting exchange limit buy order for 0.5 BTC."]]"#,
+        r#"[0,"n",[1574694400000,"on-req",null,null,[null,null,1,"tBTCUSD",null,null,0.5,0.5,"EXCHANGE LIMIT",null,null,null,0,"ACTIVE",null,null,7000,0,0,0,null,null,null,0,0,null,null,null,null,null,null,null],null,"SUCCESS","Submitting exchange limit buy order for 0.5 BTC."]]"#,
+        r#"[0,"hb"]"#,
+    ];
+    for message in &messages {
+        wss::HandlerImpl::on_message(&mut handler, message, &snd).unwrap();
+    }
+
+    assert_eq!(order_rcv.wait().unwrap(), Ok(1574694400000));
+    let err = cancel_rcv.wait().unwrap().unwrap_err();
+    assert_eq!(err.kind, RestErrorKind::Rejected);
+    assert_eq!(err.error_code, 10001);
+    assert_eq!(err.error_msg, "Order not found.");
+
+    // The creation date is kept after a failed cancel.
+    let state = trading.state.lock().unwrap();
+    assert!(state.pending.is_empty());
+    assert_eq!(state.order_dates.get(&1).map(|date| date.as_str()), Some("2019-11-25"));
+}
+
+#[test]
+fn wallet_balances() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"[[["BTCUSD",[null,null,null,"0.0006","2000.0",null,null,null,null,null]]]]"#);
+    transport.respond(StatusCode::OK, r#"[["exchange","BTC",1.23456789,0,1.2,null,null],["exchange","USD",1e-7,0,null,null,null],["margin","BTC",10,0,10,null,null]]"#);
+
+    let params = Params {
+        streaming_endpoint: "wss://localhost".to_owned(),
+        rest_endpoint: "https://localhost".to_owned(),
+        weight_per_minute: None,
+    };
+    let key_pair = KeyPair::new("api_key".to_owned(), "secret_key".to_owned());
+    let client = Client::with_transport(params, Some(key_pair), transport.clone()).unwrap();
+
+    // Only the exchange wallet is used, amounts are kept as sent by Bitfinex.
+    let balances = client.balances().wait().unwrap();
+    assert_eq!(balances.len(), 2);
+    assert_eq!(balances["BTC"], Balance {
+        free: "1.2".to_owned(),
+        locked: "0.03456789".to_owned(),
+    });
+    assert_eq!(balances["USD"], Balance {
+        free: "1e-7".to_owned(),
+        locked: "0.00000000".to_owned(),
+    });
+
+    let requests = transport.requests();
+    assert_eq!(requests[1].uri().path(), "/v2/auth/r/wallets");
+    assert!(requests[1].headers().contains_key("bfx-signature"));
+}
@@ -0,0 +1,827 @@
+use futures::prelude::*;
+use futures::sync::mpsc::{unbounded, UnboundedSender};
+use futures::sync::oneshot;
+use failure::{bail, format_err, Fail};
+use std::borrow::{Borrow, Cow};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use log::{debug, error};
+use crate::Side;
+use crate::order_book::LimitUpdate;
+use crate::tick::{Price, Size, RoundingMode};
+use crate::api::{
+    self,
+    GenerateOrderId,
+    Notification,
+    NotificationFlags,
+    Order,
+    OrderAck,
+    OrderType,
+    TimeInForce,
+    Cancel,
+    CancelAck,
+    Trade,
+    OrderConfirmation,
+    OrderExpiration,
+    OrderUpdate,
+};
+use crate::api::wss;
+use crate::api::stream::StreamHandle;
+use crate::api::errors::ErrorKinded;
+use crate::api::symbol::{Symbol, WithSymbol};
+use crate::api::timestamp::{Timestamp, Timestamped, IntoTimestamped};
+use crate::api::bitfinex::{Keys, Client, nonce};
+use crate::api::bitfinex::errors::RestError;
+
+impl Client {
+    crate fn new_stream(&self, symbol: Symbol, flags: NotificationFlags)
+        -> StreamHandle
+    {
+        let streaming_endpoint = self.params.streaming_endpoint.clone();
+        let keys = self.keys.clone();
+        let raw_log = wss::RawLog::new(self.log_raw, "bitfinex");
+        StreamHandle::spawn(move |snd, shutdown| {
+            let address = format!(
+                "{}/ws/2",
+                streaming_endpoint,
+            );
+
+            debug!("initiating WebSocket connection at {}", address);
+
+            if let Err(err) = ws::connect(address, |out| {
+                shutdown.register(&out);
+                let handler = HandlerImpl::new(symbol, flags, keys.clone(), raw_log);
+                wss::Handler::new(out, snd.clone(), wss::KeepAlive::False, raw_log, handler)
+            })
+            {
+                error!("WebSocket connection terminated with error: `{}`", err);
+            }
+        })
+    }
+
+    crate fn order_impl(&self, order: WithSymbol<&Order>)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
+    {
+        if let Err(err) = order.validate(&order.symbol()) {
+            return Box::new(Err(err.into()).into_future());
+        }
+
+        let trading = match &self.trading {
+            Some(trading) if order.funds.is_none() && !order.type_.is_stop() => trading,
+            _ => return Box::new(
+                Err(api::errors::RestErrorKind::Unsupported.into())
+                    .map_err(api::errors::ApiError::RestError)
+                    .into_future()
+            ),
+        };
+
+        let order_id = order.order_id.clone().unwrap_or_else(|| Self::new_order_id(""));
+        let cid = match order_id.parse() {
+            Ok(cid) => cid,
+            Err(..) => return Box::new(
+                Err(api::errors::RestErrorKind::InvalidRequest.into())
+                    .map_err(api::errors::ApiError::RestError)
+                    .into_future()
+            ),
+        };
+
+        let symbol = order.symbol();
+        let size = order.size.unticked(symbol.size_tick());
+        let new_order = BitfinexNewOrder {
+            type_: order_type(order.type_, order.time_in_force),
+            symbol: format!("t{}", symbol.name()),
+            // The side of the order is given by the sign of the amount.
+            amount: match order.side {
+                Side::Bid => size,
+                Side::Ask => Cow::Owned(format!("-{}", size)),
+            },
+            price: match order.type_ {
+                OrderType::Market => None,
+                _ => Some(order.price.unticked(symbol.price_tick())),
+            },
+            cid,
+            flags: match order.type_ {
+                OrderType::LimitMaker => Some(POST_ONLY),
+                _ => None,
+            },
+        };
+
+        let payload = serde_json::to_string(&(0, "on", (), new_order)).unwrap();
+        let raw_log = wss::RawLog::new(self.log_raw, "bitfinex");
+        let fut = Trading::request(trading, RequestKind::Order, cid, payload, raw_log)
+            .map(move |timestamp| OrderAck { order_id }.with_timestamp(timestamp));
+        Box::new(fut)
+    }
+
+    crate fn cancel_impl(&self, cancel: WithSymbol<&Cancel>)
+        -> Box<dyn Future<Item = Timestamped<CancelAck>, Error = api::errors::CancelError> + Send + 'static>
+    {
+        let trading = match &self.trading {
+            Some(trading) => trading,
+            None => return Box::new(
+                Err(api::errors::RestErrorKind::Unsupported.into())
+                    .map_err(api::errors::ApiError::RestError)
+                    .into_future()
+            ),
+        };
+
+        let cid = match cancel.order_id().parse() {
+            Ok(cid) => cid,
+            Err(..) => return Box::new(
+                Err(api::errors::RestErrorKind::InvalidRequest.into())
+                    .map_err(api::errors::ApiError::RestError)
+                    .into_future()
+            ),
+        };
+
+        let cancel = BitfinexCancel {
+            cid,
+            cid_date: trading.order_date(cid),
+        };
+
+        let payload = serde_json::to_string(&(0, "oc", (), cancel)).unwrap();
+        let raw_log = wss::RawLog::new(self.log_raw, "bitfinex");
+        let fut = Trading::request(trading, RequestKind::Cancel, cid, payload, raw_log)
+            .map(|timestamp| CancelAck.with_timestamp(timestamp));
+        Box::new(fut)
+    }
+}
+
+/// Bitfinex order flag for post-only orders.
+const POST_ONLY: u32 = 4096;
+
+fn order_type(type_: OrderType, time_in_force: TimeInForce) -> &'static str {
+    match (type_, time_in_force) {
+        (OrderType::Market, _) => "EXCHANGE MARKET",
+        (_, TimeInForce::ImmediateOrCancel) => "EXCHANGE IOC",
+        (_, TimeInForce::FillOrKilll) => "EXCHANGE FOK",
+        (_, TimeInForce::GoodTilCanceled) => "EXCHANGE LIMIT",
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+struct BitfinexSubscription<'a> {
+    event: &'a str,
+    channel: &'a str,
+    symbol: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prec: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    len: Option<&'a str>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+#[allow(non_snake_case)]
+struct BitfinexAuthentication<'a> {
+    event: &'a str,
+    apiKey: &'a str,
+    authSig: String,
+    authNonce: usize,
+    authPayload: String,
+    filter: &'a [&'a str],
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+struct BitfinexNewOrder<'a> {
+    #[serde(rename = "type")]
+    type_: &'a str,
+    symbol: String,
+    amount: Cow<'a, str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price: Option<Cow<'a, str>>,
+    cid: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+struct BitfinexCancel {
+    cid: i64,
+    cid_date: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct BitfinexEvent<'a> {
+    event: &'a str,
+    #[serde(borrow)]
+    channel: Option<&'a str>,
+    chanId: Option<u64>,
+    #[serde(borrow)]
+    status: Option<&'a str>,
+    code: Option<i32>,
+    #[serde(borrow)]
+    msg: Option<Cow<'a, str>>, // error message can contain escaped characters
+}
+
+/// Return the authentication payload for `keys`, only forwarding the messages
+/// related to trading on the authenticated channel.
+fn authentication(keys: &Keys) -> String {
+    let nonce = nonce();
+    let auth_payload = format!("AUTH{}", nonce);
+    let auth = BitfinexAuthentication {
+        event: "auth",
+        apiKey: &keys.api_key,
+        authSig: keys.sign(&auth_payload),
+        authNonce: nonce,
+        authPayload: auth_payload,
+        filter: &["trading"],
+    };
+
+    match serde_json::to_string(&auth) {
+        Ok(value) => value,
+        Err(err) => {
+            panic!("failed to serialize `BitfinexAuthentication`: `{}`", err);
+        }
+    }
+}
+
+fn field(array: &[Value], index: usize) -> Result<&Value, failure::Error> {
+    array.get(index).ok_or_else(|| format_err!("missing field at index {}", index))
+}
+
+fn array(value: &Value) -> Result<&[Value], failure::Error> {
+    value.as_array()
+        .map(|array| array.borrow())
+        .ok_or_else(|| format_err!("expected an array, found `{}`", value))
+}
+
+fn number(value: &Value) -> Result<f64, failure::Error> {
+    value.as_f64().ok_or_else(|| format_err!("expected a number, found `{}`", value))
+}
+
+fn string(value: &Value) -> Result<&str, failure::Error> {
+    value.as_str().ok_or_else(|| format_err!("expected a string, found `{}`", value))
+}
+
+fn timestamp(value: &Value) -> Result<Timestamp, failure::Error> {
+    value.as_u64().ok_or_else(|| format_err!("expected a timestamp, found `{}`", value))
+}
+
+/// Client order ids are integers, but Bitfinex may send them as floats.
+fn client_order_id(value: &Value) -> Result<i64, failure::Error> {
+    value.as_i64()
+        .or_else(|| value.as_f64().map(|cid| cid as i64))
+        .ok_or_else(|| format_err!("expected a client order id, found `{}`", value))
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+enum Channel {
+    Book,
+    Trades,
+}
+
+pub(super) struct HandlerImpl {
+    symbol: Symbol,
+    flags: NotificationFlags,
+    keys: Option<Keys>,
+
+    /// Channels subscribed to, keyed by the `chanId` assigned by Bitfinex.
+    channels: HashMap<u64, Channel>,
+
+    /// Open orders of the user data stream, keyed by Bitfinex order id: client order id
+    /// along with the remaining size, for filling the `OrderUpdate` notifications.
+    orders: HashMap<u64, (String, Size)>,
+
+    raw_log: wss::RawLog,
+}
+
+impl HandlerImpl {
+    pub(super) fn new(
+        symbol: Symbol,
+        flags: NotificationFlags,
+        keys: Option<Keys>,
+        raw_log: wss::RawLog
+    ) -> Self
+    {
+        HandlerImpl {
+            symbol,
+            flags,
+            keys,
+            channels: HashMap::new(),
+            orders: HashMap::new(),
+            raw_log,
+        }
+    }
+
+    fn price(&self, value: &Value) -> Result<Price, failure::Error> {
+        Ok(Price(self.symbol.price_tick().from_f64(number(value)?, RoundingMode::Nearest)?))
+    }
+
+    /// Sizes are signed, the sign is ignored.
+    fn size(&self, value: &Value) -> Result<Size, failure::Error> {
+        Ok(Size(self.symbol.size_tick().from_f64(number(value)?.abs(), RoundingMode::Nearest)?))
+    }
+
+    /// Convert a `[PRICE, COUNT, AMOUNT]` book entry.
+    fn convert_book_entry(&self, entry: &Value) -> Result<LimitUpdate, failure::Error> {
+        let entry = array(entry)?;
+        let amount = number(field(entry, 2)?)?;
+
+        // A count of 0 means that the price level must be removed.
+        let size = if number(field(entry, 1)?)? == 0. {
+            Size(0)
+        } else {
+            self.size(field(entry, 2)?)?
+        };
+
+        Ok(LimitUpdate {
+            side: if amount > 0. { Side::Bid } else { Side::Ask },
+            price: self.price(field(entry, 0)?)?,
+            size,
+        })
+    }
+
+    /// Convert a `[ID, MTS, AMOUNT, PRICE]` trade.
+    fn convert_trade(&self, trade: &Value) -> Result<Timestamped<Trade>, failure::Error> {
+        let trade = array(trade)?;
+        let amount = number(field(trade, 2)?)?;
+
+        Ok(Trade {
+            size: self.size(field(trade, 2)?)?,
+            price: self.price(field(trade, 3)?)?,
+            // A positive amount means that the taker was buying.
+            maker_side: if amount > 0. { Side::Ask } else { Side::Bid },
+        }.with_timestamp(timestamp(field(trade, 1)?)?))
+    }
+
+    fn parse_event(&mut self, json: &str) -> Result<(), failure::Error> {
+        let event: BitfinexEvent<'_> = serde_json::from_str(json)?;
+
+        match event.event {
+            "subscribed" => {
+                let chan_id = event.chanId.ok_or_else(|| format_err!("missing `chanId`"))?;
+                let channel = match event.channel {
+                    Some("book") => Channel::Book,
+                    Some("trades") => Channel::Trades,
+                    other => bail!("unexpected channel: `{:?}`", other),
+                };
+                self.channels.insert(chan_id, channel);
+            }
+
+            "auth" if event.status != Some("OK") => {
+                bail!("authentication failed: `{}`", event.msg.unwrap_or_default());
+            }
+
+            "error" => {
+                bail!(
+                    "received error: `{}` [code = {}]",
+                    event.msg.unwrap_or_default(),
+                    event.code.unwrap_or(-1)
+                );
+            }
+
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Parse a message of the authenticated channel, i.e. with a `chanId` of 0.
+    fn parse_account_message(&mut self, message: &[Value], out: &wss::NotifSender)
+        -> Result<(), failure::Error>
+    {
+        let symbol = format!("t{}", self.symbol.name());
+
+        match string(field(message, 1)?)? {
+            // `[ID, GID, CID, SYMBOL, MTS_CREATE, MTS_UPDATE, AMOUNT, AMOUNT_ORIG, TYPE,
+            //   TYPE_PREV, MTS_TIF, _, FLAGS, STATUS, _, _, PRICE, ...]`
+            "os" => {
+                for order in array(field(message, 2)?)? {
+                    let order = array(order)?;
+                    if string(field(order, 3)?)? != symbol {
+                        continue;
+                    }
+
+                    let order_id = client_order_id(field(order, 2)?)?.to_string();
+                    let remaining = self.size(field(order, 6)?)?;
+                    self.orders.insert(timestamp(field(order, 0)?)?, (order_id, remaining));
+                }
+            }
+
+            "on" => {
+                let order = array(field(message, 2)?)?;
+                if string(field(order, 3)?)? != symbol {
+                    return Ok(());
+                }
+
+                let order_id = client_order_id(field(order, 2)?)?.to_string();
+                let remaining = self.size(field(order, 6)?)?;
+                self.orders.insert(timestamp(field(order, 0)?)?, (order_id.clone(), remaining));
+
+                let order = OrderConfirmation {
+                    order_id,
+                    price: self.price(field(order, 16)?)?,
+                    size: self.size(field(order, 7)?)?,
+                    side: if number(field(order, 7)?)? > 0. { Side::Bid } else { Side::Ask },
+                }.with_timestamp(timestamp(field(order, 4)?)?);
+                out.unbounded_send(Notification::OrderConfirmation(order)).unwrap();
+            }
+
+            "oc" => {
+                let order = array(field(message, 2)?)?;
+                if self.orders.remove(&timestamp(field(order, 0)?)?).is_none() {
+                    return Ok(());
+                }
+
+                // Fills have already been notified through `tu` messages.
+                if string(field(order, 13)?)?.starts_with("EXECUTED") {
+                    return Ok(());
+                }
+
+                let expiration = OrderExpiration {
+                    order_id: client_order_id(field(order, 2)?)?.to_string(),
+                }.with_timestamp(timestamp(field(order, 5)?)?);
+                out.unbounded_send(Notification::OrderExpiration(expiration)).unwrap();
+            }
+
+            // `[ID, SYMBOL, MTS_CREATE, ORDER_ID, EXEC_AMOUNT, EXEC_PRICE, ORDER_TYPE,
+            //   ORDER_PRICE, MAKER, FEE, FEE_CURRENCY, ...]`
+            //
+            // `te` messages carry the same trades as `tu` messages but without fees.
+            "tu" => {
+                let trade = array(field(message, 2)?)?;
+                let consumed_size = self.size(field(trade, 4)?)?;
+                let consumed_price = self.price(field(trade, 5)?)?;
+                let id = timestamp(field(trade, 3)?)?;
+                let (order_id, remaining) = match self.orders.get_mut(&id) {
+                    Some(order) => order,
+                    None => return Ok(()),
+                };
+                *remaining = remaining.saturating_sub(consumed_size);
+
+                let update = OrderUpdate {
+                    order_id: order_id.clone(),
+                    consumed_size,
+                    remaining_size: *remaining,
+                    consumed_price,
+                    // Fees are negative, maker rebates are positive and are not reported
+                    // as commissions.
+                    commission: self.symbol.commission_tick().from_f64(
+                        (-number(field(trade, 9)?)?).max(0.),
+                        RoundingMode::Nearest
+                    )?,
+                    commission_asset: field(trade, 10)?.as_str().map(|asset| asset.to_owned()),
+                    is_fill: true,
+                }.with_timestamp(timestamp(field(trade, 2)?)?);
+                out.unbounded_send(Notification::OrderUpdate(update)).unwrap();
+            }
+
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn parse_message(&mut self, json: &str, out: &wss::NotifSender) -> Result<(), failure::Error> {
+        let message: Value = serde_json::from_str(json)?;
+
+        // Events are JSON objects, channel messages are arrays whose first element is
+        // the `chanId`.
+        let message = match message {
+            Value::Object(..) => return self.parse_event(json),
+            Value::Array(message) => message,
+            other => bail!("unexpected message: `{}`", other),
+        };
+
+        let chan_id = timestamp(field(&message, 0)?)?;
+        let payload = field(&message, 1)?;
+
+        if payload.as_str() == Some("hb") {
+            return Ok(());
+        }
+
+        if chan_id == 0 {
+            if self.flags.contains_orders() {
+                self.parse_account_message(&message, out)?;
+            }
+            return Ok(());
+        }
+
+        match self.channels.get(&chan_id) {
+            Some(Channel::Book) => {
+                let entries = array(payload)?;
+
+                // A snapshot is a (possibly empty) array of entries, an update is a
+                // single entry.
+                let notif = if entries.first().map(|e| e.is_array()).unwrap_or(true) {
+                    Notification::BookSnapshot(
+                        entries.iter()
+                            .map(|entry| Ok(self.convert_book_entry(entry)?.timestamped()))
+                            .collect::<Result<Vec<_>, failure::Error>>()?
+                    )
+                } else {
+                    Notification::LimitUpdates(
+                        vec![self.convert_book_entry(payload)?.timestamped()]
+                    )
+                };
+                out.unbounded_send(notif).unwrap();
+            }
+
+            // The snapshot of past trades is not forwarded, and `tu` messages repeat
+            // `te` messages.
+            Some(Channel::Trades) if payload.as_str() == Some("te") => {
+                let trade = self.convert_trade(field(&message, 2)?)?;
+                out.unbounded_send(Notification::Trade(trade)).unwrap();
+            }
+
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn subscribe(&self, out: &ws::Sender, channel: &str, prec: Option<&str>, len: Option<&str>)
+        -> ws::Result<()>
+    {
+        let symbol = format!("t{}", self.symbol.name());
+        let subscription = BitfinexSubscription {
+            event: "subscribe",
+            channel,
+            symbol: &symbol,
+            prec,
+            len,
+        };
+
+        match serde_json::to_string(&subscription) {
+            Ok(value) => self.raw_log.send(out, value),
+            Err(err) => {
+                panic!("failed to serialize `BitfinexSubscription`: `{}`", err);
+            }
+        }
+    }
+}
+
+impl wss::HandlerImpl for HandlerImpl {
+    fn on_open(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        if self.flags.contains_book() {
+            self.subscribe(out, "book", Some("P0"), Some("100"))?;
+        }
+
+        if self.flags.contains_trades() {
+            self.subscribe(out, "trades", None, None)?;
+        }
+
+        if self.flags.contains_orders() {
+            if let Some(keys) = self.keys.as_ref() {
+                self.raw_log.send_secret(out, authentication(keys), "auth")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_message(&mut self, text: &str, out: &wss::NotifSender) -> Result<(), failure::Error> {
+        self.parse_message(text, out)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+crate enum RequestKind {
+    Order,
+    Cancel,
+}
+
+pub(super) type Response = Result<Timestamp, RestError>;
+
+pub(super) struct TradingState {
+    /// Whether a connection is open or being opened.
+    connected: bool,
+
+    /// Sender of the current connection, if any.
+    out: Option<ws::Sender>,
+
+    /// Whether the current connection is authenticated.
+    authenticated: bool,
+
+    /// Payloads waiting for the connection to be authenticated.
+    queue: Vec<String>,
+
+    /// Requests waiting for their notification, keyed by client order id.
+    pub(super) pending: HashMap<(RequestKind, i64), oneshot::Sender<Response>>,
+
+    /// UTC creation date of the orders sent by this client, needed for canceling them.
+    pub(super) order_dates: HashMap<i64, String>,
+}
+
+/// The authenticated WebSocket connection used as an input channel for orders and
+/// cancels. The connection is opened upon the first request, and opened again upon
+/// the next request if it was closed.
+crate struct Trading {
+    streaming_endpoint: String,
+    keys: Keys,
+    pub(super) state: Mutex<TradingState>,
+}
+
+impl Trading {
+    crate fn new(streaming_endpoint: String, keys: Keys) -> Self {
+        Trading {
+            streaming_endpoint,
+            keys,
+            state: Mutex::new(TradingState {
+                connected: false,
+                out: None,
+                authenticated: false,
+                queue: Vec::new(),
+                pending: HashMap::new(),
+                order_dates: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Return the UTC creation date of the order with client order id `cid`, assuming the
+    /// order was created today if it was not sent by this client.
+    fn order_date(&self, cid: i64) -> String {
+        self.state.lock().unwrap().order_dates.get(&cid).cloned().unwrap_or_else(|| {
+            chrono::Utc::now().format("%Y-%m-%d").to_string()
+        })
+    }
+
+    /// Send `payload` through the input channel and wait for the notification of the
+    /// request of kind `kind` for the order with client order id `cid`.
+    fn request<K: api::errors::ErrorKind>(
+        trading: &Arc<Trading>,
+        kind: RequestKind,
+        cid: i64,
+        payload: String,
+        raw_log: wss::RawLog,
+    ) -> impl Future<Item = Timestamp, Error = api::errors::ApiError<K>> + Send + 'static
+        where RestError: ErrorKinded<K>
+    {
+        let (snd, rcv) = oneshot::channel();
+
+        {
+            let mut state = trading.state.lock().unwrap();
+            state.pending.insert((kind, cid), snd);
+            if kind == RequestKind::Order {
+                let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+                state.order_dates.insert(cid, date);
+            }
+
+            if state.authenticated {
+                let sent = match &state.out {
+                    Some(out) => raw_log.send(out, payload),
+                    None => Err(ws::Error::new(ws::ErrorKind::Internal, "no connection")),
+                };
+
+                if let Err(err) = sent {
+                    error!("failed to send request: `{}`", err);
+                    state.pending.remove(&(kind, cid));
+                }
+            } else {
+                state.queue.push(payload);
+                if !state.connected {
+                    state.connected = true;
+                    Trading::connect(trading.clone(), raw_log);
+                }
+            }
+        }
+
+        // A canceled receiver means that the connection was closed before receiving
+        // the notification: the request may or may not have been processed.
+        rcv.map_err(api::errors::RequestError::new)
+            .map_err(api::errors::ApiError::RequestError)
+            .and_then(|response| {
+                response.map_err(|error| {
+                    let kind = error.kind();
+                    api::errors::ApiError::RestError(error.context(kind).into())
+                })
+            })
+    }
+
+    fn connect(trading: Arc<Trading>, raw_log: wss::RawLog) {
+        thread::spawn(move || {
+            let address = format!(
+                "{}/ws/2",
+                trading.streaming_endpoint,
+            );
+
+            debug!("initiating WebSocket connection at {}", address);
+
+            // The input channel does not forward any notification.
+            let (snd, _rcv) = unbounded::<!>();
+            if let Err(err) = ws::connect(address, |out| {
+                trading.state.lock().unwrap().out = Some(out.clone());
+                let handler = TradingHandlerImpl::new(trading.clone(), raw_log);
+                wss::Handler::new(out, snd.clone(), wss::KeepAlive::True, raw_log, handler)
+            })
+            {
+                error!("WebSocket connection terminated with error: `{}`", err);
+            }
+
+            // Dropping the pending senders fails the corresponding requests.
+            let mut state = trading.state.lock().unwrap();
+            state.connected = false;
+            state.out = None;
+            state.authenticated = false;
+            state.queue.clear();
+            state.pending.clear();
+        });
+    }
+}
+
+pub(super) struct TradingHandlerImpl {
+    trading: Arc<Trading>,
+    raw_log: wss::RawLog,
+}
+
+impl TradingHandlerImpl {
+    pub(super) fn new(trading: Arc<Trading>, raw_log: wss::RawLog) -> Self {
+        TradingHandlerImpl {
+            trading,
+            raw_log,
+        }
+    }
+
+    fn parse_message(&mut self, json: &str) -> Result<(), failure::Error> {
+        let message: Value = serde_json::from_str(json)?;
+
+        let message = match message {
+            Value::Object(..) => {
+                let event: BitfinexEvent<'_> = serde_json::from_str(json)?;
+                if event.event != "auth" {
+                    return Ok(());
+                }
+
+                let mut state = self.trading.state.lock().unwrap();
+                if event.status != Some("OK") {
+                    let error = RestError::rejected(
+                        event.code,
+                        event.msg.as_ref().map(|msg| msg.borrow()).unwrap_or("<empty>")
+                    );
+                    for (_, snd) in state.pending.drain() {
+                        let _ = snd.send(Err(error.clone()));
+                    }
+                    state.queue.clear();
+                    if let Some(out) = &state.out {
+                        out.close(ws::CloseCode::Normal)?;
+                    }
+                    bail!("authentication failed: `{}`", error);
+                }
+
+                state.authenticated = true;
+                let queue = std::mem::replace(&mut state.queue, Vec::new());
+                if let Some(out) = &state.out {
+                    for payload in queue {
+                        self.raw_log.send(out, payload)?;
+                    }
+                }
+                return Ok(());
+            }
+            Value::Array(message) => message,
+            other => bail!("unexpected message: `{}`", other),
+        };
+
+        // `[0, "n", [MTS, TYPE, MESSAGE_ID, _, NOTIFY_INFO, CODE, STATUS, TEXT]]`
+        if field(&message, 1)?.as_str() != Some("n") {
+            return Ok(());
+        }
+
+        let notification = array(field(&message, 2)?)?;
+        let kind = match string(field(notification, 1)?)? {
+            "on-req" => RequestKind::Order,
+            "oc-req" => RequestKind::Cancel,
+            _ => return Ok(()),
+        };
+
+        // The order array carries the client order id at index 2.
+        let cid = client_order_id(field(array(field(notification, 4)?)?, 2)?)?;
+        let response = match string(field(notification, 6)?)? {
+            "SUCCESS" => Ok(timestamp(field(notification, 0)?)?),
+            _ => Err(RestError::rejected(
+                field(notification, 5)?.as_i64().map(|code| code as i32),
+                field(notification, 7)?.as_str().unwrap_or("<empty>")
+            )),
+        };
+
+        let mut state = self.trading.state.lock().unwrap();
+        if kind == RequestKind::Cancel && response.is_ok() {
+            state.order_dates.remove(&cid);
+        }
+
+        if let Some(snd) = state.pending.remove(&(kind, cid)) {
+            let _ = snd.send(response);
+        }
+        Ok(())
+    }
+}
+
+impl wss::HandlerImpl<!> for TradingHandlerImpl {
+    fn on_open(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        self.raw_log.send_secret(out, authentication(&self.trading.keys), "auth")
+    }
+
+    fn on_message(&mut self, text: &str, _: &UnboundedSender<!>) -> Result<(), failure::Error> {
+        self.parse_message(text)
+    }
+
+    fn on_ping(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        // Only the connection thread and this handler are left once all the clients
+        // have been dropped.
+        if Arc::strong_count(&self.trading) <= 2 {
+            debug!("client dropped, closing the input channel");
+            return out.close(ws::CloseCode::Normal);
+        }
+        Ok(())
+    }
+}
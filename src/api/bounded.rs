@@ -0,0 +1,135 @@
+//! A module defining a bounded notification stream.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use futures::prelude::*;
+use futures::task::{self, Task};
+use log::warn;
+use crate::api::Notification;
+
+struct Shared {
+    queue: VecDeque<Notification>,
+    capacity: usize,
+    dropped: usize,
+    task: Option<Task>,
+    done: bool,
+}
+
+impl Shared {
+    fn push(&mut self, notif: Notification) {
+        if self.queue.len() >= self.capacity {
+            let oldest_book_update = self.queue.iter().position(|notif| {
+                if let Notification::LimitUpdates(..) = notif { true } else { false }
+            });
+
+            // Book snapshots, trades and order notifications are never dropped, hence the
+            // queue may temporarily hold more than `capacity` notifications.
+            if let Some(index) = oldest_book_update {
+                self.queue.remove(index);
+                self.dropped += 1;
+                warn!("bounded stream is full, dropped {} book updates so far", self.dropped);
+            }
+        }
+        self.queue.push_back(notif);
+    }
+
+    fn notify(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.notify();
+        }
+    }
+}
+
+/// A notification stream holding at most `capacity` pending notifications. When full,
+/// the oldest pending `Notification::LimitUpdates` is dropped in order to make room,
+/// but book snapshots, trades and order notifications are never dropped.
+///
+/// # Note
+/// Dropping limit updates means that an order book maintained from this stream will
+/// go out of sync: this stream is meant for consumers which favor bounded memory
+/// usage over an exact order book.
+///
+/// This is not backed by `futures::sync::mpsc::channel`: a full bounded channel can
+/// only reject or delay the incoming notification, whereas making room requires
+/// removing the oldest limit update from the pending ones.
+pub struct BoundedStream {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl BoundedStream {
+    /// Wrap `stream` into a `BoundedStream` with the given `capacity`. This spawns a
+    /// background thread continuously draining `stream`, which stops when either
+    /// `stream` ends or the `BoundedStream` is dropped.
+    ///
+    /// # Panics
+    /// Panic if `capacity` is `0`.
+    pub fn new<S>(stream: S, capacity: usize) -> Self
+        where S: Stream<Item = Notification, Error = ()> + Send + 'static
+    {
+        use std::thread;
+
+        if capacity == 0 {
+            panic!("`capacity` cannot be 0");
+        }
+
+        let shared = Arc::new(Mutex::new(Shared {
+            queue: VecDeque::with_capacity(capacity),
+            capacity,
+            dropped: 0,
+            task: None,
+            done: false,
+        }));
+        let weak = Arc::downgrade(&shared);
+
+        thread::spawn(move || {
+            let fut = stream.for_each(|notif| {
+                match weak.upgrade() {
+                    Some(shared) => {
+                        let mut shared = shared.lock().unwrap();
+                        shared.push(notif);
+                        shared.notify();
+                        Ok(())
+                    }
+
+                    // The `BoundedStream` object was dropped.
+                    None => Err(()),
+                }
+            });
+
+            use tokio::runtime::current_thread;
+            let _ = current_thread::block_on_all(fut);
+
+            if let Some(shared) = weak.upgrade() {
+                let mut shared = shared.lock().unwrap();
+                shared.done = true;
+                shared.notify();
+            }
+        });
+
+        BoundedStream {
+            shared,
+        }
+    }
+
+    /// Return the number of `Notification::LimitUpdates` dropped so far.
+    pub fn dropped(&self) -> usize {
+        self.shared.lock().unwrap().dropped
+    }
+}
+
+impl Stream for BoundedStream {
+    type Item = Notification;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Notification>, ()> {
+        let mut shared = self.shared.lock().unwrap();
+        match shared.queue.pop_front() {
+            Some(notif) => Ok(Async::Ready(Some(notif))),
+            None if shared.done => Ok(Async::Ready(None)),
+            None => {
+                shared.task = Some(task::current());
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Compatibility shims exposing the futures 0.1 based API as `std::future::Future` and
+//! futures 0.3 streams, so that it can be used with `async`/`await`. Requires the `compat`
+//! feature.
+//!
+//! # Note
+//! REST requests are still driven by tokio 0.1, hence the futures returned here must be
+//! polled from within a tokio 0.1 runtime, e.g. by bridging them back with
+//! `futures03::compat::Compat`.
+
+use std::future::Future;
+use futures03::compat::{Future01CompatExt, Stream01CompatExt};
+use futures03::future::ready;
+use futures03::stream::StreamExt;
+use crate::api::{self, ApiClient, Balances, Cancel, CancelAck, Order, OrderAck};
+use crate::api::symbol::WithSymbol;
+use crate::api::timestamp::Timestamped;
+
+/// Send an order through `client`, see `ApiClient::order`.
+pub fn order<C: ApiClient>(client: &C, order: WithSymbol<&Order>)
+    -> impl Future<Output = Result<Timestamped<OrderAck>, api::errors::OrderError>>
+{
+    client.order(order).compat()
+}
+
+/// Send a cancel order through `client`, see `ApiClient::cancel`.
+pub fn cancel<C: ApiClient>(client: &C, cancel: WithSymbol<&Cancel>)
+    -> impl Future<Output = Result<Timestamped<CancelAck>, api::errors::CancelError>>
+{
+    client.cancel(cancel).compat()
+}
+
+/// Retrieve the balances of the account of `client`, see `ApiClient::balances`.
+pub fn balances<C: ApiClient>(client: &C)
+    -> impl Future<Output = Result<Balances, api::errors::Error>>
+{
+    client.balances().compat()
+}
+
+/// Convert a notification stream, e.g. `ApiClient::Stream`, into a futures 0.3 stream.
+/// The notification streams of this crate never fail, an error would end the stream.
+pub fn stream<S>(stream: S) -> impl futures03::Stream<Item = S::Item>
+    where S: futures::Stream<Error = ()>
+{
+    stream.compat()
+        .take_while(|notif| ready(notif.is_ok()))
+        .filter_map(|notif| ready(notif.ok()))
+}
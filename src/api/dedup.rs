@@ -0,0 +1,82 @@
+//! A module defining a stream combinator filtering out the no-op limit updates of a
+//! notification stream.
+
+use futures::prelude::*;
+use futures::try_ready;
+use crate::order_book::OrderBook;
+use crate::api::Notification;
+
+#[must_use = "streams do nothing unless polled"]
+/// A stream combinator filtering out the limit updates of the underlying notification stream
+/// which leave the size at their limit unchanged, e.g. a size update equal to the current
+/// size, or the removal of a limit which is not in the book. A shadow `OrderBook` is
+/// maintained for that purpose.
+///
+/// `Notification::LimitUpdates` left without any update are dropped altogether. Book
+/// snapshots and resyncs reset the shadow order book and are forwarded unchanged, as are
+/// all the other notifications.
+pub struct Dedup<S> {
+    stream: S,
+    order_book: OrderBook,
+}
+
+impl<S> Dedup<S> {
+    /// Filter out the no-op limit updates of `stream`.
+    pub fn new(stream: S) -> Self {
+        Dedup {
+            stream,
+            order_book: OrderBook::new(),
+        }
+    }
+
+    /// Return the shadow order book, as rebuilt from the notifications forwarded so far.
+    pub fn order_book(&self) -> &OrderBook {
+        &self.order_book
+    }
+}
+
+impl<S> Stream for Dedup<S> where S: Stream<Item = Notification> {
+    type Item = Notification;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Notification>, S::Error> {
+        loop {
+            let notif = match try_ready!(self.stream.poll()) {
+                Some(Notification::LimitUpdates(updates)) => {
+                    let order_book = &mut self.order_book;
+                    let updates: Vec<_> = updates.into_iter().filter(|update| {
+                        let changed = order_book.size_at_limit(update.side, update.price)
+                            != update.size;
+                        if changed {
+                            order_book.update(update.into_inner());
+                        }
+                        changed
+                    }).collect();
+
+                    if updates.is_empty() {
+                        continue;
+                    }
+                    Notification::LimitUpdates(updates)
+                }
+
+                Some(Notification::BookSnapshot(updates)) => {
+                    self.order_book = OrderBook::new();
+                    self.order_book.update_batch(
+                        updates.iter().map(|update| update.into_inner())
+                    );
+                    Notification::BookSnapshot(updates)
+                }
+
+                Some(Notification::BookResync) => {
+                    self.order_book = OrderBook::new();
+                    Notification::BookResync
+                }
+
+                Some(notif) => notif,
+
+                None => return Ok(Async::Ready(None)),
+            };
+            return Ok(Async::Ready(Some(notif)));
+        }
+    }
+}
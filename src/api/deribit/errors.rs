@@ -0,0 +1,82 @@
+//! A module defining error types specific to Deribit.
+
+use failure_derive::Fail;
+use serde_derive::Deserialize;
+use std::fmt;
+use std::borrow::Cow;
+use crate::api;
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+pub(super) struct DeribitRpcError<'a> {
+    code: i64,
+    message: Cow<'a, str>, // error message can contain escaped characters
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Fail)]
+/// An error returned by Deribit JSON-RPC API.
+pub struct RpcError {
+    /// Internal Deribit error code, e.g. `10009`: see API documentation.
+    pub error_code: i64,
+
+    /// Error message, e.g. `"not_enough_funds"`.
+    pub error_msg: String,
+}
+
+impl api::errors::ErrorKinded<!> for RpcError {
+    fn kind(&self) -> api::errors::RestErrorKind<!> {
+        match self.error_code {
+            // `too_many_requests`
+            10028 => api::errors::RestErrorKind::TooManyRequests,
+
+            // `internal_server_error`, `temporarily_unavailable`, `timed_out`
+            11094 | 13028 | 13888 => api::errors::RestErrorKind::OtherSide,
+
+            _ => api::errors::RestErrorKind::InvalidRequest,
+        }
+    }
+}
+
+impl api::errors::ErrorKinded<api::errors::CancelErrorKind> for RpcError {
+    fn kind(&self) -> api::errors::RestErrorKind<api::errors::CancelErrorKind> {
+        match self.error_code {
+            // `order_not_found`, `not_open_order`
+            10004 | 11044 => api::errors::RestErrorKind::Specific(
+                api::errors::CancelErrorKind::UnknownOrder
+            ),
+            _ => <Self as api::errors::ErrorKinded<!>>::kind(self).into(),
+        }
+    }
+}
+
+impl api::errors::ErrorKinded<api::errors::OrderErrorKind> for RpcError {
+    fn kind(&self) -> api::errors::RestErrorKind<api::errors::OrderErrorKind> {
+        match self.error_code {
+            // `not_enough_funds`
+            10009 => api::errors::RestErrorKind::Specific(
+                api::errors::OrderErrorKind::InsufficientBalance
+            ),
+
+            // `post_only_reject`
+            11054 => api::errors::RestErrorKind::Specific(
+                api::errors::OrderErrorKind::WouldTakeLiquidity
+            ),
+
+            _ => <Self as api::errors::ErrorKinded<!>>::kind(self).into(),
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`{}` [error_code = {}]", self.error_msg, self.error_code)
+    }
+}
+
+impl<'a> From<DeribitRpcError<'a>> for RpcError {
+    fn from(error: DeribitRpcError<'a>) -> Self {
+        RpcError {
+            error_code: error.code,
+            error_msg: error.message.into_owned(),
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Implementation of `ApiClient` for the Deribit API.
+//!
+//! Deribit speaks JSON-RPC 2.0 over WebSocket: unlike the other clients, requests like
+//! `order`, `cancel` or `balances` are calls sent over a WebSocket connection shared by
+//! the clones of a `Client`, each response being matched with its request by id.
+//!
+//! Deribit amounts are expressed in USD for inverse futures, and in the base currency
+//! otherwise. Sizes are instead expressed in contracts, the size tick of a symbol being
+//! the minimum trade amount: e.g. a size of 3 ticks is 3 contracts of 10 USD for
+//! `BTC-PERPETUAL`, and 0.3 contract of 1 BTC for BTC options.
+
+pub mod errors;
+mod rpc;
+mod requests;
+mod wss;
+mod test;
+
+use openssl::pkey::{PKey, Private};
+use openssl::{sign::Signer, hash::MessageDigest};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use futures::prelude::*;
+use serde_derive::{Serialize, Deserialize};
+use log::debug;
+use crate::tick::{self, Tick, TickUnit, RoundingMode, Size};
+use crate::api::{
+    self,
+    Params,
+    ApiClient,
+    GenerateOrderId,
+    NotificationFlags,
+    Order,
+    OrderAck,
+    Cancel,
+    CancelAck,
+    Balances,
+    DepositAddress,
+    Ticker,
+    Trade,
+    WithdrawAck,
+    WithdrawClient,
+};
+use crate::api::symbol::{self, Symbol, WithSymbol};
+use crate::api::timestamp::{timestamp_ms, Timestamped};
+use self::rpc::{RpcConnection, RequestId};
+
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// A Deribit key pair: client id + client secret.
+pub struct KeyPair {
+    client_id: String,
+    client_secret: String,
+}
+
+impl KeyPair {
+    /// Return a new key pair.
+    pub fn new(client_id: String, client_secret: String) -> Self {
+        KeyPair {
+            client_id,
+            client_secret,
+        }
+    }
+}
+
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyPair")
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+struct Keys {
+    client_id: String,
+    client_secret: Arc<PKey<Private>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+struct DeribitAuthentication<'a> {
+    grant_type: &'a str,
+    client_id: &'a str,
+    timestamp: u64,
+    signature: String,
+    nonce: String,
+    data: &'a str,
+}
+
+impl Keys {
+    /// Return the hex encoded HMAC-SHA256 signature of `payload`.
+    fn sign(&self, payload: &str) -> String {
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.client_secret).unwrap();
+        signer.update(payload.as_bytes()).unwrap();
+        hex::encode(&signer.sign_to_vec().unwrap())
+    }
+
+    /// Return the `public/auth` request authenticating the connection it is sent on.
+    fn authentication(&self, id: RequestId) -> String {
+        let timestamp = timestamp_ms();
+        let nonce = uuid::Uuid::new_v4().simple().to_string();
+
+        let auth = DeribitAuthentication {
+            grant_type: "client_signature",
+            client_id: &self.client_id,
+            timestamp,
+            signature: self.sign(&format!("{}\n{}\n", timestamp, nonce)),
+            nonce,
+            data: "",
+        };
+        rpc::request_payload(id, "public/auth", auth)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+/// Information about a Deribit instrument which does not fit in a `Symbol`.
+struct Instrument {
+    symbol: Symbol,
+
+    /// Amount of one contract, e.g. 10 (USD) for `BTC-PERPETUAL`.
+    contract_size: f64,
+
+    /// Whether amounts are expressed in USD, i.e. for inverse futures.
+    inverse: bool,
+
+    /// Currency in which commissions are paid, e.g. `"BTC"`.
+    settlement_currency: String,
+}
+
+impl Instrument {
+    /// Convert a Deribit amount to a size in contracts.
+    fn size(&self, amount: f64) -> Result<Size, tick::ConversionError> {
+        let contracts = amount / self.contract_size;
+        Ok(Size(self.symbol.size_tick().from_f64(contracts, RoundingMode::Nearest)?))
+    }
+
+    /// Convert a size in contracts to a Deribit amount.
+    fn amount(&self, size: TickUnit) -> f64 {
+        let ticks_per_unit = self.symbol.size_tick().ticks_per_unit() as f64;
+        let amount = size as f64 * self.contract_size / ticks_per_unit;
+
+        // Get rid of floating point errors, Deribit amounts have at most 8 decimals.
+        (amount * 1e8).round() / 1e8
+    }
+
+    /// Value of `amount` traded at `price`, in the currency which adds up across trades:
+    /// the base currency for inverse futures, the quote currency otherwise.
+    fn value(&self, amount: f64, price: f64) -> f64 {
+        if self.inverse {
+            amount / price
+        } else {
+            amount * price
+        }
+    }
+
+    /// Price at which `amount` was traded for `value`, see `Instrument::value`.
+    fn price(&self, amount: f64, value: f64) -> f64 {
+        if self.inverse {
+            amount / value
+        } else {
+            value / amount
+        }
+    }
+}
+
+/// Return the tick matching a Deribit tick size given as a float, e.g. `Tick::new(2)` for
+/// `0.5` and `2.5`, or `None` if it has more than 8 decimals.
+fn tick_from_f64(tick_size: f64) -> Option<Tick> {
+    fn gcd(a: u64, b: u64) -> u64 {
+        if b == 0 { a } else { gcd(b, a % b) }
+    }
+
+    let mut pow = 1u64;
+    for _ in 0..=8 {
+        let scaled = tick_size * pow as f64;
+        let rounded = scaled.round();
+        if rounded >= 1. && (scaled - rounded).abs() < 1e-6 {
+            return Some(Tick::new(pow / gcd(pow, rounded as u64)));
+        }
+        pow *= 10;
+    }
+    None
+}
+
+#[derive(Clone)]
+/// A Deribit API client.
+///
+/// Both the requests and the notification streams go through `Params::streaming_endpoint`,
+/// `Params::rest_endpoint` is hence unused.
+pub struct Client {
+    params: Params,
+    keys: Option<Keys>,
+    rpc: RpcConnection,
+    instruments: Arc<HashMap<String, Instrument>>,
+
+    /// Settlement currencies of the instruments, whose balances are retrieved by
+    /// `ApiClient::balances`.
+    currencies: Arc<Vec<String>>,
+    log_raw: bool,
+    /// Custom order id generation scheme, see `Client::with_order_id_generator`.
+    order_id_generator: Option<api::OrderIdGenerator>,
+}
+
+impl Client {
+    /// Create a new Deribit API client with given `params`. If `key_pair` is not
+    /// `None`, this will enable performing private requests and will forward the
+    /// user data stream.
+    ///
+    /// # Note
+    /// This method will block, fetching the available instruments from Deribit.
+    pub fn new(params: Params, key_pair: Option<KeyPair>) -> Result<Self, failure::Error> {
+        let keys = match key_pair {
+            Some(pair) => Some(Keys {
+                client_id: pair.client_id,
+                client_secret: Arc::new(PKey::hmac(pair.client_secret.as_bytes())?),
+            }),
+            None => None,
+        };
+
+        let rpc = RpcConnection::new(
+            params.streaming_endpoint.clone(),
+            keys.clone(),
+            params.rate_limiter(),
+            api::wss::RawLog::new(false, "deribit")
+        );
+
+        let mut client = Client {
+            params,
+            keys,
+            rpc,
+            instruments: Arc::new(HashMap::new()),
+            currencies: Arc::new(Vec::new()),
+            log_raw: false,
+            order_id_generator: None,
+        };
+
+        use tokio::runtime::current_thread;
+        debug!("requesting instruments");
+        let instruments = current_thread::Runtime::new()?.block_on(client.get_instruments())?;
+        debug!("received instruments");
+
+        let mut currencies: Vec<_> = instruments.values()
+            .map(|instrument| instrument.settlement_currency.clone())
+            .collect();
+        currencies.sort();
+        currencies.dedup();
+
+        client.instruments = Arc::new(instruments);
+        client.currencies = Arc::new(currencies);
+        Ok(client)
+    }
+
+    /// Create a new Deribit API client to the test API, see `Params::deribit_testnet` and
+    /// `Client::new`.
+    pub fn new_testnet(key_pair: Option<KeyPair>) -> Result<Self, failure::Error> {
+        Self::new(Params::deribit_testnet(), key_pair)
+    }
+
+    /// Log every raw inbound text frame of the notification streams and of the JSON-RPC
+    /// connection, as well as every outbound payload, at `trace` level with `"deribit"`
+    /// as the log target. Disabled by default.
+    pub fn with_log_raw(self, log_raw: bool) -> Self {
+        let rpc = RpcConnection::new(
+            self.params.streaming_endpoint.clone(),
+            self.keys.clone(),
+            self.params.rate_limiter(),
+            api::wss::RawLog::new(log_raw, "deribit")
+        );
+
+        Client {
+            log_raw,
+            rpc,
+            ..self
+        }
+    }
+
+    /// Use `generator` instead of `GenerateOrderId::new_order_id` for generating order ids
+    /// from a hint, see `Order::with_order_id_from`.
+    pub fn with_order_id_generator<F>(self, generator: F) -> Self
+        where F: Fn(&str) -> String + Send + Sync + 'static
+    {
+        Client {
+            order_id_generator: Some(Arc::new(generator)),
+            ..self
+        }
+    }
+
+    /// Return the instrument named like `symbol`.
+    fn instrument(&self, symbol: Symbol) -> Option<&Instrument> {
+        self.instruments.get(&symbol::canonical_name(symbol.name()))
+    }
+}
+
+impl ApiClient for Client {
+    type Stream = api::stream::StreamHandle;
+
+    fn find_symbol(&self, symbol: &str) -> Option<Symbol> {
+        self.instruments.get(&symbol::canonical_name(symbol))
+            .map(|instrument| instrument.symbol)
+    }
+
+    fn generate_order_id(&self, hint: &str) -> String {
+        match &self.order_id_generator {
+            Some(generator) => generator(hint),
+            None => Self::new_order_id(hint),
+        }
+    }
+
+    fn ticker(&self, symbol: Symbol)
+        -> Box<dyn Future<Item = Ticker, Error = api::errors::Error> + Send + 'static>
+    {
+        self.ticker_impl(symbol)
+    }
+
+    fn recent_trades(&self, symbol: Symbol, limit: usize)
+        -> Box<dyn Future<Item = Vec<Timestamped<Trade>>, Error = api::errors::Error> + Send + 'static>
+    {
+        self.recent_trades_impl(symbol, limit)
+    }
+
+    fn stream_with_flags(&self, symbol: Symbol, flags: NotificationFlags) -> Self::Stream {
+        self.new_stream(symbol, flags)
+    }
+
+    fn order(&self, order: WithSymbol<&Order>)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
+    {
+        self.order_impl(order)
+    }
+
+    fn cancel(&self, cancel: WithSymbol<&Cancel>)
+        -> Box<dyn Future<Item = Timestamped<CancelAck>, Error = api::errors::CancelError> + Send + 'static>
+    {
+        Box::new(self.cancel_impl(cancel))
+    }
+
+    fn replace(&self, replaced: WithSymbol<&Order>, new: &Order)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
+    {
+        api::replace_by_cancel(self, replaced, new)
+    }
+
+    fn ping(&self)
+        -> Box<dyn Future<Item = Timestamped<()>, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.ping_impl())
+    }
+
+    fn balances(&self)
+        -> Box<dyn Future<Item = Balances, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.balances_impl())
+    }
+
+    fn deposit_address(&self, asset: &str)
+        -> Box<dyn Future<Item = DepositAddress, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.deposit_address_impl(asset))
+    }
+}
+
+impl WithdrawClient for Client {
+    fn withdraw(&self, _: &str, _: &str, _: &str, _: Option<&str>)
+        -> Box<dyn Future<Item = WithdrawAck, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(
+            Err(api::errors::RestErrorKind::Unsupported.into())
+                .map_err(api::errors::ApiError::RestError)
+                .into_future()
+        )
+    }
+}
+
+impl GenerateOrderId for Client {
+    fn new_order_id(hint: &str) -> String {
+        hint.to_owned()
+    }
+}
@@ -0,0 +1,492 @@
+use failure::format_err;
+use futures::prelude::*;
+use futures::future;
+use log::error;
+use std::collections::HashMap;
+use serde_derive::{Serialize, Deserialize};
+use crate::Side;
+use crate::tick::{Tick, Tickable, TickUnit, Price, RoundingMode};
+use crate::api::{
+    self,
+    TimeInForce,
+    OrderType,
+    Order,
+    OrderAck,
+    Cancel,
+    CancelAck,
+    Balance,
+    Balances,
+};
+use crate::api::symbol::{self, Symbol, WithSymbol};
+use crate::api::timestamp::{Timestamped, IntoTimestamped};
+use crate::api::deribit::{Client, Instrument, tick_from_f64};
+use crate::api::deribit::rpc::parse_result;
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub(super) struct DeribitOrder<'a> {
+    instrument_name: &'a str,
+    amount: f64,
+    #[serde(rename = "type")]
+    type_: &'a str,
+    label: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_in_force: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trigger: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trigger_price: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+struct DeribitInstrumentName<'a> {
+    instrument_name: &'a str,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+struct DeribitCurrency<'a> {
+    currency: &'a str,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+struct DeribitLabel<'a> {
+    label: &'a str,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+struct DeribitInstrumentsRequest<'a> {
+    currency: &'a str,
+    expired: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+struct DeribitTradesRequest<'a> {
+    instrument_name: &'a str,
+    count: usize,
+    sorting: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Deserialize)]
+struct DeribitInstrument<'a> {
+    instrument_name: &'a str,
+    kind: &'a str,
+    tick_size: f64,
+    contract_size: f64,
+    min_trade_amount: f64,
+    quote_currency: &'a str,
+    settlement_currency: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Deserialize)]
+struct DeribitAccountSummary<'a> {
+    currency: &'a str,
+    balance: f64,
+    available_funds: f64,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Deserialize)]
+struct DeribitStats {
+    volume: Option<f64>,
+    volume_usd: Option<f64>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Deserialize)]
+struct DeribitTicker {
+    best_bid_price: Option<f64>,
+    best_ask_price: Option<f64>,
+    last_price: Option<f64>,
+    stats: DeribitStats,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Deserialize)]
+/// A public trade, `direction` is the side of the taker.
+pub(super) struct DeribitTrade<'a> {
+    pub(super) price: f64,
+    pub(super) amount: f64,
+    pub(super) direction: &'a str,
+    pub(super) timestamp: u64,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+struct DeribitTrades<'a> {
+    #[serde(borrow)]
+    trades: Vec<DeribitTrade<'a>>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct DeribitDepositAddress<'a> {
+    address: &'a str,
+}
+
+trait AsStr {
+    fn as_str(self) -> &'static str;
+}
+
+impl AsStr for TimeInForce {
+    fn as_str(self) -> &'static str {
+        match self {
+            TimeInForce::GoodTilCanceled => "good_til_cancelled",
+            TimeInForce::FillOrKilll => "fill_or_kill",
+            TimeInForce::ImmediateOrCancel => "immediate_or_cancel",
+        }
+    }
+}
+
+impl AsStr for OrderType {
+    fn as_str(self) -> &'static str {
+        match self {
+            OrderType::Limit | OrderType::LimitMaker => "limit",
+            OrderType::Market => "market",
+            OrderType::StopLoss => "stop_market",
+            OrderType::StopLossLimit => "stop_limit",
+        }
+    }
+}
+
+/// Parse a deposit address returned by Deribit, `null` meaning that there is no address.
+fn deposit_address(result: &serde_json::Value)
+    -> Result<Option<api::DepositAddress>, api::errors::Error>
+{
+    let address: Option<DeribitDepositAddress<'_>> = parse_result(result)?;
+    Ok(address.map(|address| api::DepositAddress {
+        address: address.address.to_owned(),
+        tag: None,
+    }))
+}
+
+/// Return the maker side of a trade whose taker `direction` is given.
+pub(super) fn maker_side(direction: &str) -> Option<Side> {
+    match direction {
+        "buy" => Some(Side::Ask),
+        "sell" => Some(Side::Bid),
+        _ => None,
+    }
+}
+
+/// Return the floating point value sent to Deribit for a price of an order which passed
+/// `Order::validate`.
+fn number(value: &Tickable, tick: Tick) -> f64 {
+    value.unticked(tick).parse().expect("validated order value")
+}
+
+/// Return the method and the parameters of the request sending `order`, which passed
+/// `Order::validate` and is not funds-based, labeled with `label`.
+pub(super) fn order_request<'a>(
+    order: &WithSymbol<&'a Order>,
+    instrument: &'a Instrument,
+    label: &'a str
+) -> (&'static str, DeribitOrder<'a>)
+{
+    let symbol = order.symbol();
+    let market = order.type_.is_market();
+    let stop_price = order.stop_price.as_ref()
+        .filter(|_| order.type_.is_stop())
+        .map(|stop_price| number(stop_price, symbol.price_tick()));
+
+    let deribit_order = DeribitOrder {
+        instrument_name: instrument.symbol.name(),
+        amount: instrument.amount(order.size.ticked(symbol.size_tick())),
+        type_: order.type_.as_str(),
+        label,
+        price: if market { None } else { Some(number(&order.price, symbol.price_tick())) },
+        time_in_force: if market { None } else { Some(order.time_in_force.as_str()) },
+        post_only: if market { None } else { Some(order.type_ == OrderType::LimitMaker) },
+        trigger: stop_price.map(|_| "last_price"),
+        trigger_price: stop_price,
+    };
+
+    let method = match order.side {
+        Side::Bid => "private/buy",
+        Side::Ask => "private/sell",
+    };
+    (method, deribit_order)
+}
+
+/// Parse the result of `private/cancel_by_label`, i.e. the number of canceled orders.
+pub(super) fn cancel_ack(result: &serde_json::Value)
+    -> Result<Timestamped<CancelAck>, api::errors::CancelError>
+{
+    let canceled: u64 = parse_result(result)?;
+    if canceled == 0 {
+        Err(api::errors::RestErrorKind::Specific(
+            api::errors::CancelErrorKind::UnknownOrder
+        ).into()).map_err(api::errors::ApiError::RestError)?;
+    }
+    Ok(CancelAck.timestamped())
+}
+
+/// Return an error for a symbol which is not a Deribit instrument.
+fn unknown_instrument<T, K: api::errors::ErrorKind>()
+    -> Box<dyn Future<Item = T, Error = api::errors::ApiError<K>> + Send + 'static>
+    where T: Send + 'static
+{
+    Box::new(
+        Err(api::errors::RestErrorKind::InvalidRequest.into())
+            .map_err(api::errors::ApiError::RestError)
+            .into_future()
+    )
+}
+
+impl Client {
+    crate fn order_impl(&self, order: WithSymbol<&Order>)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
+    {
+        if let Err(err) = order.validate(&order.symbol()) {
+            return Box::new(Err(err.into()).into_future());
+        }
+
+        if order.funds.is_some() {
+            return Box::new(
+                Err(api::errors::RestErrorKind::Unsupported.into())
+                    .map_err(api::errors::ApiError::RestError)
+                    .into_future()
+            );
+        }
+
+        let symbol = order.symbol();
+        let instrument = match self.instrument(symbol) {
+            Some(instrument) => instrument,
+            None => return unknown_instrument(),
+        };
+
+        // The label identifies the order in the notifications, like a client order id.
+        let label = match &order.order_id {
+            Some(order_id) => order_id.clone(),
+            None => uuid::Uuid::new_v4().simple().to_string(),
+        };
+
+        let (method, deribit_order) = order_request(&order, instrument, &label);
+
+        // The response carries the Deribit order id, we keep using the label.
+        let fut = self.rpc.call(method, deribit_order).and_then(move |_| {
+            Ok(OrderAck {
+                order_id: label,
+            }.timestamped())
+        });
+        Box::new(fut)
+    }
+
+    crate fn cancel_impl(&self, cancel: WithSymbol<&Cancel>)
+        -> impl Future<Item = Timestamped<CancelAck>, Error = api::errors::CancelError> + Send + 'static
+    {
+        let params = DeribitLabel {
+            label: cancel.order_id(),
+        };
+
+        // Cancels all the orders with this label, which identifies a single order unless
+        // the same order id was used several times.
+        self.rpc.call("private/cancel_by_label", params).and_then(|result| cancel_ack(&result))
+    }
+
+    crate fn ping_impl(&self)
+        -> impl Future<Item = Timestamped<()>, Error = api::errors::Error> + Send + 'static
+    {
+        self.rpc.call("public/get_time", ()).and_then(|result| {
+            let timestamp = parse_result(&result)?;
+            Ok(().with_timestamp(timestamp))
+        })
+    }
+
+    crate fn balances_impl(&self)
+        -> impl Future<Item = Balances, Error = api::errors::Error> + Send + 'static
+    {
+        let summaries = self.currencies.iter().map(|currency| {
+            let params = DeribitCurrency {
+                currency,
+            };
+            self.rpc.call("private/get_account_summary", params)
+        }).collect::<Vec<_>>();
+
+        future::join_all(summaries).and_then(|results| {
+            let mut balances = Balances::new();
+            for result in &results {
+                let summary: DeribitAccountSummary<'_> = parse_result(result)?;
+
+                // The part of the balance which is not available is used as margin.
+                let locked = (summary.balance - summary.available_funds).max(0.);
+                balances.insert(summary.currency.to_owned(), Balance {
+                    free: summary.available_funds.to_string(),
+                    locked: locked.to_string(),
+                });
+            }
+            Ok(balances)
+        })
+    }
+
+    crate fn ticker_impl(&self, symbol: Symbol)
+        -> Box<dyn Future<Item = api::Ticker, Error = api::errors::Error> + Send + 'static>
+    {
+        let instrument = match self.instrument(symbol) {
+            Some(instrument) => instrument.clone(),
+            None => return unknown_instrument(),
+        };
+
+        let params = DeribitInstrumentName {
+            instrument_name: symbol.name(),
+        };
+
+        let fut = self.rpc.call("public/ticker", params).and_then(move |result| {
+            let ticker: DeribitTicker = parse_result(&result)?;
+
+            // Deribit sends `null` values for empty sides or if no trade happened yet.
+            let convert = |value: Option<f64>, default| {
+                value.map(|value| symbol.price_tick().from_f64(value, RoundingMode::Nearest))
+                    .unwrap_or(Ok(default))
+                    .map(Price)
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)
+            };
+
+            // The volume is given in the base currency, and in USD as well for futures.
+            let volume = if instrument.inverse {
+                ticker.stats.volume_usd
+            } else {
+                ticker.stats.volume
+            };
+
+            Ok(api::Ticker {
+                best_bid: convert(ticker.best_bid_price, 0)?,
+                best_ask: convert(ticker.best_ask_price, TickUnit::max_value())?,
+                last: convert(ticker.last_price, 0)?,
+                volume_24h: instrument.size(volume.unwrap_or(0.))
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)?,
+            })
+        });
+        Box::new(fut)
+    }
+
+    crate fn recent_trades_impl(&self, symbol: Symbol, limit: usize)
+        -> Box<dyn Future<Item = Vec<Timestamped<api::Trade>>, Error = api::errors::Error> + Send + 'static>
+    {
+        let instrument = match self.instrument(symbol) {
+            Some(instrument) => instrument.clone(),
+            None => return unknown_instrument(),
+        };
+
+        let params = DeribitTradesRequest {
+            instrument_name: symbol.name(),
+            count: limit,
+            sorting: "desc",
+        };
+
+        let fut = self.rpc.call("public/get_last_trades_by_instrument", params)
+            .and_then(move |result| {
+                let trades: DeribitTrades<'_> = parse_result(&result)?;
+
+                // Most recent trades come first.
+                trades.trades.into_iter().rev().map(|trade| {
+                    let maker_side = maker_side(trade.direction)
+                        .ok_or_else(|| format_err!("wrong side: `{}`", trade.direction).compat())
+                        .map_err(api::errors::RequestError::new)
+                        .map_err(api::errors::ApiError::RequestError)?;
+
+                    let price = symbol.price_tick().from_f64(trade.price, RoundingMode::Nearest)
+                        .map_err(api::errors::RequestError::new)
+                        .map_err(api::errors::ApiError::RequestError)?;
+
+                    Ok(api::Trade {
+                        price: Price(price),
+                        size: instrument.size(trade.amount)
+                            .map_err(api::errors::RequestError::new)
+                            .map_err(api::errors::ApiError::RequestError)?,
+                        maker_side,
+                    }.with_timestamp(trade.timestamp))
+                }).collect::<Result<_, api::errors::Error>>()
+            });
+        Box::new(fut)
+    }
+
+    crate fn deposit_address_impl(&self, asset: &str)
+        -> impl Future<Item = api::DepositAddress, Error = api::errors::Error> + Send + 'static
+    {
+        let params = DeribitCurrency {
+            currency: asset,
+        };
+        let rpc = self.rpc.clone();
+        let currency = asset.to_owned();
+
+        self.rpc.call("private/get_current_deposit_address", params).and_then(move |result| {
+            // `null` if no address was created yet for this currency, in which case we
+            // create one.
+            match deposit_address(&result) {
+                Ok(Some(address)) => future::Either::A(Ok(address).into_future()),
+                Ok(None) => {
+                    let params = DeribitCurrency {
+                        currency: &currency,
+                    };
+                    let fut = rpc.call("private/create_deposit_address", params)
+                        .and_then(move |result| {
+                            deposit_address(&result)?
+                                .ok_or_else(|| {
+                                    format_err!("no deposit address created for `{}`", currency)
+                                        .compat()
+                                })
+                                .map_err(api::errors::RequestError::new)
+                                .map_err(api::errors::ApiError::RequestError)
+                        });
+                    future::Either::B(fut)
+                }
+                Err(err) => future::Either::A(Err(err).into_future()),
+            }
+        })
+    }
+
+    pub(super) fn get_instruments(&self)
+        -> impl Future<Item = HashMap<String, Instrument>, Error = api::errors::Error> + Send + 'static
+    {
+        let params = DeribitInstrumentsRequest {
+            currency: "any",
+            expired: false,
+        };
+
+        self.rpc.call("public/get_instruments", params).and_then(|result| {
+            let products: Vec<DeribitInstrument<'_>> = parse_result(&result)?;
+
+            let mut instruments = HashMap::new();
+            for p in products {
+                let price_tick = match tick_from_f64(p.tick_size) {
+                    Some(tick) => tick,
+                    None => {
+                        error!("cannot read price tick for instrument `{}`", p.instrument_name);
+                        continue;
+                    }
+                };
+
+                // One size tick is one minimum trade amount, which must divide the contract
+                // size.
+                let ticks_per_contract = (p.contract_size / p.min_trade_amount).round();
+                if ticks_per_contract < 1.
+                    || (ticks_per_contract * p.min_trade_amount - p.contract_size).abs()
+                        > 1e-9 * p.contract_size
+                {
+                    error!("cannot read size tick for instrument `{}`", p.instrument_name);
+                    continue;
+                }
+                let size_tick = Tick::new(ticks_per_contract as TickUnit);
+
+                let symbol = match Symbol::new(p.instrument_name, price_tick, size_tick) {
+                    Some(symbol) => symbol,
+                    None => {
+                        error!("symbol name too long: `{}`", p.instrument_name);
+                        continue;
+                    }
+                };
+
+                let instrument = Instrument {
+                    symbol: symbol.with_quote_asset(p.quote_currency)
+                        .with_commission_tick(Tick::new(100_000_000)),
+                    contract_size: p.contract_size,
+                    inverse: p.kind.starts_with("future")
+                        && p.quote_currency != p.settlement_currency,
+                    settlement_currency: p.settlement_currency.to_owned(),
+                };
+                instruments.insert(symbol::canonical_name(p.instrument_name), instrument);
+            }
+            Ok(instruments)
+        })
+    }
+}
@@ -0,0 +1,323 @@
+//! A JSON-RPC 2.0 connection carrying the requests of a Deribit `Client` over WebSocket.
+
+use failure::Fail;
+use futures::prelude::*;
+use futures::sync::{mpsc, oneshot};
+use log::{debug, error};
+use serde::Serialize;
+use serde_derive::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use crate::api;
+use crate::api::errors::ErrorKinded;
+use crate::api::rate_limit::{self, RateLimiter};
+use crate::api::wss;
+use crate::api::deribit::Keys;
+use crate::api::deribit::errors::{RpcError, DeribitRpcError};
+
+/// Id of a JSON-RPC request, echoed by the matching response.
+pub(super) type RequestId = usize;
+
+/// Id reserved for the authentication request sent upon connecting, other requests are
+/// numbered from 1.
+pub(super) const AUTH_ID: RequestId = 0;
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+struct DeribitRequest<'a, P> {
+    jsonrpc: &'a str,
+    id: RequestId,
+    method: &'a str,
+    params: P,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+/// Either the response to a request, or a notification if `id` is `None`.
+pub(super) struct DeribitResponse<'a> {
+    pub(super) id: Option<RequestId>,
+    pub(super) method: Option<&'a str>,
+    #[serde(borrow)]
+    pub(super) error: Option<DeribitRpcError<'a>>,
+    pub(super) result: Option<Value>,
+}
+
+impl<'a> DeribitResponse<'a> {
+    /// Return the result of the request, `null` results being returned as `Value::Null`.
+    pub(super) fn into_result(self) -> Result<Value, RpcError> {
+        match self.error {
+            Some(error) => Err(error.into()),
+            None => Ok(self.result.unwrap_or(Value::Null)),
+        }
+    }
+}
+
+/// Return the payload calling `method` with `params`.
+pub(super) fn request_payload<P: Serialize>(id: RequestId, method: &str, params: P) -> String {
+    let request = DeribitRequest {
+        jsonrpc: "2.0",
+        id,
+        method,
+        params,
+    };
+
+    match serde_json::to_string(&request) {
+        Ok(value) => value,
+        Err(err) => {
+            panic!("failed to serialize `DeribitRequest`: `{}`", err);
+        }
+    }
+}
+
+/// Deserialize the result of a request.
+pub(super) fn parse_result<'a, T, K>(result: &'a Value) -> Result<T, api::errors::ApiError<K>>
+    where T: serde::Deserialize<'a>, K: api::errors::ErrorKind
+{
+    T::deserialize(result)
+        .map_err(api::errors::RequestError::new)
+        .map_err(api::errors::ApiError::RequestError)
+}
+
+pub(super) type Responder = oneshot::Sender<Result<Value, RpcError>>;
+
+#[derive(Default)]
+pub(super) struct State {
+    /// Sender of the current connection, if any.
+    out: Option<ws::Sender>,
+
+    /// Whether the current connection is authenticated, if needed, and can carry requests.
+    pub(super) ready: bool,
+
+    /// Whether a connection is open or being opened.
+    connected: bool,
+
+    /// Whether the `RpcConnection` was dropped, in which case the connection must close.
+    closed: bool,
+
+    /// Requests waiting for the connection to be ready.
+    pub(super) queued: Vec<String>,
+
+    /// Requests waiting for their response, by id.
+    pub(super) pending: HashMap<RequestId, Responder>,
+}
+
+struct Shared {
+    endpoint: String,
+    keys: Option<Keys>,
+    rate_limiter: Option<RateLimiter>,
+    raw_log: wss::RawLog,
+    next_id: AtomicUsize,
+
+    /// Not owned by the connection thread, so that dropping the last `RpcConnection`
+    /// closes the connection.
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Clone)]
+/// A JSON-RPC connection shared by the clones of a `Client`, opened upon the first request
+/// and authenticated with the keys of the client, if any. If the connection closes, the
+/// requests waiting for a response fail with `RestErrorKind::UnknownStatus` and the next
+/// request opens a new connection.
+pub(super) struct RpcConnection {
+    shared: Arc<Shared>,
+}
+
+impl RpcConnection {
+    pub(super) fn new(
+        endpoint: String,
+        keys: Option<Keys>,
+        rate_limiter: Option<RateLimiter>,
+        raw_log: wss::RawLog
+    ) -> Self
+    {
+        RpcConnection {
+            shared: Arc::new(Shared {
+                endpoint,
+                keys,
+                rate_limiter,
+                raw_log,
+                next_id: AtomicUsize::new(AUTH_ID + 1),
+                state: Arc::new(Mutex::new(State::default())),
+            }),
+        }
+    }
+
+    /// Call `method` with `params`, and return the result. The request is sent once
+    /// the rate limiter allows it.
+    pub(super) fn call<K, P>(&self, method: &str, params: P)
+        -> Box<dyn Future<Item = Value, Error = api::errors::ApiError<K>> + Send + 'static>
+        where K: api::errors::ErrorKind, RpcError: ErrorKinded<K>, P: Serialize
+    {
+        let id = self.shared.next_id.fetch_add(1, Ordering::SeqCst);
+        let payload = request_payload(id, method, params);
+        let shared = self.shared.clone();
+
+        let fut = rate_limit::acquire(self.shared.rate_limiter.as_ref(), 1)
+            .then(move |_| shared.send(id, payload))
+            .then(|response| match response {
+                Ok(Ok(result)) => Ok(result),
+                Ok(Err(error)) => {
+                    let kind = error.kind();
+                    Err(api::errors::ApiError::RestError(error.context(kind).into()))
+                }
+
+                // The connection closed before the response was received.
+                Err(oneshot::Canceled) => Err(api::errors::ApiError::RestError(
+                    api::errors::RestErrorKind::UnknownStatus.into()
+                )),
+            });
+        Box::new(fut)
+    }
+}
+
+impl Shared {
+    /// Send `payload`, or queue it if the connection is not ready yet.
+    fn send(&self, id: RequestId, payload: String) -> oneshot::Receiver<Result<Value, RpcError>> {
+        let (snd, rcv) = oneshot::channel();
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        state.pending.insert(id, snd);
+
+        match &state.out {
+            Some(out) if state.ready => {
+                // The connection is closing, the request will fail.
+                if self.raw_log.send(out, payload).is_err() {
+                    state.pending.remove(&id);
+                }
+            }
+
+            _ => {
+                state.queued.push(payload);
+                if !state.connected {
+                    state.connected = true;
+                    self.connect();
+                }
+            }
+        }
+        rcv
+    }
+
+    fn connect(&self) {
+        let state = self.state.clone();
+        let endpoint = self.endpoint.clone();
+        let keys = self.keys.clone();
+        let raw_log = self.raw_log;
+
+        thread::spawn(move || {
+            debug!("initiating JSON-RPC connection");
+
+            // The connection does not forward any notification.
+            let (snd, _) = mpsc::unbounded::<!>();
+
+            if let Err(err) = ws::connect(endpoint, |out| {
+                let handler = RpcHandler::new(state.clone(), keys.clone(), raw_log, &out);
+                wss::Handler::new(out, snd.clone(), wss::KeepAlive::True, raw_log, handler)
+            })
+            {
+                error!("JSON-RPC connection terminated with error: `{}`", err);
+            }
+
+            // Dropping the responders fails the pending requests.
+            let mut state = state.lock().unwrap();
+            state.out = None;
+            state.ready = false;
+            state.connected = false;
+            state.queued.clear();
+            state.pending.clear();
+        });
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        if let Some(out) = &state.out {
+            let _ = out.shutdown();
+        }
+    }
+}
+
+pub(super) struct RpcHandler {
+    state: Arc<Mutex<State>>,
+    keys: Option<Keys>,
+    raw_log: wss::RawLog,
+    out: ws::Sender,
+}
+
+impl RpcHandler {
+    pub(super) fn new(
+        state: Arc<Mutex<State>>,
+        keys: Option<Keys>,
+        raw_log: wss::RawLog,
+        out: &ws::Sender
+    ) -> Self
+    {
+        {
+            let mut state = state.lock().unwrap();
+            if state.closed {
+                let _ = out.shutdown();
+            }
+            state.out = Some(out.clone());
+        }
+
+        RpcHandler {
+            state,
+            keys,
+            raw_log,
+            out: out.clone(),
+        }
+    }
+
+    /// Send the queued requests, and let the following ones be sent right away.
+    fn ready(&self) -> ws::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.ready = true;
+        for payload in state.queued.drain(..) {
+            self.raw_log.send(&self.out, payload)?;
+        }
+        Ok(())
+    }
+}
+
+impl wss::HandlerImpl<!> for RpcHandler {
+    fn on_open(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        match &self.keys {
+            Some(keys) => {
+                let auth = keys.authentication(AUTH_ID);
+                self.raw_log.send_secret(out, auth, "authentication")
+            }
+            None => self.ready(),
+        }
+    }
+
+    fn on_message(&mut self, text: &str, _: &mpsc::UnboundedSender<!>)
+        -> Result<(), failure::Error>
+    {
+        let response: DeribitResponse<'_> = serde_json::from_str(text)?;
+
+        // Notifications are not expected on this connection.
+        let id = match response.id {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let result = response.into_result();
+
+        if id == AUTH_ID {
+            // Private requests are rejected by Deribit without authentication, but
+            // public ones can still go through.
+            if let Err(err) = result {
+                error!("authentication failed: {}", err);
+            }
+            self.ready()?;
+            return Ok(());
+        }
+
+        if let Some(responder) = self.state.lock().unwrap().pending.remove(&id) {
+            let _ = responder.send(result);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+#![cfg(test)]
+
+use futures::prelude::*;
+use futures::sync::{mpsc, oneshot};
+use openssl::pkey::PKey;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use crate::Side;
+use crate::tick::{Tick, Price, Size};
+use crate::api::{
+    Notification,
+    NotificationFlags,
+    Order,
+    OrderType,
+    Trade,
+    OrderConfirmation,
+    OrderUpdate,
+};
+use crate::api::errors::{ApiError, RestErrorKind, CancelErrorKind};
+use crate::api::harness;
+use crate::api::wss::{self, RawLog};
+use crate::api::symbol::{Symbol, IntoWithSymbol};
+use crate::api::deribit::{Instrument, Keys, tick_from_f64};
+use crate::api::deribit::requests::{order_request, cancel_ack};
+use crate::api::deribit::rpc::{self, RpcConnection, RpcHandler, State, AUTH_ID};
+use crate::api::deribit::wss::HandlerImpl;
+
+/// `BTC-PERPETUAL`: contracts of 10 USD, prices with a tick of 0.5.
+fn perpetual() -> Instrument {
+    Instrument {
+        symbol: Symbol::new("BTC-PERPETUAL", Tick::new(2), Tick::new(1))
+            .unwrap()
+            .with_commission_tick(Tick::new(100_000_000)),
+        contract_size: 10.,
+        inverse: true,
+        settlement_currency: "BTC".to_owned(),
+    }
+}
+
+fn handler(instrument: Instrument) -> HandlerImpl {
+    HandlerImpl::new(instrument, NotificationFlags::ALL, None, RawLog::new(false, "deribit"))
+}
+
+#[test]
+fn ticks_and_amounts() {
+    assert_eq!(tick_from_f64(0.5), Some(Tick::new(2)));
+    assert_eq!(tick_from_f64(2.5), Some(Tick::new(2)));
+    assert_eq!(tick_from_f64(0.0005), Some(Tick::new(2000)));
+    assert_eq!(tick_from_f64(1.), Some(Tick::new(1)));
+
+    let instrument = perpetual();
+    assert_eq!(instrument.size(30.).unwrap(), Size(3));
+    assert_eq!(instrument.amount(3), 30.);
+
+    // Options: contracts of 1 BTC, with a minimum trade amount of 0.1 BTC.
+    let option = Instrument {
+        symbol: Symbol::new("BTC-27DEC24-100000-C", Tick::new(2000), Tick::new(10)).unwrap(),
+        contract_size: 1.,
+        inverse: false,
+        settlement_currency: "BTC".to_owned(),
+    };
+    assert_eq!(option.size(0.3).unwrap(), Size(3));
+    assert_eq!(option.amount(3), 0.3);
+}
+
+#[test]
+fn book_snapshot_then_changes() {
+    let mut handler = handler(perpetual());
+
+    let frames = [
+        r#"{"jsonrpc":"2.0","id":1,"result":["book.BTC-PERPETUAL.raw","trades.BTC-PERPETUAL.raw"]}"#,
+        r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-PERPETUAL.raw","data":{"type":"snapshot","timestamp":1554373962454,"instrument_name":"BTC-PERPETUAL","change_id":297217,"bids":[["new",5042.5,30],["new",5042,200]],"asks":[["new",5043,40]]}}}"#,
+        r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-PERPETUAL.raw","data":{"type":"change","timestamp":1554373911330,"prev_change_id":297217,"instrument_name":"BTC-PERPETUAL","change_id":297218,"bids":[["delete",5042.5,0]],"asks":[["change",5043,10],["new",5044.5,20]]}}}"#,
+        r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"trades.BTC-PERPETUAL.raw","data":[{"trade_seq":30289432,"trade_id":"48079254","timestamp":1590484156350,"tick_direction":0,"price":5043,"instrument_name":"BTC-PERPETUAL","index_price":5042.9,"direction":"buy","amount":30}]}}"#,
+    ];
+
+    let expected = harness::order_book(&[(10_084, 20)], &[(10_086, 1), (10_089, 2)]);
+    let notifs = harness::assert_book(&mut handler, &frames, &expected);
+    assert_eq!(notifs.len(), 3);
+
+    match &notifs[2] {
+        Notification::Trade(trade) => {
+            assert_eq!(trade.timestamp(), 1590484156350);
+            assert_eq!(trade.into_inner(), Trade {
+                price: Price(10_086),
+                size: Size(3),
+                maker_side: Side::Ask,
+            });
+        }
+        other => panic!("expected a trade, found `{:?}`", other),
+    }
+
+    // Change 297219 is missing, wait for a new snapshot.
+    let notifs = harness::replay(&mut handler, &[
+        r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-PERPETUAL.raw","data":{"type":"change","timestamp":1554373911330,"prev_change_id":297219,"instrument_name":"BTC-PERPETUAL","change_id":297220,"bids":[],"asks":[["delete",5043,0]]}}}"#,
+        r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-PERPETUAL.raw","data":{"type":"change","timestamp":1554373911330,"prev_change_id":297220,"instrument_name":"BTC-PERPETUAL","change_id":297221,"bids":[],"asks":[["delete",5044.5,0]]}}}"#,
+    ]);
+    assert_eq!(notifs, vec![Notification::BookResync]);
+}
+
+#[test]
+fn order_changes() {
+    let mut handler = handler(perpetual());
+
+    let notifs = harness::replay(&mut handler, &[
+        r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"user.orders.BTC-PERPETUAL.raw","data":{"time_in_force":"good_til_cancelled","price":5000,"post_only":false,"order_type":"limit","order_state":"open","order_id":"4008965646","max_show":50,"last_update_timestamp":1588160100000,"label":"my-order","is_liquidation":false,"instrument_name":"BTC-PERPETUAL","filled_amount":0,"direction":"buy","creation_timestamp":1588160100000,"commission":0,"average_price":0,"api":true,"amount":50}}}"#,
+        r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"user.orders.BTC-PERPETUAL.raw","data":{"time_in_force":"good_til_cancelled","price":5000,"post_only":false,"order_type":"limit","order_state":"open","order_id":"4008965646","max_show":50,"last_update_timestamp":1588160101000,"label":"my-order","is_liquidation":false,"instrument_name":"BTC-PERPETUAL","filled_amount":20,"direction":"buy","creation_timestamp":1588160100000,"commission":0.000002,"average_price":5000,"api":true,"amount":50}}}"#,
+        r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"user.orders.BTC-PERPETUAL.raw","data":{"time_in_force":"good_til_cancelled","price":5000,"post_only":false,"order_type":"limit","order_state":"cancelled","order_id":"4008965646","max_show":50,"last_update_timestamp":1588160102000,"label":"my-order","is_liquidation":false,"instrument_name":"BTC-PERPETUAL","filled_amount":20,"direction":"buy","creation_timestamp":1588160100000,"commission":0.000002,"average_price":5000,"api":true,"amount":50}}}"#,
+    ]);
+
+    assert_eq!(notifs.len(), 3);
+    match &notifs[0] {
+        Notification::OrderConfirmation(confirmation) => {
+            assert_eq!(confirmation.timestamp(), 1588160100000);
+            assert_eq!(confirmation.clone().into_inner(), OrderConfirmation {
+                order_id: "my-order".to_owned(),
+                price: Price(10_000),
+                size: Size(5),
+                side: Side::Bid,
+            });
+        }
+        other => panic!("expected an order confirmation, found `{:?}`", other),
+    }
+
+    match &notifs[1] {
+        Notification::OrderUpdate(update) => {
+            assert_eq!(update.clone().into_inner(), OrderUpdate {
+                order_id: "my-order".to_owned(),
+                consumed_size: Size(2),
+                remaining_size: Size(3),
+                consumed_price: Price(10_000),
+                commission: 200,
+                commission_asset: Some("BTC".to_owned()),
+                is_fill: true,
+            });
+        }
+        other => panic!("expected an order update, found `{:?}`", other),
+    }
+
+    assert!(if let Notification::OrderExpiration(..) = notifs[2] { true } else { false });
+}
+
+#[test]
+fn maker_rebate() {
+    let mut handler = handler(perpetual());
+
+    let notifs = harness::replay(&mut handler, &[
+        r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"user.orders.BTC-PERPETUAL.raw","data":{"time_in_force":"good_til_cancelled","price":5000,"post_only":true,"order_type":"limit","order_state":"open","order_id":"4008965647","max_show":50,"last_update_timestamp":1588160100000,"label":"maker","is_liquidation":false,"instrument_name":"BTC-PERPETUAL","filled_amount":0,"direction":"sell","creation_timestamp":1588160100000,"commission":0,"average_price":0,"api":true,"amount":50}}}"#,
+        r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"user.orders.BTC-PERPETUAL.raw","data":{"time_in_force":"good_til_cancelled","price":5000,"post_only":true,"order_type":"limit","order_state":"open","order_id":"4008965647","max_show":50,"last_update_timestamp":1588160101000,"label":"maker","is_liquidation":false,"instrument_name":"BTC-PERPETUAL","filled_amount":20,"direction":"sell","creation_timestamp":1588160100000,"commission":-0.000001,"average_price":5000,"api":true,"amount":50}}}"#,
+    ]);
+
+    assert_eq!(notifs.len(), 2);
+    match &notifs[1] {
+        Notification::OrderUpdate(update) => {
+            // The rebate is not reported as a positive commission.
+            assert_eq!(update.consumed_size, Size(2));
+            assert_eq!(update.commission, 0);
+        }
+        other => panic!("expected an order update, found `{:?}`", other),
+    }
+}
+
+/// Return a sender which is not connected to anything, along with the `WebSocket` owning its
+/// channel, which must be kept alive for the sender to accept messages.
+fn sender() -> (ws::WebSocket<impl ws::Factory>, ws::Sender) {
+    let socket = ws::WebSocket::new(|_: ws::Sender| |_: ws::Message| Ok(())).unwrap();
+    let out = socket.broadcaster();
+    (socket, out)
+}
+
+fn keys() -> Keys {
+    Keys {
+        client_id: "client_id".to_owned(),
+        client_secret: Arc::new(PKey::hmac(b"client_secret").unwrap()),
+    }
+}
+
+#[test]
+fn rpc_responses() {
+    let (_socket, out) = sender();
+    let (snd, _) = mpsc::unbounded::<!>();
+    let state = Arc::new(Mutex::new(State::default()));
+    let mut handler = RpcHandler::new(state.clone(), None, RawLog::new(false, "deribit"), &out);
+
+    // Without keys, the connection is ready right away.
+    wss::HandlerImpl::on_open(&mut handler, &out).unwrap();
+    assert!(state.lock().unwrap().ready);
+
+    let (snd1, rcv1) = oneshot::channel();
+    let (snd2, rcv2) = oneshot::channel();
+    state.lock().unwrap().pending.insert(1, snd1);
+    state.lock().unwrap().pending.insert(2, snd2);
+
+    // Responses are matched with their request by id, whatever their order. Notifications
+    // and unknown ids are ignored.
+    let frames = [
+        r#"{"jsonrpc":"2.0","method":"heartbeat","params":{"type":"test_request"}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"result":1554373962454}"#,
+        r#"{"jsonrpc":"2.0","id":7,"result":null}"#,
+        r#"{"jsonrpc":"2.0","id":1,"error":{"code":10009,"message":"not_enough_funds"}}"#,
+    ];
+    for frame in &frames {
+        wss::HandlerImpl::on_message(&mut handler, frame, &snd).unwrap();
+    }
+
+    assert_eq!(rcv2.wait().unwrap(), Ok(json!(1554373962454u64)));
+    let err = rcv1.wait().unwrap().unwrap_err();
+    assert_eq!(err.error_code, 10009);
+    assert_eq!(err.error_msg, "not_enough_funds");
+    assert!(state.lock().unwrap().pending.is_empty());
+}
+
+#[test]
+fn rpc_authentication() {
+    let keys = keys();
+
+    let auth: serde_json::Value = serde_json::from_str(&keys.authentication(AUTH_ID)).unwrap();
+    assert_eq!(auth["id"], json!(AUTH_ID));
+    assert_eq!(auth["method"], json!("public/auth"));
+    let params = &auth["params"];
+    assert_eq!(params["grant_type"], json!("client_signature"));
+    assert_eq!(params["client_id"], json!("client_id"));
+    let payload = format!(
+        "{}\n{}\n",
+        params["timestamp"].as_u64().unwrap(),
+        params["nonce"].as_str().unwrap()
+    );
+    assert_eq!(params["signature"], json!(keys.sign(&payload)));
+
+    let (_socket, out) = sender();
+    let (snd, _) = mpsc::unbounded::<!>();
+    let state = Arc::new(Mutex::new(State::default()));
+    let mut handler = RpcHandler::new(
+        state.clone(),
+        Some(keys),
+        RawLog::new(false, "deribit"),
+        &out
+    );
+
+    // Requests are queued until the authentication response is received.
+    wss::HandlerImpl::on_open(&mut handler, &out).unwrap();
+    state.lock().unwrap().queued.push(rpc::request_payload(1, "private/get_position", ()));
+    assert!(!state.lock().unwrap().ready);
+
+    let frame = r#"{"jsonrpc":"2.0","id":0,"result":{"token_type":"bearer","scope":"connection","refresh_token":"1554373962454.1c","expires_in":31536000,"access_token":"1554373962454.1b"}}"#;
+    wss::HandlerImpl::on_message(&mut handler, frame, &snd).unwrap();
+    let state = state.lock().unwrap();
+    assert!(state.ready);
+    assert!(state.queued.is_empty());
+}
+
+#[test]
+fn rpc_disconnect() {
+    // Nothing listens on this port: the connection fails and so does the pending call.
+    let connection = RpcConnection::new(
+        "ws://127.0.0.1:1".to_owned(),
+        None,
+        None,
+        RawLog::new(false, "deribit")
+    );
+    match connection.call::<!, _>("public/get_time", ()).wait() {
+        Err(ApiError::RestError(err)) => assert_eq!(err.kind(), RestErrorKind::UnknownStatus),
+        other => panic!("expected a REST error, found `{:?}`", other),
+    }
+}
+
+#[test]
+fn order_payload() {
+    let instrument = perpetual();
+    let symbol = instrument.symbol;
+
+    let order = Order::new("5042.5", "30", Side::Bid).with_order_type(OrderType::LimitMaker);
+    let (method, params) = order_request(&order.with_symbol(symbol), &instrument, "my-order");
+    let payload: serde_json::Value =
+        serde_json::from_str(&rpc::request_payload(1, method, params)).unwrap();
+    assert_eq!(payload, json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "private/buy",
+        "params": {
+            "instrument_name": "BTC-PERPETUAL",
+            "amount": 300.,
+            "type": "limit",
+            "label": "my-order",
+            "price": 5042.5,
+            "time_in_force": "good_til_cancelled",
+            "post_only": true,
+        },
+    }));
+
+    let order = Order::market("3", Side::Ask)
+        .with_order_type(OrderType::StopLoss)
+        .with_stop_price("5000");
+    let (method, params) = order_request(&order.with_symbol(symbol), &instrument, "stop");
+    assert_eq!(method, "private/sell");
+    assert_eq!(serde_json::to_value(params).unwrap(), json!({
+        "instrument_name": "BTC-PERPETUAL",
+        "amount": 30.,
+        "type": "stop_market",
+        "label": "stop",
+        "trigger": "last_price",
+        "trigger_price": 5000.,
+    }));
+}
+
+#[test]
+fn cancel_by_label() {
+    assert!(cancel_ack(&json!(1)).is_ok());
+    match cancel_ack(&json!(0)) {
+        Err(ApiError::RestError(err)) => assert_eq!(
+            err.kind(),
+            RestErrorKind::Specific(CancelErrorKind::UnknownOrder)
+        ),
+        other => panic!("expected a REST error, found `{:?}`", other),
+    }
+}
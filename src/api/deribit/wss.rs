@@ -0,0 +1,410 @@
+use failure::{bail, format_err};
+use serde_derive::{Deserialize, Serialize};
+use serde::de::IgnoredAny;
+use serde_json::Value;
+use log::{debug, error, warn};
+use std::collections::HashMap;
+use crate::Side;
+use crate::order_book::LimitUpdate;
+use crate::tick::{Price, Size, RoundingMode};
+use crate::api::{
+    Notification,
+    NotificationFlags,
+    Trade,
+    OrderConfirmation,
+    OrderExpiration,
+    OrderUpdate,
+};
+use crate::api::wss;
+use crate::api::stream::StreamHandle;
+use crate::api::symbol::Symbol;
+use crate::api::timestamp::IntoTimestamped;
+use crate::api::deribit::{Client, Keys, Instrument};
+use crate::api::deribit::rpc::{self, DeribitResponse, RequestId, AUTH_ID};
+use crate::api::deribit::requests::{DeribitTrade, maker_side};
+
+impl Client {
+    crate fn new_stream(&self, symbol: Symbol, flags: NotificationFlags)
+        -> StreamHandle
+    {
+        let instrument = self.instrument(symbol).cloned();
+        let keys = if flags.contains_orders() { self.keys.clone() } else { None };
+        let streaming_endpoint = self.params.streaming_endpoint.clone();
+        let raw_log = wss::RawLog::new(self.log_raw, "deribit");
+
+        StreamHandle::spawn(move |snd, shutdown| {
+            let instrument = match instrument {
+                Some(instrument) => instrument,
+                None => {
+                    error!("unknown instrument `{}`", symbol.name());
+                    return;
+                }
+            };
+
+            debug!("initiating WebSocket connection");
+
+            if let Err(err) = ws::connect(streaming_endpoint, |out| {
+                shutdown.register(&out);
+                let handler = HandlerImpl::new(instrument.clone(), flags, keys.clone(), raw_log);
+                wss::Handler::new(out, snd.clone(), wss::KeepAlive::True, raw_log, handler)
+            })
+            {
+                error!("WebSocket connection terminated with error: `{}`", err);
+            }
+        })
+    }
+}
+
+/// Filled amount of an order, along with the value and commission of its fills, see
+/// `Instrument::value`.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+struct Fill {
+    filled_amount: f64,
+    value: f64,
+    commission: f64,
+}
+
+pub(super) struct HandlerImpl {
+    instrument: Instrument,
+    flags: NotificationFlags,
+
+    /// Keys for authenticating the connection, if the orders must be forwarded.
+    keys: Option<Keys>,
+
+    /// Change id of the last change applied to the order book, this is used for
+    /// checking the ordering of the changes. `None` while waiting for a snapshot.
+    last_change_id: Option<u64>,
+
+    /// Fills of the orders seen so far, by Deribit order id.
+    fills: HashMap<String, Fill>,
+
+    /// Id of the next request sent to Deribit.
+    next_id: RequestId,
+
+    /// Kept for subscribing to the private channels once authenticated, and for
+    /// subscribing again to the order book.
+    out: Option<ws::Sender>,
+
+    raw_log: wss::RawLog,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+struct DeribitChannels<'a> {
+    channels: &'a [String],
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+/// A notification of a subscribed channel.
+struct DeribitNotification<'a, T> {
+    #[serde(borrow)]
+    params: DeribitParams<'a, T>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+struct DeribitParams<'a, T> {
+    channel: &'a str,
+    data: T,
+}
+
+/// An `[action, price, amount]` change of the order book, where `action` is one of `new`,
+/// `change` or `delete`.
+type DeribitLevel<'a> = (&'a str, f64, f64);
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+struct DeribitBook<'a> {
+    #[serde(rename = "type")]
+    type_: &'a str,
+    timestamp: u64,
+    change_id: u64,
+    prev_change_id: Option<u64>,
+    #[serde(borrow)]
+    bids: Vec<DeribitLevel<'a>>,
+    #[serde(borrow)]
+    asks: Vec<DeribitLevel<'a>>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+struct DeribitOrder<'a> {
+    order_id: &'a str,
+    label: Option<&'a str>,
+    order_state: &'a str,
+    direction: &'a str,
+    /// A number, or `"market_price"` for market orders.
+    price: Value,
+    amount: f64,
+    filled_amount: f64,
+    average_price: Option<f64>,
+    commission: Option<f64>,
+    last_update_timestamp: u64,
+}
+
+impl HandlerImpl {
+    pub(super) fn new(
+        instrument: Instrument,
+        flags: NotificationFlags,
+        keys: Option<Keys>,
+        raw_log: wss::RawLog
+    ) -> Self
+    {
+        HandlerImpl {
+            instrument,
+            flags,
+            keys,
+            last_change_id: None,
+            fills: HashMap::new(),
+            next_id: AUTH_ID,
+            out: None,
+            raw_log,
+        }
+    }
+
+    fn symbol(&self) -> Symbol {
+        self.instrument.symbol
+    }
+
+    fn book_channel(&self) -> String {
+        format!("book.{}.raw", self.symbol().name())
+    }
+
+    fn convert_deribit_side(&self, side: &str) -> Result<Side, failure::Error> {
+        let side = match side {
+            "buy" => Side::Bid,
+            "sell" => Side::Ask,
+            other => bail!("wrong side: `{}`", other),
+        };
+        Ok(side)
+    }
+
+    fn price(&self, price: f64) -> Result<Price, failure::Error> {
+        Ok(Price(self.symbol().price_tick().from_f64(price, RoundingMode::Nearest)?))
+    }
+
+    fn send_request(&mut self, out: &ws::Sender, method: &str, channels: &[String])
+        -> ws::Result<()>
+    {
+        self.next_id += 1;
+        let params = DeribitChannels {
+            channels,
+        };
+        self.raw_log.send(out, rpc::request_payload(self.next_id, method, params))
+    }
+
+    /// Subscribe again to the order book, Deribit then sends a fresh snapshot.
+    fn resubscribe_book(&mut self) -> ws::Result<()> {
+        if let Some(out) = self.out.clone() {
+            let channels = [self.book_channel()];
+            self.send_request(&out, "public/unsubscribe", &channels)?;
+            self.send_request(&out, "public/subscribe", &channels)?;
+        }
+        Ok(())
+    }
+
+    fn parse_message(&mut self, json: &str, out: &wss::NotifSender) -> Result<(), failure::Error> {
+        let response: DeribitResponse<'_> = serde_json::from_str(json)?;
+
+        match response.id {
+            Some(AUTH_ID) => {
+                if let Err(err) = response.into_result() {
+                    bail!("authentication failed: {}", err);
+                }
+
+                if let Some(ws_out) = self.out.clone() {
+                    let name = self.symbol().name().to_owned();
+                    let channels = [format!("user.orders.{}.raw", name)];
+                    self.send_request(&ws_out, "private/subscribe", &channels)?;
+                }
+                return Ok(());
+            }
+
+            Some(_) => {
+                if let Err(err) = response.into_result() {
+                    bail!("subscription failed: {}", err);
+                }
+                return Ok(());
+            }
+
+            None if response.method != Some("subscription") => return Ok(()),
+
+            None => (),
+        }
+
+        let notification: DeribitNotification<'_, IgnoredAny> = serde_json::from_str(json)?;
+        let channel = notification.params.channel;
+        let symbol = self.symbol();
+        let name = symbol.name();
+
+        if channel == format!("book.{}.raw", name) && self.flags.contains_book() {
+            let book: DeribitNotification<'_, DeribitBook<'_>> = serde_json::from_str(json)?;
+            self.process_book(book.params.data, out)?;
+        } else if channel == format!("trades.{}.raw", name) && self.flags.contains_trades() {
+            let trades: DeribitNotification<'_, Vec<DeribitTrade<'_>>> = serde_json::from_str(json)?;
+            for trade in trades.params.data {
+                let maker_side = maker_side(trade.direction)
+                    .ok_or_else(|| format_err!("wrong side: `{}`", trade.direction))?;
+
+                let trade = Notification::Trade(Trade {
+                    price: self.price(trade.price)?,
+                    size: self.instrument.size(trade.amount)?,
+                    maker_side,
+                }.with_timestamp(trade.timestamp));
+                out.unbounded_send(trade).unwrap();
+            }
+        } else if channel == format!("user.orders.{}.raw", name) && self.flags.contains_orders() {
+            let order: DeribitNotification<'_, DeribitOrder<'_>> = serde_json::from_str(json)?;
+            self.process_order(order.params.data, out)?;
+        }
+        Ok(())
+    }
+
+    fn process_book(&mut self, book: DeribitBook<'_>, out: &wss::NotifSender)
+        -> Result<(), failure::Error>
+    {
+        let bid = book.bids.iter().map(|level| (level, Side::Bid));
+        let ask = book.asks.iter().map(|level| (level, Side::Ask));
+
+        let mut updates = Vec::new();
+        for (&(action, price, amount), side) in bid.chain(ask) {
+            let size = if action == "delete" {
+                Size(0)
+            } else {
+                self.instrument.size(amount)?
+            };
+
+            updates.push(LimitUpdate {
+                side,
+                price: self.price(price)?,
+                size,
+            }.with_timestamp(book.timestamp));
+        }
+
+        if book.type_ == "snapshot" {
+            self.last_change_id = Some(book.change_id);
+            out.unbounded_send(Notification::BookSnapshot(updates)).unwrap();
+            return Ok(());
+        }
+
+        match self.last_change_id {
+            // Changes sent before the snapshot, after subscribing again.
+            None => (),
+
+            Some(last_change_id) if book.prev_change_id != Some(last_change_id) => {
+                // We missed some changes, start over from a fresh snapshot.
+                warn!("desynchronized order book, subscribing again");
+                out.unbounded_send(Notification::BookResync).unwrap();
+                self.last_change_id = None;
+                self.resubscribe_book()?;
+            }
+
+            Some(_) => {
+                self.last_change_id = Some(book.change_id);
+                if !updates.is_empty() {
+                    out.unbounded_send(Notification::LimitUpdates(updates)).unwrap();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn process_order(&mut self, order: DeribitOrder<'_>, out: &wss::NotifSender)
+        -> Result<(), failure::Error>
+    {
+        let order_id = order.label
+            .filter(|label| !label.is_empty())
+            .unwrap_or(order.order_id)
+            .to_owned();
+        let timestamp = order.last_update_timestamp;
+
+        let previous = self.fills.get(order.order_id).cloned();
+        if previous.is_none() && order.order_state != "rejected" {
+            // Market orders carry no price, they are not confirmed.
+            if let Some(price) = order.price.as_f64() {
+                let confirmation = OrderConfirmation {
+                    order_id: order_id.clone(),
+                    price: self.price(price)?,
+                    size: self.instrument.size(order.amount)?,
+                    side: self.convert_deribit_side(order.direction)?,
+                }.with_timestamp(timestamp);
+                out.unbounded_send(Notification::OrderConfirmation(confirmation)).unwrap();
+            }
+        }
+
+        // Deribit only gives the average price and the total commission of the fills, from
+        // which we derive the price and commission of the last fill.
+        let previous = previous.unwrap_or_default();
+        let fill = Fill {
+            filled_amount: order.filled_amount,
+            value: order.average_price
+                .filter(|_| order.filled_amount > 0.)
+                .map(|price| self.instrument.value(order.filled_amount, price))
+                .unwrap_or(0.),
+            commission: order.commission.unwrap_or(0.),
+        };
+
+        if fill.filled_amount > previous.filled_amount {
+            let consumed_amount = fill.filled_amount - previous.filled_amount;
+            let consumed_value = fill.value - previous.value;
+            // Maker rebates are negative commissions, which `OrderUpdate::commission`
+            // cannot represent: they are reported as a zero commission.
+            let commission = (fill.commission - previous.commission).max(0.);
+
+            let update = OrderUpdate {
+                order_id: order_id.clone(),
+                consumed_size: self.instrument.size(consumed_amount)?,
+                remaining_size: self.instrument.size(order.amount - order.filled_amount)?,
+                consumed_price: self.price(self.instrument.price(consumed_amount, consumed_value))?,
+                commission: self.symbol().commission_tick()
+                    .from_f64(commission, RoundingMode::Nearest)?,
+                commission_asset: Some(self.instrument.settlement_currency.clone()),
+                is_fill: true,
+            }.with_timestamp(timestamp);
+            out.unbounded_send(Notification::OrderUpdate(update)).unwrap();
+        }
+
+        match order.order_state {
+            "open" | "untriggered" => {
+                self.fills.insert(order.order_id.to_owned(), fill);
+            }
+
+            "cancelled" | "rejected" => {
+                self.fills.remove(order.order_id);
+                let expiration = OrderExpiration {
+                    order_id,
+                }.with_timestamp(timestamp);
+                out.unbounded_send(Notification::OrderExpiration(expiration)).unwrap();
+            }
+
+            _ => {
+                self.fills.remove(order.order_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl wss::HandlerImpl for HandlerImpl {
+    fn on_open(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        self.out = Some(out.clone());
+
+        let name = self.symbol().name().to_owned();
+        let mut channels = Vec::new();
+        if self.flags.contains_book() {
+            channels.push(format!("book.{}.raw", name));
+        }
+        if self.flags.contains_trades() {
+            channels.push(format!("trades.{}.raw", name));
+        }
+        if !channels.is_empty() {
+            self.send_request(out, "public/subscribe", &channels)?;
+        }
+
+        // The private channels are subscribed to once authenticated.
+        if let Some(keys) = &self.keys {
+            self.raw_log.send_secret(out, keys.authentication(AUTH_ID), "authentication")?;
+        }
+        Ok(())
+    }
+
+    fn on_message(&mut self, text: &str, out: &wss::NotifSender) -> Result<(), failure::Error> {
+        self.parse_message(text, out)
+    }
+}
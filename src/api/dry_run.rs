@@ -0,0 +1,177 @@
+//! A module defining an `ApiClient` decorator which does not send any order.
+
+use std::sync::Arc;
+use futures::prelude::*;
+use log::{debug, error};
+use crate::api::{
+    ApiClient,
+    GenerateOrderId,
+    Order,
+    OrderAck,
+    Cancel,
+    CancelAck,
+    Notification,
+    NotificationFlags,
+    Balances,
+    DepositAddress,
+    Ticker,
+    Trade,
+};
+use crate::api::errors::{self, ApiError, CancelErrorKind, RestErrorKind};
+use crate::api::symbol::{Symbol, WithSymbol, IntoWithSymbol};
+use crate::api::order_ids::{OrderIdStore, MemoryOrderIdStore};
+use crate::api::timestamp::{Timestamped, IntoTimestamped};
+
+/// An `ApiClient` decorator for testing strategies against live market data without
+/// risking capital: `order` and `cancel` requests are never sent to the exchange, but
+/// are immediately acknowledged instead. Everything else, including the notification
+/// stream and `balances`, is forwarded to the wrapped client.
+///
+/// Each acknowledged order is assigned a synthetic exchange order id, recorded in an
+/// `OrderIdStore` like GDAX order ids are, so that canceling an order which was not
+/// previously acknowledged fails with `CancelErrorKind::UnknownOrder`. Since a store
+/// never forgets an order id, canceling the same order twice succeeds.
+///
+/// # Note
+/// Since orders never reach the exchange, the notification stream will not contain
+/// any notification about them.
+pub struct DryRunClient<C> {
+    inner: C,
+
+    /// Synthetic exchange order ids of the orders acknowledged so far.
+    order_ids: Arc<dyn OrderIdStore>,
+}
+
+impl<C: ApiClient> DryRunClient<C> {
+    /// Wrap `inner`.
+    pub fn new(inner: C) -> Self {
+        DryRunClient {
+            inner,
+            order_ids: Arc::new(MemoryOrderIdStore::new()),
+        }
+    }
+
+    /// Use `store` for recording the synthetic exchange order ids of acknowledged orders.
+    /// By default, they are only kept in memory.
+    pub fn with_order_id_store<S: OrderIdStore + 'static>(self, store: S) -> Self {
+        DryRunClient {
+            order_ids: Arc::new(store),
+            ..self
+        }
+    }
+
+    /// Return a reference to the wrapped client.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C: ApiClient> GenerateOrderId for DryRunClient<C> {
+    fn new_order_id(hint: &str) -> String {
+        C::new_order_id(hint)
+    }
+}
+
+impl<C: ApiClient> ApiClient for DryRunClient<C> {
+    type Stream = C::Stream;
+
+    fn find_symbol(&self, symbol: &str) -> Option<Symbol> {
+        self.inner.find_symbol(symbol)
+    }
+
+    fn generate_order_id(&self, hint: &str) -> String {
+        self.inner.generate_order_id(hint)
+    }
+
+    fn ticker(&self, symbol: Symbol)
+        -> Box<dyn Future<Item = Ticker, Error = errors::Error> + Send + 'static>
+    {
+        self.inner.ticker(symbol)
+    }
+
+    fn recent_trades(&self, symbol: Symbol, limit: usize)
+        -> Box<dyn Future<Item = Vec<Timestamped<Trade>>, Error = errors::Error> + Send + 'static>
+    {
+        self.inner.recent_trades(symbol, limit)
+    }
+
+    fn stream_with_flags(&self, symbol: Symbol, flags: NotificationFlags) -> Self::Stream {
+        self.inner.stream_with_flags(symbol, flags)
+    }
+
+    fn stream_many(&self, symbols: &[Symbol], flags: NotificationFlags)
+        -> Box<dyn Stream<Item = WithSymbol<Notification>, Error = ()> + Send + 'static>
+    {
+        self.inner.stream_many(symbols, flags)
+    }
+
+    fn order(&self, order: WithSymbol<&Order>)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = errors::OrderError> + Send + 'static>
+    {
+        use uuid::Uuid;
+
+        if let Err(err) = order.validate(&order.symbol()) {
+            return Box::new(Err(err.into()).into_future());
+        }
+
+        let order_id = match order.order_id() {
+            Some(order_id) => order_id.to_owned(),
+            None => Uuid::new_v4().to_string(),
+        };
+
+        debug!("dry run: acknowledging order {}", order_id);
+        let server_id = Uuid::new_v4().to_string();
+        if let Err(err) = self.order_ids.insert(order_id.clone(), server_id) {
+            error!("failed to store order id {}: `{}`", order_id, err);
+        }
+
+        Box::new(Ok(OrderAck {
+            order_id,
+        }.timestamped()).into_future())
+    }
+
+    fn cancel(&self, cancel: WithSymbol<&Cancel>)
+        -> Box<dyn Future<Item = Timestamped<CancelAck>, Error = errors::CancelError> + Send + 'static>
+    {
+        if self.order_ids.get(cancel.order_id()).is_some() {
+            debug!("dry run: acknowledging cancel of order {}", cancel.order_id());
+            Box::new(Ok(CancelAck.timestamped()).into_future())
+        } else {
+            let kind = RestErrorKind::Specific(CancelErrorKind::UnknownOrder);
+            Box::new(Err(ApiError::RestError(kind.into())).into_future())
+        }
+    }
+
+    fn replace(&self, replaced: WithSymbol<&Order>, new: &Order)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = errors::OrderError> + Send + 'static>
+    {
+        let known = replaced.order_id()
+            .and_then(|order_id| self.order_ids.get(order_id))
+            .is_some();
+
+        if known {
+            self.order(new.with_symbol(replaced.symbol()))
+        } else {
+            let kind = RestErrorKind::InvalidRequest;
+            Box::new(Err(ApiError::RestError(kind.into())).into_future())
+        }
+    }
+
+    fn ping(&self)
+        -> Box<dyn Future<Item = Timestamped<()>, Error = errors::Error> + Send + 'static>
+    {
+        self.inner.ping()
+    }
+
+    fn balances(&self)
+        -> Box<dyn Future<Item = Balances, Error = errors::Error> + Send + 'static>
+    {
+        self.inner.balances()
+    }
+
+    fn deposit_address(&self, asset: &str)
+        -> Box<dyn Future<Item = DepositAddress, Error = errors::Error> + Send + 'static>
+    {
+        self.inner.deposit_address(asset)
+    }
+}
@@ -3,6 +3,8 @@
 use failure::{Context, Backtrace};
 use failure_derive::Fail;
 use std::fmt;
+use crate::tick::{Tick, TickUnit, ConversionErrorKind};
+use crate::api::Order;
 
 #[doc(hidden)]
 pub trait ErrorKind: private::Sealed + failure::Fail + Copy + Sized { }
@@ -95,6 +97,10 @@ pub enum RestErrorKind<K: ErrorKind> {
     #[fail(display = "too many requests")]
     /// Too many requests were sent during a given time window, check rate limits.
     TooManyRequests,
+
+    #[fail(display = "ip address was banned")]
+    /// The IP address was banned for repeatedly breaking the rate limits.
+    AddressBanned,
     
     #[fail(display = "unknown execution status, could have been a success")]
     /// Execution status are unknown: e.g. timeout.
@@ -112,6 +118,10 @@ pub enum RestErrorKind<K: ErrorKind> {
     /// The request timestamp was outside of the specified time window.
     OutsideTimeWindow,
 
+    #[fail(display = "unsupported by the exchange")]
+    /// The request (or one of its parameters) is not supported by the exchange.
+    Unsupported,
+
     #[fail(display = "{}", _0)]
     /// More specific error kind, depending on the request being made.
     Specific(K),
@@ -162,6 +172,20 @@ pub enum ApiError<K: ErrorKind> {
 /// Error type adding error kinds specific to `ApiClient::order`.
 pub type OrderError = ApiError<OrderErrorKind>;
 
+impl OrderError {
+    /// Return the replaced order if this error was returned by `ApiClient::replace` after
+    /// the replaced order was canceled, see `ReplaceError`.
+    pub fn replaced_order(&self) -> Option<&Order> {
+        use failure::Fail;
+
+        let replace_error = match self {
+            ApiError::RestError(err) => err.inner.cause()?.downcast_ref::<ReplaceError>(),
+            ApiError::RequestError(err) => err.inner.downcast_ref::<ReplaceError>(),
+        };
+        replace_error.map(|err| &err.replaced)
+    }
+}
+
 /// Error type adding error kinds specific to `ApiClient::cancel`.
 pub type CancelError = ApiError<CancelErrorKind>;
 
@@ -172,10 +196,12 @@ impl From<RestErrorKind<!>> for RestErrorKind<CancelErrorKind> {
     fn from(err: RestErrorKind<!>) -> RestErrorKind<CancelErrorKind> {
         match err {
             RestErrorKind::TooManyRequests => RestErrorKind::TooManyRequests,
+            RestErrorKind::AddressBanned => RestErrorKind::AddressBanned,
             RestErrorKind::InvalidRequest => RestErrorKind::InvalidRequest,
             RestErrorKind::UnknownStatus => RestErrorKind::UnknownStatus,
             RestErrorKind::OtherSide => RestErrorKind::OtherSide,
             RestErrorKind::OutsideTimeWindow => RestErrorKind::OutsideTimeWindow,
+            RestErrorKind::Unsupported => RestErrorKind::Unsupported,
             RestErrorKind::Specific(x) => x,
         }
     }
@@ -185,15 +211,184 @@ impl From<RestErrorKind<!>> for RestErrorKind<OrderErrorKind> {
     fn from(err: RestErrorKind<!>) -> RestErrorKind<OrderErrorKind> {
         match err {
             RestErrorKind::TooManyRequests => RestErrorKind::TooManyRequests,
+            RestErrorKind::AddressBanned => RestErrorKind::AddressBanned,
             RestErrorKind::InvalidRequest => RestErrorKind::InvalidRequest,
             RestErrorKind::UnknownStatus => RestErrorKind::UnknownStatus,
             RestErrorKind::OtherSide => RestErrorKind::OtherSide,
             RestErrorKind::OutsideTimeWindow => RestErrorKind::OutsideTimeWindow,
+            RestErrorKind::Unsupported => RestErrorKind::Unsupported,
             RestErrorKind::Specific(x) => x,
         }
     }
 }
 
+/// Convert an error returned by a cancel request into an error suitable for an order
+/// request, e.g. when emulating an order replacement. `CancelErrorKind::UnknownOrder`
+/// maps to `RestErrorKind::InvalidRequest`.
+crate fn cancel_to_order_error(err: CancelError) -> OrderError {
+    use failure::Fail;
+
+    match err {
+        ApiError::RestError(err) => {
+            let kind = match err.kind() {
+                RestErrorKind::TooManyRequests => RestErrorKind::TooManyRequests,
+                RestErrorKind::AddressBanned => RestErrorKind::AddressBanned,
+                RestErrorKind::UnknownStatus => RestErrorKind::UnknownStatus,
+                RestErrorKind::OtherSide => RestErrorKind::OtherSide,
+                RestErrorKind::OutsideTimeWindow => RestErrorKind::OutsideTimeWindow,
+                RestErrorKind::Unsupported => RestErrorKind::Unsupported,
+                RestErrorKind::InvalidRequest |
+                RestErrorKind::Specific(CancelErrorKind::UnknownOrder) => {
+                    RestErrorKind::InvalidRequest
+                }
+            };
+            ApiError::RestError(err.context(kind).into())
+        }
+        ApiError::RequestError(err) => ApiError::RequestError(err),
+    }
+}
+
+/// Convert an error returned by a request without specific error kinds, e.g.
+/// `ApiClient::balances`, into an error suitable for an order request.
+crate fn to_order_error(err: Error) -> OrderError {
+    use failure::Fail;
+
+    match err {
+        ApiError::RestError(err) => {
+            let kind = err.kind().into();
+            ApiError::RestError(err.context(kind).into())
+        }
+        ApiError::RequestError(err) => ApiError::RequestError(err),
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "the replaced order was canceled but the new order failed: {}", error)]
+/// Cause of an error returned by `ApiClient::replace` when the replaced order was canceled
+/// but the new order failed. The replaced order is not sent again, since it may have been
+/// partially filled before being canceled.
+pub struct ReplaceError {
+    /// The replaced order, as it was sent to the exchange.
+    pub replaced: Order,
+
+    #[cause]
+    /// Error returned when sending the new order.
+    pub error: OrderError,
+}
+
+/// Attach the canceled `replaced` order to `err`, returned when sending the order replacing
+/// it, see `ApiClient::replace`. The kind of `err` is kept.
+crate fn replace_error(err: OrderError, replaced: Order) -> OrderError {
+    use failure::Fail;
+
+    match err {
+        ApiError::RestError(inner) => {
+            let kind = inner.kind();
+            let err = ReplaceError { replaced, error: ApiError::RestError(inner) };
+            ApiError::RestError(err.context(kind).into())
+        }
+        ApiError::RequestError(inner) => {
+            let err = ReplaceError { replaced, error: ApiError::RequestError(inner) };
+            ApiError::RequestError(RequestError::new(err))
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Fail)]
+/// An error returned by `Order::validate`, i.e. an order which would be rejected by
+/// any exchange listing the symbol.
+pub enum OrderValidationError {
+    #[fail(display = "price `{}` cannot be expressed with tick {} ({})", price, tick, kind)]
+    /// The order price is not a multiple of the symbol price tick, or is too large.
+    InvalidPrice {
+        /// The offending price.
+        price: String,
+
+        /// The symbol price tick.
+        tick: Tick,
+
+        /// Why the price could not be converted.
+        kind: ConversionErrorKind,
+    },
+
+    #[fail(display = "size `{}` cannot be expressed with tick {} ({})", size, tick, kind)]
+    /// The order size is not a multiple of the symbol size tick, or is too large.
+    InvalidSize {
+        /// The offending size.
+        size: String,
+
+        /// The symbol size tick.
+        tick: Tick,
+
+        /// Why the size could not be converted.
+        kind: ConversionErrorKind,
+    },
+
+    #[fail(display = "funds `{}` cannot be expressed with tick {} ({})", funds, tick, kind)]
+    /// The order funds are not a multiple of the symbol funds tick, or are too large.
+    InvalidFunds {
+        /// The offending funds.
+        funds: String,
+
+        /// The symbol funds tick.
+        tick: Tick,
+
+        /// Why the funds could not be converted.
+        kind: ConversionErrorKind,
+    },
+
+    #[fail(display = "order notional {} is below the minimum notional {}", notional, min_notional)]
+    /// The order price times its size, or its funds, is below `Symbol::min_notional`.
+    /// Both values are expressed in price ticks.
+    BelowMinNotional {
+        /// The order notional, rounded down.
+        notional: TickUnit,
+
+        /// The minimum notional of the symbol.
+        min_notional: TickUnit,
+    },
+
+    #[fail(
+        display = "stop price `{}` cannot be expressed with tick {} ({})",
+        stop_price,
+        tick,
+        kind
+    )]
+    /// The order stop price is not a multiple of the symbol price tick, or is too large.
+    InvalidStopPrice {
+        /// The offending stop price.
+        stop_price: String,
+
+        /// The symbol price tick.
+        tick: Tick,
+
+        /// Why the stop price could not be converted.
+        kind: ConversionErrorKind,
+    },
+
+    #[fail(display = "stop order without a stop price")]
+    /// The order is a stop order but no stop price was provided.
+    MissingStopPrice,
+
+    #[fail(display = "order size is zero")]
+    /// The order size is zero.
+    ZeroSize,
+
+    #[fail(display = "order funds are zero")]
+    /// The funds of a funds-based market order are zero.
+    ZeroFunds,
+}
+
+impl From<OrderValidationError> for OrderError {
+    /// An invalid order maps to `RestErrorKind::InvalidRequest`, with the validation error
+    /// as the cause.
+    fn from(err: OrderValidationError) -> OrderError {
+        use failure::Fail;
+
+        ApiError::RestError(err.context(RestErrorKind::InvalidRequest).into())
+    }
+}
+
 crate trait ErrorKinded<K: ErrorKind> {
     fn kind(&self) -> RestErrorKind<K>;
 }
@@ -3,10 +3,13 @@
 pub mod errors;
 mod wss;
 mod rest;
+mod test;
+
+pub use crate::api::order_ids;
 
 use openssl::pkey::{PKey, Private};
-use chashmap::CHashMap;
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::Arc;
 use futures::prelude::*;
 use serde_derive::{Serialize, Deserialize};
@@ -16,18 +19,26 @@ use crate::api::{
     Params,
     ApiClient,
     GenerateOrderId,
-    Notification,
     NotificationFlags,
     Order,
     OrderAck,
     Cancel,
     CancelAck,
-    Balances
+    Balances,
+    DepositAddress,
+    Ticker,
+    Trade,
+    WithdrawAck,
+    WithdrawClient,
 };
-use crate::api::symbol::{Symbol, WithSymbol};
-use crate::api::timestamp::{Timestamped, IntoTimestamped};
+use crate::api::symbol::{self, Symbol, WithSymbol};
+use crate::api::rate_limit::RateLimiter;
+use crate::api::transport::{self, HttpTransport};
+use crate::api::order_ids::{OrderIdStore, MemoryOrderIdStore};
+use crate::tick::{TickUnit, Price, Size};
+use crate::api::timestamp::{ClockOffset, Timestamped, IntoTimestamped};
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 /// A GDAX key pair: api key + secret key, along with a pass phrase.
 pub struct KeyPair {
     api_key: String,
@@ -46,23 +57,92 @@ impl KeyPair {
     }
 }
 
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyPair")
+            .field("api_key", &self.api_key)
+            .field("secret_key", &"<redacted>")
+            .field("pass_phrase", &"<redacted>")
+            .finish()
+    }
+}
+
 #[derive(Clone)]
 struct Keys {
     api_key: String,
     secret_key: Arc<PKey<Private>>,
     pass_phrase: String,
+    /// Offset of the GDAX clock, applied to the timestamp of signed requests.
+    clock: ClockOffset,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+/// Fee rates applied by GDAX, e.g. `0.0025` for a 0.25% fee. GDAX does not send fees
+/// along with its `match` messages, so these are used for computing the commission of
+/// the `OrderUpdate` notifications.
+pub struct Fees {
+    /// Fee rate applied when our order was resting on the book.
+    pub maker: f64,
+
+    /// Fee rate applied when our order took liquidity.
+    pub taker: f64,
+}
+
+impl Fees {
+    /// Commission in `symbol.commission_tick()` units, for a trade of `size` at `price`
+    /// with the given fee `rate`.
+    fn commission(rate: f64, symbol: Symbol, size: Size, price: Price) -> TickUnit {
+        let size = size.0 as f64 / symbol.size_tick().ticks_per_unit() as f64;
+        let price = price.0 as f64 / symbol.price_tick().ticks_per_unit() as f64;
+        let commission_ticks = symbol.commission_tick().ticks_per_unit() as f64;
+        (size * price * rate * commission_ticks).round() as TickUnit
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// How to handle an order book limit of a `snapshot` or `l2update` message whose price
+/// does not fall on the price tick of the symbol. Limits which cannot be converted at all,
+/// e.g. because of a malformed value, are dropped with a warning whatever the policy, the
+/// other limits of the message being forwarded in any case.
+///
+/// # Note
+/// With `OffTickPolicy::Truncate` and `OffTickPolicy::Round`, an off-tick limit may land
+/// on the same tick as another limit of the book, in which case the two levels are merged:
+/// the last update received sets the size of the level.
+pub enum OffTickPolicy {
+    /// Drop the limit with a warning. This is the default.
+    Skip,
+
+    /// Truncate the price to the tick below, like `Tick::ticked`.
+    Truncate,
+
+    /// Round the price to the nearest tick.
+    Round,
 }
 
+impl Default for OffTickPolicy {
+    fn default() -> Self {
+        OffTickPolicy::Skip
+    }
+}
+
+#[derive(Clone)]
 /// A GDAX API client.
 pub struct Client {
     params: Params,
     keys: Option<Keys>,
+    fees: Fees,
+    off_tick_policy: OffTickPolicy,
 
     /// client order id => server order id
-    order_ids: Arc<CHashMap<String, String>>,
+    order_ids: Arc<dyn OrderIdStore>,
 
-    symbols: HashMap<String, Symbol>,
-    http_client: hyper::Client<hyper_tls::HttpsConnector<hyper::client::HttpConnector>>,
+    symbols: Arc<HashMap<String, Symbol>>,
+    rate_limiter: Option<RateLimiter>,
+    log_raw: bool,
+    http_client: Arc<dyn HttpTransport>,
+    /// Custom order id generation scheme, see `Client::with_order_id_generator`.
+    order_id_generator: Option<api::OrderIdGenerator>,
 }
 
 impl Client {
@@ -71,8 +151,27 @@ impl Client {
     /// the user data stream.
     ///
     /// # Note
-    /// This method will block, fetching the available symbols from GDAX.
+    /// This method will block, fetching the available symbols from GDAX, as well as
+    /// synchronizing with the server time if `key_pair` is not `None`, see
+    /// `Client::sync_time`.
     pub fn new(params: Params, key_pair: Option<KeyPair>) -> Result<Self, failure::Error> {
+        Self::with_transport(params, key_pair, transport::https()?)
+    }
+
+    /// Create a new GDAX API client to the sandbox, see `Params::gdax_sandbox` and
+    /// `Client::new`.
+    pub fn new_testnet(key_pair: Option<KeyPair>) -> Result<Self, failure::Error> {
+        Self::new(Params::gdax_sandbox(), key_pair)
+    }
+
+    /// Create a new GDAX API client like `Client::new`, sending its REST requests
+    /// (including the ones made by `Client::new`) through `http_client`.
+    pub fn with_transport(
+        params: Params,
+        key_pair: Option<KeyPair>,
+        http_client: Arc<dyn HttpTransport>
+    ) -> Result<Self, failure::Error>
+    {
         let keys = match key_pair {
             Some(pair) => {
                 let secret_key = PKey::hmac(&base64::decode(&pair.secret_key)?)?;
@@ -81,38 +180,144 @@ impl Client {
                     api_key: pair.api_key,
                     secret_key: Arc::new(secret_key),
                     pass_phrase: pair.pass_phrase,
+                    clock: ClockOffset::default(),
                 })
             },
             None => None,
         };
 
-        let http_client = hyper::Client::builder().build::<_, hyper::Body>(
-            hyper_tls::HttpsConnector::new(2)?
-        );
-
+        let rate_limiter = params.rate_limiter();
         let mut client = Client {
             params,
             keys,
-            order_ids: Arc::new(CHashMap::new()),
-            symbols: HashMap::new(),
+            fees: Fees::default(),
+            off_tick_policy: OffTickPolicy::default(),
+            order_ids: Arc::new(MemoryOrderIdStore::new()),
+            symbols: Arc::new(HashMap::new()),
+            rate_limiter,
+            log_raw: false,
             http_client,
+            order_id_generator: None,
         };
 
         use tokio::runtime::current_thread;
+        if client.keys.is_some() {
+            debug!("synchronizing with server time");
+            let skew = current_thread::Runtime::new()?.block_on(client.sync_time())?;
+            debug!("server clock is {}ms ahead", skew);
+        }
+
         debug!("requesting symbols");
-        client.symbols = current_thread::Runtime::new()?
-            .block_on(client.get_symbols())?;
+        client.symbols = Arc::new(
+            current_thread::Runtime::new()?.block_on(client.get_symbols())?
+        );
         debug!("received symbols");
 
         Ok(client)
     }
+
+    /// Log every raw inbound text frame of the notification streams, as well as every
+    /// outbound subscription payload, at `trace` level with `"gdax"` as the log target.
+    /// Payloads carrying credentials are not logged verbatim. Disabled by default.
+    pub fn with_log_raw(self, log_raw: bool) -> Self {
+        Client {
+            log_raw,
+            ..self
+        }
+    }
+
+    /// Use `generator` instead of `GenerateOrderId::new_order_id` for generating order ids
+    /// from a hint, see `Order::with_order_id_from`.
+    ///
+    /// # Note
+    /// Coinbase Pro only accepts UUIDs as client order ids, orders carrying another
+    /// id are rejected with `RestErrorKind::InvalidRequest` before being sent.
+    pub fn with_order_id_generator<F>(self, generator: F) -> Self
+        where F: Fn(&str) -> String + Send + Sync + 'static
+    {
+        Client {
+            order_id_generator: Some(Arc::new(generator)),
+            ..self
+        }
+    }
+
+    /// Return the rate limiter delaying the REST requests of this client, if rate limiting
+    /// is enabled in `Params`.
+    pub fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    /// Use `store` for mapping client order ids to GDAX order ids, e.g. a
+    /// `FileOrderIdStore` so that orders placed before a restart can still be canceled.
+    /// By default, the mapping is only kept in memory.
+    pub fn with_order_id_store<S: OrderIdStore + 'static>(self, store: S) -> Self {
+        Client {
+            order_ids: Arc::new(store),
+            ..self
+        }
+    }
+
+    /// Use the given `fees` for computing the commissions of our orders. By default, no
+    /// fees are applied, i.e. commissions are always `0`.
+    pub fn with_fees(mut self, fees: Fees) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Use `policy` for the order book limits whose price does not fall on the price tick.
+    /// By default, such limits are dropped.
+    pub fn with_off_tick_policy(mut self, policy: OffTickPolicy) -> Self {
+        self.off_tick_policy = policy;
+        self
+    }
+
+    /// Fetch the GDAX server time and measure the offset of its clock relative to the
+    /// local clock, in ms. If this client has a key pair, the offset is then applied to
+    /// the timestamp of signed requests (for this client and its clones, including the
+    /// authentication of the user channel), which GDAX rejects if they are more than 30s
+    /// away from the server time. Done by `Client::new` when given a key pair.
+    pub fn sync_time(&self)
+        -> impl Future<Item = i64, Error = api::errors::Error> + Send + 'static
+    {
+        let clock = self.keys.as_ref().map(|keys| keys.clock.clone());
+        self.get_server_time().map(move |(server_time, sent, received)| {
+            let offset = clock.unwrap_or_default();
+            offset.update(server_time, sent, received)
+        })
+    }
+
+    /// Return the offset of the GDAX clock relative to the local clock, in ms, as last
+    /// measured by `Client::sync_time`. Always return `0` if this client was created
+    /// without a key pair.
+    pub fn clock_skew(&self) -> i64 {
+        self.keys.as_ref().map(|keys| keys.clock.get()).unwrap_or(0)
+    }
 }
 
 impl ApiClient for Client {
-    type Stream = futures::sync::mpsc::UnboundedReceiver<Notification>;
+    type Stream = api::stream::StreamHandle;
 
     fn find_symbol(&self, symbol: &str) -> Option<Symbol> {
-        self.symbols.get(&symbol.to_lowercase()).cloned()
+        self.symbols.get(&symbol::canonical_name(symbol)).cloned()
+    }
+
+    fn generate_order_id(&self, hint: &str) -> String {
+        match &self.order_id_generator {
+            Some(generator) => generator(hint),
+            None => Self::new_order_id(hint),
+        }
+    }
+
+    fn ticker(&self, symbol: Symbol)
+        -> Box<dyn Future<Item = Ticker, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.ticker_impl(symbol))
+    }
+
+    fn recent_trades(&self, symbol: Symbol, limit: usize)
+        -> Box<dyn Future<Item = Vec<Timestamped<Trade>>, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.recent_trades_impl(symbol, limit))
     }
 
     fn stream_with_flags(&self, symbol: Symbol, flags: NotificationFlags) -> Self::Stream {
@@ -122,7 +327,7 @@ impl ApiClient for Client {
     fn order(&self, order: WithSymbol<&Order>)
         -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
     {
-        Box::new(self.order_impl(order))
+        self.order_impl(order)
     }
 
     fn cancel(&self, cancel: WithSymbol<&Cancel>)
@@ -131,6 +336,12 @@ impl ApiClient for Client {
        self.cancel_impl(cancel)
     }
 
+    fn replace(&self, replaced: WithSymbol<&Order>, new: &Order)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
+    {
+        api::replace_by_cancel(self, replaced, new)
+    }
+
     fn ping(&self)
         -> Box<dyn Future<Item = Timestamped<()>, Error = api::errors::Error> + Send + 'static>
     {
@@ -142,6 +353,24 @@ impl ApiClient for Client {
     {
         Box::new(self.balances_impl())
     }
+
+    fn deposit_address(&self, asset: &str)
+        -> Box<dyn Future<Item = DepositAddress, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.deposit_address_impl(asset))
+    }
+}
+
+impl WithdrawClient for Client {
+    fn withdraw(&self, _: &str, _: &str, _: &str, _: Option<&str>)
+        -> Box<dyn Future<Item = WithdrawAck, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(
+            Err(api::errors::RestErrorKind::Unsupported.into())
+                .map_err(api::errors::ApiError::RestError)
+                .into_future()
+        )
+    }
 }
 
 impl GenerateOrderId for Client {
@@ -1,12 +1,13 @@
 use openssl::{sign::Signer, hash::MessageDigest};
 use hyper::{Method, Request};
 use futures::prelude::*;
-use failure::Fail;
+use failure::{Fail, format_err};
 use log::{warn, debug, error};
 use std::collections::HashMap;
+use std::sync::Arc;
 use serde_derive::{Serialize, Deserialize};
 use crate::Side;
-use crate::tick::Tick;
+use crate::tick::{Tick, Price, Size};
 use crate::api::{
     self,
     TimeInForce,
@@ -19,21 +20,44 @@ use crate::api::{
     Balances
 };
 use crate::api::errors::ErrorKinded;
-use crate::api::symbol::{Symbol, WithSymbol};
-use crate::api::timestamp::{convert_str_timestamp, timestamp_ms, Timestamped, IntoTimestamped};
-use crate::api::gdax::Client;
+use crate::api::rate_limit::{self, RateLimiter};
+use crate::api::symbol::{self, Symbol, WithSymbol};
+use crate::api::timestamp::{
+    convert_str_timestamp,
+    timestamp_ms,
+    Timestamp,
+    Timestamped,
+    IntoTimestamped,
+};
+use crate::api::gdax::{Keys, Client};
 use crate::api::gdax::errors::RestError;
+use crate::api::transport::HttpTransport;
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
 struct GdaxOrder<'a> {
-    size: &'a str,
-    price: &'a str,
+    #[serde(rename = "type")]
+    type_: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    funds: Option<&'a str>,
     side: &'a str,
     product_id: &'a str,
     #[serde(borrow)]
     client_oid: Option<&'a str>,
-    time_in_force: &'a str,
-    post_only: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_in_force: Option<&'a str>,
+
+    // Coinbase Pro rejects market orders carrying a `post_only` flag.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_only: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_price: Option<&'a str>,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
@@ -51,11 +75,47 @@ struct GdaxAccount<'a> {
     hold: &'a str,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct GdaxTicker<'a> {
+    price: &'a str,
+    bid: &'a str,
+    ask: &'a str,
+    volume: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct GdaxTrade<'a> {
+    time: &'a str,
+    price: &'a str,
+    size: &'a str,
+    side: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct GdaxCoinbaseAccount<'a> {
+    id: &'a str,
+    currency: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct GdaxDepositAddress<'a> {
+    address: &'a str,
+    destination_tag: Option<&'a str>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Deserialize)]
+struct GdaxServerTime {
+    /// Seconds since the epoch, with a millisecond precision.
+    epoch: f64,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
 struct GdaxProduct<'a> {
     id: &'a str,
     base_currency: &'a str,
+    quote_currency: &'a str,
     quote_increment: &'a str,
+    min_market_funds: Option<&'a str>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
@@ -87,25 +147,38 @@ impl AsStr for TimeInForce {
     }
 }
 
-impl Client {
-    fn request<K: api::errors::ErrorKind>(
-        &self,
-        path: &str,
-        method: Method,
-        body: String
-    ) -> impl Future<Item = hyper::Chunk, Error = api::errors::ApiError<K>> + Send + 'static
-            where RestError: ErrorKinded<K>
-    {
-        let address = format!(
-            "{}/{}",
-            self.params.rest_endpoint,
-            path,
-        );
-
+type HttpClient = Arc<dyn HttpTransport>;
+
+/// Send a (possibly signed) request to the REST API. This is a free function rather than a
+/// method so that it can be called from within `'static` futures, in order to chain requests.
+/// The request is signed once `rate_limiter` allows it to be sent, since the signature
+/// carries a timestamp.
+fn request<K: api::errors::ErrorKind>(
+    http_client: &HttpClient,
+    rate_limiter: Option<&RateLimiter>,
+    rest_endpoint: &str,
+    keys: Option<&Keys>,
+    path: &str,
+    method: Method,
+    body: String
+) -> impl Future<Item = hyper::Chunk, Error = api::errors::ApiError<K>> + Send + 'static
+        where RestError: ErrorKinded<K>
+{
+    let address = format!(
+        "{}/{}",
+        rest_endpoint,
+        path,
+    );
+
+    let http_client = http_client.clone();
+    let keys = keys.cloned();
+    let path = path.to_owned();
+
+    rate_limit::acquire(rate_limiter, 1).then(move |_| {
         let mut request = Request::builder();
 
-        if let Some(keys) = self.keys.as_ref() {
-            let timestamp = timestamp_ms() as f64 / 1000.;
+        if let Some(keys) = keys {
+            let timestamp = keys.clock.timestamp_ms() as f64 / 1000.;
             let mut signer = Signer::new(MessageDigest::sha256(), &keys.secret_key).unwrap();
             let what = format!("{}{}/{}{}", timestamp, method, path, body);
             signer.update(what.as_bytes()).unwrap();
@@ -121,62 +194,112 @@ impl Client {
             .uri(&address)
             .header("User-Agent", &b"hyper"[..])
             .header("Content-Type", &b"application/json"[..]);
-        
+
         // Unwrap because it is a bug if this fails (header failed to parse or something)
         let request = request.body(body.into()).unwrap();
-        self.http_client.request(request).and_then(|res| {
-            let status = res.status();
-            res.into_body().concat2().and_then(move |body| {
-                Ok((status, body))
-            })
-        })
-        .map_err(api::errors::RequestError::new)
-        .map_err(api::errors::ApiError::RequestError).and_then(|(status, body)| {
-            if status != hyper::StatusCode::OK {
-                let gdax_error = serde_json::from_slice(&body);
-                let error = RestError::from_gdax_error(status, gdax_error.ok());
-                let kind = error.kind();
-                Err(
-                    api::errors::ApiError::RestError(error.context(kind).into())
-                )?;
-            }
-            Ok(body)
-        })
+        http_client.request(request)
+    })
+    .map_err(|err| api::errors::RequestError::new(err.compat()))
+    .map_err(api::errors::ApiError::RequestError).and_then(|(status, body)| {
+        if status != hyper::StatusCode::OK {
+            let gdax_error = serde_json::from_slice(&body);
+            let error = RestError::from_gdax_error(status, gdax_error.ok());
+            let kind = error.kind();
+            Err(
+                api::errors::ApiError::RestError(error.context(kind).into())
+            )?;
+        }
+        Ok(body)
+    })
+}
+
+impl Client {
+    fn request<K: api::errors::ErrorKind>(
+        &self,
+        path: &str,
+        method: Method,
+        body: String
+    ) -> impl Future<Item = hyper::Chunk, Error = api::errors::ApiError<K>> + Send + 'static
+            where RestError: ErrorKinded<K>
+    {
+        request(
+            &self.http_client,
+            self.rate_limiter.as_ref(),
+            &self.params.rest_endpoint,
+            self.keys.as_ref(),
+            path,
+            method,
+            body
+        )
     }
 
     crate fn order_impl(&self, order: WithSymbol<&Order>)
-        -> impl Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
     {
         use std::borrow::Borrow;
 
+        if let Err(err) = order.validate(&order.symbol()) {
+            return Box::new(Err(err.into()).into_future());
+        }
+
         // Note that GDAX only accepts custom client ids in the form of UUIDs, so there can
         // never be duplicate orders inserted in the `order_ids` map. This is actually quite
         // neat because checking for duplicate orders in a synchronized manner would have been
         // difficult otherwise.
 
+        // Custom order id generators may not produce UUIDs, reject the order early.
+        if let Some(order_id) = &order.order_id {
+            if let Err(err) = uuid::Uuid::parse_str(order_id) {
+                let err = format_err!("client order id `{}` is not a UUID: {}", order_id, err);
+                let kind = api::errors::RestErrorKind::InvalidRequest;
+                return Box::new(
+                    Err(api::errors::ApiError::RestError(err.context(kind).into())).into_future()
+                );
+            }
+        }
+
         let symbol = order.symbol();
 
         let client_oid = order.order_id.clone();
         let time_in_force = order.time_in_force;
 
+        let market = order.type_.is_market();
+
         let size = order.size.unticked(symbol.size_tick());
         let price = order.price.unticked(symbol.price_tick());
+        let stop_price = order.stop_price.as_ref()
+            .filter(|_| order.type_.is_stop())
+            .map(|stop_price| stop_price.unticked(symbol.price_tick()));
+
+        // Funds are expressed in the quote currency, on the `quote_increment` grid which is
+        // also the price tick.
+        let funds = order.funds.as_ref().map(|funds| funds.unticked(symbol.funds_tick()));
 
         let order = GdaxOrder {
-            size: size.borrow(),
-            price: price.borrow(),
+            type_: if market { "market" } else { "limit" },
+            size: if funds.is_none() { Some(size.borrow()) } else { None },
+            price: if market { None } else { Some(price.borrow()) },
+            funds: funds.as_ref().map(|funds| funds.borrow()),
             side: order.side.as_str(),
             product_id: symbol.name(),
             client_oid: client_oid.as_ref().map(|oid| oid.as_ref()),
-            time_in_force: time_in_force.as_str(),
-            post_only: order.type_ == OrderType::LimitMaker,
+            time_in_force: if market { None } else { Some(time_in_force.as_str()) },
+            post_only: if market { None } else { Some(order.type_ == OrderType::LimitMaker) },
+
+            // A `loss` stop triggers when the last trade price drops to the stop price, an
+            // `entry` stop when it rises to it.
+            stop: stop_price.as_ref().map(|_| match order.side {
+                Side::Ask => "loss",
+                Side::Bid => "entry",
+            }),
+            stop_price: stop_price.as_ref().map(|stop_price| stop_price.borrow()),
         };
 
         let body = serde_json::to_string(&order).expect("invalid json");
 
         let order_ids = self.order_ids.clone();
 
-        self.request("orders", Method::POST, body).and_then(move |body| {
+        let fut = self.request("orders", Method::POST, body).and_then(move |body| {
             let ack: GdaxOrderAck<'_> = serde_json::from_slice(&body)
                 .map_err(api::errors::RequestError::new)
                 .map_err(api::errors::ApiError::RequestError)?;
@@ -201,20 +324,23 @@ impl Client {
                 Some(id) => id.clone(),
                 None => ack.id.to_owned(),
             };
-            order_ids.insert(order_id.clone(), ack.id.to_owned());
+            if let Err(err) = order_ids.insert(order_id.clone(), ack.id.to_owned()) {
+                error!("failed to store order id {}: `{}`", order_id, err);
+            }
             debug!("insert order id {} (from REST)", order_id);
 
             Ok(OrderAck {
                 order_id,
             }.with_timestamp(timestamp))
-        })
+        });
+        Box::new(fut)
     }
 
     crate fn cancel_impl(&self, cancel: WithSymbol<&Cancel>)
         -> Box<dyn Future<Item = Timestamped<CancelAck>, Error = api::errors::CancelError> + Send + 'static>
     {
         let endpoint = match self.order_ids.get(&cancel.order_id) {
-            Some(order_id) => format!("orders/{}", *order_id),
+            Some(order_id) => format!("orders/{}", order_id),
             None => {
                 warn!("called `cancel` with a not yet inserted order id");
                 return Box::new(
@@ -251,6 +377,138 @@ impl Client {
         })
     }
 
+    crate fn ticker_impl(&self, symbol: Symbol)
+        -> impl Future<Item = api::Ticker, Error = api::errors::Error> + Send + 'static
+    {
+        let endpoint = format!("products/{}/ticker", symbol.name());
+
+        self.request(&endpoint, Method::GET, String::new()).and_then(move |body| {
+            let ticker: GdaxTicker<'_> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            let convert = |tick: Tick, value: &str| {
+                tick.ticked(value)
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)
+            };
+
+            Ok(api::Ticker {
+                best_bid: Price(convert(symbol.price_tick(), ticker.bid)?),
+                best_ask: Price(convert(symbol.price_tick(), ticker.ask)?),
+                last: Price(convert(symbol.price_tick(), ticker.price)?),
+                volume_24h: Size(convert(symbol.size_tick(), ticker.volume)?),
+            })
+        })
+    }
+
+    crate fn recent_trades_impl(&self, symbol: Symbol, limit: usize)
+        -> impl Future<Item = Vec<Timestamped<api::Trade>>, Error = api::errors::Error> + Send + 'static
+    {
+        let endpoint = format!("products/{}/trades?limit={}", symbol.name(), limit);
+
+        self.request(&endpoint, Method::GET, String::new()).and_then(move |body| {
+            let trades: Vec<GdaxTrade<'_>> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            let convert = |tick: Tick, value: &str| {
+                tick.ticked(value)
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)
+            };
+
+            // Trades are sent newest first, and `side` is the side of the maker order.
+            let mut trades = trades.into_iter().take(limit).map(|trade| {
+                let maker_side = match trade.side {
+                    "buy" => Side::Bid,
+                    "sell" => Side::Ask,
+                    _ => Err(api::errors::RestErrorKind::OtherSide.into())
+                        .map_err(api::errors::ApiError::RestError)?,
+                };
+
+                let timestamp = convert_str_timestamp(trade.time)
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)?;
+
+                Ok(api::Trade {
+                    price: Price(convert(symbol.price_tick(), trade.price)?),
+                    size: Size(convert(symbol.size_tick(), trade.size)?),
+                    maker_side,
+                }.with_timestamp(timestamp))
+            }).collect::<Result<Vec<_>, _>>()?;
+
+            trades.reverse();
+            Ok(trades)
+        })
+    }
+
+    crate fn deposit_address_impl(&self, asset: &str)
+        -> impl Future<Item = api::DepositAddress, Error = api::errors::Error> + Send + 'static
+    {
+        // Deposit addresses are generated through the Coinbase account linked to the
+        // GDAX account, so we first need to find the Coinbase account id for `asset`.
+        let asset = asset.to_owned();
+        let http_client = self.http_client.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let rest_endpoint = self.params.rest_endpoint.clone();
+        let keys = self.keys.clone();
+
+        self.request("coinbase-accounts", Method::GET, String::new()).and_then(move |body| {
+            let accounts: Vec<GdaxCoinbaseAccount<'_>> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            let account = accounts.into_iter()
+                .find(|account| account.currency.eq_ignore_ascii_case(&asset))
+                .ok_or_else(|| {
+                    api::errors::ApiError::RestError(
+                        api::errors::RestErrorKind::InvalidRequest.into()
+                    )
+                })?;
+
+            Ok(format!("coinbase-accounts/{}/addresses", account.id))
+        }).and_then(move |path| {
+            request(
+                &http_client,
+                rate_limiter.as_ref(),
+                &rest_endpoint,
+                keys.as_ref(),
+                &path,
+                Method::POST,
+                String::new()
+            )
+        }).and_then(|body| {
+            let address: GdaxDepositAddress<'_> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            Ok(api::DepositAddress {
+                address: address.address.to_owned(),
+                // Assets without a memo may come with an empty destination tag.
+                tag: address.destination_tag
+                    .filter(|tag| !tag.is_empty())
+                    .map(|tag| tag.to_owned()),
+            })
+        })
+    }
+
+    /// Return the server time along with the local times at which the request was sent and
+    /// at which the response was received.
+    crate fn get_server_time(&self)
+        -> impl Future<Item = (Timestamp, Timestamp, Timestamp), Error = api::errors::Error>
+            + Send + 'static
+    {
+        let sent = timestamp_ms();
+        self.request("time", Method::GET, String::new()).and_then(move |body| {
+            let received = timestamp_ms();
+            let time: GdaxServerTime = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+            Ok(((time.epoch * 1000.).round() as Timestamp, sent, received))
+        })
+    }
+
     crate fn get_symbols(&self)
         -> impl Future<Item = HashMap<String, Symbol>, Error = api::errors::Error> + Send + 'static
     {
@@ -290,8 +548,28 @@ impl Client {
                     }
                 };
 
+                let commission_tick = currencies.get(p.quote_currency)
+                    .and_then(|c| Tick::tick_size(c.min_size));
+
                 if let Some(symbol) = Symbol::new(p.id, price_tick, size_tick) {
-                    symbols.insert(symbol.name().to_lowercase(), symbol);
+                    let mut symbol = symbol.with_base_asset(p.base_currency)
+                        .with_quote_asset(p.quote_currency);
+                    match commission_tick {
+                        Some(tick) => symbol = symbol.with_commission_tick(tick),
+                        None => warn!("cannot read commission tick for symbol `{}`", p.id),
+                    }
+
+                    // Minimum funds are expressed in the quote currency, i.e. with the price tick.
+                    match p.min_market_funds.map(|funds| price_tick.ticked(funds)) {
+                        Some(Ok(min_notional)) => symbol = symbol.with_min_notional(min_notional),
+                        Some(Err(err)) => warn!(
+                            "cannot read min notional for symbol `{}`: `{}`",
+                            p.id,
+                            err
+                        ),
+                        None => (),
+                    }
+                    symbols.insert(symbol::canonical_name(symbol.name()), symbol);
                 } else {
                     error!("symbol name too long: `{}`", p.id);
                 }
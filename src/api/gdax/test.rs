@@ -0,0 +1,274 @@
+#![cfg(test)]
+
+use std::sync::Arc;
+use futures::prelude::*;
+use failure::Fail;
+use hyper::StatusCode;
+use crate::Side;
+use crate::tick::{Tick, Price, Size};
+use crate::api::{ApiClient, Notification, NotificationFlags, Params, Order, Trade};
+use crate::api::harness::{self, MockTransport};
+use crate::api::wss::RawLog;
+use crate::api::symbol::{Symbol, IntoWithSymbol};
+use crate::api::errors::{ApiError, RestErrorKind};
+use crate::api::timestamp::timestamp_ms;
+use crate::api::gdax::{Client, KeyPair, Fees, OffTickPolicy};
+use crate::api::order_ids::MemoryOrderIdStore;
+use crate::api::gdax::wss::HandlerImpl;
+
+#[test]
+fn book_snapshot_then_updates() {
+    let symbol = Symbol::new("BTC-USD", Tick::new(100), Tick::new(100_000_000)).unwrap();
+    let mut handler = HandlerImpl::new(
+        symbol,
+        NotificationFlags::ALL,
+        None,
+        Arc::new(MemoryOrderIdStore::new()),
+        Fees::default(),
+        OffTickPolicy::default(),
+        RawLog::new(false, "gdax")
+    );
+
+    let frames = [
+        r#"{"type":"subscriptions","channels":[{"name":"level2","product_ids":["BTC-USD"]}]}"#,
+        r#"{"type":"snapshot","product_id":"BTC-USD","bids":[["6500.11","0.45"],["6500.10","1.2"]],"asks":[["6500.15","0.57"]]}"#,
+        r#"{"type":"l2update","product_id":"BTC-USD","time":"2019-08-14T20:42:27.265Z","changes":[["buy","6500.09","0.84"],["sell","6500.15","0"]]}"#,
+        r#"{"type":"l2update","product_id":"BTC-USD","time":"2019-08-14T20:42:28.265Z","changes":[["buy","6500.11","0"],["sell","6507.00","1.8"]]}"#,
+    ];
+
+    let expected = harness::order_book(
+        &[(650_010, 120_000_000), (650_009, 84_000_000)],
+        &[(650_700, 180_000_000)]
+    );
+    let notifs = harness::assert_book(&mut handler, &frames, &expected);
+    assert_eq!(notifs.len(), 3);
+    assert!(if let Notification::BookSnapshot(..) = notifs[0] { true } else { false });
+}
+
+#[test]
+fn order_size_change() {
+    let symbol = Symbol::new("BTC-USD", Tick::new(100), Tick::new(100_000_000)).unwrap();
+    let mut handler = HandlerImpl::new(
+        symbol,
+        NotificationFlags::ORDERS,
+        None,
+        Arc::new(MemoryOrderIdStore::new()),
+        Fees::default(),
+        OffTickPolicy::default(),
+        RawLog::new(false, "gdax")
+    );
+
+    let frames = [
+        r#"{"type":"received","time":"2019-08-14T20:42:27.265Z","product_id":"BTC-USD","order_id":"d50ec984-77a8-460a-b958-66f114b0de9b","client_oid":"my-order","size":"1.34","price":"502.1","side":"buy","order_type":"limit"}"#,
+        r#"{"type":"change","time":"2019-08-14T20:42:28.265Z","product_id":"BTC-USD","order_id":"d50ec984-77a8-460a-b958-66f114b0de9b","new_size":"0.84","old_size":"1.34","price":"502.1","side":"buy"}"#,
+
+        // Not one of our orders.
+        r#"{"type":"change","time":"2019-08-14T20:42:29.265Z","product_id":"BTC-USD","order_id":"ac928c66-ca53-498f-9c13-a110027a60e8","new_size":"1","old_size":"2","price":"400.23","side":"sell"}"#,
+    ];
+
+    let notifs = harness::replay(&mut handler, &frames);
+    assert_eq!(notifs.len(), 2);
+    match &notifs[1] {
+        Notification::OrderUpdate(update) => {
+            assert_eq!(update.order_id, "my-order");
+            assert_eq!(update.consumed_size, Size(50_000_000));
+            assert_eq!(update.remaining_size, Size(84_000_000));
+            assert_eq!(update.consumed_price, Price(50_210));
+            assert_eq!(update.commission, 0);
+            assert!(!update.is_fill);
+        }
+        other => panic!("expected an order update, found `{:?}`", other),
+    }
+}
+
+#[test]
+fn off_tick_limits() {
+    let frames = [
+        r#"{"type":"snapshot","product_id":"BTC-USD","bids":[["6500.115","0.45"],["6500.10","1.2"]],"asks":[["6500.15","0.57"]]}"#,
+        r#"{"type":"l2update","product_id":"BTC-USD","time":"2019-08-14T20:42:27.265Z","changes":[["buy","6500.091","0.84"],["sell","6500.15","0"],["sell","abc","1"]]}"#,
+    ];
+
+    let symbol = Symbol::new("BTC-USD", Tick::new(100), Tick::new(100_000_000)).unwrap();
+    let handler = |policy| HandlerImpl::new(
+        symbol,
+        NotificationFlags::ORDER_BOOK,
+        None,
+        Arc::new(MemoryOrderIdStore::new()),
+        Fees::default(),
+        policy,
+        RawLog::new(false, "gdax")
+    );
+
+    // By default, off-tick and malformed limits are dropped and the other limits are
+    // still forwarded.
+    let expected = harness::order_book(&[(650_010, 120_000_000)], &[]);
+    let notifs = harness::assert_book(
+        &mut handler(OffTickPolicy::default()),
+        &frames,
+        &expected
+    );
+    assert_eq!(notifs.len(), 2);
+
+    let expected = harness::order_book(
+        &[(650_011, 45_000_000), (650_010, 120_000_000), (650_009, 84_000_000)],
+        &[]
+    );
+    harness::assert_book(&mut handler(OffTickPolicy::Truncate), &frames, &expected);
+
+    let expected = harness::order_book(
+        &[(650_012, 45_000_000), (650_010, 120_000_000), (650_009, 84_000_000)],
+        &[]
+    );
+    harness::assert_book(&mut handler(OffTickPolicy::Round), &frames, &expected);
+}
+
+#[test]
+fn recent_trades() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"[{"id":"BTC-USD","base_currency":"BTC","quote_currency":"USD","quote_increment":"0.01","min_market_funds":"10"}]"#);
+    transport.respond(StatusCode::OK, r#"[{"id":"BTC","min_size":"0.00000001"},{"id":"USD","min_size":"0.01"}]"#);
+    transport.respond(StatusCode::OK, r#"[{"time":"2019-08-14T20:42:27.265Z","trade_id":74,"price":"10000.01","size":"0.01","side":"sell"},{"time":"2019-08-14T20:42:26.1Z","trade_id":73,"price":"10000.00","size":"0.5","side":"buy"}]"#);
+
+    let params = Params {
+        streaming_endpoint: "wss://localhost".to_owned(),
+        rest_endpoint: "https://localhost".to_owned(),
+        weight_per_minute: None,
+    };
+    let client = Client::with_transport(params, None, transport.clone()).unwrap();
+    let symbol = client.find_symbol("BTC-USD").unwrap();
+
+    // Trades are sent newest first, `side` being the side of the maker.
+    let trades = client.recent_trades(symbol, 2).wait().unwrap();
+    assert_eq!(trades.len(), 2);
+    assert_eq!(trades[0].timestamp(), 1565815346100);
+    assert_eq!(trades[0].into_inner(), Trade {
+        price: Price(1_000_000),
+        size: Size(50_000_000),
+        maker_side: Side::Bid,
+    });
+    assert_eq!(trades[1].timestamp(), 1565815347265);
+    assert_eq!(trades[1].into_inner(), Trade {
+        price: Price(1_000_001),
+        size: Size(1_000_000),
+        maker_side: Side::Ask,
+    });
+
+    let requests = transport.requests();
+    assert_eq!(requests[2].uri().path_and_query().unwrap(), "/products/BTC-USD/trades?limit=2");
+}
+
+#[test]
+fn order_id_generator() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"[{"id":"BTC-USD","base_currency":"BTC","quote_currency":"USD","quote_increment":"0.01","min_market_funds":"10"}]"#);
+    transport.respond(StatusCode::OK, r#"[{"id":"BTC","min_size":"0.00000001"},{"id":"USD","min_size":"0.01"}]"#);
+    transport.respond(StatusCode::OK, r#"{"id":"d0c5340b-6d6c-49d9-b567-48c4bfca13d2","created_at":"2019-08-14T20:42:27.265Z","status":"pending"}"#);
+
+    let params = Params {
+        streaming_endpoint: "wss://localhost".to_owned(),
+        rest_endpoint: "https://localhost".to_owned(),
+        weight_per_minute: None,
+    };
+    let client = Client::with_transport(params, None, transport.clone()).unwrap();
+    let symbol = client.find_symbol("BTC-USD").unwrap();
+
+    // Without a custom generator, GDAX generates UUIDs whatever the hint.
+    let order = Order::new("10000.00", "0.5", Side::Bid).with_order_id_from(&client, "a1");
+    assert!(uuid::Uuid::parse_str(order.order_id().unwrap()).is_ok());
+
+    // `Order::with_order_id` ignores the custom generator.
+    let client = client.with_order_id_generator(|hint| format!("mm-{}", hint));
+    let order = Order::new("10000.00", "0.5", Side::Bid).with_order_id::<Client>("a1");
+    assert!(uuid::Uuid::parse_str(order.order_id().unwrap()).is_ok());
+
+    // Non-UUID order ids are rejected before sending anything.
+    let order = Order::new("10000.00", "0.5", Side::Bid).with_order_id_from(&client, "a1");
+    assert_eq!(order.order_id(), Some("mm-a1"));
+    match client.order(order.with_symbol(symbol)).wait() {
+        Err(ApiError::RestError(err)) => {
+            assert_eq!(err.kind(), RestErrorKind::InvalidRequest);
+            assert!(format!("{}", err.cause().unwrap()).contains("is not a UUID"));
+        }
+        other => panic!("expected a REST error, found `{:?}`", other),
+    }
+
+    let client = client.with_order_id_generator(|_| {
+        "6d5d1d6c-0a3c-4b5c-9b1d-2f0c3b6f8a41".to_owned()
+    });
+    let order = Order::new("10000.00", "0.5", Side::Bid).with_order_id_from(&client, "a1");
+    let ack = client.order(order.with_symbol(symbol)).wait().unwrap();
+    assert_eq!(ack.order_id, "6d5d1d6c-0a3c-4b5c-9b1d-2f0c3b6f8a41");
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 3);
+    let body: serde_json::Value = serde_json::from_slice(requests[2].body()).unwrap();
+    assert_eq!(body["client_oid"], "6d5d1d6c-0a3c-4b5c-9b1d-2f0c3b6f8a41");
+}
+
+#[test]
+fn sync_time() {
+    let transport = Arc::new(MockTransport::default());
+    let server_time = timestamp_ms() + 3_600_000;
+    transport.respond(
+        StatusCode::OK,
+        &format!(r#"{{"iso":"2019-08-14T20:42:27.265Z","epoch":{}}}"#, server_time as f64 / 1000.)
+    );
+    transport.respond(StatusCode::OK, r#"[{"id":"BTC-USD","base_currency":"BTC","quote_currency":"USD","quote_increment":"0.01","min_market_funds":"10"}]"#);
+    transport.respond(StatusCode::OK, r#"[{"id":"BTC","min_size":"0.00000001"},{"id":"USD","min_size":"0.01"}]"#);
+
+    let params = Params {
+        streaming_endpoint: "wss://localhost".to_owned(),
+        rest_endpoint: "https://localhost".to_owned(),
+        weight_per_minute: None,
+    };
+    let key_pair = KeyPair::new("key".to_owned(), base64::encode("secret"), "pass".to_owned());
+    let client = Client::with_transport(params, Some(key_pair), transport.clone()).unwrap();
+
+    // The server clock is one hour ahead, give or take the time spent in the request.
+    let skew = client.clock_skew();
+    assert!((skew - 3_600_000).abs() < 5_000, "unexpected skew: {}", skew);
+
+    // Signed requests sent after synchronizing are stamped with the server time.
+    assert_eq!(transport.paths(), vec!["/time", "/products", "/currencies"]);
+    let requests = transport.requests();
+    let stamp: f64 = std::str::from_utf8(requests[1].headers()["CB-ACCESS-TIMESTAMP"].as_bytes())
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((stamp * 1000.).round() as u64 >= server_time);
+}
+
+#[test]
+fn deposit_address() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"[{"id":"BTC-USD","base_currency":"BTC","quote_currency":"USD","quote_increment":"0.01","min_market_funds":"10"}]"#);
+    transport.respond(StatusCode::OK, r#"[{"id":"BTC","min_size":"0.00000001"},{"id":"USD","min_size":"0.01"}]"#);
+    transport.respond(StatusCode::OK, r#"[{"id":"c7c3f3a4-3a4b-5a2c-a3c5-1b4a4e8b6f2d","currency":"BTC"},{"id":"1b4a4e8b-3a4b-5a2c-a3c5-c7c3f3a46f2d","currency":"XRP"}]"#);
+    transport.respond(StatusCode::OK, r#"{"address":"1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2","destination_tag":""}"#);
+    transport.respond(StatusCode::OK, r#"[{"id":"c7c3f3a4-3a4b-5a2c-a3c5-1b4a4e8b6f2d","currency":"BTC"},{"id":"1b4a4e8b-3a4b-5a2c-a3c5-c7c3f3a46f2d","currency":"XRP"}]"#);
+    transport.respond(StatusCode::OK, r#"{"address":"rw2ciyaNshpHe7bCHo4bRWq6pqqynnWKQg","destination_tag":"1234567"}"#);
+
+    let params = Params {
+        streaming_endpoint: "wss://localhost".to_owned(),
+        rest_endpoint: "https://localhost".to_owned(),
+        weight_per_minute: None,
+    };
+    let client = Client::with_transport(params, None, transport.clone()).unwrap();
+
+    // An empty destination tag means that there is no tag.
+    let address = client.deposit_address("btc").wait().unwrap();
+    assert_eq!(address.address, "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2");
+    assert_eq!(address.tag, None);
+
+    let address = client.deposit_address("XRP").wait().unwrap();
+    assert_eq!(address.tag.as_ref().map(|tag| tag.as_str()), Some("1234567"));
+
+    assert_eq!(transport.paths(), vec![
+        "/products",
+        "/currencies",
+        "/coinbase-accounts",
+        "/coinbase-accounts/c7c3f3a4-3a4b-5a2c-a3c5-1b4a4e8b6f2d/addresses",
+        "/coinbase-accounts",
+        "/coinbase-accounts/1b4a4e8b-3a4b-5a2c-a3c5-c7c3f3a46f2d/addresses",
+    ]);
+}
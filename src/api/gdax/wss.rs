@@ -1,12 +1,10 @@
-use futures::sync::mpsc::{unbounded, UnboundedReceiver};
-use std::thread;
 use std::collections::HashMap;
-use chashmap::CHashMap;
 use std::sync::Arc;
-use log::{debug, error};
+use log::{debug, error, warn};
 use failure::bail;
 use serde_derive::{Serialize, Deserialize};
-use crate::{tick, Side};
+use crate::Side;
+use crate::tick::{self, Price, Size, RoundingMode};
 use crate::order_book::LimitUpdate;
 use crate::api::{
     Notification,
@@ -18,36 +16,41 @@ use crate::api::{
 };
 use crate::api::symbol::Symbol;
 use crate::api::wss;
-use crate::api::timestamp::{convert_str_timestamp, timestamp_ms, IntoTimestamped};
-use crate::api::gdax::{Keys, Client};
+use crate::api::stream::StreamHandle;
+use crate::api::timestamp::{convert_str_timestamp, Timestamped, IntoTimestamped};
+use crate::api::gdax::{Keys, Client, Fees, OffTickPolicy};
+use crate::api::order_ids::OrderIdStore;
 
 impl Client {
     crate fn new_stream(&self, symbol: Symbol, flags: NotificationFlags)
-        -> UnboundedReceiver<Notification>
+        -> StreamHandle
     {
         let streaming_endpoint = self.params.streaming_endpoint.clone();
         let keys = self.keys.clone();
         let order_ids = self.order_ids.clone();
-        let (snd, rcv) = unbounded();
-        thread::spawn(move || {
+        let fees = self.fees;
+        let off_tick_policy = self.off_tick_policy;
+        let raw_log = wss::RawLog::new(self.log_raw, "gdax");
+        StreamHandle::spawn(move |snd, shutdown| {
             debug!("initiating WebSocket connection at {}", streaming_endpoint);
             
             if let Err(err) = ws::connect(streaming_endpoint, |out| {
-                wss::Handler::new(out, snd.clone(), wss::KeepAlive::False, HandlerImpl {
+                shutdown.register(&out);
+                let handler = HandlerImpl::new(
                     symbol,
                     flags,
-                    state: SubscriptionState::NotSubscribed,
-                    keys: keys.clone(),
-                    orders: HashMap::new(),
-                    order_ids: order_ids.clone(),
-                })
+                    keys.clone(),
+                    order_ids.clone(),
+                    fees,
+                    off_tick_policy,
+                    raw_log
+                );
+                wss::Handler::new(out, snd.clone(), wss::KeepAlive::False, raw_log, handler)
             })
             {
                 error!("WebSocket connection terminated with error: `{}`", err);
             }
-        });
-        
-        rcv
+        })
     }
 }
 
@@ -57,7 +60,7 @@ enum SubscriptionState {
     Subscribed,
 }
 
-struct HandlerImpl {
+pub(super) struct HandlerImpl {
     symbol: Symbol,
     flags: NotificationFlags,
     state: SubscriptionState,
@@ -67,7 +70,11 @@ struct HandlerImpl {
     orders: HashMap<String, OrderConfirmation>,
 
     /// client order id => server order id (shared with `Client`)
-    order_ids: Arc<CHashMap<String, String>>,
+    order_ids: Arc<dyn OrderIdStore>,
+
+    fees: Fees,
+    off_tick_policy: OffTickPolicy,
+    raw_log: wss::RawLog,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
@@ -141,6 +148,17 @@ struct GdaxDone<'a> {
     time: &'a str,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct GdaxChange<'a> {
+    time: &'a str,
+    order_id: &'a str,
+    price: Option<&'a str>,
+
+    /// Market orders specified by funds carry `new_funds` and `old_funds` instead.
+    new_size: Option<&'a str>,
+    old_size: Option<&'a str>,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
 struct GdaxError<'a> {
     message: &'a str,
@@ -154,18 +172,69 @@ struct EventType<'a> {
 }
 
 impl HandlerImpl {
+    pub(super) fn new(
+        symbol: Symbol,
+        flags: NotificationFlags,
+        keys: Option<Keys>,
+        order_ids: Arc<dyn OrderIdStore>,
+        fees: Fees,
+        off_tick_policy: OffTickPolicy,
+        raw_log: wss::RawLog
+    ) -> Self
+    {
+        HandlerImpl {
+            symbol,
+            flags,
+            state: SubscriptionState::NotSubscribed,
+            keys,
+            orders: HashMap::new(),
+            order_ids,
+            fees,
+            off_tick_policy,
+            raw_log,
+        }
+    }
+
     fn convert_gdax_update(&self, l: (&str, &str), side: Side)
         -> Result<LimitUpdate, tick::ConversionError>
     {
+        let price_tick = self.symbol.price_tick();
+        let price = match self.off_tick_policy {
+            OffTickPolicy::Skip => price_tick.ticked_exact(l.0)?,
+            OffTickPolicy::Truncate => price_tick.ticked_rounded(l.0, RoundingMode::Down)?,
+            OffTickPolicy::Round => price_tick.ticked_rounded(l.0, RoundingMode::Nearest)?,
+        };
+
         Ok(
             LimitUpdate {
                 side,
-                price: self.symbol.price_tick().ticked(l.0)?,
-                size: self.symbol.size_tick().ticked(l.1)?,
+                price: Price(price),
+                size: Size(self.symbol.size_tick().ticked(l.1)?),
             }
         )
     }
 
+    /// Convert the `(side, price, size)` limits of a `snapshot` or `l2update` message,
+    /// dropping the ones which cannot be converted so that a single bad limit does not
+    /// stall the whole order book.
+    fn convert_gdax_updates<'a, I>(&self, limits: I) -> Vec<Timestamped<LimitUpdate>>
+        where I: Iterator<Item = (&'a str, &'a str, &'a str)>
+    {
+        limits.filter_map(|(side, price, size)| {
+            let update = self.convert_gdax_side(side).and_then(|side| {
+                Ok(self.convert_gdax_update((price, size), side)?)
+            });
+
+            match update {
+                Ok(update) => Some(update.timestamped()),
+                Err(err) => {
+                    warn!("skipping {} limit at `{}`: `{}`", side, price, err);
+                    None
+                }
+            }
+        }).collect()
+    }
+
     fn convert_gdax_side(&self, side: &str) -> Result<Side, failure::Error> {
         let side = match side {
             "buy" => Side::Bid,
@@ -186,37 +255,25 @@ impl HandlerImpl {
                 self.state = SubscriptionState::Subscribed;
             },
 
-            "snapshot" if self.flags.contains(NotificationFlags::ORDER_BOOK) => {
+            "snapshot" if self.flags.contains_book() => {
                 let snapshot: GdaxBookSnapshot<'_> = serde_json::from_str(json)?;
 
                 let bid = snapshot.bids
                     .into_iter()
-                    .map(|(price, size)| self.convert_gdax_update((price, size), Side::Bid))
-                    .map(|l| Ok(l?.timestamped()));
+                    .map(|(price, size)| ("buy", price, size));
 
                 let ask = snapshot.asks
                     .into_iter()
-                    .map(|(price, size)| self.convert_gdax_update((price, size), Side::Ask))
-                    .map(|l| Ok(l?.timestamped()));
-                
-                let notif = Notification::LimitUpdates(
-                    bid.chain(ask).collect::<Result<Vec<_>, tick::ConversionError>>()?
-                );
+                    .map(|(price, size)| ("sell", price, size));
+
+                let notif = Notification::BookSnapshot(self.convert_gdax_updates(bid.chain(ask)));
                 out.unbounded_send(notif).unwrap();
             },
 
-            "l2update" if self.flags.contains(NotificationFlags::ORDER_BOOK) => {
+            "l2update" if self.flags.contains_book() => {
                 let update: GdaxLimitUpdate<'_> = serde_json::from_str(json)?;
 
-                let updates = update.changes
-                    .into_iter()
-                    .map(|(side, price, size)| {
-                        let side = self.convert_gdax_side(side)?;
-                        Ok(self.convert_gdax_update((price, size), side)?)
-                    })
-                    .map(|l: Result<_, failure::Error>| Ok(l?.timestamped()));
-
-                let updates = updates.collect::<Result<Vec<_>, failure::Error>>()?;
+                let updates = self.convert_gdax_updates(update.changes.into_iter());
                 if !updates.is_empty() {
                     let notif = Notification::LimitUpdates(updates);
                     out.unbounded_send(notif).unwrap();
@@ -224,17 +281,18 @@ impl HandlerImpl {
             },
 
             "match"
-                if self.flags.contains(NotificationFlags::TRADES | NotificationFlags::ORDERS) =>
+                if self.flags.contains_trades() || self.flags.contains_orders() =>
             {
                 let trade: GdaxMatch<'_> = serde_json::from_str(json)?;
                 let timestamp = convert_str_timestamp(trade.time)?;
                 
-                let size = self.symbol.size_tick().ticked(trade.size)?;
-                let price = self.symbol.price_tick().ticked(trade.price)?;
+                let size = Size(self.symbol.size_tick().ticked(trade.size)?);
+                let price = Price(self.symbol.price_tick().ticked(trade.price)?);
 
                 // An order which is about us
-                if self.flags.contains(NotificationFlags::ORDERS) && trade.profile_id.is_some() {
-                    let update_order = |order: &mut OrderConfirmation| {
+                if self.flags.contains_orders() && trade.profile_id.is_some() {
+                    let symbol = self.symbol;
+                    let update_order = |order: &mut OrderConfirmation, rate| {
                         order.size -= size;
 
                         out.unbounded_send(
@@ -243,21 +301,23 @@ impl HandlerImpl {
                                 consumed_size: size,
                                 consumed_price: price,
                                 remaining_size: order.size,
-                                commission: 0,
+                                commission: Fees::commission(rate, symbol, size, price),
+                                commission_asset: symbol.quote_asset().map(|a| a.to_owned()),
+                                is_fill: true,
                             }.with_timestamp(timestamp))
                         ).unwrap();
                     };
 
                     // These two conditions are exclusive.
                     if let Some(order) = self.orders.get_mut(trade.taker_order_id) {
-                        update_order(order);
+                        update_order(order, self.fees.taker);
                     }
                     if let Some(order) = self.orders.get_mut(trade.maker_order_id) {
-                        update_order(order);
+                        update_order(order, self.fees.maker);
                     }
                 }
 
-                if self.flags.contains(NotificationFlags::TRADES) {
+                if self.flags.contains_trades() {
                     out.unbounded_send(
                         Notification::Trade(Trade {
                             size,
@@ -268,12 +328,12 @@ impl HandlerImpl {
                 }
             },
 
-            "received" if self.flags.contains(NotificationFlags::ORDERS) => {
+            "received" if self.flags.contains_orders() => {
                 let received: GdaxReceived<'_> = serde_json::from_str(json)?;
                 let timestamp = convert_str_timestamp(received.time)?;
 
-                let size = self.symbol.size_tick().ticked(received.size)?;
-                let price = self.symbol.price_tick().ticked(received.price)?;
+                let size = Size(self.symbol.size_tick().ticked(received.size)?);
+                let price = Price(self.symbol.price_tick().ticked(received.price)?);
                 let side = self.convert_gdax_side(received.side)?;
 
                 // The order id specified by the user, which defaults to the server order id
@@ -283,7 +343,13 @@ impl HandlerImpl {
                 
                 // Don't forget to update the concurrent map `server order id => client order id`
                 // in case the WebSocket notif arrives before the HTTP response
-                self.order_ids.insert(order_id.clone(), received.order_id.to_owned());
+                if let Err(err) = self.order_ids.insert(
+                    order_id.clone(),
+                    received.order_id.to_owned()
+                )
+                {
+                    error!("failed to store order id {}: `{}`", order_id, err);
+                }
                 debug!("insert order id {} (from WSS)", order_id);
                 
                 let order = OrderConfirmation {
@@ -300,7 +366,7 @@ impl HandlerImpl {
                 ).unwrap();
             }
 
-            "done" if self.flags.contains(NotificationFlags::ORDERS) => {
+            "done" if self.flags.contains_orders() => {
                 let done: GdaxDone<'_> = serde_json::from_str(json)?;
                 let timestamp = convert_str_timestamp(done.time)?;
 
@@ -320,6 +386,44 @@ impl HandlerImpl {
                 ).unwrap();
             }
 
+            // The size of an order decreased without trading, e.g. because of self-trade
+            // prevention.
+            "change" if self.flags.contains_orders() => {
+                let change: GdaxChange<'_> = serde_json::from_str(json)?;
+                let timestamp = convert_str_timestamp(change.time)?;
+
+                let (new_size, old_size) = match (change.new_size, change.old_size) {
+                    (Some(new_size), Some(old_size)) => (new_size, old_size),
+                    _ => return Ok(()),
+                };
+                let new_size = Size(self.symbol.size_tick().ticked(new_size)?);
+                let old_size = Size(self.symbol.size_tick().ticked(old_size)?);
+
+                let order = match self.orders.get_mut(change.order_id) {
+                    Some(order) => order,
+                    None => return Ok(()),
+                };
+                order.size = new_size;
+
+                let consumed_price = match change.price {
+                    Some(price) => Price(self.symbol.price_tick().ticked(price)?),
+                    None => order.price,
+                };
+
+                // No trade happened, hence no commission was paid.
+                out.unbounded_send(
+                    Notification::OrderUpdate(OrderUpdate {
+                        order_id: order.order_id.clone(),
+                        consumed_size: old_size.saturating_sub(new_size),
+                        consumed_price,
+                        remaining_size: new_size,
+                        commission: 0,
+                        commission_asset: self.symbol.quote_asset().map(|a| a.to_owned()),
+                        is_fill: false,
+                    }.with_timestamp(timestamp))
+                ).unwrap();
+            }
+
             "error" => {
                 let error: GdaxError<'_> = serde_json::from_str(json)?;
                 bail!("{}: {:?}", error.message, error.reason);
@@ -346,7 +450,7 @@ impl wss::HandlerImpl for HandlerImpl {
         let auth = self.keys.as_ref().map(|keys| {
             use openssl::{sign::Signer, hash::MessageDigest};
 
-            let timestamp = timestamp_ms() as f64 / 1000.;
+            let timestamp = keys.clock.timestamp_ms() as f64 / 1000.;
             let mut signer = Signer::new(MessageDigest::sha256(), &keys.secret_key).unwrap();
             let what = format!("{}GET/users/self/verify", timestamp);
             signer.update(what.as_bytes()).unwrap();
@@ -369,7 +473,10 @@ impl wss::HandlerImpl for HandlerImpl {
         };
         
         match serde_json::to_string(&subscription) {
-            Ok(value) => out.send(value),
+            Ok(value) if subscription.auth.is_some() => {
+                self.raw_log.send_secret(out, value, "subscription")
+            }
+            Ok(value) => self.raw_log.send(out, value),
             Err(err) => {
                 panic!("failed to serialize `Subscription`: `{}`", err);
             }
@@ -0,0 +1,147 @@
+#![cfg(test)]
+
+//! A test harness feeding recorded raw WebSocket frames to the `HandlerImpl` of an exchange,
+//! for checking that the order book rebuilt from the resulting notifications is right, as
+//! well as serving canned REST responses through `MockTransport`.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+use futures::prelude::*;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver};
+use hyper::{Body, Chunk, Request, StatusCode};
+use crate::Side;
+use crate::tick::{TickUnit, Price, Size};
+use crate::order_book::{LimitUpdate, OrderBook};
+use crate::api::{Notification, Params};
+use crate::api::wss::{self, NotifSender};
+use crate::api::transport::{HttpTransport, ResponseFuture};
+
+/// Return a `NotifSender` to be passed to `HandlerImpl::on_message`, along with the
+/// receiving end.
+crate fn notif_sender() -> (NotifSender, UnboundedReceiver<Notification>) {
+    unbounded()
+}
+
+/// Feed `frames` to `handler` in order, as if they were received from the WebSocket
+/// connection, and return all the notifications sent by `handler`.
+///
+/// # Panics
+/// Panic if `handler` fails to process a frame.
+crate fn replay<H: wss::HandlerImpl>(handler: &mut H, frames: &[&str]) -> Vec<Notification> {
+    let (snd, rcv) = notif_sender();
+    for frame in frames {
+        if let Err(err) = handler.on_message(frame, &snd) {
+            panic!("failed to process frame `{}`: `{}`", frame, err);
+        }
+    }
+    drop(snd);
+    rcv.wait().collect::<Result<Vec<_>, _>>().unwrap()
+}
+
+/// Rebuild an order book from `notifications` the same way `LiveOrderBook` does.
+crate fn rebuild_order_book(notifications: &[Notification]) -> OrderBook {
+    let mut order_book = OrderBook::new();
+    for notif in notifications {
+        match notif {
+            Notification::LimitUpdates(updates) => {
+                order_book.update_batch(updates.iter().map(|update| update.into_inner()));
+            }
+            Notification::BookSnapshot(updates) => {
+                order_book = OrderBook::new();
+                order_book.update_batch(updates.iter().map(|update| update.into_inner()));
+            }
+            Notification::BookResync => order_book = OrderBook::new(),
+            _ => (),
+        }
+    }
+    order_book
+}
+
+/// Return an order book with the given `(price, size)` limits on each side.
+crate fn order_book(bid: &[(TickUnit, TickUnit)], ask: &[(TickUnit, TickUnit)]) -> OrderBook {
+    let mut order_book = OrderBook::new();
+    order_book.update_batch(
+        bid.iter()
+            .map(|&(price, size)| LimitUpdate::new(Price(price), Size(size), Side::Bid))
+            .chain(ask.iter().map(|&(price, size)| {
+                LimitUpdate::new(Price(price), Size(size), Side::Ask)
+            }))
+    );
+    order_book
+}
+
+/// Assert that `actual` is `expected`, listing the limits which differ otherwise.
+crate fn assert_same_book(actual: &OrderBook, expected: &OrderBook) {
+    let differences = actual.differences(expected);
+    if differences.is_empty() {
+        return;
+    }
+
+    let mut message = String::from("order books differ, expected:\n");
+    for update in differences {
+        message.push_str(&format!(
+            "  {:?} {} -> size {} (found {})\n",
+            update.side,
+            update.price,
+            update.size,
+            actual.size_at_limit(update.side, update.price)
+        ));
+    }
+    panic!("{}", message);
+}
+
+/// Feed `frames` to `handler` and assert that the rebuilt order book is `expected`.
+/// Return the notifications sent by `handler`.
+crate fn assert_book<H: wss::HandlerImpl>(handler: &mut H, frames: &[&str], expected: &OrderBook)
+    -> Vec<Notification>
+{
+    let notifications = replay(handler, frames);
+    assert_same_book(&rebuild_order_book(&notifications), expected);
+    notifications
+}
+
+/// Return `Params` pointing to `localhost`, for clients talking to a `MockTransport`.
+crate fn params() -> Params {
+    Params {
+        streaming_endpoint: "wss://localhost".to_owned(),
+        rest_endpoint: "https://localhost".to_owned(),
+        weight_per_minute: None,
+    }
+}
+
+/// A transport serving canned responses in order, and recording the requests it received.
+#[derive(Default)]
+crate struct MockTransport {
+    responses: Mutex<VecDeque<(StatusCode, String)>>,
+    requests: Mutex<Vec<Request<Chunk>>>,
+}
+
+impl MockTransport {
+    /// Queue a response with the given `status` and `body`.
+    crate fn respond(&self, status: StatusCode, body: &str) {
+        self.responses.lock().unwrap().push_back((status, body.to_owned()));
+    }
+
+    /// Return the requests received so far, with their whole body.
+    crate fn requests(&self) -> MutexGuard<'_, Vec<Request<Chunk>>> {
+        self.requests.lock().unwrap()
+    }
+
+    /// Return the paths of the requests received so far.
+    crate fn paths(&self) -> Vec<String> {
+        self.requests().iter().map(|request| request.uri().path().to_owned()).collect()
+    }
+}
+
+impl HttpTransport for MockTransport {
+    /// # Panics
+    /// Panic if no response is queued.
+    fn request(&self, request: Request<Body>) -> ResponseFuture {
+        let (parts, body) = request.into_parts();
+        let body = body.concat2().wait().unwrap();
+        self.requests.lock().unwrap().push(Request::from_parts(parts, body));
+
+        let (status, body) = self.responses.lock().unwrap().pop_front().unwrap();
+        Box::new(Ok((status, body.into())).into_future())
+    }
+}
@@ -3,9 +3,12 @@
 pub mod errors;
 mod rest;
 mod wss;
+mod test;
 
 use serde_derive::{Serialize, Deserialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 use log::debug;
 use futures::prelude::*;
 use crate::api::{
@@ -13,18 +16,24 @@ use crate::api::{
     Params,
     ApiClient,
     GenerateOrderId,
-    Notification,
     NotificationFlags,
     Order,
     OrderAck,
     Cancel,
     CancelAck,
     Balances,
+    DepositAddress,
+    Ticker,
+    Trade,
+    WithdrawAck,
+    WithdrawClient,
 };
-use crate::api::symbol::{Symbol, WithSymbol};
+use crate::api::symbol::{self, Symbol, WithSymbol};
+use crate::api::rate_limit::RateLimiter;
+use crate::api::transport::{self, HttpTransport};
 use crate::api::timestamp::{Timestamped, IntoTimestamped};
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 /// An HitBTC key pair: public key + secret key.
 pub struct KeyPair {
     public_key: String,
@@ -41,19 +50,43 @@ impl KeyPair {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyPair")
+            .field("public_key", &self.public_key)
+            .field("secret_key", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
 struct Keys {
     public_key: String,
     secret_key: String,
     auth_header: String,
 }
 
+impl fmt::Debug for Keys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keys")
+            .field("public_key", &self.public_key)
+            .field("secret_key", &"<redacted>")
+            .field("auth_header", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Clone)]
 /// An HitBTC API client.
 pub struct Client {
     params: Params,
     keys: Option<Keys>,
     symbols: HashMap<String, Symbol>,
-    http_client: hyper::Client<hyper_tls::HttpsConnector<hyper::client::HttpConnector>>,
+    rate_limiter: Option<RateLimiter>,
+    log_raw: bool,
+    http_client: Arc<dyn HttpTransport>,
+    /// Custom order id generation scheme, see `Client::with_order_id_generator`.
+    order_id_generator: Option<api::OrderIdGenerator>,
 }
 
 impl Client {
@@ -64,10 +97,24 @@ impl Client {
     /// # Note
     /// This method will block, fetching the available symbols from HitBTC.
     pub fn new(params: Params, key_pair: Option<KeyPair>) -> Result<Self, failure::Error> {
-        let http_client = hyper::Client::builder().build::<_, hyper::Body>(
-            hyper_tls::HttpsConnector::new(2)?
-        );
+        Self::with_transport(params, key_pair, transport::https()?)
+    }
 
+    /// Create a new HitBTC API client to the demo API, see `Params::hitbtc_demo` and
+    /// `Client::new`.
+    pub fn new_testnet(key_pair: Option<KeyPair>) -> Result<Self, failure::Error> {
+        Self::new(Params::hitbtc_demo(), key_pair)
+    }
+
+    /// Create a new HitBTC API client like `Client::new`, sending its REST requests
+    /// (including the ones made by `Client::new`) through `http_client`.
+    pub fn with_transport(
+        params: Params,
+        key_pair: Option<KeyPair>,
+        http_client: Arc<dyn HttpTransport>
+    ) -> Result<Self, failure::Error>
+    {
+        let rate_limiter = params.rate_limiter();
         let mut client = Client {
             params,
             keys: key_pair.map(|key_pair| {
@@ -80,7 +127,10 @@ impl Client {
                 }
             }),
             symbols: HashMap::new(),
+            rate_limiter,
+            log_raw: false,
             http_client,
+            order_id_generator: None,
         };
 
         use tokio::runtime::current_thread;
@@ -91,13 +141,59 @@ impl Client {
 
         Ok(client)
     }
+
+    /// Log every raw inbound text frame of the notification streams, as well as every
+    /// outbound subscription payload, at `trace` level with `"hitbtc"` as the log target.
+    /// Payloads carrying credentials are not logged verbatim. Disabled by default.
+    pub fn with_log_raw(self, log_raw: bool) -> Self {
+        Client {
+            log_raw,
+            ..self
+        }
+    }
+
+    /// Use `generator` instead of `GenerateOrderId::new_order_id` for generating order ids
+    /// from a hint, see `Order::with_order_id_from`.
+    pub fn with_order_id_generator<F>(self, generator: F) -> Self
+        where F: Fn(&str) -> String + Send + Sync + 'static
+    {
+        Client {
+            order_id_generator: Some(Arc::new(generator)),
+            ..self
+        }
+    }
+
+    /// Return the rate limiter delaying the REST requests of this client, if rate limiting
+    /// is enabled in `Params`.
+    pub fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
 }
 
 impl ApiClient for Client {
-    type Stream = futures::sync::mpsc::UnboundedReceiver<Notification>;
+    type Stream = api::stream::StreamHandle;
 
     fn find_symbol(&self, symbol: &str) -> Option<Symbol> {
-        self.symbols.get(&symbol.to_lowercase()).cloned()
+        self.symbols.get(&symbol::canonical_name(symbol)).cloned()
+    }
+
+    fn generate_order_id(&self, hint: &str) -> String {
+        match &self.order_id_generator {
+            Some(generator) => generator(hint),
+            None => Self::new_order_id(hint),
+        }
+    }
+
+    fn ticker(&self, symbol: Symbol)
+        -> Box<dyn Future<Item = Ticker, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.ticker_impl(symbol))
+    }
+
+    fn recent_trades(&self, symbol: Symbol, limit: usize)
+        -> Box<dyn Future<Item = Vec<Timestamped<Trade>>, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.recent_trades_impl(symbol, limit))
     }
 
     fn stream_with_flags(&self, symbol: Symbol, flags: NotificationFlags) -> Self::Stream {
@@ -107,7 +203,7 @@ impl ApiClient for Client {
     fn order(&self, order: WithSymbol<&Order>)
         -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
     {
-        Box::new(self.order_impl(order))
+        self.order_impl(order)
     }
 
     fn cancel(&self, cancel: WithSymbol<&Cancel>)
@@ -116,6 +212,12 @@ impl ApiClient for Client {
         Box::new(self.cancel_impl(cancel))
     }
 
+    fn replace(&self, replaced: WithSymbol<&Order>, new: &Order)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
+    {
+        self.replace_impl(replaced, new)
+    }
+
     fn ping(&self)
         -> Box<dyn Future<Item = Timestamped<()>, Error = api::errors::Error> + Send + 'static>
     {
@@ -127,6 +229,24 @@ impl ApiClient for Client {
     {
         Box::new(self.balances_impl())
     }
+
+    fn deposit_address(&self, asset: &str)
+        -> Box<dyn Future<Item = DepositAddress, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(self.deposit_address_impl(asset))
+    }
+}
+
+impl WithdrawClient for Client {
+    fn withdraw(&self, _: &str, _: &str, _: &str, _: Option<&str>)
+        -> Box<dyn Future<Item = WithdrawAck, Error = api::errors::Error> + Send + 'static>
+    {
+        Box::new(
+            Err(api::errors::RestErrorKind::Unsupported.into())
+                .map_err(api::errors::ApiError::RestError)
+                .into_future()
+        )
+    }
 }
 
 impl GenerateOrderId for Client {
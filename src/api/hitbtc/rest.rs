@@ -5,7 +5,7 @@ use std::collections::HashMap;
 use hyper::Method;
 use log::error;
 use crate::Side;
-use crate::tick::Tick;
+use crate::tick::{Tick, TickUnit, Price, Size};
 use crate::api::{
     self,
     OrderType,
@@ -18,7 +18,8 @@ use crate::api::{
 use crate::api::timestamp::{convert_str_timestamp, Timestamped, IntoTimestamped};
 use crate::api::query_string::QueryString;
 use crate::api::errors::ErrorKinded;
-use crate::api::symbol::{Symbol, WithSymbol};
+use crate::api::rate_limit;
+use crate::api::symbol::{self, Symbol, WithSymbol};
 use crate::api::hitbtc::Client;
 use crate::api::hitbtc::errors::RestError;
 
@@ -40,6 +41,9 @@ impl AsStr for OrderType {
         match self {
             OrderType::Limit => "limit",
             OrderType::LimitMaker => "limit",
+            OrderType::Market => "market",
+            OrderType::StopLoss => "stopMarket",
+            OrderType::StopLossLimit => "stopLimit",
         }
     }
 }
@@ -72,6 +76,8 @@ struct HitBtcCancelAck<'a> {
 #[allow(non_snake_case)]
 struct HitBtcSymbol<'a> {
     id: &'a str,
+    baseCurrency: &'a str,
+    quoteCurrency: &'a str,
     quantityIncrement: &'a str,
     tickSize: &'a str,
 }
@@ -83,6 +89,29 @@ struct HitBtcBalance<'a> {
     reserved: &'a str,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct HitBtcTicker<'a> {
+    bid: Option<&'a str>,
+    ask: Option<&'a str>,
+    last: Option<&'a str>,
+    volume: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct HitBtcTrade<'a> {
+    price: &'a str,
+    quantity: &'a str,
+    side: &'a str,
+    timestamp: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct HitBtcDepositAddress<'a> {
+    address: &'a str,
+    paymentId: Option<&'a str>,
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
 struct HitBtcError<'a> {
     #[serde(borrow)]
@@ -121,13 +150,11 @@ impl Client {
 
         // Unwrap because it is a bug if this fails (header failed to parse or something)
         let request = request.body(query.into()).unwrap();
-        self.http_client.request(request).and_then(|res| {
-            let status = res.status();
-            res.into_body().concat2().and_then(move |body| {
-                Ok((status, body))
-            })
+        let http_client = self.http_client.clone();
+        rate_limit::acquire(self.rate_limiter.as_ref(), 1).then(move |_| {
+            http_client.request(request)
         })
-        .map_err(api::errors::RequestError::new)
+        .map_err(|err| api::errors::RequestError::new(err.compat()))
         .map_err(api::errors::ApiError::RequestError)
         .and_then(|(status, body)| {
             if status != hyper::StatusCode::OK {
@@ -143,24 +170,40 @@ impl Client {
     }
 
     crate fn order_impl(&self, order: WithSymbol<&Order>)
-        -> impl Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
     {
         use std::borrow::Borrow;
 
+        if let Err(err) = order.validate(&order.symbol()) {
+            return Box::new(Err(err.into()).into_future());
+        }
+
+        if order.funds.is_some() {
+            return Box::new(
+                Err(api::errors::RestErrorKind::Unsupported.into())
+                    .map_err(api::errors::ApiError::RestError)
+                    .into_future()
+            );
+        }
+
         let mut query = QueryString::new();
         let symbol = order.symbol();
         query.push_str("symbol", symbol.name());
         query.push_str("side", order.side.as_str());
         query.push_str("type", order.type_.as_str());
-        query.push_str("timeInForce", order.time_in_force.as_str());
         query.push_str(
             "quantity",
             order.size.unticked(symbol.size_tick()).borrow() as &str
         );
-        query.push_str(
-            "price",
-            order.price.unticked(symbol.price_tick()).borrow() as &str
-        );
+
+        // Market orders use the default time in force of the exchange.
+        if !order.type_.is_market() {
+            query.push_str("timeInForce", order.time_in_force.as_str());
+            query.push_str(
+                "price",
+                order.price.unticked(symbol.price_tick()).borrow() as &str
+            );
+        }
 
         if let Some(order_id) = &order.order_id {
             query.push_str("clientOrderId", order_id);
@@ -170,7 +213,14 @@ impl Client {
             query.push_str("postOnly", "true");
         }
 
-        self.request("api/2/order", Method::POST, query).and_then(|body| {
+        if let (true, Some(stop_price)) = (order.type_.is_stop(), &order.stop_price) {
+            query.push_str(
+                "stopPrice",
+                stop_price.unticked(symbol.price_tick()).borrow() as &str
+            );
+        }
+
+        let fut = self.request("api/2/order", Method::POST, query).and_then(|body| {
             let ack: HitBtcOrderAck<'_> = serde_json::from_slice(&body)
                 .map_err(api::errors::RequestError::new)
                 .map_err(api::errors::ApiError::RequestError)?;
@@ -182,7 +232,73 @@ impl Client {
             Ok(OrderAck {
                 order_id: ack.clientOrderId.to_owned(),
             }.with_timestamp(timestamp))
-        })
+        });
+        Box::new(fut)
+    }
+
+    crate fn replace_impl(&self, replaced: WithSymbol<&Order>, new: &Order)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
+    {
+        use std::borrow::Borrow;
+        use uuid::Uuid;
+
+        let order_id = match replaced.order_id() {
+            Some(order_id) => order_id,
+            None => return Box::new(
+                Err(api::errors::RestErrorKind::InvalidRequest.into())
+                    .map_err(api::errors::ApiError::RestError)
+                    .into_future()
+            ),
+        };
+
+        // The native replacement only amends the size and the price of an order.
+        if new.side != replaced.side
+            || new.type_ != replaced.type_
+            || new.time_in_force != replaced.time_in_force
+        {
+            return Box::new(
+                Err(api::errors::RestErrorKind::InvalidRequest.into())
+                    .map_err(api::errors::ApiError::RestError)
+                    .into_future()
+            );
+        }
+
+        let symbol = replaced.symbol();
+        if let Err(err) = new.validate(&symbol) {
+            return Box::new(Err(err.into()).into_future());
+        }
+
+        let mut query = QueryString::new();
+        query.push_str(
+            "quantity",
+            new.size.unticked(symbol.size_tick()).borrow() as &str
+        );
+        query.push_str(
+            "price",
+            new.price.unticked(symbol.price_tick()).borrow() as &str
+        );
+
+        // HitBTC requires an id for the new order.
+        match &new.order_id {
+            Some(new_order_id) => query.push_str("requestClientId", new_order_id),
+            None => query.push_str("requestClientId", &Uuid::new_v4().simple().to_string()),
+        }
+
+        let endpoint = format!("api/2/order/{}", order_id);
+        let fut = self.request(&endpoint, Method::PATCH, query).and_then(|body| {
+            let ack: HitBtcOrderAck<'_> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            let timestamp = convert_str_timestamp(ack.createdAt)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            Ok(OrderAck {
+                order_id: ack.clientOrderId.to_owned(),
+            }.with_timestamp(timestamp))
+        });
+        Box::new(fut)
     }
 
     crate fn cancel_impl(&self, cancel: WithSymbol<&Cancel>)
@@ -224,6 +340,97 @@ impl Client {
         })
     }
 
+    crate fn ticker_impl(&self, symbol: Symbol)
+        -> impl Future<Item = api::Ticker, Error = api::errors::Error> + Send + 'static
+    {
+        let endpoint = format!("api/2/public/ticker/{}", symbol.name());
+        let query = QueryString::new();
+
+        self.request(&endpoint, Method::GET, query).and_then(move |body| {
+            let ticker: HitBtcTicker<'_> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            // HitBTC sends `null` values for empty sides or if no trade happened yet.
+            let convert = |tick: Tick, value: Option<&str>, default| {
+                value.map(|value| tick.ticked(value))
+                    .unwrap_or(Ok(default))
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)
+            };
+
+            Ok(api::Ticker {
+                best_bid: Price(convert(symbol.price_tick(), ticker.bid, 0)?),
+                best_ask: Price(convert(symbol.price_tick(), ticker.ask, TickUnit::max_value())?),
+                last: Price(convert(symbol.price_tick(), ticker.last, 0)?),
+                volume_24h: Size(convert(symbol.size_tick(), Some(ticker.volume), 0)?),
+            })
+        })
+    }
+
+    crate fn recent_trades_impl(&self, symbol: Symbol, limit: usize)
+        -> impl Future<Item = Vec<Timestamped<api::Trade>>, Error = api::errors::Error> + Send + 'static
+    {
+        let endpoint = format!("api/2/public/trades/{}?limit={}", symbol.name(), limit);
+        let query = QueryString::new();
+
+        self.request(&endpoint, Method::GET, query).and_then(move |body| {
+            let trades: Vec<HitBtcTrade<'_>> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            let convert = |tick: Tick, value: &str| {
+                tick.ticked(value)
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)
+            };
+
+            // Trades are sent newest first. As for the `updateTrades` notifications, `side`
+            // is used as the side of the maker.
+            let mut trades = trades.into_iter().take(limit).map(|trade| {
+                let maker_side = match trade.side {
+                    "buy" => Side::Bid,
+                    "sell" => Side::Ask,
+                    _ => Err(api::errors::RestErrorKind::OtherSide.into())
+                        .map_err(api::errors::ApiError::RestError)?,
+                };
+
+                let timestamp = convert_str_timestamp(trade.timestamp)
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)?;
+
+                Ok(api::Trade {
+                    price: Price(convert(symbol.price_tick(), trade.price)?),
+                    size: Size(convert(symbol.size_tick(), trade.quantity)?),
+                    maker_side,
+                }.with_timestamp(timestamp))
+            }).collect::<Result<Vec<_>, _>>()?;
+
+            trades.reverse();
+            Ok(trades)
+        })
+    }
+
+    crate fn deposit_address_impl(&self, asset: &str)
+        -> impl Future<Item = api::DepositAddress, Error = api::errors::Error> + Send + 'static
+    {
+        let endpoint = format!("api/2/account/crypto/address/{}", asset);
+        let query = QueryString::new();
+
+        self.request(&endpoint, Method::GET, query).and_then(|body| {
+            let address: HitBtcDepositAddress<'_> = serde_json::from_slice(&body)
+                .map_err(api::errors::RequestError::new)
+                .map_err(api::errors::ApiError::RequestError)?;
+
+            Ok(api::DepositAddress {
+                address: address.address.to_owned(),
+                tag: address.paymentId
+                    .filter(|tag| !tag.is_empty())
+                    .map(|tag| tag.to_owned()),
+            })
+        })
+    }
+
     crate fn get_symbols(&self)
         -> impl Future<Item = HashMap<String, Symbol>, Error = api::errors::Error> + Send + 'static
     {
@@ -253,7 +460,9 @@ impl Client {
                 };
 
                 if let Some(symbol) = Symbol::new(p.id, price_tick, size_tick) {
-                    symbols.insert(symbol.name().to_lowercase(), symbol);
+                    let symbol = symbol.with_base_asset(p.baseCurrency)
+                        .with_quote_asset(p.quoteCurrency);
+                    symbols.insert(symbol::canonical_name(symbol.name()), symbol);
                 } else {
                     error!("symbol name too long: `{}`", p.id);
                 }
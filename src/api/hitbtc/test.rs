@@ -0,0 +1,125 @@
+#![cfg(test)]
+
+use std::sync::Arc;
+use futures::prelude::*;
+use hyper::StatusCode;
+use crate::Side;
+use crate::tick::{Tick, Price, Size};
+use crate::api::{
+    ApiClient,
+    Notification,
+    NotificationFlags,
+    Params,
+    Trade,
+    Order,
+    OrderType,
+    TimeInForce,
+};
+use crate::api::errors::{ApiError, RestErrorKind};
+use crate::api::harness::{self, MockTransport};
+use crate::api::wss::RawLog;
+use crate::api::symbol::{Symbol, IntoWithSymbol};
+use crate::api::hitbtc::{Client, KeyPair};
+use crate::api::hitbtc::wss::HandlerImpl;
+
+#[test]
+fn book_snapshot_then_updates() {
+    let symbol = Symbol::new("ETHBTC", Tick::new(1_000_000), Tick::new(1000)).unwrap();
+    let mut handler = HandlerImpl::new(
+        symbol,
+        NotificationFlags::ALL,
+        None,
+        RawLog::new(false, "hitbtc")
+    );
+
+    let frames = [
+        r#"{"jsonrpc":"2.0","method":"snapshotOrderbook","params":{"ask":[{"price":"0.054588","size":"0.245"},{"price":"0.054590","size":"1.000"}],"bid":[{"price":"0.054558","size":"0.500"}],"symbol":"ETHBTC","sequence":8073827}}"#,
+        r#"{"jsonrpc":"2.0","method":"updateOrderbook","params":{"ask":[{"price":"0.054588","size":"0.000"}],"bid":[{"price":"0.054557","size":"0.100"}],"symbol":"ETHBTC","sequence":8073828}}"#,
+
+        // Gap in the sequence: the book is resynchronized and updates are ignored until
+        // the next snapshot.
+        r#"{"jsonrpc":"2.0","method":"updateOrderbook","params":{"ask":[],"bid":[{"price":"0.054559","size":"3.000"}],"symbol":"ETHBTC","sequence":8073830}}"#,
+        r#"{"jsonrpc":"2.0","method":"updateOrderbook","params":{"ask":[],"bid":[{"price":"0.054560","size":"3.000"}],"symbol":"ETHBTC","sequence":8073831}}"#,
+
+        r#"{"jsonrpc":"2.0","method":"snapshotOrderbook","params":{"ask":[{"price":"0.054600","size":"2.000"}],"bid":[{"price":"0.054550","size":"1.500"}],"symbol":"ETHBTC","sequence":8073840}}"#,
+        r#"{"jsonrpc":"2.0","method":"updateOrderbook","params":{"ask":[],"bid":[{"price":"0.054551","size":"0.200"}],"symbol":"ETHBTC","sequence":8073841}}"#,
+    ];
+
+    let expected = harness::order_book(&[(54_551, 200), (54_550, 1500)], &[(54_600, 2000)]);
+    let notifs = harness::assert_book(&mut handler, &frames, &expected);
+    assert!(if let Notification::BookSnapshot(..) = notifs[0] { true } else { false });
+    assert_eq!(notifs[2], Notification::BookResync);
+    assert!(if let Notification::BookSnapshot(..) = notifs[3] { true } else { false });
+    assert_eq!(notifs.len(), 5);
+}
+
+#[test]
+fn key_pair_debug_is_redacted() {
+    let key_pair = KeyPair::new("my-public-key".to_owned(), "my-secret-key".to_owned());
+    let debug = format!("{:?}", key_pair);
+    assert!(debug.contains("my-public-key"));
+    assert!(!debug.contains("my-secret-key"));
+    assert!(debug.contains("<redacted>"));
+}
+
+#[test]
+fn recent_trades() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"[{"id":"ETHBTC","baseCurrency":"ETH","quoteCurrency":"BTC","quantityIncrement":"0.001","tickSize":"0.000001"}]"#);
+    transport.respond(StatusCode::OK, r#"[{"id":55124,"price":"0.054590","quantity":"0.100","side":"sell","timestamp":"2018-10-02T07:27:19.128Z"},{"id":55123,"price":"0.054588","quantity":"1.000","side":"buy","timestamp":"2018-10-02T07:27:18Z"}]"#);
+
+    let params = Params {
+        streaming_endpoint: "wss://localhost".to_owned(),
+        rest_endpoint: "https://localhost".to_owned(),
+        weight_per_minute: None,
+    };
+    let client = Client::with_transport(params, None, transport.clone()).unwrap();
+    let symbol = client.find_symbol("ETHBTC").unwrap();
+
+    // Trades are sent newest first, `side` being used as the side of the maker.
+    let trades = client.recent_trades(symbol, 2).wait().unwrap();
+    assert_eq!(trades.len(), 2);
+    assert_eq!(trades[0].timestamp(), 1538465238000);
+    assert_eq!(trades[0].into_inner(), Trade {
+        price: Price(54_588),
+        size: Size(1000),
+        maker_side: Side::Bid,
+    });
+    assert_eq!(trades[1].timestamp(), 1538465239128);
+    assert_eq!(trades[1].into_inner(), Trade {
+        price: Price(54_590),
+        size: Size(100),
+        maker_side: Side::Ask,
+    });
+
+    assert_eq!(transport.paths()[1], "/api/2/public/trades/ETHBTC");
+}
+
+#[test]
+fn replace_amends_size_and_price_only() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"[{"id":"ETHBTC","baseCurrency":"ETH","quoteCurrency":"BTC","quantityIncrement":"0.001","tickSize":"0.000001"}]"#);
+
+    let client = Client::with_transport(harness::params(), None, transport.clone()).unwrap();
+    let symbol = client.find_symbol("ETHBTC").unwrap();
+
+    // HitBTC cannot change the side, type or time in force of an order: such replacements
+    // are rejected without sending anything.
+    let order = || Order::new("0.054590", "0.100", Side::Bid);
+    let replaced = order().with_order_id::<Client>("old");
+    let news = vec![
+        Order::new("0.054590", "0.100", Side::Ask),
+        order().with_order_type(OrderType::LimitMaker),
+        order().with_time_in_force(TimeInForce::ImmediateOrCancel),
+    ];
+    for new in news {
+        match client.replace(replaced.with_symbol(symbol), &new).wait() {
+            Err(ApiError::RestError(err)) => {
+                assert_eq!(err.kind(), RestErrorKind::InvalidRequest)
+            }
+            other => panic!("expected a REST error, found `{:?}`", other),
+        }
+    }
+
+    assert_eq!(transport.paths(), vec!["/api/2/public/symbol".to_owned()]);
+}
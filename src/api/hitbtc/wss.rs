@@ -1,11 +1,9 @@
-use futures::sync::mpsc::{unbounded, UnboundedReceiver};
 use failure::{bail, format_err};
-use std::thread;
 use serde_derive::{Deserialize, Serialize};
-use log::{debug, error};
+use log::{debug, error, warn};
 use crate::Side;
 use crate::order_book::LimitUpdate;
-use crate::tick;
+use crate::tick::{self, Price, Size};
 use crate::api::{
     Notification,
     NotificationFlags,
@@ -15,18 +13,19 @@ use crate::api::{
     OrderUpdate,
 };
 use crate::api::wss;
+use crate::api::stream::StreamHandle;
 use crate::api::symbol::Symbol;
 use crate::api::timestamp::{convert_str_timestamp, IntoTimestamped};
 use crate::api::hitbtc::{Keys, Client};
 
 impl Client {
     crate fn new_stream(&self, symbol: Symbol, flags: NotificationFlags)
-        -> UnboundedReceiver<Notification>
+        -> StreamHandle
     {
         let streaming_endpoint = self.params.streaming_endpoint.clone();
         let keys = self.keys.clone();
-        let (snd, rcv) = unbounded();
-        thread::spawn(move || {
+        let raw_log = wss::RawLog::new(self.log_raw, "hitbtc");
+        StreamHandle::spawn(move |snd, shutdown| {
             let address = format!(
                "{}/api/2/ws",
                 streaming_endpoint,
@@ -35,20 +34,14 @@ impl Client {
             debug!("initiating WebSocket connection at {}", address);
             
             if let Err(err) = ws::connect(address, |out| {
-                wss::Handler::new(out, snd.clone(), wss::KeepAlive::False, HandlerImpl {
-                    symbol,
-                    flags,
-                    state: SubscriptionState::new(),
-                    keys: keys.clone(),
-                    last_sequence: None,
-                })
+                shutdown.register(&out);
+                let handler = HandlerImpl::new(symbol, flags, keys.clone(), raw_log);
+                wss::Handler::new(out, snd.clone(), wss::KeepAlive::False, raw_log, handler)
             })
             {
                 error!("WebSocket connection terminated with error: `{}`", err);
             }
-        });
-        
-        rcv
+        })
     }
 }
 
@@ -71,7 +64,7 @@ impl SubscriptionState {
 
 type SequenceNumber = u64;
 
-struct HandlerImpl {
+pub(super) struct HandlerImpl {
     symbol: Symbol,
     flags: NotificationFlags,
     keys: Option<Keys>,
@@ -80,6 +73,11 @@ struct HandlerImpl {
     /// Keep track of the sequence number sent by HitBTC, this is used for checking
     /// the of the ordering of the limit updates.
     last_sequence: Option<SequenceNumber>,
+
+    /// Kept for subscribing again to the order book when it goes out of sync.
+    out: Option<ws::Sender>,
+
+    raw_log: wss::RawLog,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize)]
@@ -184,14 +182,32 @@ struct MethodType<'a> {
 }
 
 impl HandlerImpl {
+    pub(super) fn new(
+        symbol: Symbol,
+        flags: NotificationFlags,
+        keys: Option<Keys>,
+        raw_log: wss::RawLog
+    ) -> Self
+    {
+        HandlerImpl {
+            symbol,
+            flags,
+            keys,
+            state: SubscriptionState::new(),
+            last_sequence: None,
+            out: None,
+            raw_log,
+        }
+    }
+
     fn convert_hit_btc_update(&self, l: HitBtcLimitUpdate<'_>, side: Side)
         -> Result<LimitUpdate, tick::ConversionError>
     {
         Ok(
             LimitUpdate {
                 side,
-                price: self.symbol.price_tick().ticked(l.price)?,
-                size: self.symbol.size_tick().ticked(l.size)?,
+                price: Price(self.symbol.price_tick().ticked(l.price)?),
+                size: Size(self.symbol.size_tick().ticked(l.size)?),
             }
         )
     }
@@ -215,12 +231,25 @@ impl HandlerImpl {
 
         match method {
             "snapshotOrderbook" | "updateOrderbook"
-                if self.flags.contains(NotificationFlags::ORDER_BOOK) =>
+                if self.flags.contains_book() =>
             {
+                // Updates are meaningless until we have received a snapshot.
+                if method == "updateOrderbook" && !self.state.order_book {
+                    return Ok(());
+                }
+
                 let snapshot: HitBtcBookUpdate<'_> = serde_json::from_str(json)?;
 
                 if !self.last_sequence.map(|s| s + 1 == snapshot.params.sequence).unwrap_or(true) {
-                    panic!("desynchronized order book");
+                    // Subscribing again will make HitBTC send a fresh snapshot.
+                    warn!("desynchronized order book, subscribing again");
+                    self.last_sequence = None;
+                    self.state.order_book = false;
+                    if let Some(ws_out) = &self.out {
+                        self.subscribe_order_book(ws_out)?;
+                    }
+                    out.unbounded_send(Notification::BookResync).unwrap();
+                    return Ok(());
                 }
 
                 self.state.order_book = true;
@@ -237,25 +266,26 @@ impl HandlerImpl {
                     .map(|l| Ok(l?.timestamped()));
                 
                 let updates = bid.chain(ask).collect::<Result<Vec<_>, tick::ConversionError>>()?;
-                if !updates.is_empty() {
-                    let notif = Notification::LimitUpdates(updates);
-                    out.unbounded_send(notif).unwrap();
+                if method == "snapshotOrderbook" {
+                    out.unbounded_send(Notification::BookSnapshot(updates)).unwrap();
+                } else if !updates.is_empty() {
+                    out.unbounded_send(Notification::LimitUpdates(updates)).unwrap();
                 }
             }
 
-            "snapshotTrades" if self.flags.contains(NotificationFlags::TRADES) => {
+            "snapshotTrades" if self.flags.contains_trades() => {
                 self.state.trades = true
             }
 
-            "updateTrades" if self.flags.contains(NotificationFlags::TRADES) => {
+            "updateTrades" if self.flags.contains_trades() => {
                 let trades: HitBtcTrades<'_> = serde_json::from_str(json)?;
 
                 for trade in trades.params.data {
                     let timestamp = convert_str_timestamp(trade.timestamp)?;
 
                     let trade = Notification::Trade(Trade {
-                        size: self.symbol.size_tick().ticked(trade.quantity)?,
-                        price: self.symbol.price_tick().ticked(trade.price)?,
+                        size: Size(self.symbol.size_tick().ticked(trade.quantity)?),
+                        price: Price(self.symbol.price_tick().ticked(trade.price)?),
                         maker_side: self.convert_hit_btc_side(trade.side)?,
                     }.with_timestamp(timestamp));
 
@@ -263,19 +293,19 @@ impl HandlerImpl {
                 }
             }
 
-            "activeOrders" if self.flags.contains(NotificationFlags::ORDERS) => {
+            "activeOrders" if self.flags.contains_orders() => {
                 self.state.report = true
             }
 
-            "report" if self.flags.contains(NotificationFlags::ORDERS) => {
+            "report" if self.flags.contains_orders() => {
                 let report: HitBtcReport<'_> = serde_json::from_str(json)?;
                 let timestamp = convert_str_timestamp(report.params.updatedAt)?;
 
                 match report.params.status {
                     "new" => {
                         let order = OrderConfirmation {
-                            size: self.symbol.size_tick().ticked(report.params.quantity)?,
-                            price: self.symbol.price_tick().ticked(report.params.price)?,
+                            size: Size(self.symbol.size_tick().ticked(report.params.quantity)?),
+                            price: Price(self.symbol.price_tick().ticked(report.params.price)?),
                             side: self.convert_hit_btc_side(report.params.side)?,
                             order_id: report.params.clientOrderId.to_owned(),
                         }.with_timestamp(timestamp);
@@ -285,17 +315,21 @@ impl HandlerImpl {
                     "partiallyFilled" | "filled" => {
                         let update = OrderUpdate {
                             order_id: report.params.clientOrderId.to_owned(),
-                            consumed_size: self.symbol.size_tick().ticked(
+                            consumed_size: Size(self.symbol.size_tick().ticked(
                                 report.params.tradeQuantity
                                     .ok_or_else(|| format_err!("missing trade quantity"))?
-                            )?,
-                            consumed_price: self.symbol.price_tick().ticked(
+                            )?),
+                            consumed_price: Price(self.symbol.price_tick().ticked(
                                 report.params.tradePrice
                                     .ok_or_else(|| format_err!("missing trade price"))?
-                            )?,
-                            remaining_size: self.symbol.size_tick().ticked(report.params.quantity)?
-                                - self.symbol.size_tick().ticked(report.params.cumQuantity)?,
+                            )?),
+                            remaining_size: Size(
+                                self.symbol.size_tick().ticked(report.params.quantity)?
+                                    - self.symbol.size_tick().ticked(report.params.cumQuantity)?
+                            ),
                             commission: 0,
+                            commission_asset: self.symbol.quote_asset().map(|a| a.to_owned()),
+                            is_fill: true,
                         }.with_timestamp(timestamp);
                         out.unbounded_send(Notification::OrderUpdate(update)).unwrap();
                     }
@@ -317,23 +351,32 @@ impl HandlerImpl {
     }
 }
 
-impl wss::HandlerImpl for HandlerImpl {
-    fn on_open(&mut self, out: &ws::Sender) -> ws::Result<()> {
-        let params = HitBtcSymbol {
-            symbol: self.symbol.name()
-        };
-
+impl HandlerImpl {
+    fn subscribe_order_book(&self, out: &ws::Sender) -> ws::Result<()> {
         let subscription = HitBtcSubscription {
             method: "subscribeOrderbook",
-            params,
+            params: HitBtcSymbol {
+                symbol: self.symbol.name()
+            },
         };
-        
+
         match serde_json::to_string(&subscription) {
-            Ok(value) => out.send(value)?,
+            Ok(value) => self.raw_log.send(out, value),
             Err(err) => {
                 panic!("failed to serialize `HitBtcSubscription`: `{}`", err);
             }
         }
+    }
+}
+
+impl wss::HandlerImpl for HandlerImpl {
+    fn on_open(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        self.out = Some(out.clone());
+        self.subscribe_order_book(out)?;
+
+        let params = HitBtcSymbol {
+            symbol: self.symbol.name()
+        };
 
         let subscription = HitBtcSubscription {
             method: "subscribeTrades",
@@ -341,7 +384,7 @@ impl wss::HandlerImpl for HandlerImpl {
         };
         
         match serde_json::to_string(&subscription) {
-            Ok(value) => out.send(value)?,
+            Ok(value) => self.raw_log.send(out, value)?,
             Err(err) => {
                 panic!("failed to serialize `HitBtcSubscription`: `{}`", err);
             }
@@ -358,7 +401,7 @@ impl wss::HandlerImpl for HandlerImpl {
             };
 
             match serde_json::to_string(&auth) {
-                Ok(value) => out.send(value)?,
+                Ok(value) => self.raw_log.send_secret(out, value, "login")?,
                 Err(err) => {
                     panic!("failed to serialize `HitBtcAuthentication`: `{}`", err);
                 }
@@ -370,7 +413,7 @@ impl wss::HandlerImpl for HandlerImpl {
             };
 
             match serde_json::to_string(&report) {
-                Ok(value) => out.send(value)?,
+                Ok(value) => self.raw_log.send(out, value)?,
                 Err(err) => {
                     panic!("failed to serialize `HitBtcReportSubscription`: `{}`", err);
                 }
@@ -0,0 +1,147 @@
+//! A module defining error types specific to KuCoin.
+
+use failure_derive::Fail;
+use serde_derive::Deserialize;
+use hyper::StatusCode;
+use std::fmt;
+use std::borrow::Cow;
+use crate::api;
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+pub(super) struct KuCoinRestError<'a> {
+    code: Cow<'a, str>,
+    msg: Option<Cow<'a, str>>, // error message can contain escaped characters
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Fail)]
+/// An error returned by KuCoin REST API.
+pub struct RestError {
+    /// Error kind.
+    pub kind: RestErrorKind,
+
+    /// Internal KuCoin error code, e.g. `"400100"`: see API documentation.
+    pub error_code: String,
+
+    /// Error message.
+    pub error_msg: String,
+}
+
+impl api::errors::ErrorKinded<!> for RestError {
+    fn kind(&self) -> api::errors::RestErrorKind<!> {
+        if self.kind == RestErrorKind::TooManyRequests || self.error_code == "429000" {
+            return api::errors::RestErrorKind::TooManyRequests;
+        }
+
+        if self.kind == RestErrorKind::InternalError
+            || self.kind == RestErrorKind::ServiceUnavailable
+        {
+            return api::errors::RestErrorKind::OtherSide;
+        }
+
+        api::errors::RestErrorKind::InvalidRequest
+    }
+}
+
+impl api::errors::ErrorKinded<api::errors::CancelErrorKind> for RestError {
+    fn kind(&self) -> api::errors::RestErrorKind<api::errors::CancelErrorKind> {
+        if self.error_msg.starts_with("order_not_exist") || self.kind == RestErrorKind::NotFound {
+            return api::errors::RestErrorKind::Specific(
+                api::errors::CancelErrorKind::UnknownOrder
+            );
+        }
+        <Self as api::errors::ErrorKinded<!>>::kind(self).into()
+    }
+}
+
+impl api::errors::ErrorKinded<api::errors::OrderErrorKind> for RestError {
+    fn kind(&self) -> api::errors::RestErrorKind<api::errors::OrderErrorKind> {
+        if self.error_code == "200004" {
+            return api::errors::RestErrorKind::Specific(
+                api::errors::OrderErrorKind::InsufficientBalance
+            );
+        }
+        <Self as api::errors::ErrorKinded<!>>::kind(self).into()
+    }
+}
+
+impl fmt::Display for RestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: `{}` [error_code = {}]", self.kind, self.error_msg, self.error_code)
+    }
+}
+
+impl RestError {
+    /// KuCoin reports some errors with an `OK` HTTP status, in which case only the error
+    /// code of the response body tells that the request failed.
+    pub(super) fn from_kucoin_error(status: StatusCode, kucoin_error: Option<KuCoinRestError>)
+        -> Self
+    {
+        let kind = if status == StatusCode::OK {
+            RestErrorKind::BadRequest
+        } else {
+            RestErrorKind::from_status_code(status)
+        };
+
+        RestError {
+            kind,
+            error_code: kucoin_error.as_ref().map(|error| error.code.to_string())
+                .unwrap_or_else(|| "<empty>".to_owned()),
+            error_msg: kucoin_error.and_then(|error| error.msg.map(|msg| msg.to_string()))
+                .unwrap_or_else(|| "<empty>".to_owned()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Fail)]
+/// Translate an HTTP error code to a KuCoin error category.
+pub enum RestErrorKind {
+    #[fail(display = "bad request")]
+    /// Malformed request, issue on the lib side or consumer side.
+    BadRequest,
+
+    #[fail(display = "unauthorized")]
+    /// Invalid API key, signature or pass phrase.
+    Unauthorized,
+
+    #[fail(display = "forbidden")]
+    /// Action is forbidden for API key.
+    Forbidden,
+
+    #[fail(display = "not found")]
+    /// The requested resource could not be found.
+    NotFound,
+
+    #[fail(display = "too many requests")]
+    /// The client broke the request rate limit set by KuCoin. See KuCoin API
+    /// documentation for rate limits.
+    TooManyRequests,
+
+    #[fail(display = "internal server error")]
+    /// Issue on KuCoin side.
+    InternalError,
+
+    #[fail(display = "service unavailable")]
+    /// Service is down for maintenance.
+    ServiceUnavailable,
+
+    #[fail(display = "unknown error, HTTP status code = {}", _0)]
+    /// Unknown error.
+    Unknown(StatusCode),
+}
+
+impl RestErrorKind {
+    fn from_status_code(code: StatusCode) -> Self {
+        use self::RestErrorKind::*;
+        match code {
+            StatusCode::OK => panic!("`RestErrorKind::from_status_code` with `StatusCode::Ok`"),
+            StatusCode::BAD_REQUEST => BadRequest,
+            StatusCode::UNAUTHORIZED => Unauthorized,
+            StatusCode::FORBIDDEN => Forbidden,
+            StatusCode::NOT_FOUND => NotFound,
+            StatusCode::TOO_MANY_REQUESTS => TooManyRequests,
+            StatusCode::INTERNAL_SERVER_ERROR => InternalError,
+            StatusCode::SERVICE_UNAVAILABLE => ServiceUnavailable,
+            other => Unknown(other),
+        }
+    }
+}
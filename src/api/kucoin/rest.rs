@@ -0,0 +1,492 @@
+use hyper::{Method, Request};
+use futures::prelude::*;
+use failure::Fail;
+use log::error;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use serde_derive::{Serialize, Deserialize};
+use crate::Side;
+use crate::tick::{Tick, TickUnit, Price, Size};
+use crate::api::{
+    self,
+    TimeInForce,
+    OrderType,
+    Order,
+    OrderAck,
+    Cancel,
+    CancelAck,
+    Balance,
+    Balances,
+};
+use crate::api::errors::ErrorKinded;
+use crate::api::rate_limit::{self, RateLimiter};
+use crate::api::symbol::{self, Symbol, WithSymbol};
+use crate::api::timestamp::{timestamp_ms, Timestamped, IntoTimestamped};
+use crate::api::kucoin::{Keys, Client, HttpClient};
+use crate::api::kucoin::errors::{RestError, KuCoinRestError};
+
+/// Error code carried by the successful responses.
+const SUCCESS: &str = "200000";
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct KuCoinCode<'a> {
+    code: Cow<'a, str>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+/// KuCoin wraps the payload of each successful response in a `data` field.
+struct KuCoinResponse<T> {
+    data: T,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+#[allow(non_snake_case)]
+struct KuCoinOrder<'a> {
+    clientOid: &'a str,
+    side: &'a str,
+    symbol: &'a str,
+    #[serde(rename = "type")]
+    type_: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    funds: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeInForce: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    postOnly: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stopPrice: Option<&'a str>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct KuCoinSymbol<'a> {
+    symbol: &'a str,
+    baseCurrency: &'a str,
+    quoteCurrency: &'a str,
+    baseIncrement: &'a str,
+    priceIncrement: &'a str,
+    quoteIncrement: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct KuCoinAccount<'a> {
+    currency: &'a str,
+    available: &'a str,
+    holds: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct KuCoinStats<'a> {
+    buy: Option<&'a str>,
+    sell: Option<&'a str>,
+    last: Option<&'a str>,
+    vol: Option<&'a str>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct KuCoinTrade<'a> {
+    price: &'a str,
+    size: &'a str,
+    side: &'a str,
+    /// Nanoseconds since the epoch.
+    time: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct KuCoinDepositAddress<'a> {
+    address: &'a str,
+    memo: Option<&'a str>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct KuCoinInstanceServer<'a> {
+    endpoint: &'a str,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct KuCoinBullet<'a> {
+    token: &'a str,
+    #[serde(borrow)]
+    instanceServers: Vec<KuCoinInstanceServer<'a>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+/// Partial order book snapshot, limits are `[price, size]` pairs.
+pub(super) struct KuCoinBookSnapshot {
+    pub(super) sequence: String,
+    pub(super) bids: Vec<(String, String)>,
+    pub(super) asks: Vec<(String, String)>,
+}
+
+trait AsStr {
+    fn as_str(self) -> &'static str;
+}
+
+impl AsStr for Side {
+    fn as_str(self) -> &'static str {
+        match self {
+            Side::Ask => "sell",
+            Side::Bid => "buy",
+        }
+    }
+}
+
+impl AsStr for TimeInForce {
+    fn as_str(self) -> &'static str {
+        match self {
+            TimeInForce::GoodTilCanceled => "GTC",
+            TimeInForce::FillOrKilll => "FOK",
+            TimeInForce::ImmediateOrCancel => "IOC",
+        }
+    }
+}
+
+/// Parse the payload of a successful response.
+fn parse_data<'a, T, K>(body: &'a [u8]) -> Result<T, api::errors::ApiError<K>>
+    where T: serde::Deserialize<'a>, K: api::errors::ErrorKind
+{
+    let response: KuCoinResponse<T> = serde_json::from_slice(body)
+        .map_err(api::errors::RequestError::new)
+        .map_err(api::errors::ApiError::RequestError)?;
+    Ok(response.data)
+}
+
+/// Send a (possibly signed) request to the REST API. `path` includes the query string, if
+/// any. This is a free function rather than a method so that the notification streams can
+/// request order book snapshots without a `Client`. The request is signed once
+/// `rate_limiter` allows it to be sent, since the signature carries a timestamp.
+fn request<K: api::errors::ErrorKind>(
+    http_client: &HttpClient,
+    rate_limiter: Option<&RateLimiter>,
+    rest_endpoint: &str,
+    keys: Option<&Keys>,
+    path: &str,
+    method: Method,
+    body: String
+) -> impl Future<Item = hyper::Chunk, Error = api::errors::ApiError<K>> + Send + 'static
+        where RestError: ErrorKinded<K>
+{
+    let address = format!(
+        "{}/{}",
+        rest_endpoint,
+        path,
+    );
+
+    let http_client = http_client.clone();
+    let keys = keys.cloned();
+    let path = path.to_owned();
+
+    rate_limit::acquire(rate_limiter, 1).then(move |_| {
+        let mut request = Request::builder();
+
+        if let Some(keys) = keys {
+            let timestamp = timestamp_ms();
+            let signature = keys.sign(&format!("{}{}/{}{}", timestamp, method, path, body));
+
+            request.header("KC-API-KEY", keys.api_key.as_bytes())
+                .header("KC-API-SIGN", signature.as_bytes())
+                .header("KC-API-TIMESTAMP", timestamp.to_string().as_bytes())
+                .header("KC-API-PASSPHRASE", keys.pass_phrase.as_bytes())
+                .header("KC-API-KEY-VERSION", &b"2"[..]);
+        }
+
+        request.method(method)
+            .uri(&address)
+            .header("User-Agent", &b"hyper"[..])
+            .header("Content-Type", &b"application/json"[..]);
+
+        // Unwrap because it is a bug if this fails (header failed to parse or something)
+        let request = request.body(body.into()).unwrap();
+        http_client.request(request)
+    })
+    .map_err(|err| api::errors::RequestError::new(err.compat()))
+    .map_err(api::errors::ApiError::RequestError).and_then(|(status, body)| {
+        let code: Option<KuCoinCode<'_>> = serde_json::from_slice(&body).ok();
+        if status != hyper::StatusCode::OK || code.map(|c| c.code != SUCCESS).unwrap_or(true) {
+            let kucoin_error: Option<KuCoinRestError<'_>> = serde_json::from_slice(&body).ok();
+            let error = RestError::from_kucoin_error(status, kucoin_error);
+            let kind = error.kind();
+            Err(
+                api::errors::ApiError::RestError(error.context(kind).into())
+            )?;
+        }
+        Ok(body)
+    })
+}
+
+/// Request a snapshot of the 100 best limits of each side of the order book of `symbol`.
+pub(super) fn book_snapshot(http_client: &HttpClient, rest_endpoint: &str, symbol: Symbol)
+    -> impl Future<Item = KuCoinBookSnapshot, Error = api::errors::Error> + Send + 'static
+{
+    let path = format!("api/v1/market/orderbook/level2_100?symbol={}", symbol.name());
+    request(http_client, None, rest_endpoint, None, &path, Method::GET, String::new())
+        .and_then(|body| parse_data(&body))
+}
+
+impl Client {
+    fn request<K: api::errors::ErrorKind>(
+        &self,
+        path: &str,
+        method: Method,
+        body: String
+    ) -> impl Future<Item = hyper::Chunk, Error = api::errors::ApiError<K>> + Send + 'static
+            where RestError: ErrorKinded<K>
+    {
+        request(
+            &self.http_client,
+            self.rate_limiter.as_ref(),
+            &self.params.rest_endpoint,
+            self.keys.as_ref(),
+            path,
+            method,
+            body
+        )
+    }
+
+    /// Request a WebSocket connection token, return the address to connect to. The token
+    /// grants access to the private channels if `private` is `true`, which requires a key
+    /// pair.
+    crate fn bullet_impl(&self, private: bool)
+        -> impl Future<Item = String, Error = api::errors::Error> + Send + 'static
+    {
+        let path = if private {
+            "api/v1/bullet-private"
+        } else {
+            "api/v1/bullet-public"
+        };
+
+        self.request(path, Method::POST, String::new()).and_then(|body| {
+            let bullet: KuCoinBullet<'_> = parse_data(&body)?;
+            let server = bullet.instanceServers.first().ok_or_else(|| {
+                api::errors::ApiError::RestError(api::errors::RestErrorKind::OtherSide.into())
+            })?;
+
+            Ok(format!(
+                "{}?token={}&connectId={}",
+                server.endpoint,
+                bullet.token,
+                uuid::Uuid::new_v4().simple()
+            ))
+        })
+    }
+
+    crate fn order_impl(&self, order: WithSymbol<&Order>)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = api::errors::OrderError> + Send + 'static>
+    {
+        use std::borrow::Borrow;
+
+        if let Err(err) = order.validate(&order.symbol()) {
+            return Box::new(Err(err.into()).into_future());
+        }
+
+        let symbol = order.symbol();
+
+        // KuCoin requires a client order id.
+        let client_oid = match &order.order_id {
+            Some(order_id) => order_id.clone(),
+            None => uuid::Uuid::new_v4().simple().to_string(),
+        };
+
+        let market = order.type_.is_market();
+
+        let size = order.size.unticked(symbol.size_tick());
+        let price = order.price.unticked(symbol.price_tick());
+        let stop_price = order.stop_price.as_ref()
+            .filter(|_| order.type_.is_stop())
+            .map(|stop_price| stop_price.unticked(symbol.price_tick()));
+
+        // Funds are expressed in the quote currency, see `Symbol::funds_tick`.
+        let funds = order.funds.as_ref().map(|funds| funds.unticked(symbol.funds_tick()));
+
+        let kucoin_order = KuCoinOrder {
+            clientOid: &client_oid,
+            side: order.side.as_str(),
+            symbol: symbol.name(),
+            type_: if market { "market" } else { "limit" },
+            price: if market { None } else { Some(price.borrow()) },
+            size: if funds.is_none() { Some(size.borrow()) } else { None },
+            funds: funds.as_ref().map(|funds| funds.borrow()),
+            timeInForce: if market { None } else { Some(order.time_in_force.as_str()) },
+            postOnly: if market { None } else { Some(order.type_ == OrderType::LimitMaker) },
+
+            // A `loss` stop triggers when the last trade price drops to the stop price, an
+            // `entry` stop when it rises to it.
+            stop: stop_price.as_ref().map(|_| match order.side {
+                Side::Ask => "loss",
+                Side::Bid => "entry",
+            }),
+            stopPrice: stop_price.as_ref().map(|stop_price| stop_price.borrow()),
+        };
+
+        let body = serde_json::to_string(&kucoin_order).expect("invalid json");
+
+        // The ack only carries the server order id, we keep using the client order id.
+        let fut = self.request("api/v1/orders", Method::POST, body).and_then(move |_| {
+            Ok(OrderAck {
+                order_id: client_oid,
+            }.timestamped())
+        });
+        Box::new(fut)
+    }
+
+    crate fn cancel_impl(&self, cancel: WithSymbol<&Cancel>)
+        -> impl Future<Item = Timestamped<CancelAck>, Error = api::errors::CancelError> + Send + 'static
+    {
+        let path = format!("api/v1/order/client-order/{}", cancel.order_id());
+
+        self.request(&path, Method::DELETE, String::new()).and_then(|_| {
+            Ok(CancelAck.timestamped())
+        })
+    }
+
+    crate fn ping_impl(&self)
+        -> impl Future<Item = Timestamped<()>, Error = api::errors::Error> + Send + 'static
+    {
+        self.request("api/v1/timestamp", Method::GET, String::new()).and_then(|body| {
+            let timestamp = parse_data(&body)?;
+            Ok(().with_timestamp(timestamp))
+        })
+    }
+
+    crate fn balances_impl(&self)
+        -> impl Future<Item = Balances, Error = api::errors::Error> + Send + 'static
+    {
+        self.request("api/v1/accounts?type=trade", Method::GET, String::new()).and_then(|body| {
+            let accounts: Vec<KuCoinAccount<'_>> = parse_data(&body)?;
+
+            let balances = accounts.into_iter().map(|account| {
+                (account.currency.to_owned(), Balance {
+                    free: account.available.to_owned(),
+                    locked: account.holds.to_owned(),
+                })
+            }).collect();
+            Ok(balances)
+        })
+    }
+
+    crate fn ticker_impl(&self, symbol: Symbol)
+        -> impl Future<Item = api::Ticker, Error = api::errors::Error> + Send + 'static
+    {
+        let path = format!("api/v1/market/stats?symbol={}", symbol.name());
+
+        self.request(&path, Method::GET, String::new()).and_then(move |body| {
+            let stats: KuCoinStats<'_> = parse_data(&body)?;
+
+            // KuCoin sends `null` values for empty sides or if no trade happened yet.
+            let convert = |tick: Tick, value: Option<&str>, default| {
+                value.map(|value| tick.ticked(value))
+                    .unwrap_or(Ok(default))
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)
+            };
+
+            Ok(api::Ticker {
+                best_bid: Price(convert(symbol.price_tick(), stats.buy, 0)?),
+                best_ask: Price(convert(symbol.price_tick(), stats.sell, TickUnit::max_value())?),
+                last: Price(convert(symbol.price_tick(), stats.last, 0)?),
+                volume_24h: Size(convert(symbol.size_tick(), stats.vol, 0)?),
+            })
+        })
+    }
+
+    crate fn recent_trades_impl(&self, symbol: Symbol, limit: usize)
+        -> impl Future<Item = Vec<Timestamped<api::Trade>>, Error = api::errors::Error> + Send + 'static
+    {
+        // KuCoin does not accept a limit and always sends the last 100 trades, oldest first.
+        let path = format!("api/v1/market/histories?symbol={}", symbol.name());
+
+        self.request(&path, Method::GET, String::new()).and_then(move |body| {
+            let trades: Vec<KuCoinTrade<'_>> = parse_data(&body)?;
+
+            let convert = |tick: Tick, value: &str| {
+                tick.ticked(value)
+                    .map_err(api::errors::RequestError::new)
+                    .map_err(api::errors::ApiError::RequestError)
+            };
+
+            // `side` is the side of the taker order.
+            let skip = trades.len().saturating_sub(limit);
+            trades.into_iter().skip(skip).map(|trade| {
+                let maker_side = match trade.side {
+                    "buy" => Side::Ask,
+                    "sell" => Side::Bid,
+                    _ => Err(api::errors::RestErrorKind::OtherSide.into())
+                        .map_err(api::errors::ApiError::RestError)?,
+                };
+
+                Ok(api::Trade {
+                    price: Price(convert(symbol.price_tick(), trade.price)?),
+                    size: Size(convert(symbol.size_tick(), trade.size)?),
+                    maker_side,
+                }.with_timestamp(trade.time / 1_000_000))
+            }).collect::<Result<_, api::errors::Error>>()
+        })
+    }
+
+    crate fn deposit_address_impl(&self, asset: &str)
+        -> impl Future<Item = api::DepositAddress, Error = api::errors::Error> + Send + 'static
+    {
+        let path = format!("api/v1/deposit-addresses?currency={}", asset);
+
+        self.request(&path, Method::GET, String::new()).and_then(|body| {
+            let address: KuCoinDepositAddress<'_> = parse_data(&body)?;
+
+            Ok(api::DepositAddress {
+                address: address.address.to_owned(),
+                tag: address.memo.filter(|memo| !memo.is_empty()).map(|memo| memo.to_owned()),
+            })
+        })
+    }
+
+    crate fn get_symbols(&self)
+        -> impl Future<Item = HashMap<String, Symbol>, Error = api::errors::Error> + Send + 'static
+    {
+        self.request("api/v1/symbols", Method::GET, String::new()).and_then(|body| {
+            let products: Vec<KuCoinSymbol<'_>> = parse_data(&body)?;
+
+            let mut symbols = HashMap::new();
+            for p in products {
+                let price_tick = match Tick::tick_size(p.priceIncrement) {
+                    Some(tick) => tick,
+                    None => {
+                        error!("cannot read price tick for symbol `{}`", p.symbol);
+                        continue;
+                    }
+                };
+
+                let size_tick = match Tick::tick_size(p.baseIncrement) {
+                    Some(tick) => tick,
+                    None => {
+                        error!("cannot read size tick for symbol `{}`", p.symbol);
+                        continue;
+                    }
+                };
+
+                let funds_tick = match Tick::tick_size(p.quoteIncrement) {
+                    Some(tick) => tick,
+                    None => {
+                        error!("cannot read quote increment for symbol `{}`", p.symbol);
+                        continue;
+                    }
+                };
+
+                if let Some(symbol) = Symbol::new(p.symbol, price_tick, size_tick) {
+                    let symbol = symbol.with_base_asset(p.baseCurrency)
+                        .with_quote_asset(p.quoteCurrency)
+                        .with_funds_tick(funds_tick);
+                    symbols.insert(symbol::canonical_name(symbol.name()), symbol);
+                } else {
+                    error!("symbol name too long: `{}`", p.symbol);
+                }
+            }
+            Ok(symbols)
+        })
+    }
+}
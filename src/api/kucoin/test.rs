@@ -0,0 +1,282 @@
+#![cfg(test)]
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use futures::prelude::*;
+use hyper::{Method, StatusCode};
+use openssl::pkey::PKey;
+use openssl::{sign::Signer, hash::MessageDigest};
+use crate::Side;
+use crate::tick::{Tick, Price, Size};
+use crate::api::{
+    ApiClient,
+    Notification,
+    NotificationFlags,
+    Params,
+    Order,
+    Cancel,
+    Trade,
+    OrderConfirmation,
+    OrderUpdate,
+};
+use crate::api::harness::{self, MockTransport};
+use crate::api::wss::RawLog;
+use crate::api::symbol::{Symbol, IntoWithSymbol};
+use crate::api::errors::{ApiError, RestErrorKind, OrderErrorKind};
+use crate::api::kucoin::{Client, KeyPair};
+use crate::api::kucoin::wss::HandlerImpl;
+
+fn handler(symbol: Symbol, transport: Arc<MockTransport>) -> HandlerImpl {
+    HandlerImpl::new(
+        symbol,
+        NotificationFlags::ALL,
+        true,
+        transport,
+        "https://localhost".to_owned(),
+        RawLog::new(false, "kucoin")
+    )
+}
+
+/// Return the base64 encoded HMAC-SHA256 signature of `payload` with `"secret_key"`.
+fn sign(payload: &str) -> String {
+    let key = PKey::hmac(b"secret_key").unwrap();
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+    signer.update(payload.as_bytes()).unwrap();
+    base64::encode(&signer.sign_to_vec().unwrap())
+}
+
+#[test]
+fn book_snapshot_then_updates() {
+    let symbol = Symbol::new("BTC-USDT", Tick::new(10), Tick::new(1000)).unwrap();
+    let snapshot = r#"{
+        "sequence": "100",
+        "bids": [["7000.1", "1.5"], ["7000", "2"]],
+        "asks": [["7000.5", "0.5"]]
+    }"#;
+    let mut handler = handler(symbol, Arc::default()).with_book_snapshot(snapshot);
+
+    let frames = [
+        r#"{"id":"5c24c5da03aa673885cd67a0","type":"welcome"}"#,
+        r#"{"id":"1","type":"ack"}"#,
+
+        // The first change is already included in the snapshot.
+        r#"{"type":"message","topic":"/market/level2:BTC-USDT","subject":"trade.l2update","data":{"sequenceStart":100,"sequenceEnd":101,"symbol":"BTC-USDT","changes":{"asks":[["7000.6","1","101"]],"bids":[["7000.1","1.5","100"]]}}}"#,
+        r#"{"type":"message","topic":"/market/level2:BTC-USDT","subject":"trade.l2update","data":{"sequenceStart":102,"sequenceEnd":103,"symbol":"BTC-USDT","changes":{"asks":[["7000.5","0","103"]],"bids":[["7000.2","0.1","102"]]}}}"#,
+        r#"{"type":"message","topic":"/market/match:BTC-USDT","subject":"trade.l3match","data":{"sequence":"104","type":"match","symbol":"BTC-USDT","side":"sell","price":"7000.2","size":"0.1","tradeId":"5c24c5da03aa673885cd67aa","takerOrderId":"5c24c5d903aa6772d55b371e","makerOrderId":"5c2187d003aa677bd09d5c93","time":"1545913818099033203"}}"#,
+        r#"{"type":"message","topic":"/market/level2:BTC-USDT","subject":"trade.l2update","data":{"sequenceStart":104,"sequenceEnd":104,"symbol":"BTC-USDT","changes":{"asks":[],"bids":[["7000.2","0","104"]]}}}"#,
+        r#"{"id":"2","type":"pong"}"#,
+    ];
+
+    let expected = harness::order_book(&[(70_001, 1500), (70_000, 2000)], &[(70_006, 1000)]);
+    let notifs = harness::assert_book(&mut handler, &frames, &expected);
+    assert_eq!(notifs.len(), 4);
+    assert!(if let Notification::BookSnapshot(..) = notifs[0] { true } else { false });
+
+    match &notifs[2] {
+        Notification::Trade(trade) => {
+            assert_eq!(trade.timestamp(), 1545913818099);
+            assert_eq!(trade.into_inner(), Trade {
+                price: Price(70_002),
+                size: Size(100),
+                maker_side: Side::Bid,
+            });
+        }
+        other => panic!("expected a trade, found `{:?}`", other),
+    }
+}
+
+#[test]
+fn sequence_gap() {
+    let symbol = Symbol::new("BTC-USDT", Tick::new(10), Tick::new(1000)).unwrap();
+    let snapshot = r#"{"sequence": "100", "bids": [["7000", "2"]], "asks": []}"#;
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"{"code":"200000","data":{"sequence":"110","bids":[["7000","3"]],"asks":[]}}"#);
+    let mut handler = handler(symbol, transport.clone()).with_book_snapshot(snapshot);
+
+    let notifs = harness::replay(&mut handler, &[
+        r#"{"type":"message","topic":"/market/level2:BTC-USDT","subject":"trade.l2update","data":{"sequenceStart":101,"sequenceEnd":101,"symbol":"BTC-USDT","changes":{"asks":[],"bids":[["7000","1","101"]]}}}"#,
+    ]);
+    assert_eq!(notifs.len(), 1);
+
+    // Changes 102 to 104 are missing, wait for a new snapshot.
+    let notifs = harness::replay(&mut handler, &[
+        r#"{"type":"message","topic":"/market/level2:BTC-USDT","subject":"trade.l2update","data":{"sequenceStart":105,"sequenceEnd":105,"symbol":"BTC-USDT","changes":{"asks":[],"bids":[["7000","3","105"]]}}}"#,
+    ]);
+    assert_eq!(notifs, vec![Notification::BookResync]);
+
+    // Changes are buffered until the new snapshot is received, the ones up to 110 being
+    // included in it.
+    let frame = r#"{"type":"message","topic":"/market/level2:BTC-USDT","subject":"trade.l2update","data":{"sequenceStart":111,"sequenceEnd":111,"symbol":"BTC-USDT","changes":{"asks":[],"bids":[["6999","1","111"]]}}}"#;
+    let mut notifs = vec![];
+    for _ in 0..100 {
+        notifs = harness::replay(&mut handler, &[frame]);
+        if !notifs.is_empty() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(notifs.len(), 1);
+    assert!(if let Notification::BookSnapshot(..) = notifs[0] { true } else { false });
+    harness::assert_same_book(
+        &harness::rebuild_order_book(&notifs),
+        &harness::order_book(&[(70_000, 3000), (69_990, 1000)], &[])
+    );
+
+    assert_eq!(transport.paths(), vec!["/api/v1/market/orderbook/level2_100".to_owned()]);
+}
+
+#[test]
+fn order_changes() {
+    let symbol = Symbol::new("BTC-USDT", Tick::new(10), Tick::new(1000))
+        .unwrap()
+        .with_quote_asset("USDT");
+    let mut handler = handler(symbol, Arc::default());
+
+    let notifs = harness::replay(&mut handler, &[
+        r#"{"type":"message","topic":"/spotMarket/tradeOrders","subject":"orderChange","channelType":"private","data":{"symbol":"BTC-USDT","orderType":"limit","side":"buy","orderId":"5cdfc138b21023a909e5ad55","type":"open","orderTime":1593487481683297666,"size":"0.5","filledSize":"0","price":"7000.1","clientOid":"my-order","remainSize":"0.5","status":"open","ts":1593487481683297666}}"#,
+        r#"{"type":"message","topic":"/spotMarket/tradeOrders","subject":"orderChange","channelType":"private","data":{"symbol":"ETH-USDT","orderType":"limit","side":"buy","orderId":"5cdfc138b21023a909e5ad56","type":"open","orderTime":1593487481683297666,"size":"1","filledSize":"0","price":"200","clientOid":"other-order","remainSize":"1","status":"open","ts":1593487481683297666}}"#,
+        r#"{"type":"message","topic":"/spotMarket/tradeOrders","subject":"orderChange","channelType":"private","data":{"symbol":"BTC-USDT","orderType":"limit","side":"buy","orderId":"5cdfc138b21023a909e5ad55","type":"match","orderTime":1593487481683297666,"size":"0.5","filledSize":"0.2","price":"7000.1","clientOid":"my-order","remainSize":"0.3","matchPrice":"7000.1","matchSize":"0.2","tradeId":"5ef9d8c1f7f3f4000960d7c2","status":"match","ts":1593487482038606180}}"#,
+        r#"{"type":"message","topic":"/spotMarket/tradeOrders","subject":"orderChange","channelType":"private","data":{"symbol":"BTC-USDT","orderType":"limit","side":"buy","orderId":"5cdfc138b21023a909e5ad55","type":"canceled","orderTime":1593487481683297666,"size":"0.5","filledSize":"0.2","price":"7000.1","clientOid":"my-order","remainSize":"0","status":"done","ts":1593487483038606180}}"#,
+    ]);
+
+    assert_eq!(notifs.len(), 3);
+    match &notifs[0] {
+        Notification::OrderConfirmation(confirmation) => {
+            assert_eq!(confirmation.timestamp(), 1593487481683);
+            assert_eq!(confirmation.clone().into_inner(), OrderConfirmation {
+                order_id: "my-order".to_owned(),
+                price: Price(70_001),
+                size: Size(500),
+                side: Side::Bid,
+            });
+        }
+        other => panic!("expected an order confirmation, found `{:?}`", other),
+    }
+
+    match &notifs[1] {
+        Notification::OrderUpdate(update) => {
+            assert_eq!(update.clone().into_inner(), OrderUpdate {
+                order_id: "my-order".to_owned(),
+                consumed_size: Size(200),
+                remaining_size: Size(300),
+                consumed_price: Price(70_001),
+                commission: 0,
+                commission_asset: Some("USDT".to_owned()),
+                is_fill: true,
+            });
+        }
+        other => panic!("expected an order update, found `{:?}`", other),
+    }
+
+    assert!(if let Notification::OrderExpiration(..) = notifs[2] { true } else { false });
+}
+
+#[test]
+fn signed_requests() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"{"code":"200000","data":[{"symbol":"BTC-USDT","name":"BTC-USDT","baseCurrency":"BTC","quoteCurrency":"USDT","baseMinSize":"0.00001","quoteMinSize":"0.01","baseMaxSize":"10000","quoteMaxSize":"99999999","baseIncrement":"0.00000001","quoteIncrement":"0.000001","priceIncrement":"0.1","enableTrading":true}]}"#);
+    transport.respond(StatusCode::OK, r#"{"code":"200000","data":{"orderId":"5bd6e9286d99522a52e458de"}}"#);
+    transport.respond(StatusCode::OK, r#"{"code":"200000","data":{"orderId":"5bd6e9286d99522a52e458df"}}"#);
+    transport.respond(StatusCode::OK, r#"{"code":"200000","data":{"cancelledOrderId":"5bd6e9286d99522a52e458de","clientOid":"my-order"}}"#);
+
+    // Errors may be reported with an `OK` status.
+    transport.respond(StatusCode::OK, r#"{"code":"200004","msg":"Balance insufficient!"}"#);
+
+    let params = Params {
+        streaming_endpoint: "wss://localhost".to_owned(),
+        rest_endpoint: "https://localhost".to_owned(),
+        weight_per_minute: None,
+    };
+    let key_pair = KeyPair::new(
+        "api_key".to_owned(),
+        "secret_key".to_owned(),
+        "pass_phrase".to_owned()
+    );
+    let client = Client::with_transport(params, Some(key_pair), transport.clone()).unwrap();
+    let symbol = client.find_symbol("BTC-USDT").unwrap();
+    assert_eq!(symbol.price_tick(), Tick::new(10));
+    assert_eq!(symbol.funds_tick(), Tick::new(1_000_000));
+
+    let order = Order::new("7000.1", "0.5", Side::Bid).with_order_id::<Client>("my-order");
+    let ack = client.order(order.with_symbol(symbol)).wait().unwrap();
+    assert_eq!(ack.order_id, "my-order");
+
+    // Funds are expressed with the quote increment, finer than the price tick.
+    let order = Order::market_with_funds("10.000005").with_order_id::<Client>("funds-order");
+    client.order(order.with_symbol(symbol)).wait().unwrap();
+
+    let cancel = Cancel::new("my-order".to_owned());
+    client.cancel(cancel.with_symbol(symbol)).wait().unwrap();
+
+    let order = Order::new("7000.1", "0.5", Side::Bid);
+    match client.order(order.with_symbol(symbol)).wait() {
+        Err(ApiError::RestError(err)) => {
+            assert_eq!(err.kind(), RestErrorKind::Specific(OrderErrorKind::InsufficientBalance));
+        }
+        other => panic!("expected a REST error, found `{:?}`", other),
+    }
+
+    assert_eq!(transport.paths(), vec![
+        "/api/v1/symbols".to_owned(),
+        "/api/v1/orders".to_owned(),
+        "/api/v1/orders".to_owned(),
+        "/api/v1/order/client-order/my-order".to_owned(),
+        "/api/v1/orders".to_owned(),
+    ]);
+
+    let requests = transport.requests();
+    for request in requests.iter() {
+        let headers = request.headers();
+        let timestamp = headers["KC-API-TIMESTAMP"].to_str().unwrap();
+        let body = std::str::from_utf8(request.body()).unwrap();
+        let payload = format!("{}{}{}{}", timestamp, request.method(), request.uri().path(), body);
+
+        assert_eq!(headers["KC-API-KEY"], "api_key");
+        assert_eq!(headers["KC-API-SIGN"], sign(&payload).as_str());
+        assert_eq!(headers["KC-API-PASSPHRASE"], sign("pass_phrase").as_str());
+        assert_eq!(headers["KC-API-KEY-VERSION"], "2");
+    }
+
+    let body: serde_json::Value = serde_json::from_slice(requests[1].body()).unwrap();
+    assert_eq!(body["clientOid"], "my-order");
+    assert_eq!(body["price"], "7000.1");
+    assert_eq!(body["size"], "0.5");
+
+    let body: serde_json::Value = serde_json::from_slice(requests[2].body()).unwrap();
+    assert_eq!(body["funds"], "10.000005");
+    assert!(body.get("size").is_none());
+
+    assert_eq!(requests[3].method(), Method::DELETE);
+}
+
+#[test]
+fn recent_trades() {
+    let transport = Arc::new(MockTransport::default());
+    transport.respond(StatusCode::OK, r#"{"code":"200000","data":[{"symbol":"BTC-USDT","name":"BTC-USDT","baseCurrency":"BTC","quoteCurrency":"USDT","baseMinSize":"0.00001","quoteMinSize":"0.01","baseMaxSize":"10000","quoteMaxSize":"99999999","baseIncrement":"0.00000001","quoteIncrement":"0.000001","priceIncrement":"0.1","enableTrading":true}]}"#);
+    transport.respond(StatusCode::OK, r#"{"code":"200000","data":[{"sequence":"1545896668571","price":"7000.1","size":"0.5","side":"buy","time":1545904567062140823},{"sequence":"1545896668578","price":"7000.2","size":"0.1","side":"sell","time":1545904567063140823}]}"#);
+
+    let params = Params {
+        streaming_endpoint: "wss://localhost".to_owned(),
+        rest_endpoint: "https://localhost".to_owned(),
+        weight_per_minute: None,
+    };
+    let client = Client::with_transport(params, None, transport.clone()).unwrap();
+    let symbol = client.find_symbol("BTC-USDT").unwrap();
+
+    // Trades are sent oldest first, `side` being the side of the taker.
+    let trades = client.recent_trades(symbol, 1).wait().unwrap();
+    assert_eq!(trades.len(), 1);
+    assert_eq!(trades[0].timestamp(), 1545904567063);
+    assert_eq!(trades[0].into_inner(), Trade {
+        price: Price(70_002),
+        size: Size(10_000_000),
+        maker_side: Side::Bid,
+    });
+
+    let requests = transport.requests();
+    assert_eq!(
+        requests[1].uri().path_and_query().unwrap(),
+        "/api/v1/market/histories?symbol=BTC-USDT"
+    );
+}
@@ -0,0 +1,576 @@
+use failure::{bail, format_err};
+use serde_derive::{Deserialize, Serialize};
+use futures::prelude::*;
+use log::{debug, error, warn};
+use std::mem;
+use std::sync::mpsc;
+use std::thread;
+use crate::Side;
+use crate::order_book::LimitUpdate;
+use crate::tick::{self, Price, Size};
+use crate::api::{
+    self,
+    Notification,
+    NotificationFlags,
+    Trade,
+    OrderConfirmation,
+    OrderExpiration,
+    OrderUpdate,
+};
+use crate::api::wss;
+use crate::api::stream::StreamHandle;
+use crate::api::symbol::Symbol;
+use crate::api::timestamp::{Timestamp, Timestamped, IntoTimestamped};
+use crate::api::kucoin::{Client, HttpClient};
+use crate::api::kucoin::rest::{self, KuCoinBookSnapshot};
+
+impl Client {
+    crate fn new_stream(&self, symbol: Symbol, flags: NotificationFlags)
+        -> StreamHandle
+    {
+        let client = self.clone();
+        let private = self.keys.is_some() && flags.contains_orders();
+        let raw_log = wss::RawLog::new(self.log_raw, "kucoin");
+        StreamHandle::spawn(move |snd, shutdown| {
+            // The connection token must be obtained from the REST API before connecting,
+            // the returned address carries it.
+            use tokio::runtime::current_thread;
+            debug!("requesting WebSocket connection token");
+            let address = match current_thread::block_on_all(client.bullet_impl(private)) {
+                Ok(address) => address,
+                Err(err) => {
+                    error!("failed to obtain a WebSocket connection token: `{}`", err);
+                    return;
+                }
+            };
+
+            debug!("initiating WebSocket connection");
+
+            if let Err(err) = ws::connect(address, |out| {
+                shutdown.register(&out);
+                let handler = HandlerImpl::new(
+                    symbol,
+                    flags,
+                    private,
+                    client.http_client.clone(),
+                    client.params.rest_endpoint.clone(),
+                    raw_log
+                );
+                wss::Handler::new(out, snd.clone(), wss::KeepAlive::True, raw_log, handler)
+            })
+            {
+                error!("WebSocket connection terminated with error: `{}`", err);
+            }
+        })
+    }
+}
+
+type SequenceNumber = u64;
+
+type BookReceiver = mpsc::Receiver<Result<KuCoinBookSnapshot, api::errors::Error>>;
+
+#[derive(Debug)]
+struct BookWaitingState {
+    rcv: BookReceiver,
+    events: Vec<(SequenceNumber, Timestamped<LimitUpdate>)>,
+}
+
+#[derive(Debug)]
+/// State of the book snapshot request:
+/// * `None`: the request has not been made yet
+/// * `Waiting(state)`: the request has started, in the meantime we have a `Receiver`
+///   which will receive the snapshot, and the changes received since then, which may
+///   need to be applied on top of the snapshot
+/// * `Ok`: the request was completed already
+enum BookSnapshotState {
+    None,
+    Waiting(BookWaitingState),
+    Ok,
+}
+
+pub(super) struct HandlerImpl {
+    symbol: Symbol,
+    flags: NotificationFlags,
+
+    /// Whether the connection token grants access to the private channels.
+    private: bool,
+
+    /// Client of the REST API, used for requesting the order book snapshot.
+    http_client: HttpClient,
+    rest_endpoint: String,
+    book_snapshot_state: BookSnapshotState,
+
+    /// Sequence number of the last change applied to the order book, this is used for
+    /// checking the ordering of the limit updates.
+    last_sequence: Option<SequenceNumber>,
+
+    /// Id of the next message sent to KuCoin.
+    next_id: u64,
+
+    /// Kept for subscribing once KuCoin has welcomed us.
+    out: Option<ws::Sender>,
+
+    raw_log: wss::RawLog,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+#[allow(non_snake_case)]
+struct KuCoinSubscription<'a> {
+    id: String,
+    #[serde(rename = "type")]
+    type_: &'a str,
+    topic: &'a str,
+    privateChannel: bool,
+    response: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+struct KuCoinPing<'a> {
+    id: String,
+    #[serde(rename = "type")]
+    type_: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct KuCoinMessage<'a> {
+    #[serde(rename = "type")]
+    type_: &'a str,
+    topic: Option<&'a str>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct KuCoinData<T> {
+    data: T,
+}
+
+/// A `[price, size, sequence]` change of the order book.
+type KuCoinChange<'a> = (&'a str, &'a str, &'a str);
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct KuCoinChanges<'a> {
+    #[serde(borrow)]
+    asks: Vec<KuCoinChange<'a>>,
+    #[serde(borrow)]
+    bids: Vec<KuCoinChange<'a>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct KuCoinLevel2<'a> {
+    sequenceStart: SequenceNumber,
+    #[serde(borrow)]
+    changes: KuCoinChanges<'a>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+struct KuCoinMatch<'a> {
+    side: &'a str,
+    price: &'a str,
+    size: &'a str,
+    time: &'a str,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[allow(non_snake_case)]
+struct KuCoinOrderChange<'a> {
+    symbol: &'a str,
+    #[serde(rename = "type")]
+    type_: &'a str,
+    side: &'a str,
+    orderId: &'a str,
+    clientOid: Option<&'a str>,
+    price: Option<&'a str>,
+    size: Option<&'a str>,
+    remainSize: Option<&'a str>,
+    matchPrice: Option<&'a str>,
+    matchSize: Option<&'a str>,
+    ts: u64,
+}
+
+/// Return `value`, or an error mentioning the missing `field`.
+fn required<'a>(value: Option<&'a str>, field: &str) -> Result<&'a str, failure::Error> {
+    value.ok_or_else(|| format_err!("missing {}", field))
+}
+
+/// Convert a KuCoin timestamp in nanoseconds to a timestamp in milliseconds.
+fn convert_ns_timestamp(timestamp: u64) -> Timestamp {
+    timestamp / 1_000_000
+}
+
+impl HandlerImpl {
+    pub(super) fn new(
+        symbol: Symbol,
+        flags: NotificationFlags,
+        private: bool,
+        http_client: HttpClient,
+        rest_endpoint: String,
+        raw_log: wss::RawLog
+    ) -> Self
+    {
+        HandlerImpl {
+            symbol,
+            flags,
+            private,
+            http_client,
+            rest_endpoint,
+            book_snapshot_state: BookSnapshotState::None,
+            last_sequence: None,
+            next_id: 0,
+            out: None,
+            raw_log,
+        }
+    }
+
+    #[cfg(test)]
+    /// Provide the order book snapshot upfront instead of requesting it from the REST API
+    /// upon the first change.
+    pub(super) fn with_book_snapshot(mut self, snapshot: &str) -> Self {
+        let snapshot: KuCoinBookSnapshot = serde_json::from_str(snapshot).unwrap();
+        let (snd, rcv) = mpsc::sync_channel(1);
+        snd.send(Ok(snapshot)).unwrap();
+        self.book_snapshot_state = BookSnapshotState::Waiting(BookWaitingState {
+            rcv,
+            events: vec![],
+        });
+        self
+    }
+
+    fn convert_kucoin_update(&self, price: &str, size: &str, side: Side)
+        -> Result<LimitUpdate, tick::ConversionError>
+    {
+        Ok(
+            LimitUpdate {
+                side,
+                price: Price(self.symbol.price_tick().ticked(price)?),
+                size: Size(self.symbol.size_tick().ticked(size)?),
+            }
+        )
+    }
+
+    fn convert_kucoin_side(&self, side: &str) -> Result<Side, failure::Error> {
+        let side = match side {
+            "buy" => Side::Bid,
+            "sell" => Side::Ask,
+            other => bail!("wrong side: `{}`", other),
+        };
+        Ok(side)
+    }
+
+    fn next_id(&mut self) -> String {
+        self.next_id += 1;
+        self.next_id.to_string()
+    }
+
+    fn subscribe(&mut self, out: &ws::Sender, topic: &str, private: bool) -> ws::Result<()> {
+        let subscription = KuCoinSubscription {
+            id: self.next_id(),
+            type_: "subscribe",
+            topic,
+            privateChannel: private,
+            response: true,
+        };
+
+        match serde_json::to_string(&subscription) {
+            Ok(value) => self.raw_log.send(out, value),
+            Err(err) => {
+                panic!("failed to serialize `KuCoinSubscription`: `{}`", err);
+            }
+        }
+    }
+
+    fn subscribe_all(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        if self.flags.contains_book() {
+            let topic = format!("/market/level2:{}", self.symbol.name());
+            self.subscribe(out, &topic, false)?;
+        }
+
+        if self.flags.contains_trades() {
+            let topic = format!("/market/match:{}", self.symbol.name());
+            self.subscribe(out, &topic, false)?;
+        }
+
+        if self.private {
+            self.subscribe(out, "/spotMarket/tradeOrders", true)?;
+        }
+        Ok(())
+    }
+
+    fn parse_message(&mut self, json: &str, out: &wss::NotifSender) -> Result<(), failure::Error> {
+        let message: KuCoinMessage<'_> = serde_json::from_str(json)?;
+
+        match message.type_ {
+            "welcome" => {
+                if let Some(ws_out) = self.out.clone() {
+                    self.subscribe_all(&ws_out)?;
+                }
+                return Ok(());
+            }
+
+            "error" => bail!("KuCoin sent an error: `{}`", json),
+
+            "message" => (),
+
+            _ => return Ok(()),
+        }
+
+        let topic = message.topic.ok_or_else(|| format_err!("missing topic"))?;
+
+        if topic.starts_with("/market/level2:") && self.flags.contains_book() {
+            let level2: KuCoinData<KuCoinLevel2<'_>> = serde_json::from_str(json)?;
+            self.process_level2(level2.data, out)?;
+        } else if topic.starts_with("/market/match:") && self.flags.contains_trades() {
+            let trade: KuCoinData<KuCoinMatch<'_>> = serde_json::from_str(json)?;
+            let trade = trade.data;
+
+            let trade = Notification::Trade(Trade {
+                size: Size(self.symbol.size_tick().ticked(trade.size)?),
+                price: Price(self.symbol.price_tick().ticked(trade.price)?),
+                // KuCoin gives the side of the taker.
+                maker_side: self.convert_kucoin_side(trade.side)?.opposite(),
+            }.with_timestamp(convert_ns_timestamp(trade.time.parse()?)));
+
+            out.unbounded_send(trade).unwrap();
+        } else if topic == "/spotMarket/tradeOrders" && self.flags.contains_orders() {
+            let order: KuCoinData<KuCoinOrderChange<'_>> = serde_json::from_str(json)?;
+            self.process_order_change(order.data, out)?;
+        }
+        Ok(())
+    }
+
+    fn process_level2(&mut self, level2: KuCoinLevel2<'_>, out: &wss::NotifSender)
+        -> Result<(), failure::Error>
+    {
+        let bid = level2.changes.bids
+            .into_iter()
+            .map(|(price, size, sequence)| (price, size, sequence, Side::Bid));
+        let ask = level2.changes.asks
+            .into_iter()
+            .map(|(price, size, sequence)| (price, size, sequence, Side::Ask));
+
+        let mut changes = Vec::new();
+        for (price, size, sequence, side) in bid.chain(ask) {
+            let update = self.convert_kucoin_update(price, size, side)?.timestamped();
+            changes.push((sequence.parse()?, update));
+        }
+        changes.sort_by_key(|(sequence, _)| *sequence);
+
+        match mem::replace(&mut self.book_snapshot_state, BookSnapshotState::Ok) {
+            // Very first change received: time to ask for the book snapshot.
+            BookSnapshotState::None => self.request_book_snapshot(changes),
+
+            // Still waiting: buffer incoming changes.
+            BookSnapshotState::Waiting(mut state) => {
+                state.events.extend(changes);
+                if let Some(notif) = self.maybe_recv_book(state) {
+                    out.unbounded_send(notif).unwrap();
+                }
+            }
+
+            BookSnapshotState::Ok => {
+                let last_sequence = self.last_sequence.unwrap_or(0);
+                if level2.sequenceStart > last_sequence + 1 {
+                    // We missed some changes, start over from a fresh snapshot.
+                    warn!("desynchronized order book, requesting a new snapshot");
+                    out.unbounded_send(Notification::BookResync).unwrap();
+                    self.request_book_snapshot(changes);
+                    return Ok(());
+                }
+
+                let mut updates = Vec::new();
+                for (sequence, update) in changes {
+                    if sequence > last_sequence {
+                        self.last_sequence = Some(sequence);
+                        updates.push(update);
+                    }
+                }
+
+                if !updates.is_empty() {
+                    out.unbounded_send(Notification::LimitUpdates(updates)).unwrap();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn process_order_change(&self, order: KuCoinOrderChange<'_>, out: &wss::NotifSender)
+        -> Result<(), failure::Error>
+    {
+        // The channel carries the orders of all symbols.
+        if order.symbol != self.symbol.name() {
+            return Ok(());
+        }
+
+        let order_id = order.clientOid
+            .filter(|oid| !oid.is_empty())
+            .unwrap_or(order.orderId)
+            .to_owned();
+        let timestamp = convert_ns_timestamp(order.ts);
+
+        match order.type_ {
+            "open" => {
+                let order = OrderConfirmation {
+                    size: Size(
+                        self.symbol.size_tick().ticked(required(order.size, "size")?)?
+                    ),
+                    price: Price(
+                        self.symbol.price_tick().ticked(required(order.price, "price")?)?
+                    ),
+                    side: self.convert_kucoin_side(order.side)?,
+                    order_id,
+                }.with_timestamp(timestamp);
+                out.unbounded_send(Notification::OrderConfirmation(order)).unwrap();
+            }
+
+            "match" => {
+                let update = OrderUpdate {
+                    order_id,
+                    consumed_size: Size(self.symbol.size_tick().ticked(
+                        required(order.matchSize, "match size")?
+                    )?),
+                    consumed_price: Price(self.symbol.price_tick().ticked(
+                        required(order.matchPrice, "match price")?
+                    )?),
+                    remaining_size: Size(self.symbol.size_tick().ticked(
+                        required(order.remainSize, "remaining size")?
+                    )?),
+                    commission: 0,
+                    commission_asset: self.symbol.quote_asset().map(|a| a.to_owned()),
+                    is_fill: true,
+                }.with_timestamp(timestamp);
+                out.unbounded_send(Notification::OrderUpdate(update)).unwrap();
+            }
+
+            "canceled" => {
+                let expiration = OrderExpiration {
+                    order_id,
+                }.with_timestamp(timestamp);
+                out.unbounded_send(Notification::OrderExpiration(expiration)).unwrap();
+            }
+
+            // `filled` follows the last `match` of an order, which was already forwarded.
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn process_book_snapshot(
+        &mut self,
+        snapshot: Result<KuCoinBookSnapshot, api::errors::Error>,
+        buffered_events: Vec<(SequenceNumber, Timestamped<LimitUpdate>)>
+    ) -> Result<Notification, failure::Error>
+    {
+        let snapshot = snapshot?;
+        let sequence: SequenceNumber = snapshot.sequence.parse()?;
+
+        let bid = snapshot.bids
+            .iter()
+            .map(|(price, size)| self.convert_kucoin_update(price, size, Side::Bid));
+
+        let ask = snapshot.asks
+            .iter()
+            .map(|(price, size)| self.convert_kucoin_update(price, size, Side::Ask));
+
+        let mut updates = bid.chain(ask)
+            .map(|l| Ok(l?.timestamped()))
+            .collect::<Result<Vec<_>, tick::ConversionError>>()?;
+
+        self.last_sequence = Some(sequence);
+        for (event_sequence, update) in buffered_events {
+            if event_sequence > sequence {
+                self.last_sequence = Some(event_sequence);
+                updates.push(update);
+            }
+        }
+
+        Ok(Notification::BookSnapshot(updates))
+    }
+
+    fn maybe_recv_book(&mut self, state: BookWaitingState)
+        -> Option<Notification>
+    {
+        match state.rcv.try_recv() {
+            Ok(book) => {
+                debug!("received LOB snapshot");
+                match self.process_book_snapshot(book, state.events) {
+                    Ok(notif) => {
+                        self.book_snapshot_state = BookSnapshotState::Ok;
+                        Some(notif)
+                    },
+                    Err(err) => {
+                        // We cannot continue without the book.
+                        panic!(
+                            "LOB processing encountered error: `{}`",
+                            err
+                        );
+                    }
+                }
+            },
+
+            // The snapshot request has not completed yet, we wait some more.
+            Err(mpsc::TryRecvError::Empty) => {
+                self.book_snapshot_state = BookSnapshotState::Waiting(state);
+                None
+            },
+
+            // The only `Sender` has somehow disconnected, we won't receive
+            // the book hence we cannot continue.
+            Err(mpsc::TryRecvError::Disconnected) => {
+                panic!("LOB sender has disconnected");
+            }
+        }
+    }
+
+    fn request_book_snapshot(&mut self, changes: Vec<(SequenceNumber, Timestamped<LimitUpdate>)>) {
+        let (snd, rcv) = mpsc::sync_channel(1);
+
+        self.last_sequence = None;
+        self.book_snapshot_state = BookSnapshotState::Waiting(
+            BookWaitingState {
+                rcv,
+
+                // Buffer the changes we've just received.
+                events: changes,
+            }
+        );
+
+        debug!("initiating LOB request for `{}`", self.symbol.name());
+
+        let fut = rest::book_snapshot(&self.http_client, &self.rest_endpoint, self.symbol);
+        thread::spawn(move || {
+            let fut = fut.then(move |res| {
+                let _ = snd.send(res);
+                Ok::<(), !>(())
+            });
+
+            use tokio::runtime::current_thread;
+            current_thread::block_on_all(fut).unwrap();
+        });
+    }
+}
+
+impl wss::HandlerImpl for HandlerImpl {
+    fn on_open(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        self.out = Some(out.clone());
+        Ok(())
+    }
+
+    fn on_message(&mut self, text: &str, out: &wss::NotifSender) -> Result<(), failure::Error> {
+        self.parse_message(text, out)
+    }
+
+    /// KuCoin closes the connection if it does not receive a ping message regularly, the
+    /// WebSocket ping frames are not enough.
+    fn on_ping(&mut self, out: &ws::Sender) -> ws::Result<()> {
+        let ping = KuCoinPing {
+            id: self.next_id(),
+            type_: "ping",
+        };
+
+        match serde_json::to_string(&ping) {
+            Ok(value) => self.raw_log.send(out, value),
+            Err(err) => {
+                panic!("failed to serialize `KuCoinPing`: `{}`", err);
+            }
+        }
+    }
+}
@@ -3,19 +3,40 @@
 pub mod binance;
 pub mod gdax;
 pub mod hitbtc;
+pub mod bitfinex;
+pub mod kucoin;
+pub mod deribit;
 pub mod errors;
 pub mod timestamp;
 pub mod symbol;
 pub mod order_book;
+pub mod notification;
+pub mod bounded;
+pub mod stream;
+pub mod replay;
+pub mod retry;
+pub mod dry_run;
+pub mod order_ids;
+pub mod rate_limit;
+pub mod transport;
+pub mod bars;
+pub mod dedup;
+pub mod trade_tape;
+pub mod portfolio;
+#[cfg(feature = "compat")]
+pub mod compat;
 mod query_string;
 mod wss;
+mod test;
+mod harness;
 
 use futures::prelude::*;
 use std::collections::HashMap;
 use serde_derive::{Serialize, Deserialize};
 use bitflags::bitflags;
 use crate::Side;
-use crate::tick::{TickUnit, Tickable};
+use crate::tick::{Tick, TickUnit, Tickable, Price, Size, RoundingMode};
+use crate::tick::{ConversionError, ConversionErrorKind, ConversionErrorReason};
 use crate::order_book::LimitUpdate;
 
 use self::timestamp::Timestamped;
@@ -23,6 +44,28 @@ use self::symbol::{Symbol, WithSymbol};
 
 pub use self::gdax as coinbase_pro; // Just rename GDAX to its new name.
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// An exchange supported by this crate, see e.g. `Params::testnet`.
+pub enum Exchange {
+    /// Binance.
+    Binance,
+
+    /// Coinbase Pro (ex GDAX).
+    Gdax,
+
+    /// HitBTC.
+    HitBtc,
+
+    /// Bitfinex.
+    Bitfinex,
+
+    /// KuCoin.
+    KuCoin,
+
+    /// Deribit.
+    Deribit,
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 /// Params needed for an API client.
 pub struct Params {
@@ -31,6 +74,114 @@ pub struct Params {
 
     /// REST API endpoint (usually over HTTP).
     pub rest_endpoint: String,
+
+    /// Total weight of the REST requests allowed per minute, see `rate_limit::RateLimiter`.
+    /// `None` disables rate limiting.
+    #[serde(default)]
+    pub weight_per_minute: Option<u32>,
+}
+
+impl Params {
+    fn new(streaming_endpoint: &str, rest_endpoint: &str, weight_per_minute: u32) -> Self {
+        Params {
+            streaming_endpoint: streaming_endpoint.to_owned(),
+            rest_endpoint: rest_endpoint.to_owned(),
+            weight_per_minute: Some(weight_per_minute),
+        }
+    }
+
+    /// Return a rate limiter according to `self.weight_per_minute`.
+    crate fn rate_limiter(&self) -> Option<rate_limit::RateLimiter> {
+        self.weight_per_minute
+            .filter(|weight| *weight != 0)
+            .map(rate_limit::RateLimiter::new)
+    }
+
+    /// Params for the test environment of `exchange`, i.e. the binance spot testnet, the
+    /// Coinbase Pro sandbox, the HitBTC demo API or the Deribit test API. Both endpoints
+    /// point to the test environment, and so does the user data stream since it is
+    /// requested from the REST API. Return `None` for Bitfinex and KuCoin, which have no
+    /// test environment.
+    pub fn testnet(exchange: Exchange) -> Option<Self> {
+        match exchange {
+            Exchange::Binance => Some(Self::binance_testnet()),
+            Exchange::Gdax => Some(Self::gdax_sandbox()),
+            Exchange::HitBtc => Some(Self::hitbtc_demo()),
+            Exchange::Deribit => Some(Self::deribit_testnet()),
+            Exchange::Bitfinex | Exchange::KuCoin => None,
+        }
+    }
+
+    /// Params for the binance production API.
+    pub fn binance_mainnet() -> Self {
+        // Binance allows a total request weight of 1200 per minute.
+        Self::new("wss://stream.binance.com:9443", "https://api.binance.com", 1200)
+    }
+
+    /// Params for the binance spot testnet, which uses the same signing scheme as the
+    /// production API.
+    ///
+    /// # Note
+    /// Deposit addresses and withdrawals are not available on the testnet.
+    pub fn binance_testnet() -> Self {
+        Self::new("wss://testnet.binance.vision", "https://testnet.binance.vision", 1200)
+    }
+
+    /// Params for the Coinbase Pro (ex GDAX) production API.
+    pub fn gdax_mainnet() -> Self {
+        // Coinbase Pro allows 3 public requests per second, each request has a weight of 1.
+        Self::new("wss://ws-feed.pro.coinbase.com", "https://api.pro.coinbase.com", 180)
+    }
+
+    /// Params for the Coinbase Pro (ex GDAX) sandbox API.
+    pub fn gdax_sandbox() -> Self {
+        Self::new(
+            "wss://ws-feed-public.sandbox.pro.coinbase.com",
+            "https://api-public.sandbox.pro.coinbase.com",
+            180
+        )
+    }
+
+    /// Params for the HitBTC production API.
+    pub fn hitbtc_mainnet() -> Self {
+        // HitBTC allows 100 requests per second, each request has a weight of 1.
+        Self::new("wss://api.hitbtc.com", "https://api.hitbtc.com", 6000)
+    }
+
+    /// Params for the HitBTC demo API, which uses the same signing scheme as the
+    /// production API.
+    pub fn hitbtc_demo() -> Self {
+        Self::new("wss://api.demo.hitbtc.com", "https://api.demo.hitbtc.com", 6000)
+    }
+
+    /// Params for the Bitfinex production API.
+    pub fn bitfinex_mainnet() -> Self {
+        // Bitfinex allows between 10 and 90 requests per minute depending on the endpoint,
+        // each request has a weight of 1.
+        Self::new("wss://api.bitfinex.com", "https://api.bitfinex.com", 60)
+    }
+
+    /// Params for the KuCoin production API. The WebSocket endpoint is only given for
+    /// reference, KuCoin clients connect to the endpoint returned along with their
+    /// connection token.
+    pub fn kucoin_mainnet() -> Self {
+        // KuCoin allows around 30 requests per 3 seconds on most endpoints, each request
+        // has a weight of 1.
+        Self::new("wss://ws-api.kucoin.com", "https://api.kucoin.com", 600)
+    }
+
+    /// Params for the Deribit production API. Only the WebSocket endpoint is used, Deribit
+    /// clients send their requests over WebSocket as well.
+    pub fn deribit_mainnet() -> Self {
+        // Deribit allows around 20 non matching engine requests per second, each request
+        // has a weight of 1.
+        Self::new("wss://www.deribit.com/ws/api/v2", "https://www.deribit.com", 1200)
+    }
+
+    /// Params for the Deribit test API, see `Params::deribit_mainnet`.
+    pub fn deribit_testnet() -> Self {
+        Self::new("wss://test.deribit.com/ws/api/v2", "https://test.deribit.com", 1200)
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
@@ -56,10 +207,45 @@ pub enum OrderType {
     /// A limit order which cannot take liquidity, i.e. an error would be returned by
     /// the exchange if the order crosses the other side of the book.
     LimitMaker,
+
+    /// A market order, consuming liquidity at any price until it is filled. The order
+    /// price and time in force are ignored.
+    Market,
+
+    /// A market order which is only placed once the last trade price reaches the stop
+    /// price given by `Order::with_stop_price`, i.e. drops to it for a sell order or
+    /// rises to it for a buy order. The order price and time in force are ignored.
+    StopLoss,
+
+    /// A limit order which is only placed once the last trade price reaches the stop
+    /// price given by `Order::with_stop_price`, see `OrderType::StopLoss`.
+    StopLossLimit,
+}
+
+impl OrderType {
+    /// Return `true` for the order types whose price is ignored.
+    crate fn is_market(self) -> bool {
+        match self {
+            OrderType::Market | OrderType::StopLoss => true,
+            OrderType::Limit | OrderType::LimitMaker | OrderType::StopLossLimit => false,
+        }
+    }
+
+    /// Return `true` for the order types which require a stop price.
+    crate fn is_stop(self) -> bool {
+        match self {
+            OrderType::StopLoss | OrderType::StopLossLimit => true,
+            OrderType::Limit | OrderType::LimitMaker | OrderType::Market => false,
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 /// An order to be sent through the API.
+///
+/// Prices and sizes are stored as `Tickable` values rather than `Price` and `Size`, since
+/// they may be given as unticked strings whose tick is only known once the order is sent
+/// for a given symbol. `Price` and `Size` both convert into `Tickable`.
 pub struct Order {
     price: Tickable,
     size: Tickable,
@@ -69,6 +255,9 @@ pub struct Order {
     time_in_force: TimeInForce,
     time_window: u64,
     order_id: Option<String>,
+    funds: Option<Tickable>,
+    stop_price: Option<Tickable>,
+    tag: Option<String>,
 }
 
 impl Order {
@@ -87,15 +276,89 @@ impl Order {
             time_in_force: TimeInForce::GoodTilCanceled,
             time_window: 5000,
             order_id: None,
+            funds: None,
+            stop_price: None,
+            tag: None,
         }
     }
 
+    /// Return a new `Order` like `Order::new`, but with `price` and `size` converted to
+    /// tick units using the price and size ticks of `symbol`. Unticked values which do not
+    /// fall on the tick grid are rounded according to `mode`, e.g. a price of `"100.255"`
+    /// becomes `10026` with `RoundingMode::Nearest` and `Tick::new(100)`.
+    ///
+    /// # Errors
+    /// Return `Err` if a value is in an incorrect format or too large, if it does not fall
+    /// on the tick grid with `RoundingMode::Exact`, or if the resulting order does not pass
+    /// `Order::validate`, e.g. because the size was rounded down to zero.
+    pub fn try_new<T, U>(price: T, size: U, side: Side, symbol: &Symbol, mode: RoundingMode)
+        -> Result<Self, errors::OrderValidationError>
+        where T: Into<Tickable>, U: Into<Tickable>
+    {
+        use self::errors::OrderValidationError;
+
+        let tick = symbol.price_tick();
+        let price = ticks(&price.into(), tick, mode).map_err(|(price, kind)| {
+            OrderValidationError::InvalidPrice { price, tick, kind }
+        })?;
+
+        let tick = symbol.size_tick();
+        let size = ticks(&size.into(), tick, mode).map_err(|(size, kind)| {
+            OrderValidationError::InvalidSize { size, tick, kind }
+        })?;
+
+        let order = Order::new(price, size, side);
+        order.validate(symbol)?;
+        Ok(order)
+    }
+
+    /// Return a new market `Order`, with:
+    /// * `size` being the order size
+    /// * `side` being `Side::Bid` (buy) or `Side::Ask` (sell)
+    pub fn market<U: Into<Tickable>>(size: U, side: Side) -> Self {
+        Order::new(0, size, side).with_order_type(OrderType::Market)
+    }
+
+    /// Return a new market buy `Order` spending `funds`, expressed in the quote currency
+    /// of the symbol (e.g. USD for BTC-USD) and unticked with `Symbol::funds_tick`.
+    ///
+    /// # Note
+    /// Usable only on Coinbase Pro and binance.
+    pub fn market_with_funds<F: Into<Tickable>>(funds: F) -> Self {
+        Order {
+            funds: Some(funds.into()),
+            ..Order::market(0, Side::Bid)
+        }
+    }
+
+    /// Set the order price, e.g. for deriving the order replacing this one.
+    pub fn with_price<T: Into<Tickable>>(mut self, price: T) -> Self {
+        self.price = price.into();
+        self
+    }
+
+    /// Set the order size, e.g. for deriving the order replacing this one.
+    pub fn with_size<U: Into<Tickable>>(mut self, size: U) -> Self {
+        self.size = size.into();
+        self
+    }
+
     /// Set the order type.
     pub fn with_order_type(mut self, order_type: OrderType) -> Self {
         self.type_ = order_type;
         self
     }
 
+    /// Set the price triggering a stop order, see `OrderType::StopLoss` and
+    /// `OrderType::StopLossLimit`. Ignored for other order types.
+    ///
+    /// # Note
+    /// Usable only on binance, Coinbase Pro and HitBTC.
+    pub fn with_stop_price<T: Into<Tickable>>(mut self, stop_price: T) -> Self {
+        self.stop_price = Some(stop_price.into());
+        self
+    }
+
     /// Time in force, see https://www.investopedia.com/terms/t/timeinforce.asp.
     pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
         self.time_in_force = time_in_force;
@@ -115,16 +378,73 @@ impl Order {
     /// Generate an id for identifying this order. When possible, the order id will
     /// be equal to `hint`, otherwise it is assured that all ids generated by a call to
     /// this method are distinct.
+    ///
+    /// # Note
+    /// This ignores any custom generator set on a client, use `Order::with_order_id_from`
+    /// for taking it into account.
     pub fn with_order_id<C: ApiClient>(mut self, hint: &str) -> Self {
-        self.order_id = Some(C::new_order_id(hint));
+        self.order_id = Some(self.tagged(C::new_order_id(hint)));
+        self
+    }
+
+    /// Generate an id for identifying this order with `client.generate_order_id`, i.e.
+    /// with the custom generator set on `client` if any, or like `Order::with_order_id`
+    /// otherwise.
+    pub fn with_order_id_from<C: ApiClient>(mut self, client: &C, hint: &str) -> Self {
+        self.order_id = Some(self.tagged(client.generate_order_id(hint)));
         self
     }
 
+    /// Attach `tag` to this order, e.g. the name of the strategy sending it. The tag is
+    /// prepended to the order id, whether it is generated before or after calling this
+    /// method, so that it can be recovered with `parse_tag` from the order id of any
+    /// notification about this order. Tagging again replaces the previous tag, which is
+    /// only removed from the order id if the latter starts with it.
+    ///
+    /// # Note
+    /// The tag counts toward the length limit of client order ids, e.g. 36 characters on
+    /// binance. Not usable on Coinbase Pro and Bitfinex, which only accept UUIDs and
+    /// integers respectively as client order ids.
+    ///
+    /// # Panics
+    /// Panic if `tag` is empty or contains characters which are not ASCII alphanumeric.
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        if tag.is_empty() || !tag.chars().all(|c| c.is_ascii_alphanumeric()) {
+            panic!("invalid order tag `{}`", tag);
+        }
+
+        if let Some(order_id) = self.order_id.take() {
+            let prefix = self.tag.as_ref().map(|old_tag| format!("{}{}", old_tag, TAG_SEPARATOR));
+            let untagged = match prefix {
+                Some(ref prefix) if order_id.starts_with(prefix.as_str()) => {
+                    order_id[prefix.len()..].to_owned()
+                }
+                _ => order_id,
+            };
+            self.order_id = Some(format!("{}{}{}", tag, TAG_SEPARATOR, untagged));
+        }
+        self.tag = Some(tag.to_owned());
+        self
+    }
+
+    /// Prepend the tag of this order, if any, to `order_id`.
+    fn tagged(&self, order_id: String) -> String {
+        match &self.tag {
+            Some(tag) => format!("{}{}{}", tag, TAG_SEPARATOR, order_id),
+            None => order_id,
+        }
+    }
+
     /// Return the order id if one was provided.
     pub fn order_id(&self) -> Option<&str> {
         self.order_id.as_ref().map(|s| s.as_ref())
     }
 
+    /// Return the tag attached with `Order::with_tag`, if any.
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_ref().map(|s| s.as_ref())
+    }
+
     /// Return the order price.
     pub fn price(&self) -> &Tickable {
         &self.price
@@ -135,6 +455,21 @@ impl Order {
         &self.size
     }
 
+    /// Return the order side.
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// Return the funds to spend if this is a funds-based market buy order.
+    pub fn funds(&self) -> Option<&Tickable> {
+        self.funds.as_ref()
+    }
+
+    /// Return the stop price if one was provided.
+    pub fn stop_price(&self) -> Option<&Tickable> {
+        self.stop_price.as_ref()
+    }
+
     /// Return the order type.
     pub fn order_type(&self) -> OrderType {
         self.type_
@@ -149,6 +484,106 @@ impl Order {
     pub fn time_window(&self) -> u64 {
         self.time_window
     }
+
+    /// Check that this order can be sent for `symbol`, i.e. that its price is a multiple
+    /// of the price tick, that its size is a non-zero multiple of the size tick, and
+    /// likewise for the funds of a funds-based market order (`Symbol::funds_tick` is then
+    /// used and the size is ignored). The price of a market order is ignored, while stop orders
+    /// must carry a stop price which is a multiple of the price tick.
+    ///
+    /// If `symbol.min_notional()` is provided, also check that the price times the size
+    /// of a limit order, or the funds of a funds-based market order, are not below it.
+    /// Market orders expressed with a size cannot be checked since their price is unknown.
+    ///
+    /// This is called by `ApiClient::order` before sending anything, an invalid order
+    /// being reported as `RestErrorKind::InvalidRequest` with the validation error as
+    /// the cause.
+    pub fn validate(&self, symbol: &Symbol) -> Result<(), errors::OrderValidationError> {
+        use self::errors::OrderValidationError;
+
+        let ticks = |value, tick| ticks(value, tick, RoundingMode::Exact);
+
+        // Checked first, so that funds-based stop orders also require a stop price.
+        if self.type_.is_stop() {
+            let tick = symbol.price_tick();
+            let stop_price = self.stop_price.as_ref()
+                .ok_or(OrderValidationError::MissingStopPrice)?;
+            ticks(stop_price, tick).map_err(|(stop_price, kind)| {
+                OrderValidationError::InvalidStopPrice { stop_price, tick, kind }
+            })?;
+        }
+
+        if let Some(funds) = &self.funds {
+            let tick = symbol.funds_tick();
+            let funds = ticks(funds, tick).map_err(|(funds, kind)| {
+                OrderValidationError::InvalidFunds { funds, tick, kind }
+            })?;
+            if funds == 0 {
+                return Err(OrderValidationError::ZeroFunds);
+            }
+            if let Some(min_notional) = symbol.min_notional() {
+                // `min_notional` is expressed in price ticks, which may differ from the
+                // funds tick.
+                let price_ticks_per_unit = u128::from(symbol.price_tick().ticks_per_unit());
+                let funds_ticks_per_unit = u128::from(tick.ticks_per_unit());
+                let notional = u128::from(funds) * price_ticks_per_unit;
+                if notional < u128::from(min_notional) * funds_ticks_per_unit {
+                    return Err(OrderValidationError::BelowMinNotional {
+                        // Cannot overflow since it is below `min_notional`.
+                        notional: (notional / funds_ticks_per_unit) as TickUnit,
+                        min_notional,
+                    });
+                }
+            }
+            return Ok(());
+        }
+
+        let price = if !self.type_.is_market() {
+            let tick = symbol.price_tick();
+            Some(ticks(&self.price, tick).map_err(|(price, kind)| {
+                OrderValidationError::InvalidPrice { price, tick, kind }
+            })?)
+        } else {
+            None
+        };
+
+        let tick = symbol.size_tick();
+        let size = ticks(&self.size, tick).map_err(|(size, kind)| {
+            OrderValidationError::InvalidSize { size, tick, kind }
+        })?;
+        if size == 0 {
+            return Err(OrderValidationError::ZeroSize);
+        }
+
+        if let (Some(price), Some(min_notional)) = (price, symbol.min_notional()) {
+            // `price * size` is expressed in price ticks times size ticks, hence the
+            // division by the number of size ticks per unit.
+            let ticks_per_unit = u128::from(tick.ticks_per_unit());
+            let notional = u128::from(price) * u128::from(size);
+            if notional < u128::from(min_notional) * ticks_per_unit {
+                return Err(OrderValidationError::BelowMinNotional {
+                    // Cannot overflow since it is below `min_notional`.
+                    notional: (notional / ticks_per_unit) as TickUnit,
+                    min_notional,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Return the tick units of `value`, rounded according to `mode`, or a string representation
+/// of `value` along with the reason why it cannot be expressed with `tick`.
+fn ticks(value: &Tickable, tick: Tick, mode: RoundingMode)
+    -> Result<TickUnit, (String, ConversionErrorKind)>
+{
+    match value {
+        Tickable::Ticked(ticks) => tick.unticked(*ticks)
+            .map(|_| *ticks)
+            .map_err(|err| (format!("{} ticks", ticks), err.kind())),
+        Tickable::Unticked(unticked) => tick.ticked_rounded(unticked, mode)
+            .map_err(|err| (unticked.clone(), err.kind())),
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
@@ -195,39 +630,56 @@ pub struct OrderAck {
     pub order_id: String,
 }
 
+impl OrderAck {
+    /// Return a `Cancel` targeting the acknowledged order, with the default time window.
+    pub fn to_cancel(&self) -> Cancel {
+        Cancel::new(self.order_id.clone())
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 /// An acknowledgment that a cancel order has been treated by the server.
 pub struct CancelAck;
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
-/// A notification that some order has been updated, i.e. a trade crossed through this order.
+/// A notification that some order has been updated, usually because a trade crossed through
+/// this order, see `OrderUpdate::is_fill`.
 pub struct OrderUpdate {
     /// ID identifying the order being updated.
     pub order_id: String,
 
     /// Size just consumed by last trade.
-    pub consumed_size: TickUnit,
+    pub consumed_size: Size,
 
     /// Total remaining size for this order (can be maintained in a standalone way
     /// using the size of the order at insertion time, `consumed_size` and `commission`).
-    pub remaining_size: TickUnit,
+    pub remaining_size: Size,
 
     /// Price at which the last trade happened.
-    pub consumed_price: TickUnit,
+    pub consumed_price: Price,
 
     /// Commission amount (warning: for binance this may not be in the same currency as
-    /// the traded asset).
+    /// the traded asset, see `commission_asset`).
     pub commission: TickUnit,
+
+    /// Asset in which the commission was paid, e.g. `"BNB"` for binance. This is the
+    /// quote asset of the symbol for GDAX and HitBTC.
+    pub commission_asset: Option<String>,
+
+    /// Whether the size was consumed by a trade. `false` if the size of the order decreased
+    /// without trading, e.g. because of self-trade prevention on GDAX: `consumed_price` and
+    /// `commission` are then meaningless.
+    pub is_fill: bool,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 /// A liquidity consuming order.
 pub struct Trade {
     /// Price in ticks.
-    pub price: TickUnit,
+    pub price: Price,
 
     /// Size consumed by the trade.
-    pub size: TickUnit,
+    pub size: Size,
 
     /// Side of the maker:
     /// * if `Ask`, then the maker was providing liquidity on the ask side,
@@ -237,6 +689,20 @@ pub struct Trade {
     pub maker_side: Side,
 }
 
+impl Trade {
+    /// Side of the taker, i.e. the opposite of `self.maker_side`: `Bid` if the consumer
+    /// bought to the maker, `Ask` if the consumer sold to the maker.
+    pub fn taker_side(&self) -> Side {
+        self.maker_side.opposite()
+    }
+
+    /// Return `true` if the maker was the buyer, i.e. if `self.maker_side == Side::Bid`.
+    /// This matches the `m` field of binance trades.
+    pub fn is_buyer_maker(&self) -> bool {
+        self.maker_side == Side::Bid
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 /// A notification that some order has expired or was canceled.
 pub struct OrderExpiration {
@@ -251,15 +717,22 @@ pub struct OrderConfirmation {
     pub order_id: String,
 
     /// Price at which the order was inserted.
-    pub price: TickUnit,
+    pub price: Price,
 
     /// Size at which the order was inserted.
-    pub size: TickUnit,
+    pub size: Size,
 
     /// Side of the order.
     pub side: Side,
 }
 
+impl OrderConfirmation {
+    /// Return a `Cancel` targeting the confirmed order, with the default time window.
+    pub fn to_cancel(&self) -> Cancel {
+        Cancel::new(self.order_id.clone())
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 /// A notification that some event happened.
 pub enum Notification {
@@ -269,6 +742,15 @@ pub enum Notification {
     /// The limit order book has changed and should be updated.
     LimitUpdates(Vec<Timestamped<LimitUpdate>>),
 
+    /// A full snapshot of the limit order book: the current order book should be cleared
+    /// before applying these updates.
+    BookSnapshot(Vec<Timestamped<LimitUpdate>>),
+
+    /// The limit order book went out of sync with the exchange. The next `BookSnapshot`
+    /// notification will carry a fresh snapshot of the order book, hence the current
+    /// order book should be cleared.
+    BookResync,
+
     /// An order has been inserted.
     OrderConfirmation(Timestamped<OrderConfirmation>),
 
@@ -277,6 +759,52 @@ pub enum Notification {
 
     /// An order has expired or was canceled.
     OrderExpiration(Timestamped<OrderExpiration>),
+
+    /// The balances of some assets have changed, only the changed balances are carried.
+    /// Forwarded only with `NotificationFlags::BALANCES`.
+    BalanceUpdate(Timestamped<Balances>),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// Coarse category of a `Notification`, for consumers which only care about some
+/// categories and should not need to list every variant.
+pub enum NotificationKind {
+    /// Trades and order book notifications.
+    MarketData,
+
+    /// Confirmations, updates and expirations of the orders of the account.
+    Order,
+
+    /// Balance updates of the account.
+    Balance,
+}
+
+impl Notification {
+    /// Return the category of this notification.
+    pub fn kind(&self) -> NotificationKind {
+        match self {
+            Notification::Trade(..) |
+            Notification::LimitUpdates(..) |
+            Notification::BookSnapshot(..) |
+            Notification::BookResync => NotificationKind::MarketData,
+
+            Notification::OrderConfirmation(..) |
+            Notification::OrderUpdate(..) |
+            Notification::OrderExpiration(..) => NotificationKind::Order,
+
+            Notification::BalanceUpdate(..) => NotificationKind::Balance,
+        }
+    }
+
+    /// Whether this is a trade or an order book notification.
+    pub fn is_market_data(&self) -> bool {
+        self.kind() == NotificationKind::MarketData
+    }
+
+    /// Whether this notification is about an order of the account.
+    pub fn is_order_event(&self) -> bool {
+        self.kind() == NotificationKind::Order
+    }
 }
 
 bitflags! {
@@ -291,19 +819,68 @@ bitflags! {
         /// Forward order confirmations and updates.
         const ORDERS = 0b0100;
 
-        /// Forward all notifications.
+        /// Forward balance updates. Not included in `ALL`, and only supported by binance
+        /// for now.
+        const BALANCES = 0b1000;
+
+        /// Forward all notifications but balance updates.
         const ALL = Self::ORDER_BOOK.bits | Self::TRADES.bits | Self::ORDERS.bits;
     }
 }
 
+impl NotificationFlags {
+    /// Flags forwarding market data only, i.e. limit updates and trades.
+    pub fn market_data() -> Self {
+        NotificationFlags::ORDER_BOOK | NotificationFlags::TRADES
+    }
+
+    /// Whether limit updates of the order book are forwarded.
+    pub fn contains_book(self) -> bool {
+        self.contains(NotificationFlags::ORDER_BOOK)
+    }
+
+    /// Whether trades are forwarded.
+    pub fn contains_trades(self) -> bool {
+        self.contains(NotificationFlags::TRADES)
+    }
+
+    /// Whether order confirmations and updates are forwarded.
+    pub fn contains_orders(self) -> bool {
+        self.contains(NotificationFlags::ORDERS)
+    }
+
+    /// Whether balance updates are forwarded.
+    pub fn contains_balances(self) -> bool {
+        self.contains(NotificationFlags::BALANCES)
+    }
+}
+
+/// A custom order id generation scheme, mapping a hint to an order id.
+crate type OrderIdGenerator = std::sync::Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Separator between the tag and the rest of a tagged order id, see `Order::with_tag`.
+const TAG_SEPARATOR: char = '_';
+
+/// Return the tag of an order id generated for an order tagged with `Order::with_tag`,
+/// i.e. the part of `order_id` preceding the first `_`. Return `None` if `order_id` does
+/// not look tagged, but note that ids generated from a hint containing a `_` look tagged.
+pub fn parse_tag(order_id: &str) -> Option<&str> {
+    let separator = order_id.find(TAG_SEPARATOR)?;
+    let tag = &order_id[..separator];
+    if tag.is_empty() || !tag.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some(tag)
+}
+
 /// Generate order ids.
 pub trait GenerateOrderId {
     /// Use `hint` for generating an order id. Except for
-    /// GDAX, this should just be `== to_owned`.
+    /// GDAX and Bitfinex, this should just be `== to_owned`.
     fn new_order_id(hint: &str) -> String;
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 /// Account balance for one asset.
 pub struct Balance {
     /// Available amount, unticked.
@@ -313,18 +890,123 @@ pub struct Balance {
     pub locked: String,
 }
 
+impl Balance {
+    /// Return the available amount in ticks, using the given asset `tick`. An
+    /// empty amount is treated as `0`.
+    pub fn free_ticked(&self, tick: Tick) -> Result<TickUnit, ConversionError> {
+        ticked_amount(&self.free, tick)
+    }
+
+    /// Return the locked amount in ticks, using the given asset `tick`. An
+    /// empty amount is treated as `0`.
+    pub fn locked_ticked(&self, tick: Tick) -> Result<TickUnit, ConversionError> {
+        ticked_amount(&self.locked, tick)
+    }
+
+    /// Return the total (free + locked) amount in ticks, using the given asset `tick`.
+    /// Fail with `ConversionErrorReason::Overflow` if the sum does not fit in a `TickUnit`.
+    pub fn total_ticked(&self, tick: Tick) -> Result<TickUnit, ConversionError> {
+        self.free_ticked(tick)?.checked_add(self.locked_ticked(tick)?).ok_or_else(|| {
+            let total = format!("{} + {}", self.free, self.locked);
+            ConversionError::unticked(total, tick, ConversionErrorReason::Overflow)
+        })
+    }
+
+    /// Return `true` if both the free and the locked amounts are zero. An empty amount is
+    /// treated as `0`, while an amount which cannot be parsed is not considered zero.
+    pub fn is_zero(&self) -> bool {
+        is_zero_amount(&self.free) && is_zero_amount(&self.locked)
+    }
+}
+
+fn is_zero_amount(amount: &str) -> bool {
+    amount.is_empty() || amount.parse::<f64>().map(|amount| amount == 0.).unwrap_or(false)
+}
+
+fn ticked_amount(amount: &str, tick: Tick) -> Result<TickUnit, ConversionError> {
+    if amount.is_empty() {
+        return Ok(0);
+    }
+    tick.ticked(amount)
+}
+
 /// A wrapper over a (currency name) => (balance) `HashMap`.
 pub type Balances = HashMap<String, Balance>;
 
+/// Extension methods for `Balances`, which is a plain `HashMap`.
+pub trait BalancesExt {
+    /// Iterate over the assets whose balance is not zero, see `Balance::is_zero`.
+    fn nonzero<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a String, &'a Balance)> + 'a>;
+}
+
+impl BalancesExt for Balances {
+    fn nonzero<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a String, &'a Balance)> + 'a> {
+        Box::new(self.iter().filter(|(_, balance)| !balance.is_zero()))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// A snapshot of the current market state for a symbol.
+pub struct Ticker {
+    /// Best bid price in ticks, `0` if the bid side is empty.
+    pub best_bid: Price,
+
+    /// Best ask price in ticks, `TickUnit::max_value()` if the ask side is empty.
+    pub best_ask: Price,
+
+    /// Price of the last trade in ticks.
+    pub last: Price,
+
+    /// Volume traded over the last 24 hours, in size ticks.
+    pub volume_24h: Size,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// A deposit address for one asset.
+pub struct DepositAddress {
+    /// Deposit address.
+    pub address: String,
+
+    /// Additional tag (or memo) which must accompany the deposit, for assets like
+    /// XRP or XLM.
+    pub tag: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// An acknowledgment that a withdrawal request has been treated by the server.
+pub struct WithdrawAck {
+    /// ID identifying the withdrawal.
+    pub id: String,
+}
+
 /// A trait implemented by clients of various exchanges API.
 pub trait ApiClient: GenerateOrderId {
     /// Type returned by the `stream` implementor, used for continuously receiving
     /// notifications.
     type Stream: Stream<Item = Notification, Error = ()> + Send + 'static;
 
-    /// Find a symbol by name.
+    /// Generate an order id from `hint`, see `Order::with_order_id_from`. Clients on
+    /// which a custom generator can be set use it, the default implementation just calls
+    /// `Self::new_order_id`.
+    fn generate_order_id(&self, hint: &str) -> String {
+        Self::new_order_id(hint)
+    }
+
+    /// Find a symbol by name. The lookup ignores case and `-`, `/` or `_` separators,
+    /// e.g. `"btc-usdt"` will find binance's `"BTCUSDT"` symbol.
     fn find_symbol(&self, symbol: &str) -> Option<Symbol>;
 
+    /// Retrieve a snapshot of the best bid / ask and last price for the given symbol.
+    /// This is a lighter-weight alternative to maintaining a full `LiveOrderBook`.
+    fn ticker(&self, symbol: Symbol)
+        -> Box<dyn Future<Item = Ticker, Error = errors::Error> + Send + 'static>;
+
+    /// Retrieve up to `limit` of the most recent public trades for the given symbol, oldest
+    /// first. This allows backfilling the trades missed by a notification stream, e.g. while
+    /// it was reconnecting.
+    fn recent_trades(&self, symbol: Symbol, limit: usize)
+        -> Box<dyn Future<Item = Vec<Timestamped<Trade>>, Error = errors::Error> + Send + 'static>;
+
     /// Start streaming notifications, only forward those indicated by `flags`.
     fn stream_with_flags(&self, symbol: Symbol, flags: NotificationFlags) -> Self::Stream;
 
@@ -333,7 +1015,38 @@ pub trait ApiClient: GenerateOrderId {
         self.stream_with_flags(symbol, NotificationFlags::ALL)
     }
 
-    /// Send an order to the exchange.
+    /// Start streaming notifications for several symbols at once, only forward those
+    /// indicated by `flags`. Each notification is tagged with its originating symbol.
+    ///
+    /// # Note
+    /// By default, this opens one connection per symbol and merges the resulting streams.
+    /// The binance client uses a single combined connection instead.
+    fn stream_many(&self, symbols: &[Symbol], flags: NotificationFlags)
+        -> Box<dyn Stream<Item = WithSymbol<Notification>, Error = ()> + Send + 'static>
+    {
+        let mut merged: Box<dyn Stream<Item = _, Error = _> + Send> =
+            Box::new(futures::stream::empty());
+
+        for &symbol in symbols {
+            let stream = self.stream_with_flags(symbol, flags)
+                .map(move |notif| WithSymbol::new(notif, symbol));
+            merged = Box::new(merged.select(stream));
+        }
+        merged
+    }
+
+    /// Start streaming notifications, only forward those indicated by `flags`, holding
+    /// at most `capacity` pending notifications. See `BoundedStream` for how notifications
+    /// are dropped when the consumer is too slow.
+    fn stream_bounded(&self, symbol: Symbol, flags: NotificationFlags, capacity: usize)
+        -> bounded::BoundedStream
+    {
+        bounded::BoundedStream::new(self.stream_with_flags(symbol, flags), capacity)
+    }
+
+    /// Send an order to the exchange. The order is first checked with `Order::validate`,
+    /// so that an invalid order fails with `RestErrorKind::InvalidRequest` without
+    /// reaching the exchange.
     fn order(&self, order: WithSymbol<&Order>)
         -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = errors::OrderError> + Send + 'static>;
 
@@ -344,6 +1057,22 @@ pub trait ApiClient: GenerateOrderId {
     fn cancel(&self, cancel: WithSymbol<&Cancel>)
         -> Box<dyn Future<Item = Timestamped<CancelAck>, Error = errors::CancelError> + Send + 'static>;
 
+    /// Replace the order `replaced` by `new`, return the acknowledgment of `new` on
+    /// success. `replaced` is the order as it was sent to the exchange, and must carry an
+    /// order id (see `Order::with_order_id`), otherwise an error of kind
+    /// `RestErrorKind::InvalidRequest` is returned.
+    ///
+    /// HitBTC natively supports replacing an order, in which case only the size and the
+    /// price of `new` are taken into account: `new` must have the same side, order type
+    /// and time in force as `replaced`, otherwise an error of kind
+    /// `RestErrorKind::InvalidRequest` is returned. For other exchanges, this is emulated by
+    /// canceling `replaced` and then sending `new`: if the cancel fails, `new` is not
+    /// sent. If `new` fails, `replaced` is not sent again since it may have been partially
+    /// filled before being canceled: the returned error then carries `replaced`, see
+    /// `OrderError::replaced_order`, and it is up to the caller to decide what to do.
+    fn replace(&self, replaced: WithSymbol<&Order>, new: &Order)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = errors::OrderError> + Send + 'static>;
+
     /// Send a ping to the exchange. This can be used to measure the whole roundtrip time,
     /// including authentication and passage through the various software layers. For binance,
     /// the exchange must be pinged regularly in order to keep the listen key alive.
@@ -356,4 +1085,124 @@ pub trait ApiClient: GenerateOrderId {
     /// Retrieve balances for this account.
     fn balances(&self)
         -> Box<dyn Future<Item = Balances, Error = errors::Error> + Send + 'static>;
+
+    /// Flatten the position on `symbol`: cancel `open_orders`, then sell the whole free
+    /// balance of the base asset of `symbol` (e.g. BTC for BTCUSDT) with a market order,
+    /// rounded down to the size tick. Nothing is sold if this rounds to zero.
+    ///
+    /// Exchanges do not let us list the open orders, so these must be tracked by the
+    /// caller. Cancels failing with `CancelErrorKind::UnknownOrder` are ignored, since the
+    /// order may have been filled in the meantime, but any other error is returned before
+    /// anything is sold.
+    ///
+    /// # Note
+    /// Only spot symbols can be flattened: if `Symbol::base_asset` is `None`, e.g. for
+    /// Deribit instruments, an error of kind `RestErrorKind::Unsupported` is returned.
+    fn flatten(&self, symbol: Symbol, open_orders: &[Cancel])
+        -> Box<dyn Future<Item = (), Error = errors::OrderError> + Send + 'static>
+        where Self: Clone + Send + Sized + 'static
+    {
+        flatten(self, symbol, open_orders)
+    }
+
+    /// Retrieve the deposit address of this account for the given asset, e.g. `"BTC"`.
+    fn deposit_address(&self, asset: &str)
+        -> Box<dyn Future<Item = DepositAddress, Error = errors::Error> + Send + 'static>;
+}
+
+/// Replace an order by canceling it and then sending the new order, see `ApiClient::replace`.
+crate fn replace_by_cancel<C>(client: &C, replaced: WithSymbol<&Order>, new: &Order)
+    -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = errors::OrderError> + Send + 'static>
+    where C: ApiClient + Clone + Send + 'static
+{
+    use self::symbol::IntoWithSymbol;
+
+    let order_id = match replaced.order_id() {
+        Some(order_id) => order_id.to_owned(),
+        None => return Box::new(
+            Err(errors::RestErrorKind::InvalidRequest.into())
+                .map_err(errors::ApiError::RestError)
+                .into_future()
+        ),
+    };
+
+    let client = client.clone();
+    let symbol = replaced.symbol();
+    let replaced = (*replaced).clone();
+    let new = new.clone();
+
+    let cancel = Cancel::new(order_id);
+    let fut = client.cancel(cancel.with_symbol(symbol))
+        .map_err(errors::cancel_to_order_error)
+        .and_then(move |_| {
+            client.order(new.with_symbol(symbol))
+                .map_err(move |err| errors::replace_error(err, replaced))
+        });
+    Box::new(fut)
+}
+
+/// Flatten the position on a symbol, see `ApiClient::flatten`.
+fn flatten<C>(client: &C, symbol: Symbol, open_orders: &[Cancel])
+    -> Box<dyn Future<Item = (), Error = errors::OrderError> + Send + 'static>
+    where C: ApiClient + Clone + Send + 'static
+{
+    use self::symbol::IntoWithSymbol;
+
+    let base_asset = match symbol.base_asset() {
+        Some(base_asset) => base_asset.to_owned(),
+        None => return Box::new(
+            Err(errors::RestErrorKind::Unsupported.into())
+                .map_err(errors::ApiError::RestError)
+                .into_future()
+        ),
+    };
+
+    let cancels: Vec<_> = open_orders.iter().map(|cancel| {
+        client.cancel(cancel.with_symbol(symbol)).then(|result| match result {
+            Ok(_) => Ok(()),
+            Err(errors::ApiError::RestError(ref err))
+                if err.kind() == errors::RestErrorKind::Specific(
+                    errors::CancelErrorKind::UnknownOrder
+                ) => Ok(()),
+            Err(err) => Err(errors::cancel_to_order_error(err)),
+        })
+    }).collect();
+
+    let client = client.clone();
+    let fut = futures::future::join_all(cancels)
+        .and_then({
+            let client = client.clone();
+            move |_| client.balances().map_err(errors::to_order_error)
+        })
+        .and_then(move |balances| {
+            let size = match balances.get(&base_asset) {
+                Some(balance) => balance.free_ticked(symbol.size_tick())
+                    .map_err(errors::RequestError::new)
+                    .map_err(errors::ApiError::RequestError)?,
+                None => 0,
+            };
+            Ok(size)
+        })
+        .and_then(move |size| -> Box<dyn Future<Item = _, Error = _> + Send> {
+            if size == 0 {
+                return Box::new(Ok(()).into_future());
+            }
+            let order = Order::market(size, Side::Ask);
+            Box::new(client.order(order.with_symbol(symbol)).map(|_| ()))
+        });
+    Box::new(fut)
+}
+
+/// A trait implemented by clients able to withdraw funds from the exchange. This is kept
+/// separate from `ApiClient` so that code generic over `ApiClient` cannot withdraw funds
+/// by accident.
+pub trait WithdrawClient: ApiClient {
+    /// Withdraw `amount` (unticked) of `asset` to `address`, with an additional `tag`
+    /// (or memo) for assets like XRP or XLM.
+    ///
+    /// # Note
+    /// Only work for binance right now, other exchanges return an error of kind
+    /// `RestErrorKind::Unsupported`.
+    fn withdraw(&self, asset: &str, amount: &str, address: &str, tag: Option<&str>)
+        -> Box<dyn Future<Item = WithdrawAck, Error = errors::Error> + Send + 'static>;
 }
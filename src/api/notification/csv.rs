@@ -0,0 +1,127 @@
+//! A module defining a CSV writer for recording notification streams.
+//!
+//! Each row starts with a record type, followed by the notification timestamp.
+//! There is one schema for trades:
+//! ```text
+//! trade,<timestamp>,<price>,<size>,<maker side>
+//! ```
+//! and one for limit updates:
+//! ```text
+//! limit_update,<timestamp>,<price>,<size>,<side>
+//! ```
+//! where prices and sizes are unticked using the symbol ticks, and sides are
+//! written as either `bid` or `ask`.
+//!
+//! A book snapshot is recorded as a header row followed by its limits, written as
+//! limit updates:
+//! ```text
+//! snapshot,<timestamp>,<number of limits>
+//! ```
+//! and a book resync as:
+//! ```text
+//! resync,<timestamp>
+//! ```
+//! where the timestamp is the one of the previous row (`0` if none), since a resync does
+//! not carry any. Other notifications are not recorded.
+
+use std::io::{self, Write};
+use crate::Side;
+use crate::tick::{Tick, TickUnit};
+use crate::order_book::LimitUpdate;
+use crate::api::Notification;
+use crate::api::symbol::Symbol;
+use crate::api::timestamp::{Timestamp, Timestamped};
+
+/// A CSV writer for notifications of a given symbol. The underlying writer is
+/// flushed on drop.
+pub struct Writer<W: Write> {
+    inner: W,
+    symbol: Symbol,
+    last_timestamp: Timestamp,
+}
+
+fn side_str(side: Side) -> &'static str {
+    match side {
+        Side::Bid => "bid",
+        Side::Ask => "ask",
+    }
+}
+
+fn unticked(tick: Tick, ticked: TickUnit) -> io::Result<String> {
+    tick.unticked(ticked)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+}
+
+impl<W: Write> Writer<W> {
+    /// Return a new `Writer` recording notifications into `inner`, using the ticks
+    /// of `symbol` for unticking prices and sizes.
+    pub fn new(inner: W, symbol: Symbol) -> Self {
+        Writer {
+            inner,
+            symbol,
+            last_timestamp: 0,
+        }
+    }
+
+    /// Write the rows corresponding to `notif`.
+    ///
+    /// # Errors
+    /// Return `Err` if writing to the underlying writer fails, or if a price or size
+    /// cannot be unticked with the symbol ticks.
+    pub fn write(&mut self, notif: &Notification) -> io::Result<()> {
+        match notif {
+            Notification::Trade(trade) => {
+                self.last_timestamp = trade.timestamp();
+                writeln!(
+                    self.inner,
+                    "trade,{},{},{},{}",
+                    trade.timestamp(),
+                    unticked(self.symbol.price_tick(), trade.price.0)?,
+                    unticked(self.symbol.size_tick(), trade.size.0)?,
+                    side_str(trade.maker_side),
+                )
+            }
+
+            Notification::LimitUpdates(updates) => self.write_limit_updates(updates),
+
+            Notification::BookSnapshot(updates) => {
+                let timestamp = updates.first()
+                    .map(|update| update.timestamp())
+                    .unwrap_or(self.last_timestamp);
+                self.last_timestamp = timestamp;
+                writeln!(self.inner, "snapshot,{},{}", timestamp, updates.len())?;
+                self.write_limit_updates(updates)
+            }
+
+            Notification::BookResync => writeln!(self.inner, "resync,{}", self.last_timestamp),
+
+            _ => Ok(()),
+        }
+    }
+
+    fn write_limit_updates(&mut self, updates: &[Timestamped<LimitUpdate>]) -> io::Result<()> {
+        for update in updates {
+            self.last_timestamp = update.timestamp();
+            writeln!(
+                self.inner,
+                "limit_update,{},{},{},{}",
+                update.timestamp(),
+                unticked(self.symbol.price_tick(), update.price.0)?,
+                unticked(self.symbol.size_tick(), update.size.0)?,
+                side_str(update.side),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for Writer<W> {
+    fn drop(&mut self) {
+        let _ = self.inner.flush();
+    }
+}
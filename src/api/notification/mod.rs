@@ -0,0 +1,4 @@
+//! A module grouping utilities for consuming notification streams.
+
+pub mod csv;
+mod test;
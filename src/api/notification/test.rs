@@ -0,0 +1,103 @@
+#![cfg(test)]
+
+use std::io::Cursor;
+use futures::prelude::*;
+use crate::Side;
+use crate::tick::{Tick, Price, Size};
+use crate::order_book::LimitUpdate;
+use crate::api::{Notification, Trade, OrderExpiration};
+use crate::api::symbol::Symbol;
+use crate::api::timestamp::IntoTimestamped;
+use crate::api::replay::FileReplay;
+use crate::api::notification::csv::Writer;
+
+#[test]
+fn round_trip() {
+    let symbol = Symbol::new("BTCUSDT", Tick::new(100), Tick::new(1000)).unwrap();
+    let notifs = vec![
+        Notification::BookSnapshot(vec![
+            LimitUpdate::new(Price(999), Size(1), Side::Bid).with_timestamp(1),
+        ]),
+        Notification::LimitUpdates(vec![
+            LimitUpdate::new(Price(1000), Size(5), Side::Bid).with_timestamp(1),
+            LimitUpdate::new(Price(1001), Size(7), Side::Ask).with_timestamp(1),
+        ]),
+        Notification::Trade(Trade {
+            price: Price(1001),
+            size: Size(2),
+            maker_side: Side::Ask,
+        }.with_timestamp(2)),
+        Notification::LimitUpdates(vec![
+            LimitUpdate::new(Price(1001), Size(5), Side::Ask).with_timestamp(2),
+        ]),
+        Notification::LimitUpdates(vec![
+            LimitUpdate::new(Price(1001), Size(0), Side::Ask).with_timestamp(3),
+        ]),
+        Notification::BookResync,
+        Notification::BookSnapshot(vec![]),
+        Notification::BookSnapshot(vec![
+            LimitUpdate::new(Price(1002), Size(3), Side::Ask).with_timestamp(4),
+            LimitUpdate::new(Price(1000), Size(4), Side::Bid).with_timestamp(4),
+        ]),
+    ];
+
+    let mut recorded = Vec::new();
+    {
+        let mut writer = Writer::new(&mut recorded, symbol);
+        for notif in &notifs {
+            writer.write(notif).unwrap();
+        }
+    }
+
+    assert_eq!(
+        String::from_utf8(recorded.clone()).unwrap().lines().next(),
+        Some("snapshot,1,1")
+    );
+
+    let replayed: Vec<_> = FileReplay::new(Cursor::new(recorded), symbol)
+        .wait()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(replayed, notifs);
+}
+
+#[test]
+fn csv_rows() {
+    let symbol = Symbol::new("BTCUSDT", Tick::new(100), Tick::new(1000)).unwrap();
+
+    let mut recorded = Vec::new();
+    {
+        let mut writer = Writer::new(&mut recorded, symbol);
+        writer.write(&Notification::BookSnapshot(vec![
+            LimitUpdate::new(Price(1000), Size(5), Side::Bid).with_timestamp(1),
+        ])).unwrap();
+        writer.write(&Notification::BookResync).unwrap();
+        writer.write(&Notification::OrderExpiration(OrderExpiration {
+            order_id: "order".to_owned(),
+        }.with_timestamp(2))).unwrap();
+        writer.write(&Notification::Trade(Trade {
+            price: Price(1000),
+            size: Size(1500),
+            maker_side: Side::Bid,
+        }.with_timestamp(2))).unwrap();
+    }
+
+    // Resyncs carry the timestamp of the previous row, other notifications are skipped.
+    assert_eq!(
+        String::from_utf8(recorded).unwrap(),
+        "snapshot,1,1\nlimit_update,1,10.00,0.005,bid\nresync,1\ntrade,2,10.00,1.500,bid\n"
+    );
+}
+
+#[test]
+fn truncated_snapshot() {
+    let symbol = Symbol::new("BTCUSDT", Tick::new(100), Tick::new(1000)).unwrap();
+    let recorded = "snapshot,1,2\nlimit_update,1,10.00,0.005,bid\n";
+
+    // The replay stops without forwarding an incomplete snapshot.
+    let replayed: Vec<_> = FileReplay::new(Cursor::new(recorded), symbol)
+        .wait()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(replayed, vec![]);
+}
@@ -1,11 +1,22 @@
-//! A module defining an helper data structure maintaining a live order book.
+//! A module defining an helper data structure maintaining a live order book, along
+//! with a sampler of its top of the book.
 
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use crate::Side;
 use crate::order_book::OrderBook;
-use crate::api::ApiClient;
+use crate::tick::{Price, Size};
+use futures::prelude::*;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver};
+use serde_derive::{Serialize, Deserialize};
+use crate::api::{ApiClient, Notification};
+use crate::api::timestamp::{Timestamp, timestamp_ms};
 
+#[derive(Clone)]
 /// A self-maintained live order book, updated in the background each time
 /// the underlying exchange stream sends an update.
+///
+/// Clones share the same order book, which is updated until all of them are dropped.
 pub struct LiveOrderBook {
     order_book: Arc<Mutex<OrderBook>>,
 }
@@ -29,9 +40,40 @@ impl LiveOrderBook {
     /// The call will block until the initial snapshot of the order book has been
     /// received.
     pub fn new<C: ApiClient>(stream: C::Stream) -> LiveOrderBook {
+        Self::from_stream(stream)
+    }
+
+    /// Build a self-maintained live order book from any notification stream, e.g. a
+    /// `api::replay::FileReplay`.
+    ///
+    /// # Note
+    /// The call will block until the initial snapshot of the order book has been
+    /// received.
+    pub fn from_stream<S>(stream: S) -> LiveOrderBook
+        where S: Stream<Item = Notification, Error = ()> + Send + 'static
+    {
+        Self::spawn(stream, None)
+    }
+
+    /// Build a self-maintained live order book from an exchange data stream, only
+    /// retaining the `depth` best limits of each side: limits beyond are removed
+    /// after each batch of updates.
+    ///
+    /// # Note
+    /// By design, the order book will be inaccurate beyond `depth`: a limit which
+    /// was removed will not reappear if it comes back within the `depth` best limits,
+    /// until the exchange sends an update for it.
+    ///
+    /// The call will block until the initial snapshot of the order book has been
+    /// received.
+    pub fn with_depth<C: ApiClient>(stream: C::Stream, depth: usize) -> LiveOrderBook {
+        Self::spawn(stream, Some(depth))
+    }
+
+    fn spawn<S>(stream: S, depth: Option<usize>) -> LiveOrderBook
+        where S: Stream<Item = Notification, Error = ()> + Send + 'static
+    {
         use std::thread;
-        use futures::prelude::*;
-        use crate::api::Notification;
 
         let order_book = Arc::new(Mutex::new(OrderBook::new()));
         let weak = order_book.clone();
@@ -43,20 +85,51 @@ impl LiveOrderBook {
             let mut snapshot = false;
 
             let fut = stream.for_each(|notif| {
-                if let Notification::LimitUpdates(updates) = notif {
-                    if let Some(order_book) = weak.upgrade() {
-                        let mut order_book = order_book.lock().unwrap();
-                        for update in updates {
-                            order_book.update(update.into_inner());
-                        }
+                let order_book = match notif {
+                    Notification::LimitUpdates(..) |
+                    Notification::BookSnapshot(..) |
+                    Notification::BookResync => {
+                        weak.upgrade()
+                    }
+                    _ => return Ok(()),
+                };
 
-                        if !snapshot {
-                            sender.send(()).unwrap();
-                            snapshot = true;
-                        }
-                    } else {
-                        // The `LiveOrderBook` object was dropped.
-                        return Err(());
+                let order_book = match order_book {
+                    Some(order_book) => order_book,
+
+                    // The `LiveOrderBook` object was dropped.
+                    None => return Err(()),
+                };
+                let mut order_book = order_book.lock().unwrap();
+
+                let updates = match notif {
+                    Notification::LimitUpdates(updates) => Some(updates),
+                    Notification::BookSnapshot(updates) => {
+                        *order_book = OrderBook::new();
+                        Some(updates)
+                    }
+
+                    // The next book snapshot will carry a fresh order book.
+                    Notification::BookResync => {
+                        *order_book = OrderBook::new();
+                        None
+                    }
+
+                    _ => None,
+                };
+
+                if let Some(updates) = updates {
+                    order_book.update_batch(
+                        updates.into_iter().map(|update| update.into_inner())
+                    );
+
+                    if let Some(depth) = depth {
+                        order_book.truncate(depth);
+                    }
+
+                    if !snapshot {
+                        sender.send(()).unwrap();
+                        snapshot = true;
                     }
                 }
                 Ok(())
@@ -87,3 +160,78 @@ impl LiveOrderBook {
         }
     }
 }
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// A sample of the top of the book.
+pub struct BookSample {
+    /// Time at which the sample was taken.
+    pub timestamp: Timestamp,
+
+    /// Best bid price, `Price(0)` if the bid side is empty.
+    pub best_bid: Price,
+
+    /// Best ask price, `Price(TickUnit::max_value())` if the ask side is empty.
+    pub best_ask: Price,
+
+    /// Size at the best bid.
+    pub bid_size: Size,
+
+    /// Size at the best ask.
+    pub ask_size: Size,
+}
+
+/// A stream sampling the top of a `LiveOrderBook` at fixed intervals. The stream ends
+/// when the order book gets disconnected.
+pub struct BookSampler {
+    rcv: UnboundedReceiver<BookSample>,
+}
+
+impl BookSampler {
+    /// Sample `order_book` every `interval`. This spawns a background thread, which
+    /// stops when either the order book gets disconnected or the `BookSampler` is dropped.
+    /// The order book is shared with the sampler, hence it can still be used meanwhile.
+    pub fn new(order_book: &LiveOrderBook, interval: Duration) -> Self {
+        use std::thread;
+
+        let order_book = order_book.clone();
+        let (snd, rcv) = unbounded();
+        thread::spawn(move || {
+            loop {
+                let sample = match order_book.order_book() {
+                    BookState::Live(book) => {
+                        let best_bid = book.best_bid();
+                        let best_ask = book.best_ask();
+                        BookSample {
+                            timestamp: timestamp_ms(),
+                            best_bid,
+                            best_ask,
+                            bid_size: book.size_at_limit(Side::Bid, best_bid),
+                            ask_size: book.size_at_limit(Side::Ask, best_ask),
+                        }
+                    }
+                    BookState::Disconnected => break,
+                };
+
+                // The `BookSampler` object was dropped.
+                if snd.unbounded_send(sample).is_err() {
+                    break;
+                }
+
+                thread::sleep(interval);
+            }
+        });
+
+        BookSampler {
+            rcv,
+        }
+    }
+}
+
+impl Stream for BookSampler {
+    type Item = BookSample;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<BookSample>, ()> {
+        self.rcv.poll()
+    }
+}
@@ -0,0 +1,151 @@
+//! A module defining stores for the mapping between client order ids and the order ids
+//! assigned by an exchange.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use chashmap::CHashMap;
+use log::warn;
+
+/// A store mapping client order ids to the order ids assigned by an exchange. For example,
+/// GDAX only accepts cancel requests carrying its own order ids, hence orders can only be
+/// canceled if their id can be found in the store.
+pub trait OrderIdStore: Send + Sync {
+    /// Record that the order with client order id `client_id` was assigned `server_id`.
+    fn insert(&self, client_id: String, server_id: String) -> io::Result<()>;
+
+    /// Return the order id assigned to the order with client order id `client_id`, if any.
+    fn get(&self, client_id: &str) -> Option<String>;
+}
+
+#[derive(Debug, Default)]
+/// An in-memory `OrderIdStore`, lost when the client is dropped. This is the default
+/// store used by `gdax::Client` and `dry_run::DryRunClient`.
+pub struct MemoryOrderIdStore {
+    order_ids: CHashMap<String, String>,
+}
+
+impl MemoryOrderIdStore {
+    /// Return an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OrderIdStore for MemoryOrderIdStore {
+    fn insert(&self, client_id: String, server_id: String) -> io::Result<()> {
+        self.order_ids.insert(client_id, server_id);
+        Ok(())
+    }
+
+    fn get(&self, client_id: &str) -> Option<String> {
+        self.order_ids.get(client_id).map(|server_id| server_id.clone())
+    }
+}
+
+#[derive(Debug)]
+struct FileStoreInner {
+    order_ids: HashMap<String, String>,
+    file: File,
+}
+
+#[derive(Debug)]
+/// A file-backed `OrderIdStore`, so that orders placed by a previous run can still
+/// be canceled. Each mapping is appended to the file as a `client_id server_id` line.
+pub struct FileOrderIdStore {
+    inner: Mutex<FileStoreInner>,
+}
+
+impl FileOrderIdStore {
+    /// Open the store backed by the file at `path`, loading the mappings already
+    /// recorded in it. The file is created if it does not exist.
+    ///
+    /// A last line which is malformed or not terminated by a newline was torn by a crash
+    /// while being written: it is skipped with a warning and removed from the file.
+    ///
+    /// # Errors
+    /// Return `Err` if the file cannot be opened, or if it contains a malformed line
+    /// other than the last one.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let mut order_ids = HashMap::new();
+
+        // Length of the file up to the end of the last well-formed line.
+        let mut valid_len = 0;
+        let mut torn_line: Option<String> = None;
+        {
+            let mut reader = BufReader::new(&mut file);
+            let mut buf = Vec::new();
+            loop {
+                buf.clear();
+                let read = reader.read_until(b'\n', &mut buf)?;
+                if read == 0 {
+                    break;
+                }
+
+                if let Some(line) = torn_line.take() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("malformed order id line `{}`", line)
+                    ));
+                }
+
+                let line = String::from_utf8_lossy(&buf);
+                let terminated = line.ends_with('\n');
+                let line = line.trim_end_matches('\n');
+                let mut fields = line.split(' ');
+                match (fields.next(), fields.next(), fields.next()) {
+                    _ if line.is_empty() && terminated => (),
+                    (Some(client_id), Some(server_id), None) if terminated => {
+                        order_ids.insert(client_id.to_owned(), server_id.to_owned());
+                    }
+                    _ => {
+                        torn_line = Some(line.to_owned());
+                        continue;
+                    }
+                }
+                valid_len += read as u64;
+            }
+        }
+
+        if let Some(line) = torn_line {
+            warn!("skipping torn last order id line `{}`", line);
+            file.set_len(valid_len)?;
+        }
+
+        Ok(FileOrderIdStore {
+            inner: Mutex::new(FileStoreInner {
+                order_ids,
+                file,
+            }),
+        })
+    }
+}
+
+impl OrderIdStore for FileOrderIdStore {
+    fn insert(&self, client_id: String, server_id: String) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+
+        // The id may be inserted twice, from both the REST response and the WebSocket
+        // notification.
+        if inner.order_ids.get(&client_id) == Some(&server_id) {
+            return Ok(());
+        }
+
+        writeln!(inner.file, "{} {}", client_id, server_id)?;
+        inner.file.flush()?;
+        inner.order_ids.insert(client_id, server_id);
+        Ok(())
+    }
+
+    fn get(&self, client_id: &str) -> Option<String> {
+        self.inner.lock().unwrap().order_ids.get(client_id).cloned()
+    }
+}
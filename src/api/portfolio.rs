@@ -0,0 +1,172 @@
+//! A module defining helpers for tracking the position resulting from our fills.
+
+use std::collections::HashMap;
+use log::debug;
+use crate::Side;
+use crate::tick::{TickUnit, Price, Size};
+use crate::api::Notification;
+use crate::api::symbol::Symbol;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// Track the position and the PnL on one symbol, from the notification stream of this symbol.
+///
+/// The side of our orders is learnt from `Notification::OrderConfirmation`, and the fills
+/// from `Notification::OrderUpdate`, so the stream must be requested with
+/// `NotificationFlags::ORDERS`. `Notification::Trade` updates the mark price used for the
+/// unrealized PnL.
+///
+/// The average entry price follows the average cost method: increasing the position updates
+/// the average entry price, reducing it realizes the PnL against the average entry price.
+/// PnLs are expressed in `price tick * size tick` units, e.g. in 10^-10 USDT for a symbol
+/// quoted with a 0.01 price tick and traded with a 10^-8 size tick.
+pub struct PositionTracker {
+    symbol: Symbol,
+
+    /// order id => side, for the orders which have not been entirely filled yet
+    orders: HashMap<String, Side>,
+
+    /// Positive when long, negative when short.
+    position: i128,
+
+    /// Entry notional of the current position, always non-negative.
+    cost: u128,
+
+    realized_pnl: i128,
+    mark_price: Option<Price>,
+
+    /// asset => total commission paid, `None` if the exchange does not tell the asset
+    commissions: HashMap<Option<String>, TickUnit>,
+}
+
+impl PositionTracker {
+    /// Return a tracker with a flat position on `symbol`.
+    pub fn new(symbol: Symbol) -> Self {
+        PositionTracker {
+            symbol,
+            orders: HashMap::new(),
+            position: 0,
+            cost: 0,
+            realized_pnl: 0,
+            mark_price: None,
+            commissions: HashMap::new(),
+        }
+    }
+
+    /// Tracked symbol.
+    pub fn symbol(&self) -> Symbol {
+        self.symbol
+    }
+
+    /// Update the tracker with `notif`. Notifications other than order confirmations,
+    /// order updates and trades are ignored, as well as the updates of orders whose
+    /// confirmation was not received. Order updates which are not fills, see
+    /// `OrderUpdate::is_fill`, do not change the position.
+    pub fn push_notification(&mut self, notif: &Notification) {
+        match notif {
+            Notification::OrderConfirmation(confirmation) => {
+                self.orders.insert(confirmation.order_id.clone(), confirmation.side);
+            }
+
+            Notification::OrderUpdate(update) => {
+                let side = match self.orders.get(&update.order_id) {
+                    Some(side) => *side,
+                    None => {
+                        debug!("update of unknown order `{}`", update.order_id);
+                        return;
+                    }
+                };
+
+                if update.remaining_size.is_zero() {
+                    self.orders.remove(&update.order_id);
+                }
+
+                if !update.is_fill {
+                    return;
+                }
+
+                self.fill(side, update.consumed_price, update.consumed_size);
+                let commission = self.commissions
+                    .entry(update.commission_asset.clone())
+                    .or_insert(0);
+                *commission = commission.saturating_add(update.commission);
+            }
+
+            Notification::Trade(trade) => self.mark_price = Some(trade.price),
+
+            _ => (),
+        }
+    }
+
+    /// Record a fill of `size` at `price` for one of our orders on the given `side`.
+    pub fn fill(&mut self, side: Side, price: Price, size: Size) {
+        let (price, size) = (u128::from(price.0), u128::from(size.0));
+        let increases = match side {
+            Side::Bid => self.position >= 0,
+            Side::Ask => self.position <= 0,
+        };
+
+        let opened = if increases {
+            size
+        } else {
+            let held = self.position.abs() as u128;
+            let closed = std::cmp::min(size, held);
+            let closed_cost = self.cost * closed / held;
+            let pnl = (price * closed) as i128 - closed_cost as i128;
+
+            self.realized_pnl += if self.position > 0 { pnl } else { -pnl };
+            self.cost -= closed_cost;
+            self.position += match side {
+                Side::Bid => closed as i128,
+                Side::Ask => -(closed as i128),
+            };
+            size - closed
+        };
+
+        self.cost += price * opened;
+        self.position += match side {
+            Side::Bid => opened as i128,
+            Side::Ask => -(opened as i128),
+        };
+    }
+
+    /// Current position in size ticks, positive when long and negative when short.
+    pub fn position(&self) -> i128 {
+        self.position
+    }
+
+    /// Average entry price of the current position, rounded down, or `None` if the position
+    /// is flat.
+    pub fn average_entry_price(&self) -> Option<Price> {
+        if self.position == 0 {
+            return None;
+        }
+        Some(Price((self.cost / self.position.abs() as u128) as TickUnit))
+    }
+
+    /// PnL realized by reducing the position so far.
+    pub fn realized_pnl(&self) -> i128 {
+        self.realized_pnl
+    }
+
+    /// Price of the last trade, if any.
+    pub fn mark_price(&self) -> Option<Price> {
+        self.mark_price
+    }
+
+    /// PnL of the current position if it were closed at the mark price, or `None` if no
+    /// trade was received yet.
+    pub fn unrealized_pnl(&self) -> Option<i128> {
+        let mark_price = u128::from(self.mark_price?.0);
+        let value = (mark_price * self.position.abs() as u128) as i128 - self.cost as i128;
+        Some(if self.position >= 0 { value } else { -value })
+    }
+
+    /// Total commission paid in the given `asset`, in commission ticks of the symbol. Pass
+    /// `None` for the commissions whose asset was not given by the exchange.
+    pub fn commission(&self, asset: Option<&str>) -> TickUnit {
+        self.commissions
+            .get(&asset.map(|asset| asset.to_owned()))
+            .cloned()
+            .unwrap_or(0)
+    }
+}
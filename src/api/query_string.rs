@@ -1,14 +1,32 @@
 use std::fmt;
 use openssl::{sign::Signer, hash::MessageDigest, pkey::{PKey, Private}};
+use crate::api::timestamp::{timestamp_ms, ClockOffset};
 
 crate struct QueryString {
     query: String,
+    timestamp: bool,
 }
 
 impl QueryString {
     crate fn new() -> Self {
         QueryString {
             query: String::new(),
+            timestamp: false,
+        }
+    }
+
+    /// Push a `timestamp` parameter, whose value is only computed when the query is
+    /// turned into a string, i.e. when the request is actually sent. Signed queries are
+    /// stamped with the server time, see `QueryString::into_string_with_signature`.
+    crate fn push_timestamp(&mut self) {
+        self.timestamp = true;
+    }
+
+    fn finish(&mut self, clock: Option<&ClockOffset>) {
+        if self.timestamp {
+            self.timestamp = false;
+            let timestamp = clock.map(ClockOffset::timestamp_ms).unwrap_or_else(timestamp_ms);
+            self.push("timestamp", timestamp);
         }
     }
 
@@ -30,11 +48,16 @@ impl QueryString {
         write!(&mut self.query, "{}={}", name, arg).unwrap();
     }
 
-    crate fn into_string(self) -> String {
+    crate fn into_string(mut self) -> String {
+        self.finish(None);
         self.query
     }
 
-    crate fn into_string_with_signature(mut self, key: &PKey<Private>) -> String {
+    /// Sign the query with `key`, the `timestamp` parameter being corrected by `clock`.
+    crate fn into_string_with_signature(mut self, key: &PKey<Private>, clock: &ClockOffset)
+        -> String
+    {
+        self.finish(Some(clock));
         let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
         signer.update(self.query.as_bytes()).unwrap();
         let signature = hex::encode(&signer.sign_to_vec().unwrap());
@@ -0,0 +1,132 @@
+//! A module defining a token bucket rate limiter shared by the requests of a client.
+
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use futures::prelude::*;
+use futures::future::{self, Either, Loop};
+use log::{debug, error};
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: u32,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    /// Tokens refilled per millisecond.
+    fn rate(&self) -> f64 {
+        f64::from(self.capacity) / 60_000.
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill);
+        let elapsed_ms = elapsed.as_secs() as f64 * 1000. + f64::from(elapsed.subsec_millis());
+        self.tokens = (self.tokens + elapsed_ms * self.rate()).min(f64::from(self.capacity));
+        self.last_refill = now;
+    }
+
+    /// Take `weight` tokens if available, otherwise return how long to wait before
+    /// enough tokens are available.
+    fn take(&mut self, weight: u32) -> Result<(), Duration> {
+        self.refill();
+
+        let weight = f64::from(weight);
+        if self.tokens >= weight {
+            self.tokens -= weight;
+            Ok(())
+        } else {
+            let wait_ms = ((weight - self.tokens) / self.rate()).ceil() as u64;
+            Err(Duration::from_millis(cmp::max(wait_ms, 1)))
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// A token bucket holding up to `weight_per_minute` tokens and refilling continuously at
+/// `weight_per_minute` tokens per minute. Each request takes as many tokens as its weight,
+/// and is delayed until enough tokens are available.
+///
+/// Clones share the same bucket.
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl RateLimiter {
+    /// Return a full rate limiter allowing `weight_per_minute` per minute.
+    ///
+    /// # Panics
+    /// Panic if `weight_per_minute` is `0`.
+    pub fn new(weight_per_minute: u32) -> Self {
+        if weight_per_minute == 0 {
+            panic!("`weight_per_minute` cannot be 0");
+        }
+
+        RateLimiter {
+            bucket: Arc::new(Mutex::new(Bucket {
+                capacity: weight_per_minute,
+                tokens: f64::from(weight_per_minute),
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    /// Return the weight which can currently be spent without being delayed.
+    pub fn budget(&self) -> u32 {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.refill();
+        bucket.tokens as u32
+    }
+
+    /// Return a future resolving once `weight` tokens have been taken from the bucket.
+    /// A `weight` greater than the capacity of the bucket is clamped to the capacity.
+    ///
+    /// # Note
+    /// The delay relies on `tokio::timer`. If no timer is available, e.g. when the future
+    /// is not run by a tokio runtime, an error is logged and the future resolves
+    /// immediately, hence the request is sent without being delayed.
+    crate fn acquire(&self, weight: u32) -> impl Future<Item = (), Error = ()> + Send + 'static {
+        use tokio::timer::Delay;
+
+        let bucket = self.bucket.clone();
+        future::loop_fn((), move |()| {
+            let wait = {
+                let mut bucket = bucket.lock().unwrap();
+                let weight = cmp::min(weight, bucket.capacity);
+                bucket.take(weight)
+            };
+
+            match wait {
+                Ok(()) => Either::A(future::ok(Loop::Break(()))),
+                Err(wait) => {
+                    debug!("rate limit reached, delaying request for {:?}", wait);
+                    Either::B(Delay::new(Instant::now() + wait).then(|res| {
+                        match res {
+                            Ok(()) => Ok(Loop::Continue(())),
+                            Err(err) => {
+                                error!(
+                                    "no timer available for delaying request, \
+                                    sending it anyway: `{}`",
+                                    err
+                                );
+                                Ok(Loop::Break(()))
+                            }
+                        }
+                    }))
+                }
+            }
+        })
+    }
+}
+
+/// Wait for `weight` tokens from `rate_limiter` if any, resolve immediately otherwise.
+crate fn acquire(rate_limiter: Option<&RateLimiter>, weight: u32)
+    -> impl Future<Item = (), Error = ()> + Send + 'static
+{
+    match rate_limiter {
+        Some(rate_limiter) => Either::A(rate_limiter.acquire(weight)),
+        None => Either::B(future::ok(())),
+    }
+}
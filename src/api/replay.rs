@@ -0,0 +1,224 @@
+//! A module defining a notification stream replaying recorded data.
+
+use std::io::{self, BufRead};
+use std::fs::File;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use futures::prelude::*;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use failure::{bail, format_err};
+use log::error;
+use crate::Side;
+use crate::tick::{Price, Size};
+use crate::order_book::LimitUpdate;
+use crate::api::{Notification, Trade};
+use crate::api::symbol::Symbol;
+use crate::api::timestamp::{Timestamp, Timestamped, IntoTimestamped};
+
+/// A notification stream replaying data recorded in the format written by
+/// `api::notification::csv::Writer`, so that recorded data can go through the same code
+/// path as live data (e.g. be fed to `LiveOrderBook::from_stream`).
+///
+/// Consecutive limit updates sharing the same timestamp are gathered into a single
+/// `Notification::LimitUpdates`, while snapshot and resync records are replayed as
+/// `Notification::BookSnapshot` and `Notification::BookResync`. Rows are expected to be
+/// sorted by timestamp, which is the case for files written by
+/// `api::notification::csv::Writer`.
+pub struct FileReplay {
+    rcv: UnboundedReceiver<Notification>,
+}
+
+impl FileReplay {
+    /// Replay the data read from `reader` as fast as possible. Prices and sizes are
+    /// converted using the ticks of `symbol`.
+    pub fn new<R: BufRead + Send + 'static>(reader: R, symbol: Symbol) -> Self {
+        Self::spawn(reader, symbol, None)
+    }
+
+    /// Replay the data read from `reader`, waiting between two notifications for the
+    /// recorded inter-arrival delay divided by `speed`, e.g. `speed == 2.` replays
+    /// twice as fast as real time.
+    ///
+    /// # Panics
+    /// Panic if `speed` is not a positive number.
+    pub fn with_speed<R: BufRead + Send + 'static>(reader: R, symbol: Symbol, speed: f64)
+        -> Self
+    {
+        if !(speed > 0.) {
+            panic!("`speed` must be positive");
+        }
+        Self::spawn(reader, symbol, Some(speed))
+    }
+
+    /// Replay the data recorded in the file at `path` as fast as possible.
+    pub fn open<P: AsRef<Path>>(path: P, symbol: Symbol) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self::new(io::BufReader::new(file), symbol))
+    }
+
+    fn spawn<R: BufRead + Send + 'static>(reader: R, symbol: Symbol, speed: Option<f64>)
+        -> Self
+    {
+        let (snd, rcv) = unbounded();
+        thread::spawn(move || {
+            let mut replayer = Replayer {
+                snd,
+                speed,
+                last_timestamp: None,
+            };
+
+            if let Err(err) = replayer.run(reader, symbol) {
+                error!("replay terminated with error: `{}`", err);
+            }
+        });
+
+        FileReplay {
+            rcv,
+        }
+    }
+}
+
+impl Stream for FileReplay {
+    type Item = Notification;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Notification>, ()> {
+        self.rcv.poll()
+    }
+}
+
+struct Replayer {
+    snd: UnboundedSender<Notification>,
+    speed: Option<f64>,
+    last_timestamp: Option<Timestamp>,
+}
+
+enum Row {
+    Trade(Timestamped<Trade>),
+    LimitUpdate(Timestamped<LimitUpdate>),
+
+    /// Header of a snapshot, followed by the given number of limit update rows.
+    Snapshot(Timestamp, usize),
+    Resync(Timestamp),
+}
+
+fn parse_side(side: &str) -> Result<Side, failure::Error> {
+    let side = match side {
+        "bid" => Side::Bid,
+        "ask" => Side::Ask,
+        other => bail!("wrong side: `{}`", other),
+    };
+    Ok(side)
+}
+
+fn parse_row(line: &str, symbol: Symbol) -> Result<Row, failure::Error> {
+    let fields: Vec<_> = line.split(',').collect();
+    let expected_len = match fields[0] {
+        "trade" | "limit_update" => 5,
+        "snapshot" => 3,
+        "resync" => 2,
+        other => bail!("unknown record type `{}`", other),
+    };
+    if fields.len() != expected_len {
+        bail!("wrong number of fields in row `{}`", line);
+    }
+
+    let timestamp: Timestamp = fields[1].parse()?;
+    match fields[0] {
+        "snapshot" => return Ok(Row::Snapshot(timestamp, fields[2].parse()?)),
+        "resync" => return Ok(Row::Resync(timestamp)),
+        _ => (),
+    }
+
+    let price = Price(symbol.price_tick().ticked(fields[2])?);
+    let size = Size(symbol.size_tick().ticked(fields[3])?);
+    let side = parse_side(fields[4])?;
+
+    let row = match fields[0] {
+        "trade" => Row::Trade(Trade {
+            price,
+            size,
+            maker_side: side,
+        }.with_timestamp(timestamp)),
+
+        _ => Row::LimitUpdate(LimitUpdate::new(price, size, side).with_timestamp(timestamp)),
+    };
+    Ok(row)
+}
+
+impl Replayer {
+    fn run<R: BufRead>(&mut self, reader: R, symbol: Symbol) -> Result<(), failure::Error> {
+        let mut updates: Vec<Timestamped<LimitUpdate>> = Vec::new();
+
+        let mut lines = reader.lines().filter(|line| {
+            line.as_ref().map(|line| !line.is_empty()).unwrap_or(true)
+        });
+        while let Some(line) = lines.next() {
+            let line = line?;
+
+            match parse_row(&line, symbol)? {
+                Row::Trade(trade) => {
+                    self.send_updates(&mut updates)?;
+                    self.send(trade.timestamp(), Notification::Trade(trade))?;
+                }
+
+                Row::Snapshot(timestamp, len) => {
+                    self.send_updates(&mut updates)?;
+
+                    let mut snapshot = Vec::with_capacity(len);
+                    for _ in 0..len {
+                        let line = match lines.next() {
+                            Some(line) => line?,
+                            None => bail!("truncated snapshot at timestamp {}", timestamp),
+                        };
+                        match parse_row(&line, symbol)? {
+                            Row::LimitUpdate(update) => snapshot.push(update),
+                            _ => bail!("expected a snapshot limit, found `{}`", line),
+                        }
+                    }
+                    self.send(timestamp, Notification::BookSnapshot(snapshot))?;
+                }
+
+                Row::Resync(timestamp) => {
+                    self.send_updates(&mut updates)?;
+                    self.send(timestamp, Notification::BookResync)?;
+                }
+
+                Row::LimitUpdate(update) => {
+                    let same_batch = updates.last()
+                        .map(|last| last.timestamp() == update.timestamp())
+                        .unwrap_or(true);
+
+                    if !same_batch {
+                        self.send_updates(&mut updates)?;
+                    }
+                    updates.push(update);
+                }
+            }
+        }
+
+        self.send_updates(&mut updates)
+    }
+
+    fn send_updates(&mut self, updates: &mut Vec<Timestamped<LimitUpdate>>)
+        -> Result<(), failure::Error>
+    {
+        if let Some(timestamp) = updates.first().map(|update| update.timestamp()) {
+            let updates = std::mem::replace(updates, Vec::new());
+            self.send(timestamp, Notification::LimitUpdates(updates))?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, timestamp: Timestamp, notif: Notification) -> Result<(), failure::Error> {
+        if let (Some(speed), Some(last_timestamp)) = (self.speed, self.last_timestamp) {
+            let delay = timestamp.saturating_sub(last_timestamp) as f64 / speed;
+            thread::sleep(Duration::from_micros((delay * 1000.) as u64));
+        }
+        self.last_timestamp = Some(timestamp);
+
+        self.snd.unbounded_send(notif)
+            .map_err(|_| format_err!("replay stream was dropped"))
+    }
+}
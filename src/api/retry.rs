@@ -0,0 +1,197 @@
+//! A module defining an `ApiClient` decorator retrying rate limited requests.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use futures::prelude::*;
+use futures::future::{self, Either, Loop};
+use log::warn;
+use crate::api::{
+    ApiClient,
+    GenerateOrderId,
+    Order,
+    OrderAck,
+    Cancel,
+    CancelAck,
+    Notification,
+    NotificationFlags,
+    Balances,
+    DepositAddress,
+    Ticker,
+    Trade,
+};
+use crate::api::errors::{self, ApiError, ErrorKind, RestErrorKind, RequestError};
+use crate::api::symbol::{Symbol, WithSymbol, IntoWithSymbol};
+use crate::api::timestamp::Timestamped;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// An exponential backoff policy: the `n`-th retry (starting at `0`) waits for
+/// `initial * 2^n`, capped at `max`.
+pub struct Backoff {
+    /// Delay before the first retry.
+    pub initial: Duration,
+
+    /// Maximum delay between two retries.
+    pub max: Duration,
+}
+
+impl Backoff {
+    fn delay(self, retry: u32) -> Duration {
+        2u32.checked_pow(retry)
+            .and_then(|factor| self.initial.checked_mul(factor))
+            .map(|delay| std::cmp::min(delay, self.max))
+            .unwrap_or(self.max)
+    }
+}
+
+/// An `ApiClient` decorator retrying the `order`, `cancel` and `balances` requests
+/// which failed with `RestErrorKind::TooManyRequests`, waiting between two attempts
+/// according to a `Backoff` policy. Other errors, and in particular
+/// `RestErrorKind::AddressBanned`, are propagated immediately.
+///
+/// # Note
+/// The retries rely on `tokio::timer`, hence the returned futures must be run on a
+/// tokio runtime.
+pub struct RetryClient<C> {
+    inner: Arc<C>,
+    backoff: Backoff,
+    max_retries: u32,
+}
+
+impl<C: ApiClient + Send + Sync + 'static> RetryClient<C> {
+    /// Wrap `inner`, retrying a rate limited request at most `max_retries` times.
+    pub fn new(inner: C, backoff: Backoff, max_retries: u32) -> Self {
+        RetryClient {
+            inner: Arc::new(inner),
+            backoff,
+            max_retries,
+        }
+    }
+
+    /// Return a reference to the wrapped client.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    fn retry<T, K, F, Fut>(&self, mut request: F)
+        -> Box<dyn Future<Item = T, Error = ApiError<K>> + Send + 'static>
+        where T: Send + 'static,
+              K: ErrorKind + Send + 'static,
+              F: FnMut(&C) -> Fut + Send + 'static,
+              Fut: Future<Item = T, Error = ApiError<K>> + Send + 'static
+    {
+        let inner = self.inner.clone();
+        let backoff = self.backoff;
+        let max_retries = self.max_retries;
+
+        let fut = future::loop_fn(0, move |retry| {
+            request(&inner).then(move |res| {
+                match res {
+                    Err(ApiError::RestError(ref err))
+                        if retry < max_retries && is_rate_limited(err.kind()) =>
+                    {
+                        let delay = backoff.delay(retry);
+                        warn!("request was rate limited, retrying in {:?}", delay);
+
+                        Either::A(
+                            tokio::timer::Delay::new(Instant::now() + delay)
+                                .map(move |()| Loop::Continue(retry + 1))
+                                .map_err(RequestError::new)
+                                .map_err(ApiError::RequestError)
+                        )
+                    }
+
+                    res => Either::B(future::ok(Loop::Break(res))),
+                }
+            })
+        }).and_then(|res| res);
+
+        Box::new(fut)
+    }
+}
+
+fn is_rate_limited<K: ErrorKind>(kind: RestErrorKind<K>) -> bool {
+    match kind {
+        RestErrorKind::TooManyRequests => true,
+        _ => false,
+    }
+}
+
+impl<C: ApiClient> GenerateOrderId for RetryClient<C> {
+    fn new_order_id(hint: &str) -> String {
+        C::new_order_id(hint)
+    }
+}
+
+impl<C: ApiClient + Send + Sync + 'static> ApiClient for RetryClient<C> {
+    type Stream = C::Stream;
+
+    fn find_symbol(&self, symbol: &str) -> Option<Symbol> {
+        self.inner.find_symbol(symbol)
+    }
+
+    fn generate_order_id(&self, hint: &str) -> String {
+        self.inner.generate_order_id(hint)
+    }
+
+    fn ticker(&self, symbol: Symbol)
+        -> Box<dyn Future<Item = Ticker, Error = errors::Error> + Send + 'static>
+    {
+        self.inner.ticker(symbol)
+    }
+
+    fn recent_trades(&self, symbol: Symbol, limit: usize)
+        -> Box<dyn Future<Item = Vec<Timestamped<Trade>>, Error = errors::Error> + Send + 'static>
+    {
+        self.inner.recent_trades(symbol, limit)
+    }
+
+    fn stream_with_flags(&self, symbol: Symbol, flags: NotificationFlags) -> Self::Stream {
+        self.inner.stream_with_flags(symbol, flags)
+    }
+
+    fn stream_many(&self, symbols: &[Symbol], flags: NotificationFlags)
+        -> Box<dyn Stream<Item = WithSymbol<Notification>, Error = ()> + Send + 'static>
+    {
+        self.inner.stream_many(symbols, flags)
+    }
+
+    fn order(&self, order: WithSymbol<&Order>)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = errors::OrderError> + Send + 'static>
+    {
+        let symbol = order.symbol();
+        let order = (*order).clone();
+        self.retry(move |inner| inner.order(order.with_symbol(symbol)))
+    }
+
+    fn cancel(&self, cancel: WithSymbol<&Cancel>)
+        -> Box<dyn Future<Item = Timestamped<CancelAck>, Error = errors::CancelError> + Send + 'static>
+    {
+        let symbol = cancel.symbol();
+        let cancel = (*cancel).clone();
+        self.retry(move |inner| inner.cancel(cancel.with_symbol(symbol)))
+    }
+
+    fn replace(&self, replaced: WithSymbol<&Order>, new: &Order)
+        -> Box<dyn Future<Item = Timestamped<OrderAck>, Error = errors::OrderError> + Send + 'static>
+    {
+        self.inner.replace(replaced, new)
+    }
+
+    fn ping(&self)
+        -> Box<dyn Future<Item = Timestamped<()>, Error = errors::Error> + Send + 'static>
+    {
+        self.inner.ping()
+    }
+
+    fn balances(&self)
+        -> Box<dyn Future<Item = Balances, Error = errors::Error> + Send + 'static>
+    {
+        self.retry(|inner| inner.balances())
+    }
+
+    fn deposit_address(&self, asset: &str)
+        -> Box<dyn Future<Item = DepositAddress, Error = errors::Error> + Send + 'static>
+    {
+        self.inner.deposit_address(asset)
+    }
+}
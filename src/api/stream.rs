@@ -0,0 +1,109 @@
+//! A module defining the notification stream returned by the exchange clients.
+
+use std::thread;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use futures::prelude::*;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use log::debug;
+use crate::api::Notification;
+
+/// State shared between a `StreamHandle` and the thread forwarding its notifications.
+crate struct Shutdown {
+    requested: AtomicBool,
+
+    /// Sender of the WebSocket connection currently open, if any.
+    out: Mutex<Option<ws::Sender>>,
+}
+
+impl Shutdown {
+    /// Register the sender of a newly created WebSocket connection, so that it can be
+    /// shut down later. Shut it down immediately if shutdown was already requested.
+    crate fn register(&self, out: &ws::Sender) {
+        let mut current = self.out.lock().unwrap();
+        if self.requested() {
+            let _ = out.shutdown();
+        }
+        *current = Some(out.clone());
+    }
+
+    /// Return whether shutdown was requested, in which case the forwarding thread must
+    /// not reconnect.
+    crate fn requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    fn trigger(&self) {
+        let current = self.out.lock().unwrap();
+        self.requested.store(true, Ordering::SeqCst);
+        if let Some(out) = &*current {
+            let _ = out.shutdown();
+        }
+    }
+}
+
+/// A notification stream fed by a background thread holding the WebSocket connection.
+///
+/// Dropping the handle, or calling `StreamHandle::shutdown`, closes the connection and
+/// waits for the background thread to finish. Since `LiveOrderBook` and `BoundedStream`
+/// drop the stream they drain once they are themselves dropped, the connection is closed
+/// as well in that case.
+///
+/// # Note
+/// Dropping the handle blocks until the background thread has finished, which is
+/// usually immediate but may take as long as establishing a pending connection.
+pub struct StreamHandle<I = Notification> {
+    rcv: UnboundedReceiver<I>,
+    shutdown: Arc<Shutdown>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl<I: Send + 'static> StreamHandle<I> {
+    /// Spawn a thread running `f`, which forwards notifications to the given sender and
+    /// must register each WebSocket connection it opens with `Shutdown::register`.
+    crate fn spawn<F>(f: F) -> Self
+        where F: FnOnce(UnboundedSender<I>, Arc<Shutdown>) + Send + 'static
+    {
+        let (snd, rcv) = unbounded();
+        let shutdown = Arc::new(Shutdown {
+            requested: AtomicBool::new(false),
+            out: Mutex::new(None),
+        });
+
+        let thread_shutdown = shutdown.clone();
+        let thread = thread::spawn(move || f(snd, thread_shutdown));
+
+        StreamHandle {
+            rcv,
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<I> StreamHandle<I> {
+    /// Close the WebSocket connection and wait for the background thread to finish.
+    /// Equivalent to dropping the handle.
+    pub fn shutdown(self) { }
+}
+
+impl<I> Stream for StreamHandle<I> {
+    type Item = I;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<I>, ()> {
+        self.rcv.poll()
+    }
+}
+
+impl<I> Drop for StreamHandle<I> {
+    fn drop(&mut self) {
+        self.shutdown.trigger();
+        if let Some(thread) = self.thread.take() {
+            // The thread panics when the handler fails, which already got logged.
+            if thread.join().is_err() {
+                debug!("notification stream thread has panicked");
+            }
+        }
+    }
+}
@@ -3,10 +3,12 @@
 use std::ops::Deref;
 use arrayvec::ArrayString;
 use serde_derive::{Serialize, Deserialize};
-use crate::tick::Tick;
+use crate::tick::{Tick, TickUnit};
+use crate::order_book::display;
 
-/// A small string type used for symbol names.
-pub type SymbolName = ArrayString<[u8; 10]>;
+/// A small string type used for symbol names, long enough for Deribit option names like
+/// `"BTC-27DEC24-100000-C"`.
+pub type SymbolName = ArrayString<[u8; 24]>;
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 /// A type carrying information about a traded symbol.
@@ -15,6 +17,10 @@ pub struct Symbol {
     price_tick: Tick,
     size_tick: Tick,
     commission_tick: Tick,
+    funds_tick: Option<Tick>,
+    base_asset: Option<SymbolName>,
+    quote_asset: Option<SymbolName>,
+    min_notional: Option<TickUnit>,
 }
 
 impl Symbol {
@@ -24,9 +30,48 @@ impl Symbol {
             price_tick,
             size_tick,
             commission_tick: Tick::new(1),
+            funds_tick: None,
+            base_asset: None,
+            quote_asset: None,
+            min_notional: None,
         })
     }
 
+    crate fn with_commission_tick(self, commission_tick: Tick) -> Self {
+        Symbol {
+            commission_tick,
+            ..self
+        }
+    }
+
+    crate fn with_funds_tick(self, funds_tick: Tick) -> Self {
+        Symbol {
+            funds_tick: Some(funds_tick),
+            ..self
+        }
+    }
+
+    crate fn with_base_asset(self, base_asset: &str) -> Self {
+        Symbol {
+            base_asset: SymbolName::from(base_asset).ok(),
+            ..self
+        }
+    }
+
+    crate fn with_quote_asset(self, quote_asset: &str) -> Self {
+        Symbol {
+            quote_asset: SymbolName::from(quote_asset).ok(),
+            ..self
+        }
+    }
+
+    crate fn with_min_notional(self, min_notional: TickUnit) -> Self {
+        Symbol {
+            min_notional: Some(min_notional),
+            ..self
+        }
+    }
+
     /// Symbol name.
     pub fn name(&self) -> &str {
         &self.name
@@ -42,10 +87,59 @@ impl Symbol {
         self.size_tick
     }
 
-    /// Not used for now.
+    /// Tick used for commissions, e.g. `OrderUpdate::commission`. Derived from the precision
+    /// of the quote asset when provided by the exchange, `Tick::new(1)` otherwise. Can be
+    /// overridden for binance with `binance::Client::with_commission_tick`.
     pub fn commission_tick(&self) -> Tick {
         self.commission_tick
     }
+
+    /// Tick of the funds of a funds-based market order, i.e. the increment of the quote
+    /// asset when published by the exchange. Falls back to the price tick otherwise.
+    pub fn funds_tick(&self) -> Tick {
+        self.funds_tick.unwrap_or(self.price_tick)
+    }
+
+    /// Name of the base asset, e.g. `"BTC"` for `"BTCUSDT"`, if provided by the exchange.
+    /// Always `None` for derivatives, which are not backed by a balance of their base asset.
+    pub fn base_asset(&self) -> Option<&str> {
+        self.base_asset.as_ref().map(|asset| asset.as_str())
+    }
+
+    /// Name of the quote asset, e.g. `"USDT"` for `"BTCUSDT"`, if provided by the exchange.
+    pub fn quote_asset(&self) -> Option<&str> {
+        self.quote_asset.as_ref().map(|asset| asset.as_str())
+    }
+
+    /// Minimum notional value (i.e. price times size) of an order, expressed in price ticks,
+    /// if provided by the exchange.
+    pub fn min_notional(&self) -> Option<TickUnit> {
+        self.min_notional
+    }
+
+    /// Convert a price expressed in ticks to an unticked value with the price tick of this
+    /// symbol, e.g. for display. Unlike `order_book::display::displayable_price`, this does
+    /// not depend on any thread local state. The value is written in tick units if it
+    /// cannot be unticked.
+    pub fn format_price(&self, ticked: TickUnit) -> String {
+        display::unticked_or_ticked(self.price_tick, ticked)
+    }
+
+    /// Convert a size expressed in ticks to an unticked value with the size tick of this
+    /// symbol, see `Symbol::format_price`.
+    pub fn format_size(&self, ticked: TickUnit) -> String {
+        display::unticked_or_ticked(self.size_tick, ticked)
+    }
+}
+
+/// Return the key under which a symbol named `name` is looked up: lower case, without
+/// any `-`, `/` or `_` separator, so that e.g. `"BTC-USDT"`, `"btc/usdt"` and `"BTCUSDT"`
+/// all map to `"btcusdt"`.
+crate fn canonical_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !['-', '/', '_'].contains(c))
+        .flat_map(|c| c.to_lowercase())
+        .collect()
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
@@ -56,6 +150,13 @@ pub struct WithSymbol<T> {
 }
 
 impl<T> WithSymbol<T> {
+    crate fn new(inner: T, symbol: Symbol) -> Self {
+        WithSymbol {
+            symbol,
+            inner,
+        }
+    }
+
     /// Carried symbol.
     pub fn symbol(&self) -> Symbol {
         self.symbol
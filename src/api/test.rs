@@ -0,0 +1,813 @@
+#![cfg(test)]
+
+use futures::prelude::*;
+use crate::Side;
+use crate::tick::{Tick, TickUnit, Price, Size, RoundingMode};
+use crate::tick::{ConversionErrorKind, ConversionErrorReason};
+use crate::order_book::LimitUpdate;
+use crate::api::{
+    Balance,
+    Balances,
+    BalancesExt,
+    Cancel,
+    Notification,
+    NotificationKind,
+    Trade,
+    Order,
+    OrderAck,
+    OrderConfirmation,
+    OrderExpiration,
+    OrderType,
+};
+use crate::api::errors::OrderValidationError;
+use crate::api::harness;
+use crate::api::order_ids::{OrderIdStore, FileOrderIdStore};
+use crate::api::stream::StreamHandle;
+use crate::api::symbol::{self, Symbol};
+use crate::api::timestamp::{timestamp_ms, ClockOffset, IntoTimestamped};
+
+fn balance(free: &str, locked: &str) -> Balance {
+    Balance {
+        free: free.to_owned(),
+        locked: locked.to_owned(),
+    }
+}
+
+#[test]
+fn balance_ticked() {
+    let tick = Tick::new(100_000_000);
+
+    let b = balance("1.50000000", "0.25000000");
+    assert_eq!(Ok(150_000_000), b.free_ticked(tick));
+    assert_eq!(Ok(25_000_000), b.locked_ticked(tick));
+    assert_eq!(Ok(175_000_000), b.total_ticked(tick));
+
+    let b = balance("0", "");
+    assert_eq!(Ok(0), b.free_ticked(tick));
+    assert_eq!(Ok(0), b.locked_ticked(tick));
+    assert_eq!(Ok(0), b.total_ticked(tick));
+
+    assert!(balance("abc", "0").total_ticked(tick).is_err());
+
+    // Both amounts fit in a `TickUnit`, but not their sum.
+    let err = balance("100000000000", "100000000000").total_ticked(tick).unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::Overflow);
+}
+
+#[test]
+fn bounded_stream() {
+    use futures::sync::mpsc;
+    use crate::api::bounded::BoundedStream;
+
+    let update = |price| Notification::LimitUpdates(vec![
+        LimitUpdate::new(Price(price), Size(1), Side::Bid).with_timestamp(0),
+    ]);
+    let trade = |price| Notification::Trade(Trade {
+        price: Price(price),
+        size: Size(1),
+        maker_side: Side::Ask,
+    }.with_timestamp(0));
+    let snapshot = Notification::BookSnapshot(vec![]);
+
+    // Queue every notification upfront, so that the consumer is slower than the producer.
+    let (snd, rcv) = mpsc::unbounded();
+    let notifs = vec![
+        update(1), update(2), trade(10), update(3), update(4), update(5), snapshot.clone(),
+        trade(11), trade(12),
+    ];
+    for notif in notifs {
+        snd.unbounded_send(notif).unwrap();
+    }
+    drop(snd);
+
+    let bounded = BoundedStream::new(rcv, 3);
+    let start = std::time::Instant::now();
+    while bounded.dropped() < 5 {
+        assert!(start.elapsed().as_secs() < 5, "only {} updates dropped", bounded.dropped());
+        std::thread::yield_now();
+    }
+
+    // The oldest limit updates make room first, and once there are none left the queue
+    // grows past its capacity instead of dropping trades or snapshots.
+    let received: Vec<_> = bounded.wait().collect::<Result<_, _>>().unwrap();
+    assert_eq!(received, vec![trade(10), snapshot, trade(11), trade(12)]);
+}
+
+#[test]
+fn book_sampler() {
+    use std::time::Duration;
+    use futures::sync::mpsc;
+    use crate::api::order_book::{LiveOrderBook, BookState, BookSampler, BookSample};
+
+    let (snd, rcv) = mpsc::unbounded();
+    snd.unbounded_send(Notification::BookSnapshot(vec![
+        LimitUpdate::new(Price(99), Size(3), Side::Bid).with_timestamp(0),
+        LimitUpdate::new(Price(101), Size(4), Side::Ask).with_timestamp(0),
+    ])).unwrap();
+    let live_order_book = LiveOrderBook::from_stream(rcv);
+
+    let mut samples = BookSampler::new(&live_order_book, Duration::from_millis(1)).wait();
+    let sample = samples.next().unwrap().unwrap();
+    assert_eq!(sample, BookSample {
+        timestamp: sample.timestamp,
+        best_bid: Price(99),
+        best_ask: Price(101),
+        bid_size: Size(3),
+        ask_size: Size(4),
+    });
+
+    // The order book is still available while being sampled.
+    match live_order_book.order_book() {
+        BookState::Live(book) => assert_eq!(book.best_bid(), Price(99)),
+        BookState::Disconnected => panic!("order book should be live"),
+    }
+
+    // Sampling stops once the order book gets disconnected.
+    drop(snd);
+    for sample in samples {
+        assert_eq!(sample.unwrap().best_ask, Price(101));
+    }
+    match live_order_book.order_book() {
+        BookState::Live(..) => panic!("order book should be disconnected"),
+        BookState::Disconnected => (),
+    };
+}
+
+#[test]
+fn dry_run() {
+    use std::sync::Arc;
+    use hyper::StatusCode;
+    use crate::api::{ApiClient, Params};
+    use crate::api::errors::{ApiError, CancelErrorKind, RestErrorKind};
+    use crate::api::symbol::IntoWithSymbol;
+    use crate::api::dry_run::DryRunClient;
+    use crate::api::gdax;
+
+    let transport = Arc::new(harness::MockTransport::default());
+    transport.respond(StatusCode::OK, r#"[{"id":"BTC-USD","base_currency":"BTC","quote_currency":"USD","quote_increment":"0.01","min_market_funds":"10"}]"#);
+    transport.respond(StatusCode::OK, r#"[{"id":"BTC","min_size":"0.00000001"},{"id":"USD","min_size":"0.01"}]"#);
+
+    let params = Params {
+        streaming_endpoint: "wss://localhost".to_owned(),
+        rest_endpoint: "https://localhost".to_owned(),
+        weight_per_minute: None,
+    };
+    let client = gdax::Client::with_transport(params, None, transport.clone()).unwrap();
+    let client = DryRunClient::new(client);
+    let symbol = client.find_symbol("BTC-USD").unwrap();
+
+    let order = Order::new("10000.00", "0.5", Side::Bid).with_order_id_from(&client, "a1");
+    let ack = client.order(order.with_symbol(symbol)).wait().unwrap();
+    assert_eq!(Some(ack.order_id.as_str()), order.order_id());
+
+    // Acknowledged orders can be canceled and replaced, unknown ones cannot.
+    client.cancel(ack.to_cancel().with_symbol(symbol)).wait().unwrap();
+    let new = Order::new("10001.00", "0.5", Side::Bid).with_order_id_from(&client, "a2");
+    client.replace(order.with_symbol(symbol), &new).wait().unwrap();
+    let cancel = Cancel::new(new.order_id().unwrap().to_owned());
+    client.cancel(cancel.with_symbol(symbol)).wait().unwrap();
+
+    let unknown = Cancel::new("unknown".to_owned());
+    match client.cancel(unknown.with_symbol(symbol)).wait() {
+        Err(ApiError::RestError(err)) => assert_eq!(
+            err.kind(),
+            RestErrorKind::Specific(CancelErrorKind::UnknownOrder)
+        ),
+        other => panic!("expected a REST error, found `{:?}`", other),
+    }
+
+    // Nothing was sent past the symbols request.
+    assert_eq!(transport.paths(), vec!["/products", "/currencies"]);
+}
+
+#[test]
+fn canonical_symbol_name() {
+    assert_eq!(symbol::canonical_name("BTCUSDT"), "btcusdt");
+    assert_eq!(symbol::canonical_name("btc-usdt"), "btcusdt");
+    assert_eq!(symbol::canonical_name("BTC/USDT"), "btcusdt");
+    assert_eq!(symbol::canonical_name("btc_usdt"), "btcusdt");
+}
+
+#[test]
+#[should_panic(expected = "order books differ, expected:\n  Ask 101 -> size 0 (found 7)\n")]
+fn assert_same_book() {
+    harness::assert_same_book(
+        &harness::order_book(&[(100, 5)], &[(101, 7)]),
+        &harness::order_book(&[(100, 5)], &[])
+    );
+}
+
+#[test]
+fn symbol_format() {
+    let btcusdt = Symbol::new("BTCUSDT", Tick::new(100), Tick::new(1_000_000)).unwrap();
+    let ethbtc = Symbol::new("ETHBTC", Tick::new(100_000), Tick::new(1000)).unwrap();
+
+    assert_eq!(btcusdt.format_price(725_450), "7254.50");
+    assert_eq!(btcusdt.format_size(1_500_000), "1.500000");
+    assert_eq!(ethbtc.format_price(2_150), "0.02150");
+    assert_eq!(ethbtc.format_size(1_500), "1.500");
+
+    // Falls back to tick units.
+    let odd = Symbol::new("ODD", Tick::new(3), Tick::new(1)).unwrap();
+    assert_eq!(odd.format_price(10), "10");
+}
+
+#[test]
+fn bar_builder() {
+    use futures::stream;
+    use crate::api::bars::{BarBuilder, BarEvent, Candle, Window};
+
+    let trade = |price, size, timestamp| Notification::Trade(Trade {
+        price: Price(price),
+        size: Size(size),
+        maker_side: Side::Bid,
+    }.with_timestamp(timestamp));
+
+    let notifs = vec![
+        trade(100, 1, 1000),
+        trade(105, 2, 1500),
+        Notification::BookResync,
+        trade(95, 3, 1999),
+        trade(98, 1, 2000),
+        trade(99, 1, 4500),
+    ];
+
+    let events = BarBuilder::new(stream::iter_ok::<_, ()>(notifs.clone()), Window::Time(1000))
+        .collect()
+        .wait()
+        .unwrap();
+
+    assert_eq!(events, vec![
+        BarEvent::Notification(Notification::BookResync),
+        BarEvent::Candle(Candle {
+            open_time: 1000,
+            close_time: 1999,
+            open: Price(100),
+            high: Price(105),
+            low: Price(95),
+            close: Price(95),
+            volume: Size(6),
+            trades: 3,
+        }),
+        BarEvent::Candle(Candle {
+            open_time: 2000,
+            close_time: 2000,
+            open: Price(98),
+            high: Price(98),
+            low: Price(98),
+            close: Price(98),
+            volume: Size(1),
+            trades: 1,
+        }),
+        BarEvent::Candle(Candle {
+            open_time: 4000,
+            close_time: 4500,
+            open: Price(99),
+            high: Price(99),
+            low: Price(99),
+            close: Price(99),
+            volume: Size(1),
+            trades: 1,
+        }),
+    ]);
+
+    let candles: Vec<_> = BarBuilder::new(stream::iter_ok::<_, ()>(notifs), Window::Trades(2))
+        .collect()
+        .wait()
+        .unwrap()
+        .into_iter()
+        .filter_map(|event| match event {
+            BarEvent::Candle(candle) => Some((candle.open.0, candle.close.0, candle.trades)),
+            BarEvent::Notification(..) => None,
+        })
+        .collect();
+
+    assert_eq!(candles, vec![(100, 105, 2), (95, 98, 2), (99, 99, 1)]);
+}
+
+#[test]
+fn trade_tape() {
+    use crate::api::trade_tape::{TradeTape, Retention};
+
+    let trade = |price, size, timestamp| Trade {
+        price: Price(price),
+        size: Size(size),
+        maker_side: Side::Bid,
+    }.with_timestamp(timestamp);
+
+    let mut tape = TradeTape::new(Retention::Time(1000));
+    assert_eq!(tape.last(), None);
+    assert_eq!(tape.volume(1000), Size(0));
+    assert_eq!(tape.vwap(1000), None);
+
+    tape.push(trade(100, 1, 1000));
+    tape.push_notification(&Notification::BookResync);
+    tape.push_notification(&Notification::Trade(trade(110, 3, 1500)));
+    tape.push(trade(105, 2, 1800));
+    assert_eq!(tape.len(), 3);
+    assert_eq!(tape.volume(1000), Size(6));
+    assert_eq!(tape.vwap(1000), Some(Price(106)));
+    assert_eq!(tape.volume(500), Size(5));
+    assert_eq!(tape.vwap(500), Some(Price(108)));
+
+    // The trade at 1000 falls out of the retention window.
+    tape.push(trade(90, 1, 2000));
+    assert_eq!(tape.len(), 3);
+    assert_eq!(tape.last(), Some(&trade(90, 1, 2000)));
+    assert_eq!(tape.volume(5000), Size(6));
+
+    let mut tape = TradeTape::new(Retention::Trades(2));
+    tape.push(trade(100, 1, 1000));
+    tape.push(trade(110, 3, 1500));
+    tape.push(trade(105, 2, 1800));
+    assert_eq!(
+        tape.iter().cloned().collect::<Vec<_>>(),
+        vec![trade(110, 3, 1500), trade(105, 2, 1800)]
+    );
+}
+
+#[test]
+fn dedup() {
+    use futures::stream;
+    use crate::api::dedup::Dedup;
+
+    let updates = |updates: &[(TickUnit, TickUnit, Side)], timestamp| {
+        updates.iter().map(|&(price, size, side)| {
+            LimitUpdate::new(Price(price), Size(size), side).with_timestamp(timestamp)
+        }).collect::<Vec<_>>()
+    };
+
+    let notifs = vec![
+        Notification::BookSnapshot(updates(&[(100, 5, Side::Bid), (101, 7, Side::Ask)], 1)),
+        Notification::LimitUpdates(updates(&[(100, 5, Side::Bid), (101, 6, Side::Ask)], 2)),
+        Notification::LimitUpdates(updates(&[(100, 5, Side::Bid), (99, 0, Side::Bid)], 3)),
+        Notification::LimitUpdates(updates(&[(99, 3, Side::Bid), (99, 3, Side::Bid)], 4)),
+        Notification::BookResync,
+        Notification::LimitUpdates(updates(&[(100, 5, Side::Bid)], 5)),
+    ];
+
+    let mut dedup = Dedup::new(stream::iter_ok::<_, ()>(notifs.clone())).wait();
+    let forwarded = dedup.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(forwarded, vec![
+        notifs[0].clone(),
+        Notification::LimitUpdates(updates(&[(101, 6, Side::Ask)], 2)),
+        Notification::LimitUpdates(updates(&[(99, 3, Side::Bid)], 4)),
+        Notification::BookResync,
+        notifs[5].clone(),
+    ]);
+    assert_eq!(dedup.into_inner().order_book(), &harness::rebuild_order_book(&notifs));
+}
+
+#[test]
+fn position_tracker() {
+    use crate::api::OrderUpdate;
+    use crate::api::portfolio::PositionTracker;
+
+    let symbol = Symbol::new("BTCUSD", Tick::new(100), Tick::new(100)).unwrap();
+    let mut tracker = PositionTracker::new(symbol);
+
+    let confirmation = |order_id: &str, side| Notification::OrderConfirmation(OrderConfirmation {
+        order_id: order_id.to_owned(),
+        price: Price(0),
+        size: Size(0),
+        side,
+    }.with_timestamp(0));
+    let update = |order_id: &str, price, size, remaining_size| {
+        Notification::OrderUpdate(OrderUpdate {
+            order_id: order_id.to_owned(),
+            consumed_size: Size(size),
+            remaining_size: Size(remaining_size),
+            consumed_price: Price(price),
+            commission: 1,
+            commission_asset: Some("USD".to_owned()),
+            is_fill: true,
+        }.with_timestamp(0))
+    };
+
+    // Buy 3 at 100 then 1 at 104.
+    tracker.push_notification(&confirmation("buy", Side::Bid));
+    tracker.push_notification(&update("buy", 100, 3, 1));
+    tracker.push_notification(&update("buy", 104, 1, 0));
+    assert_eq!(tracker.position(), 4);
+    assert_eq!(tracker.average_entry_price(), Some(Price(101)));
+    assert_eq!(tracker.unrealized_pnl(), None);
+
+    tracker.push_notification(&Notification::Trade(Trade {
+        price: Price(103),
+        size: Size(1),
+        maker_side: Side::Bid,
+    }.with_timestamp(0)));
+    assert_eq!(tracker.unrealized_pnl(), Some(8));
+
+    // Sell 6 at 105: close the long position and go short 2.
+    tracker.push_notification(&confirmation("sell", Side::Ask));
+    tracker.push_notification(&update("sell", 105, 6, 0));
+    assert_eq!(tracker.position(), -2);
+    assert_eq!(tracker.realized_pnl(), 16);
+    assert_eq!(tracker.average_entry_price(), Some(Price(105)));
+    assert_eq!(tracker.unrealized_pnl(), Some(4));
+
+    // Buy back 2 at 106, flat.
+    tracker.fill(Side::Bid, Price(106), Size(2));
+    assert_eq!(tracker.position(), 0);
+    assert_eq!(tracker.realized_pnl(), 14);
+    assert_eq!(tracker.average_entry_price(), None);
+
+    // Updates of unknown orders are ignored.
+    tracker.push_notification(&update("other", 100, 1, 0));
+    assert_eq!(tracker.position(), 0);
+    assert_eq!(tracker.commission(Some("USD")), 3);
+
+    // A size decrease without trading is not a fill, but still ends the order.
+    tracker.push_notification(&confirmation("stp", Side::Bid));
+    tracker.push_notification(&Notification::OrderUpdate(OrderUpdate {
+        order_id: "stp".to_owned(),
+        consumed_size: Size(5),
+        remaining_size: Size(0),
+        consumed_price: Price(100),
+        commission: 0,
+        commission_asset: Some("USD".to_owned()),
+        is_fill: false,
+    }.with_timestamp(0)));
+    tracker.push_notification(&update("stp", 100, 1, 0));
+    assert_eq!(tracker.position(), 0);
+    assert_eq!(tracker.realized_pnl(), 14);
+    assert_eq!(tracker.commission(Some("USD")), 3);
+    assert_eq!(tracker.commission(None), 0);
+}
+
+#[test]
+fn rate_limiter() {
+    use crate::api::rate_limit::RateLimiter;
+
+    let limiter = RateLimiter::new(60);
+    assert_eq!(limiter.budget(), 60);
+
+    limiter.acquire(10).wait().unwrap();
+    assert_eq!(limiter.clone().budget(), 50);
+
+    // Weights greater than the capacity are clamped.
+    let limiter = RateLimiter::new(5);
+    limiter.acquire(10).wait().unwrap();
+    assert_eq!(limiter.budget(), 0);
+
+    // Without a timer, an exhausted bucket does not block forever.
+    limiter.acquire(5).wait().unwrap();
+}
+
+#[test]
+fn trade_sides() {
+    let trade = Trade {
+        price: Price(100),
+        size: Size(1),
+        maker_side: Side::Bid,
+    };
+    assert_eq!(trade.taker_side(), Side::Ask);
+    assert!(trade.is_buyer_maker());
+
+    let trade = Trade {
+        maker_side: Side::Ask,
+        ..trade
+    };
+    assert_eq!(trade.taker_side(), Side::Bid);
+    assert!(!trade.is_buyer_maker());
+}
+
+#[test]
+fn notification_kind() {
+    let trade = Notification::Trade(Trade {
+        price: Price(100),
+        size: Size(1),
+        maker_side: Side::Bid,
+    }.with_timestamp(1));
+    assert_eq!(trade.kind(), NotificationKind::MarketData);
+    assert!(trade.is_market_data());
+    assert!(!trade.is_order_event());
+
+    assert!(Notification::BookResync.is_market_data());
+    assert!(Notification::BookSnapshot(vec![]).is_market_data());
+
+    let expiration = Notification::OrderExpiration(OrderExpiration {
+        order_id: "order".to_owned(),
+    }.with_timestamp(2));
+    assert_eq!(expiration.kind(), NotificationKind::Order);
+    assert!(expiration.is_order_event());
+    assert!(!expiration.is_market_data());
+
+    let balances = Notification::BalanceUpdate(Balances::new().with_timestamp(3));
+    assert_eq!(balances.kind(), NotificationKind::Balance);
+    assert!(!balances.is_market_data());
+    assert!(!balances.is_order_event());
+}
+
+#[test]
+fn order_validate() {
+    let symbol = Symbol::new("BTCUSD", Tick::new(100), Tick::new(1000)).unwrap();
+
+    assert_eq!(Order::new("100.25", "0.001", Side::Bid).validate(&symbol), Ok(()));
+    assert_eq!(Order::new(10025, 1, Side::Bid).validate(&symbol), Ok(()));
+
+    assert_eq!(
+        Order::new("100.255", "0.001", Side::Bid).validate(&symbol),
+        Err(OrderValidationError::InvalidPrice {
+            price: "100.255".to_owned(),
+            tick: Tick::new(100),
+            kind: ConversionErrorKind::Format,
+        })
+    );
+    assert_eq!(
+        Order::new("100.25", "0.0015", Side::Ask).validate(&symbol),
+        Err(OrderValidationError::InvalidSize {
+            size: "0.0015".to_owned(),
+            tick: Tick::new(1000),
+            kind: ConversionErrorKind::Format,
+        })
+    );
+    assert_eq!(
+        Order::new("100.25", "0.000", Side::Ask).validate(&symbol),
+        Err(OrderValidationError::ZeroSize)
+    );
+
+    // The price of a market order is ignored.
+    let order = Order::new("100.255", "0.001", Side::Bid).with_order_type(OrderType::Market);
+    assert_eq!(order.validate(&symbol), Ok(()));
+    assert_eq!(Order::market(0, Side::Bid).validate(&symbol), Err(OrderValidationError::ZeroSize));
+
+    // The size of a funds-based order is ignored, funds are checked with the price tick.
+    assert_eq!(Order::market_with_funds("10.50").validate(&symbol), Ok(()));
+    assert_eq!(
+        Order::market_with_funds("10.505").validate(&symbol),
+        Err(OrderValidationError::InvalidFunds {
+            funds: "10.505".to_owned(),
+            tick: Tick::new(100),
+            kind: ConversionErrorKind::Format,
+        })
+    );
+    assert_eq!(
+        Order::market_with_funds(0).validate(&symbol),
+        Err(OrderValidationError::ZeroFunds)
+    );
+
+    // Stop orders require a stop price, the price of a stop market order is ignored.
+    let order = Order::new("100.255", "0.001", Side::Ask).with_order_type(OrderType::StopLoss);
+    assert_eq!(order.validate(&symbol), Err(OrderValidationError::MissingStopPrice));
+    assert_eq!(order.with_stop_price("99.50").validate(&symbol), Ok(()));
+
+    let order = Order::market_with_funds("10.50").with_order_type(OrderType::StopLoss);
+    assert_eq!(order.validate(&symbol), Err(OrderValidationError::MissingStopPrice));
+    assert_eq!(order.with_stop_price("99.50").validate(&symbol), Ok(()));
+
+    let order = Order::new("99", "0.001", Side::Ask)
+        .with_order_type(OrderType::StopLossLimit)
+        .with_stop_price("99.505");
+    assert_eq!(
+        order.validate(&symbol),
+        Err(OrderValidationError::InvalidStopPrice {
+            stop_price: "99.505".to_owned(),
+            tick: Tick::new(100),
+            kind: ConversionErrorKind::Format,
+        })
+    );
+}
+
+#[test]
+fn order_try_new() {
+    let symbol = Symbol::new("BTCUSD", Tick::new(100), Tick::new(1000)).unwrap();
+
+    let order = Order::try_new("100.255", "0.0015", Side::Bid, &symbol, RoundingMode::Nearest);
+    assert_eq!(order, Ok(Order::new(10026, 2, Side::Bid)));
+    let order = Order::try_new("100.255", "0.0015", Side::Bid, &symbol, RoundingMode::Down);
+    assert_eq!(order, Ok(Order::new(10025, 1, Side::Bid)));
+    let order = Order::try_new(10025, "0.001", Side::Bid, &symbol, RoundingMode::Exact);
+    assert_eq!(order, Ok(Order::new(10025, 1, Side::Bid)));
+
+    assert_eq!(
+        Order::try_new("100.255", "0.001", Side::Bid, &symbol, RoundingMode::Exact),
+        Err(OrderValidationError::InvalidPrice {
+            price: "100.255".to_owned(),
+            tick: Tick::new(100),
+            kind: ConversionErrorKind::Format,
+        })
+    );
+    assert_eq!(
+        Order::try_new("100.25", "abc", Side::Ask, &symbol, RoundingMode::Nearest),
+        Err(OrderValidationError::InvalidSize {
+            size: "abc".to_owned(),
+            tick: Tick::new(1000),
+            kind: ConversionErrorKind::Format,
+        })
+    );
+    assert_eq!(
+        Order::try_new("100.25", "0.0004", Side::Ask, &symbol, RoundingMode::Down),
+        Err(OrderValidationError::ZeroSize)
+    );
+}
+
+#[test]
+fn order_builders() {
+    let order = Order::new("0.1", "2.5", Side::Ask).with_order_type(OrderType::LimitMaker);
+    let replaced = order.clone().with_price("0.12").with_size(3);
+
+    assert_eq!(replaced.side(), Side::Ask);
+    assert_eq!(replaced.order_type(), OrderType::LimitMaker);
+    assert_eq!(replaced.price(), &"0.12".into());
+    assert_eq!(replaced.size(), &3.into());
+    assert_eq!(order.price(), &"0.1".into());
+}
+
+#[test]
+fn order_tag() {
+    use crate::api::binance::Client;
+    use crate::api::parse_tag;
+
+    let order = Order::new("0.1", "2.5", Side::Ask).with_tag("mm1").with_order_id::<Client>("a1");
+    assert_eq!(order.order_id(), Some("mm1_a1"));
+    assert_eq!(order.tag(), Some("mm1"));
+    assert_eq!(parse_tag(order.order_id().unwrap()), Some("mm1"));
+
+    // Tagging after generating the id, or tagging again, gives the same result.
+    let order = Order::new("0.1", "2.5", Side::Ask).with_order_id::<Client>("a1").with_tag("mm1");
+    assert_eq!(order.order_id(), Some("mm1_a1"));
+    let order = order.with_tag("arb");
+    assert_eq!(order.order_id(), Some("arb_a1"));
+
+    // An order id which does not carry the previous tag is kept whole.
+    let mut value = serde_json::to_value(&order).unwrap();
+    value["order_id"] = "é".into();
+    let order: Order = serde_json::from_value(value).unwrap();
+    assert_eq!(order.with_tag("mm1").order_id(), Some("mm1_é"));
+
+    assert_eq!(parse_tag("a1"), None);
+    assert_eq!(parse_tag("_a1"), None);
+    assert_eq!(parse_tag("m-m_a1"), None);
+}
+
+#[test]
+#[should_panic]
+fn invalid_order_tag() {
+    let _ = Order::new("0.1", "2.5", Side::Ask).with_tag("mm_1");
+}
+
+#[test]
+fn to_cancel() {
+    let ack = OrderAck { order_id: "my_order".to_owned() };
+    let cancel = ack.to_cancel();
+    assert_eq!(cancel.order_id(), "my_order");
+    assert_eq!(cancel.time_window(), Cancel::new(String::new()).time_window());
+
+    let confirmation = OrderConfirmation {
+        order_id: "my_order".to_owned(),
+        price: Price(100),
+        size: Size(10),
+        side: Side::Bid,
+    };
+    assert_eq!(confirmation.to_cancel(), cancel);
+}
+
+#[test]
+fn order_validate_min_notional() {
+    let symbol = Symbol::new("BTCUSD", Tick::new(100), Tick::new(1000))
+        .unwrap()
+        .with_min_notional(1000);
+
+    assert_eq!(Order::new("100", "0.1", Side::Bid).validate(&symbol), Ok(()));
+    assert_eq!(
+        Order::new("99.99", "0.1", Side::Bid).validate(&symbol),
+        Err(OrderValidationError::BelowMinNotional {
+            notional: 999,
+            min_notional: 1000,
+        })
+    );
+
+    // Cannot be checked without a price.
+    assert_eq!(Order::market("0.001", Side::Ask).validate(&symbol), Ok(()));
+
+    assert_eq!(Order::market_with_funds("10").validate(&symbol), Ok(()));
+    assert_eq!(
+        Order::market_with_funds("9.99").validate(&symbol),
+        Err(OrderValidationError::BelowMinNotional {
+            notional: 999,
+            min_notional: 1000,
+        })
+    );
+}
+
+#[test]
+fn stream_handle_shutdown() {
+    use std::{thread, time::Duration};
+    use std::sync::mpsc;
+
+    let (done_snd, done_rcv) = mpsc::channel();
+    let handle = StreamHandle::spawn(move |snd, shutdown| {
+        snd.unbounded_send(Notification::BookResync).unwrap();
+        while !shutdown.requested() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        done_snd.send(()).unwrap();
+    });
+
+    let (notif, handle) = handle.into_future().wait().map_err(|_| ()).unwrap();
+    assert_eq!(notif, Some(Notification::BookResync));
+
+    // The background thread has finished once `shutdown` returns.
+    handle.shutdown();
+    assert_eq!(done_rcv.try_recv(), Ok(()));
+}
+
+#[test]
+fn nonzero_balances() {
+    let mut balances = Balances::new();
+    balances.insert("BTC".to_owned(), balance("0.00000000", "0.00000000"));
+    balances.insert("ETH".to_owned(), balance("", "0.5"));
+    balances.insert("USD".to_owned(), balance("10.25", ""));
+    balances.insert("XRP".to_owned(), balance("", ""));
+
+    let mut nonzero: Vec<_> = balances.nonzero().map(|(asset, _)| asset.as_str()).collect();
+    nonzero.sort();
+    assert_eq!(nonzero, vec!["ETH", "USD"]);
+
+    assert!(balance("0", "0.0").is_zero());
+    assert!(!balance("abc", "0").is_zero());
+}
+
+#[cfg(feature = "compat")]
+#[test]
+fn compat_stream() {
+    use futures03::stream::StreamExt;
+    use crate::api::compat;
+
+    let stream = StreamHandle::spawn(|snd, _| {
+        snd.unbounded_send(Notification::BookResync).unwrap();
+        snd.unbounded_send(Notification::BookSnapshot(vec![])).unwrap();
+    });
+
+    let notifs = futures03::executor::block_on(compat::stream(stream).collect::<Vec<_>>());
+    assert_eq!(notifs, vec![Notification::BookResync, Notification::BookSnapshot(vec![])]);
+}
+
+#[test]
+fn clock_offset() {
+    let clock = ClockOffset::default();
+    assert_eq!(clock.get(), 0);
+
+    // The server time is assumed to be taken halfway between sending and receiving.
+    let shared = clock.clone();
+    assert_eq!(clock.update(10_000, 1_000, 1_200), 8_900);
+    assert_eq!(shared.get(), 8_900);
+    assert_eq!(clock.update(1_000, 1_000, 1_200), -100);
+
+    let before = timestamp_ms();
+    let timestamp = shared.timestamp_ms();
+    assert!(timestamp + 100 >= before && timestamp + 100 <= timestamp_ms());
+}
+
+#[test]
+fn file_order_id_store() {
+    let path = std::env::temp_dir().join(format!("trade-order-ids-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    {
+        let store = FileOrderIdStore::open(&path).unwrap();
+        assert_eq!(store.get("client-1"), None);
+        store.insert("client-1".to_owned(), "server-1".to_owned()).unwrap();
+        store.insert("client-1".to_owned(), "server-1".to_owned()).unwrap();
+        store.insert("client-2".to_owned(), "server-2".to_owned()).unwrap();
+        assert_eq!(store.get("client-1"), Some("server-1".to_owned()));
+    }
+
+    // The mappings survive re-opening the store.
+    let store = FileOrderIdStore::open(&path).unwrap();
+    assert_eq!(store.get("client-1"), Some("server-1".to_owned()));
+    assert_eq!(store.get("client-2"), Some("server-2".to_owned()));
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn file_order_id_store_torn_line() {
+    let path = std::env::temp_dir().join(format!("trade-torn-ids-{}", std::process::id()));
+
+    // A crash while writing the last line: it is skipped and removed from the file, so
+    // that the next mapping does not get appended to it.
+    std::fs::write(&path, "client-1 server-1\nclient-2 serv").unwrap();
+    {
+        let store = FileOrderIdStore::open(&path).unwrap();
+        assert_eq!(store.get("client-1"), Some("server-1".to_owned()));
+        assert_eq!(store.get("client-2"), None);
+        store.insert("client-3".to_owned(), "server-3".to_owned()).unwrap();
+    }
+    let store = FileOrderIdStore::open(&path).unwrap();
+    assert_eq!(store.get("client-3"), Some("server-3".to_owned()));
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "client-1 server-1\nclient-3 server-3\n"
+    );
+
+    // Malformed lines in the middle of the file are still an error.
+    std::fs::write(&path, "client-1\nclient-2 server-2\n").unwrap();
+    let err = FileOrderIdStore::open(&path).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -1,6 +1,8 @@
 //! A module defining helpers for working with timestamps and timestamped objects.
 
 use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
 use serde_derive::{Serialize, Deserialize};
 
 /// Base type for timestamps.
@@ -16,6 +18,35 @@ pub fn timestamp_ms() -> Timestamp {
     timestamp.as_secs() * 1000 + u64::from(timestamp.subsec_millis())
 }
 
+#[derive(Clone, Debug, Default)]
+/// Offset in milliseconds between the clock of an exchange server and the local clock, used
+/// for stamping signed requests with the server time. Clones share the same offset.
+crate struct ClockOffset(Arc<AtomicI64>);
+
+impl ClockOffset {
+    /// Return the offset to add to the local clock, in milliseconds.
+    crate fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Update the offset from the `server_time` returned by a request sent at local time
+    /// `sent` and answered at local time `received`, assuming that the server time was
+    /// taken halfway. Return the new offset.
+    crate fn update(&self, server_time: Timestamp, sent: Timestamp, received: Timestamp)
+        -> i64
+    {
+        let local_time = sent + received.saturating_sub(sent) / 2;
+        let offset = server_time as i64 - local_time as i64;
+        self.0.store(offset, Ordering::Relaxed);
+        offset
+    }
+
+    /// Return the current UTC timestamp in milliseconds, as given by the server clock.
+    crate fn timestamp_ms(&self) -> Timestamp {
+        (timestamp_ms() as i64 + self.get()) as Timestamp
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 /// Wrapper around a type carrying an additional timestamp. Deref to `T`.
 pub struct Timestamped<T> {
@@ -34,6 +65,22 @@ impl<T> Timestamped<T> {
     pub fn into_inner(self) -> T {
         self.inner
     }
+
+    /// Apply `f` to the wrapped value, keeping the same timestamp.
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Timestamped<U> {
+        Timestamped {
+            timestamp: self.timestamp,
+            inner: f(self.inner),
+        }
+    }
+
+    /// Borrow the wrapped value, keeping the same timestamp.
+    pub fn as_ref(&self) -> Timestamped<&T> {
+        Timestamped {
+            timestamp: self.timestamp,
+            inner: &self.inner,
+        }
+    }
 }
 
 impl<T> Deref for Timestamped<T> {
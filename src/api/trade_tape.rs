@@ -0,0 +1,127 @@
+//! A module defining a buffer retaining the most recent trades, for computing rolling
+//! statistics such as the traded volume over the last minute.
+
+use std::collections::VecDeque;
+use crate::tick::{TickUnit, Price, Size};
+use crate::api::{Notification, Trade};
+use crate::api::timestamp::{Timestamp, Timestamped};
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// Which trades are retained by a `TradeTape`.
+pub enum Retention {
+    /// Retain the trades whose timestamp lies within the given number of milliseconds
+    /// before the timestamp of the most recent trade. As for `bars::Window::Time`, this
+    /// is based on the trade timestamps rather than on the wall clock.
+    Time(Timestamp),
+
+    /// Retain the given number of most recent trades.
+    Trades(usize),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// A buffer of the most recent trades, bounded according to a `Retention`.
+///
+/// The tape can be fed from a notification stream with `TradeTape::push_notification`,
+/// e.g. from a worker thread sharing it behind an `Arc<Mutex<TradeTape>>`.
+pub struct TradeTape {
+    trades: VecDeque<Timestamped<Trade>>,
+    retention: Retention,
+}
+
+impl TradeTape {
+    /// Return an empty `TradeTape` retaining trades according to `retention`.
+    ///
+    /// # Panics
+    /// Panic if the interval or the number of trades of `retention` is `0`.
+    pub fn new(retention: Retention) -> Self {
+        match retention {
+            Retention::Time(0) => panic!("time retention cannot be empty"),
+            Retention::Trades(0) => panic!("trade retention cannot be empty"),
+            _ => (),
+        }
+
+        TradeTape {
+            trades: VecDeque::new(),
+            retention,
+        }
+    }
+
+    /// Record `trade`, dropping the trades which are not retained anymore.
+    pub fn push(&mut self, trade: Timestamped<Trade>) {
+        self.trades.push_back(trade);
+
+        match self.retention {
+            Retention::Time(interval) => {
+                let last = trade.timestamp();
+                while self.trades.front()
+                    .map(|trade| trade.timestamp() + interval <= last)
+                    .unwrap_or(false)
+                {
+                    self.trades.pop_front();
+                }
+            }
+
+            Retention::Trades(n) => {
+                while self.trades.len() > n {
+                    self.trades.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Record the trade carried by `notif` if it is a `Notification::Trade`, ignore `notif`
+    /// otherwise.
+    pub fn push_notification(&mut self, notif: &Notification) {
+        if let Notification::Trade(trade) = notif {
+            self.push(*trade);
+        }
+    }
+
+    /// Return the most recent trade, if any.
+    pub fn last(&self) -> Option<&Timestamped<Trade>> {
+        self.trades.back()
+    }
+
+    /// Return the number of retained trades.
+    pub fn len(&self) -> usize {
+        self.trades.len()
+    }
+
+    /// Return `true` if no trade is retained.
+    pub fn is_empty(&self) -> bool {
+        self.trades.is_empty()
+    }
+
+    /// Iterator over the retained trades, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Timestamped<Trade>> {
+        self.trades.iter()
+    }
+
+    /// Iterator over the retained trades whose timestamp lies within `window` milliseconds
+    /// before the timestamp of the most recent trade.
+    fn within(&self, window: Timestamp) -> impl Iterator<Item = &Timestamped<Trade>> {
+        let last = self.last().map(|trade| trade.timestamp()).unwrap_or(0);
+        self.trades.iter().filter(move |trade| trade.timestamp() + window > last)
+    }
+
+    /// Return the total traded size, in ticks, of the retained trades whose timestamp lies
+    /// within `window` milliseconds before the timestamp of the most recent trade.
+    pub fn volume(&self, window: Timestamp) -> Size {
+        self.within(window).fold(Size(0), |volume, trade| volume.saturating_add(trade.size))
+    }
+
+    /// Return the volume-weighted average price, in ticks and rounded down, of the retained
+    /// trades whose timestamp lies within `window` milliseconds before the timestamp of the
+    /// most recent trade, or `None` if there is no such trade with a non-zero size.
+    pub fn vwap(&self, window: Timestamp) -> Option<Price> {
+        let (notional, volume) = self.within(window).fold((0, 0), |(notional, volume), trade| {
+            let size = u128::from(trade.size.0);
+            (notional + u128::from(trade.price.0) * size, volume + size)
+        });
+
+        if volume == 0 {
+            return None;
+        }
+        Some(Price((notional / volume) as TickUnit))
+    }
+}
@@ -0,0 +1,38 @@
+//! A module defining the HTTP transport used by the clients for their REST requests, which
+//! can be replaced e.g. for serving canned responses in tests.
+
+use std::sync::Arc;
+use futures::prelude::*;
+use hyper::{Body, Chunk, Request, StatusCode};
+
+/// Future returned by `HttpTransport::request`, resolving to the status and the whole body
+/// of the response.
+pub type ResponseFuture =
+    Box<dyn Future<Item = (StatusCode, Chunk), Error = failure::Error> + Send + 'static>;
+
+/// A transport sending HTTP requests, see e.g. `binance::Client::with_transport`.
+pub trait HttpTransport: Send + Sync {
+    /// Send `request` and return the status and the whole body of the response.
+    fn request(&self, request: Request<Body>) -> ResponseFuture;
+}
+
+impl<C> HttpTransport for hyper::Client<C, Body>
+    where C: hyper::client::connect::Connect + Sync + 'static
+{
+    fn request(&self, request: Request<Body>) -> ResponseFuture {
+        let fut = hyper::Client::request(self, request).and_then(|res| {
+            let status = res.status();
+            res.into_body().concat2().map(move |body| (status, body))
+        }).map_err(From::from);
+        Box::new(fut)
+    }
+}
+
+/// Return the default HTTPS transport. Cloning the returned transport shares the
+/// underlying connection pool.
+crate fn https() -> Result<Arc<dyn HttpTransport>, failure::Error> {
+    let client = hyper::Client::builder().build::<_, Body>(
+        hyper_tls::HttpsConnector::new(2)?
+    );
+    Ok(Arc::new(client))
+}
@@ -3,7 +3,7 @@
 
 use ws::util::{Timeout, Token};
 use futures::sync::mpsc::UnboundedSender;
-use log::error;
+use log::{error, trace};
 use crate::api::Notification;
 
 pub type NotifSender = UnboundedSender<Notification>;
@@ -14,14 +14,52 @@ crate enum KeepAlive {
     False,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// Log raw text frames at `trace` level, with the exchange name as the log target.
+/// Does nothing if raw logging is disabled.
+crate struct RawLog(Option<&'static str>);
+
+impl RawLog {
+    crate fn new(log_raw: bool, exchange: &'static str) -> Self {
+        RawLog(if log_raw { Some(exchange) } else { None })
+    }
+
+    crate fn inbound(self, text: &str) {
+        if let Some(target) = self.0 {
+            trace!(target: target, "<- {}", text);
+        }
+    }
+
+    crate fn outbound(self, text: &str) {
+        if let Some(target) = self.0 {
+            trace!(target: target, "-> {}", text);
+        }
+    }
+
+    /// Log and send `payload`.
+    crate fn send(self, out: &ws::Sender, payload: String) -> ws::Result<()> {
+        self.outbound(&payload);
+        out.send(payload)
+    }
+
+    /// Send `payload` which carries credentials, hence is not logged verbatim.
+    crate fn send_secret(self, out: &ws::Sender, payload: String, what: &str)
+        -> ws::Result<()>
+    {
+        self.outbound(&format!("<{} with credentials omitted>", what));
+        out.send(payload)
+    }
+}
+
 /// An object handling a WebSocket API connection.
 /// Inside handler functions, panicking can be used to terminate
 /// the connection easily (the connection always happen in a
 /// separate, free thread).
-crate struct Handler<T> {
+crate struct Handler<T, I = Notification> {
     out: ws::Sender,
-    snd: NotifSender,
+    snd: UnboundedSender<I>,
     keep_alive: KeepAlive,
+    raw_log: RawLog,
 
     /// We keep a reference to the `EXPIRE` timeout so that we can cancel it when we receive
     /// something from the server.
@@ -30,9 +68,15 @@ crate struct Handler<T> {
     inner: T,
 }
 
-crate trait HandlerImpl {
+crate trait HandlerImpl<I = Notification> {
     fn on_open(&mut self, out: &ws::Sender) -> ws::Result<()>;
-    fn on_message(&mut self, text: &str, out: &NotifSender) -> Result<(), failure::Error>;
+    fn on_message(&mut self, text: &str, out: &UnboundedSender<I>) -> Result<(), failure::Error>;
+
+    /// Called each time a ping is sent to the server, i.e. every `PING_TIMEOUT` milliseconds
+    /// with `KeepAlive::True`.
+    fn on_ping(&mut self, _out: &ws::Sender) -> ws::Result<()> {
+        Ok(())
+    }
 }
 
 const PING: Token = Token(1);
@@ -41,11 +85,12 @@ const EXPIRE: Token = Token(2);
 const PING_TIMEOUT: u64 = 10_000;
 const EXPIRE_TIMEOUT: u64 = 30_000;
 
-impl<T> Handler<T> {
+impl<T, I> Handler<T, I> {
     crate fn new(
         out: ws::Sender,
-        snd: UnboundedSender<Notification>,
+        snd: UnboundedSender<I>,
         keep_alive: KeepAlive,
+        raw_log: RawLog,
         inner: T
     ) -> Self
     {
@@ -53,13 +98,14 @@ impl<T> Handler<T> {
             out,
             snd,
             keep_alive,
+            raw_log,
             timeout: None,
             inner,
         }
     }
 }
 
-impl<T: HandlerImpl> ws::Handler for Handler<T> {
+impl<T: HandlerImpl<I>, I> ws::Handler for Handler<T, I> {
     fn on_open(&mut self, _: ws::Handshake) -> ws::Result<()> {
         self.inner.on_open(&self.out)?;
 
@@ -73,6 +119,7 @@ impl<T: HandlerImpl> ws::Handler for Handler<T> {
         match event {
             PING => {
                 self.out.ping(vec![])?;
+                self.inner.on_ping(&self.out)?;
                 self.out.timeout(PING_TIMEOUT, PING)
             }
             EXPIRE => self.out.close(ws::CloseCode::Away),
@@ -97,6 +144,7 @@ impl<T: HandlerImpl> ws::Handler for Handler<T> {
 
     fn on_message(&mut self, msg: ws::Message) -> ws::Result<()> {
         if let ws::Message::Text(text) = msg {
+            self.raw_log.inbound(&text);
             if let Err(err) = self.inner.on_message(&text, &self.snd) {
                 error!("message handling encountered error: `{}`", err)
             }
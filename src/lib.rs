@@ -17,7 +17,7 @@ pub mod prelude {
     //! A prelude for crates using this library. Re-exports the most used types
     //! and traits.
 
-    pub use crate::tick::TickUnit;
+    pub use crate::tick::{TickUnit, Price, Size};
     pub use crate::api::{ApiClient, Notification, NotificationFlags};
     pub use crate::api::symbol::{Symbol, IntoWithSymbol};
     pub use crate::api::order_book::{LiveOrderBook, BookState};
@@ -35,3 +35,23 @@ pub enum Side {
     /// Ask / sell side.
     Ask,
 }
+
+impl Side {
+    /// Return the opposite side, e.g. the side of the maker for a taker on `self`.
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate trade;
+    /// # use trade::Side;
+    /// # fn main() {
+    /// assert_eq!(Side::Bid.opposite(), Side::Ask);
+    /// assert_eq!(Side::Ask.opposite(), Side::Bid);
+    /// # }
+    /// ```
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Bid => Side::Ask,
+            Side::Ask => Side::Bid,
+        }
+    }
+}
@@ -2,8 +2,10 @@
 
 use std::fmt;
 use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use log::warn;
 use crate::order_book::OrderBook;
-use crate::tick::{TickUnit, Tick};
+use crate::tick::{TickUnit, Tick, Price, Size};
 
 thread_local! {
     static DISPLAY_LIMIT: Cell<usize> = Cell::new(5);
@@ -28,42 +30,141 @@ pub fn set_size_tick(maybe_tick: Option<Tick>) {
     DISPLAY_SIZE_TICK.with(|dt| dt.set(maybe_tick));
 }
 
-/// Convert a ticked value to an unticked value with the current thread local price tick.
-pub fn displayable_price(ticked: TickUnit) -> String {
+/// Whether a warning was already logged about a value which could not be unticked.
+static UNTICKED_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Convert `ticked` to an unticked value with `tick`, or fall back to the value in tick
+/// units if the conversion fails, e.g. if the tick does not divide a power of ten.
+crate fn unticked_or_ticked(tick: Tick, ticked: TickUnit) -> String {
+    match tick.unticked(ticked) {
+        Ok(unticked) => unticked,
+        Err(err) => {
+            if !UNTICKED_WARNED.swap(true, Ordering::Relaxed) {
+                warn!("displaying values in tick units: `{}`", err);
+            }
+            format!("{}", ticked)
+        }
+    }
+}
+
+/// Convert a ticked price to an unticked value with the current thread local price tick.
+/// The price is displayed in tick units if it cannot be unticked.
+pub fn displayable_price(Price(ticked): Price) -> String {
     match DISPLAY_PRICE_TICK.with(|dt| dt.get()) {
-        Some(tick) => tick.unticked(ticked).unwrap(),
+        Some(tick) => unticked_or_ticked(tick, ticked),
         None => format!("{}", ticked),
     }
 }
 
-/// Convert a ticked value to an unticked value with the current thread local size tick.
-pub fn displayable_size(ticked: TickUnit) -> String {
+/// Convert a ticked size to an unticked value with the current thread local size tick.
+/// The size is displayed in tick units if it cannot be unticked.
+pub fn displayable_size(Size(ticked): Size) -> String {
     match DISPLAY_SIZE_TICK.with(|dt| dt.get()) {
-        Some(tick) => tick.unticked(ticked).unwrap(),
+        Some(tick) => unticked_or_ticked(tick, ticked),
         None => format!("{}", ticked),
     }
 }
 
-impl fmt::Display for OrderBook {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let display_limit = DISPLAY_LIMIT.with(|dl| dl.get());
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// Formatting options for displaying an order book with `OrderBook::display_with`.
+/// Prices and sizes are still converted with the thread local ticks.
+pub struct DisplayConfig {
+    /// Number of limits displayed on each side.
+    pub limit: usize,
 
-        writeln!(f, "## ASK")?;
-        let ask: Vec<_> = self.ask()
-            .take(display_limit)
-            .collect();
-        for (&price, &size) in ask.iter().rev() {
-            writeln!(f, "{}:\t{}", displayable_price(price), displayable_size(size))?;
+    /// Minimum width of the price column, prices are right-aligned.
+    pub price_width: usize,
+
+    /// Minimum width of the size columns, sizes are right-aligned.
+    pub size_width: usize,
+
+    /// Whether to display an additional column with the cumulative size from the best
+    /// limit of each side.
+    pub cumulative: bool,
+}
+
+impl Default for DisplayConfig {
+    /// Same output as the `Display` impl of `OrderBook` with the default thread local
+    /// display limit.
+    fn default() -> Self {
+        DisplayConfig {
+            limit: 5,
+            price_width: 0,
+            size_width: 0,
+            cumulative: false,
         }
+    }
+}
 
-        write!(f, "\n\n")?;
-        for (&price, &size) in self.bid()
-                                   .take(display_limit)
-        {
-            writeln!(f, "{}:\t{}", displayable_price(price), displayable_size(size))?;
+/// An order book displayed according to a `DisplayConfig`, see `OrderBook::display_with`.
+pub struct DisplayWith<'a> {
+    order_book: &'a OrderBook,
+    config: DisplayConfig,
+}
+
+impl OrderBook {
+    /// Return an object displaying this order book according to `config`.
+    pub fn display_with(&self, config: DisplayConfig) -> DisplayWith<'_> {
+        DisplayWith {
+            order_book: self,
+            config,
         }
-        writeln!(f, "## BID")?;
+    }
+}
+
+/// Write up to `config.limit` of the `limits`, which are given from the best one, in
+/// reverse order if `reverse` is `true`.
+fn write_limits<'a, I>(
+    f: &mut fmt::Formatter,
+    limits: I,
+    config: &DisplayConfig,
+    reverse: bool,
+) -> fmt::Result
+    where I: Iterator<Item = (&'a Price, &'a Size)>
+{
+    let mut cumulative = Size(0);
+    let mut rows: Vec<_> = limits.take(config.limit).map(|(&price, &size)| {
+        cumulative = cumulative.saturating_add(size);
+        (price, size, cumulative)
+    }).collect();
+
+    if reverse {
+        rows.reverse();
+    }
+
+    for (price, size, cumulative) in rows {
+        write!(
+            f,
+            "{:>pw$}:\t{:>sw$}",
+            displayable_price(price),
+            displayable_size(size),
+            pw = config.price_width,
+            sw = config.size_width
+        )?;
+        if config.cumulative {
+            write!(f, "\t{:>sw$}", displayable_size(cumulative), sw = config.size_width)?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
 
-        Ok(())
+impl<'a> fmt::Display for DisplayWith<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "## ASK")?;
+        write_limits(f, self.order_book.ask(), &self.config, true)?;
+        write!(f, "\n\n")?;
+        write_limits(f, self.order_book.bid(), &self.config, false)?;
+        writeln!(f, "## BID")
+    }
+}
+
+impl fmt::Display for OrderBook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let config = DisplayConfig {
+            limit: DISPLAY_LIMIT.with(|dl| dl.get()),
+            ..DisplayConfig::default()
+        };
+        fmt::Display::fmt(&self.display_with(config), f)
     }
 }
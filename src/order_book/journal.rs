@@ -0,0 +1,83 @@
+//! An order book recording the limit updates applied to it, for deterministic replay.
+
+use std::ops::Deref;
+use serde_derive::{Serialize, Deserialize};
+use crate::Side;
+use crate::tick::Size;
+use crate::order_book::{OrderBook, LimitUpdate, TopOfBookChange};
+
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+/// An order book recording every limit update applied to it, see `OrderBook::with_journal`.
+/// The journal can be replayed with `OrderBook::replay`, given the ticks of the order book,
+/// in order to reproduce its exact state. Deref to the underlying `OrderBook`.
+pub struct JournaledOrderBook {
+    order_book: OrderBook,
+    journal: Vec<LimitUpdate>,
+}
+
+impl JournaledOrderBook {
+    crate fn new(order_book: OrderBook) -> Self {
+        let journal = OrderBook::new().diff(&order_book).collect();
+        JournaledOrderBook {
+            order_book,
+            journal,
+        }
+    }
+
+    /// Update the given limit and record the update, see `OrderBook::update`.
+    pub fn update(&mut self, update: LimitUpdate) {
+        self.journal.push(update);
+        self.order_book.update(update);
+    }
+
+    /// Apply and record all the limit updates of `updates`, see `OrderBook::update_batch`.
+    pub fn update_batch<I>(&mut self, updates: I) -> TopOfBookChange
+        where I: IntoIterator<Item = LimitUpdate>
+    {
+        let journal = &mut self.journal;
+        self.order_book.update_batch(updates.into_iter().inspect(|update| journal.push(*update)))
+    }
+
+    /// Remove all the limits beyond the `depth` best limits of each side, see
+    /// `OrderBook::truncate`. Each removal is recorded as a limit update with a zero size.
+    ///
+    /// # Complexity
+    /// `O(n)` where `n` is the number of limits at each side.
+    pub fn truncate(&mut self, depth: usize) {
+        let removed: Vec<_> = self.order_book.bid()
+            .skip(depth)
+            .map(|(price, _)| LimitUpdate::new(*price, Size(0), Side::Bid))
+            .chain(
+                self.order_book.ask()
+                    .skip(depth)
+                    .map(|(price, _)| LimitUpdate::new(*price, Size(0), Side::Ask))
+            )
+            .collect();
+        self.update_batch(removed);
+    }
+
+    /// Remove all the limits, e.g. before applying a `Notification::BookSnapshot`. Each
+    /// removal is recorded as a limit update with a zero size.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Return the limit updates recorded so far. Limits present when the journal was
+    /// started come first.
+    pub fn journal(&self) -> &[LimitUpdate] {
+        &self.journal
+    }
+
+    /// Return the order book along with the recorded limit updates.
+    pub fn into_parts(self) -> (OrderBook, Vec<LimitUpdate>) {
+        (self.order_book, self.journal)
+    }
+}
+
+impl Deref for JournaledOrderBook {
+    type Target = OrderBook;
+
+    fn deref(&self) -> &OrderBook {
+        &self.order_book
+    }
+}
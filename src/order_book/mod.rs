@@ -1,29 +1,35 @@
 //! A module defining a simple data structure representing an order book.
 
 pub mod display;
+pub mod journal;
 mod test;
 
+use std::cmp::Ordering;
 use std::collections::btree_map::BTreeMap;
 use serde_derive::{Serialize, Deserialize};
 use crate::Side;
-use crate::tick::TickUnit;
+use crate::tick::{Tick, TickUnit, Price, Size, ConversionError};
 
-#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
 /// An order book. Internally uses two `BTreeMap`, one
 /// for the bid side and another one for the ask side.
 pub struct OrderBook {
-    ask: BTreeMap<TickUnit, TickUnit>,
-    bid: BTreeMap<TickUnit, TickUnit>,
+    ask: BTreeMap<Price, Size>,
+    bid: BTreeMap<Price, Size>,
+
+    /// `(price tick, size tick)`, see `OrderBook::with_ticks`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ticks: Option<(Tick, Tick)>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 /// Represent a limit update of the order book.
 pub struct LimitUpdate {
     /// Price of the corresponding limit.
-    pub price: TickUnit,
+    pub price: Price,
 
     /// Updated size.
-    pub size: TickUnit,
+    pub size: Size,
 
     /// Side of the corresponding limit.
     pub side: Side,
@@ -31,7 +37,7 @@ pub struct LimitUpdate {
 
 impl LimitUpdate {
     /// Return a new `LimitUpdate`.
-    pub fn new(price: TickUnit, size: TickUnit, side: Side) -> Self {
+    pub fn new(price: Price, size: Size, side: Side) -> Self {
         LimitUpdate {
             price,
             size,
@@ -40,29 +46,252 @@ impl LimitUpdate {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// Best bid and ask prices before and after applying a batch of limit updates, as
+/// returned by `OrderBook::update_batch`.
+pub struct TopOfBookChange {
+    /// Best bid price before the updates.
+    pub old_best_bid: Price,
+
+    /// Best ask price before the updates.
+    pub old_best_ask: Price,
+
+    /// Best bid price after the updates.
+    pub new_best_bid: Price,
+
+    /// Best ask price after the updates.
+    pub new_best_ask: Price,
+}
+
+impl TopOfBookChange {
+    /// Return `true` if the best bid price has changed.
+    pub fn bid_changed(&self) -> bool {
+        self.old_best_bid != self.new_best_bid
+    }
+
+    /// Return `true` if the best ask price has changed.
+    pub fn ask_changed(&self) -> bool {
+        self.old_best_ask != self.new_best_ask
+    }
+
+    /// Return `true` if either the best bid or the best ask price has changed.
+    pub fn changed(&self) -> bool {
+        self.bid_changed() || self.ask_changed()
+    }
+}
+
+/// Compare two prices at the given side, better prices first: higher prices come first
+/// for the bid side, lower prices come first for the ask side.
+///
+/// # Example
+/// ```
+/// # extern crate trade;
+/// # use trade::Side;
+/// # use trade::order_book::price_cmp;
+/// # use trade::tick::Price;
+/// # fn main() {
+/// let mut bids = vec![Price(77), Price(80), Price(78)];
+/// bids.sort_by(|a, b| price_cmp(Side::Bid, *a, *b));
+/// assert_eq!(bids, vec![Price(80), Price(78), Price(77)]);
+/// # }
+/// ```
+pub fn price_cmp(side: Side, a: Price, b: Price) -> Ordering {
+    match side {
+        Side::Bid => b.cmp(&a),
+        Side::Ask => a.cmp(&b),
+    }
+}
+
 impl OrderBook {
     /// Return an empty `OrderBook`.
     pub fn new() -> Self {
         OrderBook {
             ask: BTreeMap::new(),
             bid: BTreeMap::new(),
+            ticks: None,
+        }
+    }
+
+    /// Return an empty `OrderBook` whose prices and sizes are expressed with the given
+    /// ticks, e.g. `symbol.price_tick()` and `symbol.size_tick()`.
+    ///
+    /// The order book itself is tick-agnostic: limit updates carry values already in tick
+    /// units, so they are not checked. The ticks are used by `OrderBook::update_unticked`,
+    /// and, in debug builds, `OrderBook::merge` asserts that all the books with known
+    /// ticks use the same ones. Two order books with different ticks are never equal.
+    pub fn with_ticks(price_tick: Tick, size_tick: Tick) -> Self {
+        OrderBook {
+            ticks: Some((price_tick, size_tick)),
+            ..OrderBook::new()
+        }
+    }
+
+    /// Price tick of this order book, if given with `OrderBook::with_ticks`.
+    pub fn price_tick(&self) -> Option<Tick> {
+        self.ticks.map(|(price_tick, _)| price_tick)
+    }
+
+    /// Size tick of this order book, if given with `OrderBook::with_ticks`.
+    pub fn size_tick(&self) -> Option<Tick> {
+        self.ticks.map(|(_, size_tick)| size_tick)
+    }
+
+    /// `(price tick, size tick)` of this order book, if given with `OrderBook::with_ticks`.
+    pub fn ticks(&self) -> Option<(Tick, Tick)> {
+        self.ticks
+    }
+
+    /// Start recording every limit update applied to this order book, the limits currently
+    /// present being recorded first. See `OrderBook::replay` for reconstructing the order
+    /// book from the journal.
+    pub fn with_journal(self) -> journal::JournaledOrderBook {
+        journal::JournaledOrderBook::new(self)
+    }
+
+    /// Return the order book obtained by applying the limit updates of `journal` in order,
+    /// starting from an empty order book with the given `ticks`. See
+    /// `OrderBook::with_journal`.
+    ///
+    /// # Note
+    /// The ticks are part of the state of an order book, so `ticks` must be those of the
+    /// journaled order book, i.e. `OrderBook::ticks`, for the replay to be equal to it.
+    pub fn replay<I>(ticks: Option<(Tick, Tick)>, journal: I) -> OrderBook
+        where I: IntoIterator<Item = LimitUpdate>
+    {
+        let mut order_book = OrderBook {
+            ticks,
+            ..OrderBook::new()
+        };
+        order_book.update_batch(journal);
+        order_book
+    }
+
+    /// Return a consolidated order book where the size at each limit is the sum of the
+    /// sizes at this limit across `books`, e.g. for aggregating the order books of the
+    /// same symbol on several exchanges.
+    ///
+    /// # Note
+    /// Limits are matched by their price in ticks, hence all the books must use the same
+    /// price tick (and the same size tick). Books on different tick grids must be converted
+    /// first. The merged book has the ticks of `books`, if known.
+    ///
+    /// # Panics
+    /// In debug builds, panic if two of the `books` were created by `OrderBook::with_ticks`
+    /// with different ticks.
+    ///
+    /// # Complexity
+    /// `O(n * log(n))` where `n` is the total number of limits across `books`.
+    pub fn merge(books: &[&OrderBook]) -> OrderBook {
+        let mut merged = OrderBook::new();
+        for book in books {
+            if merged.ticks.is_none() {
+                merged.ticks = book.ticks;
+            }
+            debug_assert!(
+                book.ticks.is_none() || book.ticks == merged.ticks,
+                "merging order books with different ticks: {:?} and {:?}",
+                merged.ticks,
+                book.ticks
+            );
+
+            for (price, size) in &book.bid {
+                let entry = merged.bid.entry(*price).or_insert(Size(0));
+                *entry = entry.saturating_add(*size);
+            }
+            for (price, size) in &book.ask {
+                let entry = merged.ask.entry(*price).or_insert(Size(0));
+                *entry = entry.saturating_add(*size);
+            }
         }
+        merged
+    }
+
+    /// Return best bid price. If the bid side is empty, return `Price(0)`.
+    /// 
+    /// # Complexity
+    /// `O(1)`.
+    pub fn best_bid(&self) -> Price {
+        self.bid().next().map(|(price, _)| *price).unwrap_or(Price(0))
+    }
+
+    /// Return best ask price. If the ask side is empty, return
+    /// `Price(TickUnit::max_value())`.
+    /// 
+    /// # Complexity
+    /// `O(1)`.
+    pub fn best_ask(&self) -> Price {
+        self.ask().next().map(|(price, _)| *price).unwrap_or(Price(TickUnit::max_value()))
+    }
+
+    /// Return the best bid limit as a `(price, size)` pair, or `None` if the bid side
+    /// is empty.
+    /// 
+    /// # Complexity
+    /// `O(1)`.
+    pub fn best_bid_level(&self) -> Option<(Price, Size)> {
+        self.bid().next().map(|(price, size)| (*price, *size))
+    }
+
+    /// Return the best ask limit as a `(price, size)` pair, or `None` if the ask side
+    /// is empty.
+    /// 
+    /// # Complexity
+    /// `O(1)`.
+    pub fn best_ask_level(&self) -> Option<(Price, Size)> {
+        self.ask().next().map(|(price, size)| (*price, *size))
     }
 
-    /// Return best bid price. If the bid side is empty, return `0`.
+    /// Return whether the book is crossed (or locked), i.e. whether both sides are non-empty
+    /// and the best bid price is greater than or equal to the best ask price. This may
+    /// temporarily happen when updates are received out of order, e.g. during a resync.
     /// 
     /// # Complexity
     /// `O(1)`.
-    pub fn best_bid(&self) -> TickUnit {
-        self.bid().next().map(|(price, _)| *price).unwrap_or(0)
+    pub fn is_crossed(&self) -> bool {
+        match (self.best_bid_level(), self.best_ask_level()) {
+            (Some((bid_price, _)), Some((ask_price, _))) => bid_price >= ask_price,
+            _ => false,
+        }
     }
 
-    /// Return best ask price. If the ask side is empty, return `TickUnit::max_value()`.
+    /// Remove the limits crossing the best limit of the `trusted` side, usually the side
+    /// which was updated most recently: if `trusted` is `Side::Bid`, remove the asks whose
+    /// price is lower than or equal to the best bid price, and conversely. No-op if the
+    /// book is not crossed.
+    /// 
+    /// # Complexity
+    /// `O(log(n))` where `n` is the number of limits at the other side.
+    pub fn uncross(&mut self, trusted: Side) {
+        if !self.is_crossed() {
+            return;
+        }
+
+        match trusted {
+            Side::Bid => match self.best_bid().0.checked_add(1) {
+                Some(price) => self.ask = self.ask.split_off(&Price(price)),
+                None => self.ask.clear(),
+            },
+            Side::Ask => {
+                let best_ask = self.best_ask();
+                self.bid.split_off(&best_ask);
+            }
+        }
+    }
+
+    /// Return the size-weighted mid price (micro-price)
+    /// `(best_bid * ask_size + best_ask * bid_size) / (bid_size + ask_size)`, rounded down,
+    /// or `None` if either side is empty. The micro-price leans toward the best ask when
+    /// there is more size at the best bid, and conversely.
     /// 
     /// # Complexity
     /// `O(1)`.
-    pub fn best_ask(&self) -> TickUnit {
-        self.ask().next().map(|(price, _)| *price).unwrap_or(TickUnit::max_value())
+    pub fn micro_price(&self) -> Option<Price> {
+        let (Price(bid_price), Size(bid_size)) = self.best_bid_level()?;
+        let (Price(ask_price), Size(ask_size)) = self.best_ask_level()?;
+        let (bid_size, ask_size) = (u128::from(bid_size), u128::from(ask_size));
+
+        let weighted = u128::from(bid_price) * ask_size + u128::from(ask_price) * bid_size;
+        Some(Price((weighted / (bid_size + ask_size)) as TickUnit))
     }
 
     /// Update the given limit with the given updated size.
@@ -72,49 +301,315 @@ impl OrderBook {
     pub fn update(&mut self, update: LimitUpdate) {
         use std::collections::btree_map::Entry;
 
-        let entry = match update.side {
-            Side::Bid if update.size == 0 => {
-                self.bid.remove(&update.price);
+        let LimitUpdate { price, size, side } = update;
+
+        let entry = match side {
+            Side::Bid if size.is_zero() => {
+                self.bid.remove(&price);
                 return;
             },
-            Side::Ask if update.size == 0 => {
-                self.ask.remove(&update.price);
+            Side::Ask if size.is_zero() => {
+                self.ask.remove(&price);
                 return;
             },
-            Side::Bid => self.bid.entry(update.price),
-            Side::Ask => self.ask.entry(update.price),
+            Side::Bid => self.bid.entry(price),
+            Side::Ask => self.ask.entry(price),
         };
 
         match entry {
-            Entry::Occupied(mut entry) => *entry.get_mut() = update.size,
-            Entry::Vacant(entry) => { entry.insert(update.size); },
+            Entry::Occupied(mut entry) => *entry.get_mut() = size,
+            Entry::Vacant(entry) => { entry.insert(size); },
         };
     }
 
+    /// Update the given limit with an unticked `price` and `size`, e.g. `"7254.50"` and
+    /// `"0.15"`, converted with the ticks of this order book. See `OrderBook::update`.
+    ///
+    /// # Errors
+    /// Return `Err` if `price` or `size` does not fall exactly on the corresponding tick
+    /// grid, in which case the order book is left unchanged.
+    ///
+    /// # Panics
+    /// Panic if this order book was not created by `OrderBook::with_ticks`.
+    pub fn update_unticked(&mut self, side: Side, price: &str, size: &str)
+        -> Result<(), ConversionError>
+    {
+        let (price_tick, size_tick) = self.ticks
+            .expect("`OrderBook::update_unticked` on an order book without ticks");
+
+        let price = Price(price_tick.ticked_exact(price)?);
+        let size = Size(size_tick.ticked_exact(size)?);
+        self.update(LimitUpdate::new(price, size, side));
+        Ok(())
+    }
+
+    /// Apply all the limit updates of `updates`, and return the best bid and ask prices
+    /// before and after the updates. Note that only prices are compared: a change of the
+    /// size at the best limits is not reported.
+    ///
+    /// # Complexity
+    /// `O(m * log(n))` where `m` is the number of updates and `n` the number of limits
+    /// at each side.
+    pub fn update_batch<I>(&mut self, updates: I) -> TopOfBookChange
+        where I: IntoIterator<Item = LimitUpdate>
+    {
+        let old_best_bid = self.best_bid();
+        let old_best_ask = self.best_ask();
+
+        for update in updates {
+            self.update(update);
+        }
+
+        TopOfBookChange {
+            old_best_bid,
+            old_best_ask,
+            new_best_bid: self.best_bid(),
+            new_best_ask: self.best_ask(),
+        }
+    }
+
+    /// Replace all the limits of the given side with `limits`, given as `(price, size)`
+    /// pairs. Limits with a zero size are skipped.
+    /// 
+    /// # Complexity
+    /// `O(m * log(m))` where `m` is the number of limits in `limits`.
+    pub fn replace_side<I>(&mut self, side: Side, limits: I)
+        where I: IntoIterator<Item = (Price, Size)>
+    {
+        let entries = match side {
+            Side::Bid => &mut self.bid,
+            Side::Ask => &mut self.ask,
+        };
+
+        entries.clear();
+        entries.extend(limits.into_iter().filter(|(_, size)| !size.is_zero()));
+    }
+
+    /// Remove all the limits beyond the `depth` best limits of each side.
+    /// 
+    /// # Complexity
+    /// `O(depth + log(n))` where `n` is the number of limits at each side.
+    pub fn truncate(&mut self, depth: usize) {
+        let bid_price = self.bid().nth(depth).map(|(price, _)| *price);
+        if let Some(Price(price)) = bid_price {
+            // There is at least one bid limit above `price`, so `price + 1` cannot overflow.
+            self.bid = self.bid.split_off(&Price(price + 1));
+        }
+
+        let ask_price = self.ask().nth(depth).map(|(price, _)| *price);
+        if let Some(price) = ask_price {
+            self.ask.split_off(&price);
+        }
+    }
+
     /// Retrieve the size at the given limit.
     /// 
     /// # Complexity
     /// `O(log(n))` where `n` is the number of limits at the given side.
-    pub fn size_at_limit(&self, side: Side, price: TickUnit) -> TickUnit {
+    pub fn size_at_limit(&self, side: Side, price: Price) -> Size {
         let size = match side {
             Side::Bid => self.bid.get(&price),
             Side::Ask => self.ask.get(&price),
         };
-        size.cloned().unwrap_or(0)
+        size.cloned().unwrap_or(Size(0))
     }
 
     /// Iterator over the limits at bid, sorted by
     /// descending key.
-    pub fn bid(&self) -> impl Iterator<Item = (&TickUnit, &TickUnit)> {
+    pub fn bid(&self) -> impl Iterator<Item = (&Price, &Size)> {
         self.bid.iter().rev()
     }
 
     /// Iterator over the limits at ask, sorted by
     /// ascending key.
-    pub fn ask(&self) -> impl Iterator<Item = (&TickUnit, &TickUnit)> {
+    pub fn ask(&self) -> impl Iterator<Item = (&Price, &Size)> {
         self.ask.iter()
     }
 
+    /// Iterator over the limits at the given side whose price lies between `low` and `high`
+    /// (both inclusive), in the same order as `OrderBook::bid` or `OrderBook::ask`. Empty if
+    /// `low > high`.
+    ///
+    /// # Complexity
+    /// `O(log(n) + m)` where `m` is the number of limits in the range.
+    pub fn range(&self, side: Side, low: Price, high: Price)
+        -> impl Iterator<Item = (&Price, &Size)>
+    {
+        let limits = match side {
+            Side::Bid => &self.bid,
+            Side::Ask => &self.ask,
+        };
+
+        // `BTreeMap::range` panics if `low > high`.
+        let range = if low <= high {
+            limits.range(low..=high)
+        } else {
+            limits.range(Price(0)..Price(0))
+        };
+
+        let limits: Box<dyn Iterator<Item = (&Price, &Size)>> = match side {
+            Side::Bid => Box::new(range.rev()),
+            Side::Ask => Box::new(range),
+        };
+        limits
+    }
+
+    /// Return up to `n` of the best limits at the given side as owned `(price, size)`
+    /// pairs, sorted by descending price for the bid side and ascending price for
+    /// the ask side.
+    /// 
+    /// # Complexity
+    /// `O(n)`.
+    pub fn top(&self, side: Side, n: usize) -> Vec<(Price, Size)> {
+        let limits: Box<dyn Iterator<Item = (&Price, &Size)>> = match side {
+            Side::Bid => Box::new(self.bid()),
+            Side::Ask => Box::new(self.ask()),
+        };
+        limits.take(n).map(|(price, size)| (*price, *size)).collect()
+    }
+
+    /// Iterator over the limits at the given side as `(price, size, cumulative_size)`
+    /// triples, in the same order as `OrderBook::bid` or `OrderBook::ask`, where
+    /// `cumulative_size` is the total size from the best limit up to and including this one.
+    /// Useful e.g. for plotting depth charts.
+    pub fn cumulative(&self, side: Side)
+        -> impl Iterator<Item = (Price, Size, Size)> + '_
+    {
+        let limits: Box<dyn Iterator<Item = (&Price, &Size)>> = match side {
+            Side::Bid => Box::new(self.bid()),
+            Side::Ask => Box::new(self.ask()),
+        };
+        limits.scan(Size(0), |cumulative: &mut Size, (&price, &size)| {
+            *cumulative = cumulative.saturating_add(size);
+            Some((price, size, *cumulative))
+        })
+    }
+
+    /// Walk the given side of the book, best prices first, until the cumulative notional
+    /// `price * size` (in tick units) of the consumed limits reaches `notional`. Return
+    /// `(worst_price, filled_size)`, i.e. the price of the last consumed limit and the total
+    /// consumed size, the last limit being only partially consumed if needed (rounding up
+    /// so that the notional is reached). Return `None` if `notional` is `0` or if the side
+    /// cannot absorb `notional`.
+    ///
+    /// `side` is the side of the book being consumed, e.g. `Side::Ask` for sizing a market
+    /// buy order.
+    ///
+    /// # Complexity
+    /// `O(n)` where `n` is the number of consumed limits.
+    pub fn price_for_notional(&self, side: Side, notional: u128)
+        -> Option<(Price, Size)>
+    {
+        let limits: Box<dyn Iterator<Item = (&Price, &Size)>> = match side {
+            Side::Bid => Box::new(self.bid()),
+            Side::Ask => Box::new(self.ask()),
+        };
+
+        let mut remaining = notional;
+        let mut filled: u128 = 0;
+        for (&Price(price), &Size(size)) in limits {
+            if remaining == 0 {
+                break;
+            }
+
+            let (price, size) = (u128::from(price), u128::from(size));
+            if price == 0 {
+                continue;
+            }
+
+            let level_notional = price * size;
+            if level_notional >= remaining {
+                filled += (remaining + price - 1) / price;
+                return Some((Price(price as TickUnit), Size(filled as TickUnit)));
+            }
+
+            remaining -= level_notional;
+            filled += size;
+        }
+        None
+    }
+
+    /// Return the order book imbalance over the `depth` best limits of each side, i.e.
+    /// `(bid_volume - ask_volume) / (bid_volume + ask_volume)`. The result lies in
+    /// `[-1.0, 1.0]`, and is `0.0` if the book is empty.
+    ///
+    /// # Complexity
+    /// `O(depth)`.
+    pub fn imbalance(&self, depth: usize) -> f64 {
+        let volume = |limits: &mut dyn Iterator<Item = (&Price, &Size)>| {
+            limits.take(depth).map(|(_, size)| u128::from(size.0)).sum::<u128>()
+        };
+
+        let bid_volume = volume(&mut self.bid());
+        let ask_volume = volume(&mut self.ask());
+
+        if bid_volume + ask_volume == 0 {
+            return 0.;
+        }
+
+        (bid_volume as f64 - ask_volume as f64) / (bid_volume + ask_volume) as f64
+    }
+
+    /// Return `(bid_volume, ask_volume)`, the total size of the limits of each side whose
+    /// price lies within `bps` basis points of the mid price `(best_bid + best_ask) / 2`,
+    /// bounds included. The mid price is not rounded. Return zero sizes if either side is
+    /// empty.
+    ///
+    /// # Complexity
+    /// `O(n)` where `n` is the number of limits within the bounds.
+    pub fn depth_within_bps(&self, bps: u32) -> (Size, Size) {
+        let (bid_price, ask_price) = match (self.best_bid_level(), self.best_ask_level()) {
+            (Some((Price(bid_price), _)), Some((Price(ask_price), _))) => (bid_price, ask_price),
+            _ => return (Size(0), Size(0)),
+        };
+
+        // Work with twice the mid price so as to stay in integers: `|price - mid| <= mid * bps
+        // / 10000` iff `|2 * price - 2 * mid| * 10000 <= 2 * mid * bps`.
+        let double_mid = u128::from(bid_price) + u128::from(ask_price);
+        let bound = double_mid * u128::from(bps);
+        let within = |Price(price)| {
+            let double_price = 2 * u128::from(price);
+            let distance = if double_price > double_mid {
+                double_price - double_mid
+            } else {
+                double_mid - double_price
+            };
+            distance * 10_000 <= bound
+        };
+
+        let volume = |limits: &mut dyn Iterator<Item = (&Price, &Size)>| {
+            limits.take_while(|(price, _)| within(**price))
+                .fold(Size(0), |volume, (_, size)| volume.saturating_add(*size))
+        };
+
+        (volume(&mut self.bid()), volume(&mut self.ask()))
+    }
+
+    /// Return a CRC32 checksum over the `depth` best limits of each side, as published
+    /// by some exchanges (e.g. Kraken) for verifying the integrity of the order book.
+    ///
+    /// The checksum is computed over the concatenation of the `depth` best asks sorted by
+    /// ascending price, followed by the `depth` best bids sorted by descending price,
+    /// where each limit is written as its price followed by its size, both in tick units
+    /// and in decimal without any separator. For example, a book with asks `(90, 6)`,
+    /// `(100, 10)` and bids `(80, 8)`, `(77, 9)` gives the string `"90610010808779"`.
+    ///
+    /// # Note
+    /// Kraken writes prices and sizes with the decimal point and leading zeros removed,
+    /// which matches the representation in tick units as long as the price and size ticks
+    /// match the precision used by the exchange.
+    ///
+    /// # Complexity
+    /// `O(depth)`.
+    pub fn checksum(&self, depth: usize) -> u32 {
+        let mut canonical = String::new();
+        for (price, size) in self.ask().take(depth).chain(self.bid().take(depth)) {
+            canonical.push_str(&price.to_string());
+            canonical.push_str(&size.to_string());
+        }
+        crc32(canonical.as_bytes())
+    }
+
     /// Return an iterator over the set of limit updates to apply to `self` in
     /// order to be equal to `other`.
     /// 
@@ -153,7 +648,7 @@ impl OrderBook {
             }
 
             for (price, _) in entries {
-                updates.push(LimitUpdate::new(price, 0, side));
+                updates.push(LimitUpdate::new(price, Size(0), side));
             }
         };
 
@@ -162,4 +657,33 @@ impl OrderBook {
 
         updates.into_iter()
     }
+
+    /// Return the limit updates to apply to `self` in order to be equal to `other`, like
+    /// `OrderBook::diff`, but sorted: bid updates first, then ask updates, best prices
+    /// first. Mostly useful for reporting how two order books differ, e.g. in tests.
+    ///
+    /// # Complexity
+    /// `O((n + m) * log(n + m))` where `n` is `self`'s length and `m` is `other`'s length.
+    pub fn differences(&self, other: &OrderBook) -> Vec<LimitUpdate> {
+        let mut updates: Vec<_> = self.diff(other).collect();
+        updates.sort_by(|a, b| match (a.side, b.side) {
+            (Side::Bid, Side::Ask) => Ordering::Less,
+            (Side::Ask, Side::Bid) => Ordering::Greater,
+            (side, _) => price_cmp(side, a.price, b.price),
+        });
+        updates
+    }
+}
+
+/// CRC32 (IEEE 802.3 polynomial) of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
 }
@@ -1,11 +1,16 @@
 #![cfg(test)]
 
 use crate::Side;
-use crate::tick::TickUnit;
-use crate::order_book::{OrderBook, LimitUpdate};
+use crate::tick::{Tick, TickUnit, Price, Size};
+use crate::order_book::{OrderBook, LimitUpdate, price_cmp};
+use crate::order_book::display::DisplayConfig;
 
 fn lu(price: TickUnit, size: TickUnit, side: Side) -> LimitUpdate {
-    LimitUpdate::new(price, size, side)
+    LimitUpdate::new(Price(price), Size(size), side)
+}
+
+fn limits(limits: &[(TickUnit, TickUnit)]) -> Vec<(Price, Size)> {
+    limits.iter().map(|&(price, size)| (Price(price), Size(size))).collect()
 }
 
 #[test]
@@ -23,15 +28,19 @@ fn test_diff() {
     odb2.update(lu(78, 5, Side::Bid));
     odb2.update(lu(77, 4, Side::Bid));
 
+    // Bids first, then asks, better prices first.
     let mut diff: Vec<_> = odb1.diff(&odb2).collect();
-    diff.sort_by(|x, y| x.price.cmp(&y.price));
+    diff.sort_by(|x, y| {
+        (x.side == Side::Ask).cmp(&(y.side == Side::Ask))
+            .then(price_cmp(x.side, x.price, y.price))
+    });
 
     assert_eq!(
         diff,
         vec![
-            lu(77, 4, Side::Bid),
-            lu(78, 5, Side::Bid),
             lu(80, 0, Side::Bid),
+            lu(78, 5, Side::Bid),
+            lu(77, 4, Side::Bid),
             lu(90, 3, Side::Ask),
             lu(91, 6, Side::Ask),
         ]
@@ -49,3 +58,468 @@ fn test_diff() {
     }
     assert_eq!(odb1, odb2);
 }
+
+#[test]
+fn imbalance() {
+    let mut odb = OrderBook::new();
+    assert_eq!(odb.imbalance(5), 0.);
+
+    odb.update(lu(100, 10, Side::Ask));
+    odb.update(lu(101, 5, Side::Ask));
+    odb.update(lu(99, 10, Side::Bid));
+    odb.update(lu(98, 5, Side::Bid));
+    assert_eq!(odb.imbalance(5), 0.);
+
+    odb.update(lu(97, 45, Side::Bid));
+    assert_eq!(odb.imbalance(2), 0.);
+    assert_eq!(odb.imbalance(3), 0.6);
+
+    odb.update(lu(100, 0, Side::Ask));
+    odb.update(lu(101, 0, Side::Ask));
+    assert_eq!(odb.imbalance(1), 1.);
+}
+
+#[test]
+fn replace_side() {
+    let mut odb = OrderBook::new();
+    odb.update(lu(100, 10, Side::Ask));
+    odb.update(lu(90, 6, Side::Ask));
+    odb.update(lu(80, 8, Side::Bid));
+
+    odb.replace_side(Side::Ask, limits(&[(95, 3), (97, 0), (99, 4)]));
+
+    let mut expected = OrderBook::new();
+    expected.update(lu(95, 3, Side::Ask));
+    expected.update(lu(99, 4, Side::Ask));
+    expected.update(lu(80, 8, Side::Bid));
+    assert_eq!(odb, expected);
+}
+
+#[test]
+fn top() {
+    let mut odb = OrderBook::new();
+    odb.update(lu(100, 10, Side::Ask));
+    odb.update(lu(90, 6, Side::Ask));
+    odb.update(lu(80, 8, Side::Bid));
+    odb.update(lu(77, 9, Side::Bid));
+    odb.update(lu(75, 1, Side::Bid));
+
+    assert_eq!(odb.top(Side::Bid, 2), limits(&[(80, 8), (77, 9)]));
+    assert_eq!(odb.top(Side::Ask, 5), limits(&[(90, 6), (100, 10)]));
+    assert_eq!(OrderBook::new().top(Side::Ask, 5), limits(&[]));
+}
+
+#[test]
+fn differences() {
+    let mut odb1 = OrderBook::new();
+    odb1.update(lu(100, 10, Side::Ask));
+    odb1.update(lu(90, 6, Side::Ask));
+    odb1.update(lu(80, 8, Side::Bid));
+    odb1.update(lu(75, 1, Side::Bid));
+
+    let mut odb2 = odb1.clone();
+    odb2.update(lu(100, 0, Side::Ask));
+    odb2.update(lu(95, 4, Side::Ask));
+    odb2.update(lu(75, 2, Side::Bid));
+    odb2.update(lu(77, 9, Side::Bid));
+
+    assert_eq!(odb1.differences(&odb2), vec![
+        lu(77, 9, Side::Bid),
+        lu(75, 2, Side::Bid),
+        lu(95, 4, Side::Ask),
+        lu(100, 0, Side::Ask),
+    ]);
+    assert_eq!(odb1.differences(&odb1), vec![]);
+}
+
+#[test]
+fn cumulative() {
+    let mut odb = OrderBook::new();
+    odb.update(lu(100, 10, Side::Ask));
+    odb.update(lu(90, 6, Side::Ask));
+    odb.update(lu(80, 8, Side::Bid));
+    odb.update(lu(77, 9, Side::Bid));
+    odb.update(lu(75, 1, Side::Bid));
+
+    assert_eq!(
+        odb.cumulative(Side::Bid).collect::<Vec<_>>(),
+        vec![
+            (Price(80), Size(8), Size(8)),
+            (Price(77), Size(9), Size(17)),
+            (Price(75), Size(1), Size(18)),
+        ]
+    );
+    assert_eq!(
+        odb.cumulative(Side::Ask).collect::<Vec<_>>(),
+        vec![(Price(90), Size(6), Size(6)), (Price(100), Size(10), Size(16))]
+    );
+    assert_eq!(OrderBook::new().cumulative(Side::Ask).next(), None);
+}
+
+#[test]
+fn range() {
+    let mut odb = OrderBook::new();
+    odb.update(lu(100, 10, Side::Ask));
+    odb.update(lu(95, 2, Side::Ask));
+    odb.update(lu(90, 6, Side::Ask));
+    odb.update(lu(80, 8, Side::Bid));
+    odb.update(lu(77, 9, Side::Bid));
+    odb.update(lu(75, 1, Side::Bid));
+
+    let range = |side, low, high| {
+        odb.range(side, Price(low), Price(high))
+            .map(|(price, size)| (*price, *size))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(range(Side::Bid, 76, 80), limits(&[(80, 8), (77, 9)]));
+    assert_eq!(range(Side::Ask, 90, 99), limits(&[(90, 6), (95, 2)]));
+    assert_eq!(range(Side::Ask, 0, 85), limits(&[]));
+    assert_eq!(range(Side::Bid, 80, 75), limits(&[]));
+}
+
+#[test]
+fn best_level() {
+    let mut odb = OrderBook::new();
+    assert_eq!(odb.best_bid_level(), None);
+    assert_eq!(odb.best_ask_level(), None);
+
+    odb.update(lu(100, 10, Side::Ask));
+    odb.update(lu(90, 6, Side::Ask));
+    odb.update(lu(80, 8, Side::Bid));
+    odb.update(lu(77, 9, Side::Bid));
+
+    assert_eq!(odb.best_bid_level(), Some((Price(80), Size(8))));
+    assert_eq!(odb.best_ask_level(), Some((Price(90), Size(6))));
+}
+
+#[test]
+fn depth_within_bps() {
+    let mut odb = OrderBook::new();
+    assert_eq!(odb.depth_within_bps(10_000), (Size(0), Size(0)));
+
+    odb.update(lu(99, 1, Side::Bid));
+    odb.update(lu(98, 2, Side::Bid));
+    odb.update(lu(97, 4, Side::Bid));
+    assert_eq!(odb.depth_within_bps(10_000), (Size(0), Size(0)));
+
+    odb.update(lu(101, 10, Side::Ask));
+    odb.update(lu(102, 20, Side::Ask));
+    odb.update(lu(103, 40, Side::Ask));
+
+    // The mid price is `100`, hence 200 bps are 2 ticks: the limits at `98` and `102`
+    // lie exactly on the bounds.
+    assert_eq!(odb.depth_within_bps(200), (Size(3), Size(30)));
+    assert_eq!(odb.depth_within_bps(199), (Size(1), Size(10)));
+    assert_eq!(odb.depth_within_bps(300), (Size(7), Size(70)));
+    assert_eq!(odb.depth_within_bps(0), (Size(0), Size(0)));
+
+    // The mid price is `99.5`, which is not rounded: 50 bps are `0.4975` ticks.
+    odb.update(lu(101, 0, Side::Ask));
+    odb.update(lu(100, 5, Side::Ask));
+    assert_eq!(odb.depth_within_bps(50), (Size(0), Size(0)));
+    assert_eq!(odb.depth_within_bps(51), (Size(1), Size(5)));
+}
+
+#[test]
+fn uncross() {
+    let crossed = || {
+        let mut odb = OrderBook::new();
+        odb.update(lu(100, 10, Side::Ask));
+        odb.update(lu(95, 6, Side::Ask));
+        odb.update(lu(90, 2, Side::Ask));
+        odb.update(lu(95, 8, Side::Bid));
+        odb.update(lu(92, 9, Side::Bid));
+        odb.update(lu(85, 1, Side::Bid));
+        odb
+    };
+
+    assert!(!OrderBook::new().is_crossed());
+
+    let mut odb = crossed();
+    assert!(odb.is_crossed());
+    odb.uncross(Side::Bid);
+    assert!(!odb.is_crossed());
+    assert_eq!(odb.top(Side::Ask, 5), limits(&[(100, 10)]));
+    assert_eq!(odb.top(Side::Bid, 5), limits(&[(95, 8), (92, 9), (85, 1)]));
+
+    let mut odb = crossed();
+    odb.uncross(Side::Ask);
+    assert!(!odb.is_crossed());
+    assert_eq!(odb.top(Side::Ask, 5), limits(&[(90, 2), (95, 6), (100, 10)]));
+    assert_eq!(odb.top(Side::Bid, 5), limits(&[(85, 1)]));
+
+    // A locked book is crossed as well.
+    let mut odb = OrderBook::new();
+    odb.update(lu(90, 2, Side::Ask));
+    odb.update(lu(89, 2, Side::Bid));
+    assert!(!odb.is_crossed());
+    odb.update(lu(90, 3, Side::Bid));
+    assert!(odb.is_crossed());
+    odb.uncross(Side::Bid);
+    assert_eq!(odb.top(Side::Ask, 5), limits(&[]));
+    assert_eq!(odb.top(Side::Bid, 5), limits(&[(90, 3), (89, 2)]));
+
+    // No-op on a book which is not crossed.
+    let before = odb.clone();
+    odb.uncross(Side::Ask);
+    assert_eq!(odb, before);
+}
+
+#[test]
+fn micro_price() {
+    let mut odb = OrderBook::new();
+    assert_eq!(odb.micro_price(), None);
+
+    odb.update(lu(80, 30, Side::Bid));
+    assert_eq!(odb.micro_price(), None);
+
+    // More size at the bid: the micro-price leans toward the ask.
+    odb.update(lu(90, 10, Side::Ask));
+    assert_eq!(odb.micro_price(), Some(Price(87)));
+
+    // More size at the ask: the micro-price leans toward the bid.
+    odb.update(lu(80, 10, Side::Bid));
+    odb.update(lu(90, 30, Side::Ask));
+    assert_eq!(odb.micro_price(), Some(Price(82)));
+
+    // Same size on both sides: plain mid price, rounded down.
+    odb.update(lu(91, 10, Side::Ask));
+    odb.update(lu(90, 0, Side::Ask));
+    assert_eq!(odb.micro_price(), Some(Price(85)));
+}
+
+#[test]
+fn truncate() {
+    let mut odb = OrderBook::new();
+    odb.update(lu(100, 10, Side::Ask));
+    odb.update(lu(95, 1, Side::Ask));
+    odb.update(lu(90, 6, Side::Ask));
+    odb.update(lu(80, 8, Side::Bid));
+    odb.update(lu(77, 9, Side::Bid));
+    odb.update(lu(75, 1, Side::Bid));
+
+    odb.truncate(2);
+    assert_eq!(odb.top(Side::Bid, 5), limits(&[(80, 8), (77, 9)]));
+    assert_eq!(odb.top(Side::Ask, 5), limits(&[(90, 6), (95, 1)]));
+
+    odb.truncate(0);
+    assert_eq!(odb, OrderBook::new());
+}
+
+#[test]
+fn checksum() {
+    let mut odb = OrderBook::new();
+    assert_eq!(odb.checksum(10), 0);
+
+    odb.update(lu(100, 10, Side::Ask));
+    odb.update(lu(90, 6, Side::Ask));
+    odb.update(lu(80, 8, Side::Bid));
+    odb.update(lu(77, 9, Side::Bid));
+    odb.update(lu(75, 1, Side::Bid));
+
+    // CRC32 of `"90610010808779"`.
+    assert_eq!(odb.checksum(2), 4_106_594_910);
+}
+
+#[test]
+fn price_ordering() {
+    use std::cmp::Ordering;
+
+    assert_eq!(price_cmp(Side::Bid, Price(80), Price(77)), Ordering::Less);
+    assert_eq!(price_cmp(Side::Ask, Price(80), Price(77)), Ordering::Greater);
+    assert_eq!(price_cmp(Side::Ask, Price(80), Price(80)), Ordering::Equal);
+}
+
+#[test]
+fn merge() {
+    let mut odb1 = OrderBook::new();
+    odb1.update(lu(100, 10, Side::Ask));
+    odb1.update(lu(90, 6, Side::Ask));
+    odb1.update(lu(80, 8, Side::Bid));
+    odb1.update(lu(77, 9, Side::Bid));
+
+    let mut odb2 = OrderBook::new();
+    odb2.update(lu(91, 6, Side::Ask));
+    odb2.update(lu(90, 3, Side::Ask));
+    odb2.update(lu(85, 2, Side::Bid));
+    odb2.update(lu(77, 4, Side::Bid));
+
+    let merged = OrderBook::merge(&[&odb1, &odb2]);
+    assert_eq!(merged.best_bid(), Price(85));
+    assert_eq!(merged.best_ask(), Price(90));
+    assert_eq!(merged.top(Side::Bid, 3), limits(&[(85, 2), (80, 8), (77, 13)]));
+    assert_eq!(merged.top(Side::Ask, 3), limits(&[(90, 9), (91, 6), (100, 10)]));
+
+    assert_eq!(OrderBook::merge(&[&odb1]), odb1);
+    assert_eq!(OrderBook::merge(&[]), OrderBook::new());
+}
+
+#[test]
+fn serde_round_trip() {
+    let mut odb = OrderBook::new();
+    odb.update(lu(100, 10, Side::Ask));
+    odb.update(lu(90, 6, Side::Ask));
+    odb.update(lu(80, 8, Side::Bid));
+
+    let json = serde_json::to_string(&odb).unwrap();
+    assert_eq!(json, r#"{"ask":{"90":6,"100":10},"bid":{"80":8}}"#);
+    assert_eq!(serde_json::from_str::<OrderBook>(&json).unwrap(), odb);
+}
+
+#[test]
+fn price_for_notional() {
+    let mut odb = OrderBook::new();
+    odb.update(lu(100, 10, Side::Ask));
+    odb.update(lu(90, 6, Side::Ask));
+    odb.update(lu(80, 8, Side::Bid));
+    odb.update(lu(77, 9, Side::Bid));
+
+    assert_eq!(odb.price_for_notional(Side::Ask, 540), Some((Price(90), Size(6))));
+    assert_eq!(odb.price_for_notional(Side::Ask, 1000), Some((Price(100), Size(11))));
+    assert_eq!(odb.price_for_notional(Side::Ask, 1001), Some((Price(100), Size(11))));
+    assert_eq!(odb.price_for_notional(Side::Ask, 1540), Some((Price(100), Size(16))));
+    assert_eq!(odb.price_for_notional(Side::Ask, 1541), None);
+    assert_eq!(odb.price_for_notional(Side::Bid, 700), Some((Price(77), Size(9))));
+    assert_eq!(odb.price_for_notional(Side::Bid, 0), None);
+}
+
+#[test]
+fn update_batch() {
+    let mut odb = OrderBook::new();
+    let change = odb.update_batch(vec![
+        lu(100, 10, Side::Ask),
+        lu(90, 6, Side::Ask),
+        lu(80, 8, Side::Bid),
+    ]);
+    assert_eq!(change.old_best_bid, Price(0));
+    assert_eq!(change.old_best_ask, Price(TickUnit::max_value()));
+    assert_eq!((change.new_best_bid, change.new_best_ask), (Price(80), Price(90)));
+    assert!(change.bid_changed() && change.ask_changed());
+
+    let change = odb.update_batch(vec![lu(80, 5, Side::Bid), lu(77, 9, Side::Bid)]);
+    assert!(!change.changed());
+
+    let change = odb.update_batch(vec![lu(90, 0, Side::Ask)]);
+    assert!(!change.bid_changed());
+    assert!(change.ask_changed());
+    assert_eq!((change.old_best_ask, change.new_best_ask), (Price(90), Price(100)));
+}
+
+#[test]
+fn display_config() {
+    let mut order_book = OrderBook::new();
+    order_book.update_batch(vec![
+        lu(99, 5, Side::Bid),
+        lu(98, 10, Side::Bid),
+        lu(101, 1, Side::Ask),
+        lu(102, 20, Side::Ask),
+        lu(103, 3, Side::Ask),
+    ]);
+
+    // The default configuration matches the `Display` impl.
+    assert_eq!(
+        order_book.display_with(DisplayConfig::default()).to_string(),
+        order_book.to_string()
+    );
+    assert_eq!(
+        order_book.to_string(),
+        "## ASK\n103:\t3\n102:\t20\n101:\t1\n\n\n99:\t5\n98:\t10\n## BID\n"
+    );
+
+    let config = DisplayConfig {
+        limit: 2,
+        price_width: 4,
+        size_width: 3,
+        cumulative: true,
+    };
+    assert_eq!(
+        order_book.display_with(config).to_string(),
+        "## ASK\n 102:\t 20\t 21\n 101:\t  1\t  1\n\n\n  99:\t  5\t  5\n  98:\t 10\t 15\n## BID\n"
+    );
+}
+
+#[test]
+fn journal() {
+    let mut odb = OrderBook::new();
+    odb.update(lu(100, 10, Side::Ask));
+    odb.update(lu(80, 8, Side::Bid));
+
+    let mut journaled = odb.with_journal();
+    journaled.update(lu(90, 6, Side::Ask));
+    journaled.update_batch(vec![lu(77, 9, Side::Bid), lu(75, 1, Side::Bid)]);
+    journaled.truncate(2);
+    assert_eq!(journaled.size_at_limit(Side::Bid, Price(75)), Size(0));
+    assert_eq!(journaled.journal().len(), 6);
+    assert_eq!(
+        OrderBook::replay(journaled.ticks(), journaled.journal().iter().cloned()),
+        *journaled
+    );
+
+    journaled.clear();
+    journaled.update(lu(95, 3, Side::Ask));
+    let (odb, journal) = journaled.into_parts();
+    assert_eq!(odb.top(Side::Ask, 5), limits(&[(95, 3)]));
+    assert_eq!(OrderBook::replay(odb.ticks(), journal), odb);
+}
+
+#[test]
+fn journal_with_ticks() {
+    let mut odb = OrderBook::with_ticks(Tick::new(100), Tick::new(1000));
+    odb.update_unticked(Side::Bid, "7254.50", "0.15").unwrap();
+
+    let mut journaled = odb.with_journal();
+    journaled.update(lu(725_500, 1000, Side::Ask));
+    assert_eq!(journaled.ticks(), Some((Tick::new(100), Tick::new(1000))));
+
+    let (odb, journal) = journaled.into_parts();
+    assert_eq!(OrderBook::replay(odb.ticks(), journal.iter().cloned()), odb);
+    assert_ne!(OrderBook::replay(None, journal), odb);
+}
+
+#[test]
+fn displayable_fallback() {
+    use crate::tick::Tick;
+    use crate::order_book::display;
+
+    display::set_price_tick(Some(Tick::new(100)));
+    assert_eq!(display::displayable_price(Price(150)), "1.50");
+
+    // `3` does not divide any power of ten, the value is displayed in tick units.
+    display::set_price_tick(Some(Tick::new(3)));
+    display::set_size_tick(Some(Tick::new(3)));
+    assert_eq!(display::displayable_price(Price(150)), "150");
+    assert_eq!(display::displayable_size(Size(7)), "7");
+
+    display::set_price_tick(None);
+    display::set_size_tick(None);
+}
+
+#[test]
+fn with_ticks() {
+    let mut odb = OrderBook::with_ticks(Tick::new(100), Tick::new(1000));
+    assert_eq!(odb.price_tick(), Some(Tick::new(100)));
+    assert_eq!(odb.size_tick(), Some(Tick::new(1000)));
+    assert_eq!(OrderBook::new().price_tick(), None);
+
+    odb.update_unticked(Side::Bid, "7254.50", "0.15").unwrap();
+    odb.update_unticked(Side::Ask, "7255", "1").unwrap();
+    assert!(odb.update_unticked(Side::Ask, "7255.005", "1").is_err());
+    assert!(odb.update_unticked(Side::Ask, "7256", "0.0001").is_err());
+
+    let mut expected = OrderBook::with_ticks(Tick::new(100), Tick::new(1000));
+    expected.update(lu(725_450, 150, Side::Bid));
+    expected.update(lu(725_500, 1000, Side::Ask));
+    assert_eq!(odb, expected);
+
+    let merged = OrderBook::merge(&[&OrderBook::new(), &odb]);
+    assert_eq!(merged, expected);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic]
+fn merge_different_ticks() {
+    let odb1 = OrderBook::with_ticks(Tick::new(100), Tick::new(1000));
+    let odb2 = OrderBook::with_ticks(Tick::new(10), Tick::new(1000));
+    OrderBook::merge(&[&odb1, &odb2]);
+}
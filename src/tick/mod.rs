@@ -21,11 +21,13 @@
 //! must be carried along anyway.
 //! 
 //! This module defines utilities for converting between fractional values represented
-//! as strings (for exact precision) and values expressed in tick units.
+//! as strings (for exact precision) and values expressed in tick units. Floating point
+//! values can still be converted with `Tick::from_f64`, which makes the rounding explicit.
 
 mod test;
 
 use std::fmt;
+use std::ops::{Add, Sub, AddAssign, SubAssign};
 use std::convert::TryInto;
 use std::borrow::Cow;
 use failure_derive::Fail;
@@ -46,7 +48,127 @@ pub struct Tick(TickUnit);
 
 impl fmt::Display for Tick {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({}^-1)", self.0)
+        match self.decimals() {
+            Some(0) => write!(f, "1"),
+            Some(decimals) => write!(f, "1e-{}", decimals),
+            None => write!(f, "({}^-1)", self.0),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+/// A price expressed in price ticks, see `Symbol::price_tick`.
+///
+/// Prices and sizes are both counted in `TickUnit`, but on different tick grids: wrapping
+/// them in distinct types prevents passing one where the other is expected. Both are
+/// serialized as a bare `TickUnit`.
+pub struct Price(pub TickUnit);
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+/// A size expressed in size ticks, see `Symbol::size_tick`.
+pub struct Size(pub TickUnit);
+
+impl From<TickUnit> for Price {
+    fn from(ticks: TickUnit) -> Price {
+        Price(ticks)
+    }
+}
+
+impl From<Price> for TickUnit {
+    fn from(price: Price) -> TickUnit {
+        price.0
+    }
+}
+
+impl From<Price> for Tickable {
+    fn from(price: Price) -> Tickable {
+        Tickable::Ticked(price.0)
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<TickUnit> for Size {
+    fn from(ticks: TickUnit) -> Size {
+        Size(ticks)
+    }
+}
+
+impl From<Size> for TickUnit {
+    fn from(size: Size) -> TickUnit {
+        size.0
+    }
+}
+
+impl From<Size> for Tickable {
+    fn from(size: Size) -> Tickable {
+        Tickable::Ticked(size.0)
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Size {
+    /// Return `true` if `self` is zero, e.g. for a deleted limit.
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Return `self + other`, or the largest size in case of overflow.
+    pub fn saturating_add(self, other: Size) -> Size {
+        Size(self.0.saturating_add(other.0))
+    }
+
+    /// Return `self - other`, or a zero size if `other` is larger than `self`.
+    pub fn saturating_sub(self, other: Size) -> Size {
+        Size(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Add for Size {
+    type Output = Size;
+
+    fn add(self, other: Size) -> Size {
+        Size(self.0 + other.0)
+    }
+}
+
+impl Sub for Size {
+    type Output = Size;
+
+    fn sub(self, other: Size) -> Size {
+        Size(self.0 - other.0)
+    }
+}
+
+impl AddAssign for Size {
+    fn add_assign(&mut self, other: Size) {
+        self.0 += other.0;
+    }
+}
+
+impl SubAssign for Size {
+    fn sub_assign(&mut self, other: Size) {
+        self.0 -= other.0;
+    }
+}
+
+impl Sub for Price {
+    /// The distance between two prices is a number of ticks, not a price.
+    type Output = TickUnit;
+
+    fn sub(self, other: Price) -> TickUnit {
+        self.0 - other.0
     }
 }
 
@@ -104,29 +226,154 @@ impl Tickable {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// Why a conversion failed.
+pub enum ConversionErrorKind {
+    /// The value is in an incorrect format, or cannot be represented with the tick.
+    Format,
+
+    /// The value is too large.
+    Overflow,
+}
+
+impl fmt::Display for ConversionErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionErrorKind::Format => write!(f, "bad format"),
+            ConversionErrorKind::Overflow => write!(f, "overflow"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// Detailed reason of a conversion failure, see `ConversionError::reason`.
+pub enum ConversionErrorReason {
+    /// The unticked value is empty, e.g. an absent price.
+    Empty,
+
+    /// The value is negative.
+    Negative,
+
+    /// The unticked value contains an unexpected character, e.g. a thousands separator or
+    /// a second dot.
+    InvalidChar(char),
+
+    /// The floating point value is either infinite or NaN.
+    NotFinite,
+
+    /// The value does not fall on the tick grid.
+    NotTickAligned,
+
+    /// The value cannot be written with the requested number of decimals.
+    TooManyDecimals,
+
+    /// The number of ticks per unit does not divide any power of 10, hence values in tick
+    /// units cannot be written as decimal values.
+    UnsupportedTick,
+
+    /// The value is too large.
+    Overflow,
+}
+
+impl ConversionErrorReason {
+    /// Return the coarser `ConversionErrorKind` of this reason.
+    pub fn kind(self) -> ConversionErrorKind {
+        match self {
+            ConversionErrorReason::Overflow => ConversionErrorKind::Overflow,
+            _ => ConversionErrorKind::Format,
+        }
+    }
+}
+
+impl fmt::Display for ConversionErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionErrorReason::Empty => write!(f, "empty value"),
+            ConversionErrorReason::Negative => write!(f, "negative value"),
+            ConversionErrorReason::InvalidChar(c) => write!(f, "invalid character {:?}", c),
+            ConversionErrorReason::NotFinite => write!(f, "value is not finite"),
+            ConversionErrorReason::NotTickAligned => {
+                write!(f, "value does not fall on the tick grid")
+            }
+            ConversionErrorReason::TooManyDecimals => {
+                write!(f, "value has too many decimals for the requested style")
+            }
+            ConversionErrorReason::UnsupportedTick => {
+                write!(f, "ticks per unit do not divide a power of 10")
+            }
+            ConversionErrorReason::Overflow => write!(f, "overflow"),
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Fail)]
-#[fail(display = "failed to convert {:?} with tick {}", value, tick)]
+#[fail(display = "failed to convert {:?} with tick {}: {}", value, tick, reason)]
 /// An error which indicates that the conversion between a string value and a
 /// value in tick units has failed.
 pub struct ConversionError {
     tick: Tick,
     value: Tickable,
+    reason: ConversionErrorReason,
 }
 
 impl ConversionError {
-    fn ticked(value: TickUnit, tick: Tick) -> Self {
+    fn ticked(value: TickUnit, tick: Tick, reason: ConversionErrorReason) -> Self {
         ConversionError {
             tick,
             value: Tickable::Ticked(value),
+            reason,
         }
     }
 
-    fn unticked(value: String, tick: Tick) -> Self {
+    crate fn unticked(value: String, tick: Tick, reason: ConversionErrorReason) -> Self {
         ConversionError {
             tick,
             value: Tickable::Unticked(value),
+            reason,
         }
     }
+
+    /// Return why the conversion failed.
+    pub fn kind(&self) -> ConversionErrorKind {
+        self.reason.kind()
+    }
+
+    /// Return why the conversion failed, in more details than `ConversionError::kind`.
+    pub fn reason(&self) -> ConversionErrorReason {
+        self.reason
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// How to round a value which does not fall on the tick grid.
+pub enum RoundingMode {
+    /// Round to the nearest tick, half-way cases away from zero.
+    Nearest,
+
+    /// Round down to the tick below.
+    Down,
+
+    /// Round up to the tick above.
+    Up,
+
+    /// Do not round: the conversion fails if the value does not fall on the tick grid,
+    /// up to floating point errors for `Tick::from_f64`.
+    Exact,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+/// How many decimals to write when converting a value expressed in ticks back to an
+/// unticked value, see `Tick::unticked_with`.
+pub enum DecimalStyle {
+    /// As many decimals as carried by the tick, e.g. `"1.50"` with `Tick::new(100)`.
+    Fixed,
+
+    /// No trailing zeros, and no dot for integers, e.g. `"1.5"` and `"2"` with
+    /// `Tick::new(100)`.
+    Trimmed,
+
+    /// Exactly the given number of decimals, e.g. `"1.5000"` with `FixedAt(4)`.
+    FixedAt(u32),
 }
 
 impl Tick {
@@ -147,44 +394,163 @@ impl Tick {
         self.0
     }
 
+    /// Return the number of decimal places carried by `self` if the number of ticks
+    /// per unit is a power of ten, e.g. `6` for `Tick::new(1_000_000)`, and `None`
+    /// otherwise.
+    pub fn decimals(self) -> Option<u32> {
+        let (pad, pow) = self.pad();
+        if pow == u128::from(self.0) {
+            Some(pad as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Return the smallest power of ten greater or equal to `self.0`, along with
+    /// its exponent.
+    fn pad(self) -> (usize, u128) {
+        let mut pad: usize = 0;
+        let mut pow: u128 = 1;
+
+        // Cannot overflow since `self.0 < 10^20`.
+        while u128::from(self.0) > pow {
+            pad += 1;
+            pow *= 10;
+        }
+        (pad, pow)
+    }
+
     /// Convert an unticked value, e.g. `"0.001"` into a value expressed in ticks,
     /// e.g. if `self.ticks_per_unit == 1000` then this would return `Ok(1)`.
     ///
     /// # Note
-    /// Will truncate extra decimals if `self.ticks_per_unit()` is too low.
+    /// Will truncate extra decimals if `self.ticks_per_unit()` is too low. The value may
+    /// also be written in scientific notation, e.g. `"1.5e-8"`.
     /// 
     /// # Errors
-    /// Return `Err` if the value is in an incorrect format or if the number of ticks per
-    /// unit is badly chosen.
-    /// 
-    /// # Panics
-    /// Panic in case of overflow. Should correctly handle numbers up to (at least)
-    /// `100,000,000,000.00000001` when using a 10^-8 precision, which seems ok.
+    /// Return `Err` if the value is empty, negative or in an incorrect format, if the number
+    /// of ticks per unit is badly chosen, or in case of overflow (with
+    /// `ConversionErrorKind::Overflow`), see `ConversionError::reason`. Should correctly
+    /// handle numbers up to (at least) `100,000,000,000.00000001` when using a 10^-8
+    /// precision, which seems ok.
     pub fn ticked(self, unticked: &str) -> Result<TickUnit, ConversionError> {
+        self.ticked_rounded(unticked, RoundingMode::Down)
+    }
+
+    /// Convert an unticked value into a value expressed in ticks, like `Tick::ticked`,
+    /// but without truncating extra decimals.
+    ///
+    /// # Errors
+    /// Same as `Tick::ticked`, and also return `Err` with `ConversionErrorKind::Format`
+    /// if the value is not a multiple of the tick size, e.g. `"0.0015"` with
+    /// `self.ticks_per_unit == 1000`.
+    pub fn ticked_exact(self, unticked: &str) -> Result<TickUnit, ConversionError> {
+        self.ticked_rounded(unticked, RoundingMode::Exact)
+    }
+
+    /// Convert an unticked value into a value expressed in ticks, like `Tick::ticked`,
+    /// but rounding the values which do not fall on the tick grid according to `mode`,
+    /// e.g. `"0.0015"` gives `Ok(2)` with `RoundingMode::Nearest` and
+    /// `self.ticks_per_unit == 1000`. Unlike with `Tick::from_f64`, the rounding is exact.
+    ///
+    /// # Errors
+    /// Same as `Tick::ticked`, and also return `Err` with `ConversionErrorKind::Format`
+    /// if the value does not fall on the tick grid with `RoundingMode::Exact`.
+    pub fn ticked_rounded(self, unticked: &str, mode: RoundingMode)
+        -> Result<TickUnit, ConversionError>
+    {
+        let err = |reason| ConversionError::unticked(unticked.to_owned(), self, reason);
+
+        let (num, denom) = self.ratio(unticked)?;
+        let (quotient, remainder) = (num / denom, num % denom);
+
+        // `remainder != 0` implies `denom >= 2`, so `quotient + 1` cannot overflow.
+        let ticks = match mode {
+            RoundingMode::Down => quotient,
+            RoundingMode::Up if remainder != 0 => quotient + 1,
+            RoundingMode::Up => quotient,
+            RoundingMode::Nearest if remainder >= denom - remainder => quotient + 1,
+            RoundingMode::Nearest => quotient,
+            RoundingMode::Exact if remainder != 0 => {
+                return Err(err(ConversionErrorReason::NotTickAligned));
+            }
+            RoundingMode::Exact => quotient,
+        };
+        ticks.try_into().map_err(|_| err(ConversionErrorReason::Overflow))
+    }
+
+    /// Parse `unticked` and return the number of ticks it represents as a fraction
+    /// `(numerator, denominator)`. `unticked` may be written in scientific notation,
+    /// e.g. `"1.5e-8"`.
+    fn ratio(self, unticked: &str) -> Result<(u128, u128), ConversionError> {
+        let err = |reason| ConversionError::unticked(unticked.to_owned(), self, reason);
+        let overflow = || err(ConversionErrorReason::Overflow);
+
+        if unticked.is_empty() {
+            return Err(err(ConversionErrorReason::Empty));
+        }
+
+        let (mantissa, exponent) = match unticked.find(|c| c == 'e' || c == 'E') {
+            Some(index) => {
+                let marker = unticked[index..].chars().next().unwrap();
+                if index == 0 {
+                    return Err(err(ConversionErrorReason::InvalidChar(marker)));
+                }
+                let exponent = &unticked[index + 1..];
+                let parsed = exponent.parse::<i32>().map_err(|_| {
+                    let digits = exponent.trim_start_matches(|c| c == '+' || c == '-');
+                    let reason = match digits.chars().find(|c| !c.is_ascii_digit()) {
+                        Some(c) => ConversionErrorReason::InvalidChar(c),
+                        None if digits.is_empty() => ConversionErrorReason::InvalidChar(marker),
+                        None => ConversionErrorReason::Overflow,
+                    };
+                    err(reason)
+                })?;
+                (&unticked[..index], parsed)
+            }
+            None => (unticked, 0),
+        };
+
         let mut denom: u128 = 0;
 
-        let mut int: u64 = 0;
-        let mut fract: u64 = 0;
-        let mut base: u64 = 1;
+        let mut int: u128 = 0;
+        let mut fract: u128 = 0;
+        let mut base: u128 = 1;
         let mut left = false;
-        for c in unticked.chars().rev() {
+        for c in mantissa.chars().rev() {
             let digit = match c {
-                '0' ... '9' => (c as u64) - ('0' as u64),
-                '.' => {
+                '0' ... '9' => u128::from(c as u8 - b'0'),
+                '.' if !left => {
                     left = true;
-                    denom = u128::from(base);
+                    denom = base;
                     base = 1;
                     continue;
                 }
-                _ => return Err(ConversionError::unticked(unticked.to_owned(), self)),
+                _ => {
+                    let reason = if mantissa.starts_with('-') {
+                        ConversionErrorReason::Negative
+                    } else {
+                        // Report the first invalid character in reading order.
+                        let first_dot = mantissa.find('.');
+                        let c = mantissa.char_indices()
+                            .find(|&(index, c)| {
+                                !c.is_ascii_digit() && (c != '.' || Some(index) != first_dot)
+                            })
+                            .map(|(_, c)| c)
+                            .unwrap_or(c);
+                        ConversionErrorReason::InvalidChar(c)
+                    };
+                    return Err(err(reason));
+                }
             };
 
+            let value = digit.checked_mul(base).ok_or_else(overflow)?;
             if left {
-                int = int.checked_add(digit.checked_mul(base).unwrap()).unwrap();
+                int = int.checked_add(value).ok_or_else(overflow)?;
             } else {
-                fract = fract.checked_add(digit.checked_mul(base).unwrap()).unwrap();
+                fract = fract.checked_add(value).ok_or_else(overflow)?;
             }
-            base = base.checked_mul(10).unwrap();
+            base = base.checked_mul(10).ok_or_else(overflow)?;
         }
 
         if !left {
@@ -193,38 +559,90 @@ impl Tick {
             denom = 1;
         }
 
-        let num = u128::from(int)
-            .checked_mul(denom).unwrap()
-            .checked_add(u128::from(fract)).unwrap()
-            .checked_mul(u128::from(self.0)).unwrap();
+        let mut num = int
+            .checked_mul(denom)
+            .and_then(|num| num.checked_add(fract))
+            .and_then(|num| num.checked_mul(u128::from(self.0)))
+            .ok_or_else(overflow)?;
+
+        // Shift by the exponent. Exponents whose power of 10 does not fit in a `u128` are
+        // reported as overflows, even negative ones.
+        let magnitude = i64::from(exponent).abs() as u32;
+        let shift = 10u128.checked_pow(magnitude).ok_or_else(overflow)?;
+        if exponent >= 0 {
+            num = num.checked_mul(shift).ok_or_else(overflow)?;
+        } else {
+            denom = denom.checked_mul(shift).ok_or_else(overflow)?;
+        }
 
-        Ok((num / denom).try_into().unwrap())
+        Ok((num, denom))
+    }
+
+    /// Convert a floating point value, e.g. `0.0012` into a value expressed in ticks,
+    /// rounded according to `mode`, e.g. if `self.ticks_per_unit() == 1000` then this would
+    /// return `Ok(1)` with `RoundingMode::Down` and `Ok(2)` with `RoundingMode::Up`.
+    ///
+    /// # Note
+    /// `value` is multiplied by `self.ticks_per_unit()` before rounding, and this product
+    /// is subject to floating point errors: e.g. `0.29 * 100.` gives `28.999999999999996`.
+    /// `RoundingMode::Exact` tolerates such errors, but `RoundingMode::Down` and
+    /// `RoundingMode::Up` do not, so they may be off by one tick for values which are
+    /// supposed to fall on the tick grid. Prefer `Tick::ticked` when an exact string
+    /// representation is available.
+    ///
+    /// # Errors
+    /// Return `Err` if `value` is negative, is not finite, does not fit in a `TickUnit`
+    /// once converted, or does not fall on the tick grid with `RoundingMode::Exact`.
+    pub fn from_f64(self, value: f64, mode: RoundingMode) -> Result<TickUnit, ConversionError> {
+        let err = |reason| ConversionError::unticked(value.to_string(), self, reason);
+
+        if !value.is_finite() {
+            return Err(err(ConversionErrorReason::NotFinite));
+        }
+        if value < 0. {
+            return Err(err(ConversionErrorReason::Negative));
+        }
+
+        let scaled = value * self.0 as f64;
+        let rounded = match mode {
+            RoundingMode::Nearest => scaled.round(),
+            RoundingMode::Down => scaled.floor(),
+            RoundingMode::Up => scaled.ceil(),
+            RoundingMode::Exact => {
+                let rounded = scaled.round();
+                if (scaled - rounded).abs() > 4. * std::f64::EPSILON * rounded.max(1.) {
+                    return Err(err(ConversionErrorReason::NotTickAligned));
+                }
+                rounded
+            }
+        };
+
+        // `TickUnit::max_value() as f64` rounds up to `2^64`, which does not fit.
+        if rounded >= TickUnit::max_value() as f64 {
+            return Err(err(ConversionErrorReason::Overflow));
+        }
+        Ok(rounded as TickUnit)
     }
 
     /// Convert a value expressed in ticks back to an unticked value.
     ///
     /// # Errors
-    /// Return `Err` if the number of ticks per unit does not divide some power of 10.
-    /// 
-    /// # Panics
-    /// Panic in case of overflow.
+    /// Return `Err` if the number of ticks per unit does not divide some power of 10, or
+    /// in case of overflow (with `ConversionErrorKind::Overflow`).
     pub fn unticked(self, ticked: TickUnit) -> Result<String, ConversionError> {
-        let mut pad: usize = 0;
-        let mut pow: u64 = 1;
-        while self.0 > pow { // find the smallest power of ten greater or equal to `self.0`
-            pad += 1;
-            pow = pow.checked_mul(10).unwrap();
-        }
+        let (pad, pow) = self.pad();
 
-        if pow % self.0 != 0 {
-            return Err(ConversionError::ticked(ticked.to_owned(), self));
+        if pow % u128::from(self.0) != 0 {
+            let reason = ConversionErrorReason::UnsupportedTick;
+            return Err(ConversionError::ticked(ticked, self, reason));
         }
 
         let int = ticked / self.0;
 
-        let pow = u128::from(pow);
-        let fract = (pow * u128::from(ticked) / u128::from(self.0)) % pow; // cannot overflow
-        let fract: u64 = fract.try_into().unwrap();
+        let overflow = || ConversionError::ticked(ticked, self, ConversionErrorReason::Overflow);
+        let fract = pow.checked_mul(u128::from(ticked)).ok_or_else(overflow)?
+            / u128::from(self.0) % pow;
+        let fract: u64 = fract.try_into().map_err(|_| overflow())?;
 
         fn write(mut num: u64, out: &mut [u8], mut used: usize) -> usize {
             loop {
@@ -241,7 +659,8 @@ impl Tick {
             used
         };
         
-        let mut out = [b'0'; 21];
+        // Up to 20 digits for each part, plus the dot.
+        let mut out = [b'0'; 41];
         let _ = write(fract, &mut out[..], 0);
         out[pad] = b'.';
         let used = write(int, &mut out[..], pad + 1);
@@ -255,6 +674,51 @@ impl Tick {
         Ok(String::from_utf8(s).expect("cannot fail"))
     }
 
+    /// Convert a value expressed in ticks back to an unticked value written with the
+    /// given decimal `style`. `Tick::unticked` uses `DecimalStyle::Fixed`.
+    ///
+    /// # Errors
+    /// Same as `Tick::unticked`, and also return `Err` if `style` is
+    /// `DecimalStyle::FixedAt(decimals)` and the value cannot be written with `decimals`
+    /// decimals, e.g. `"0.05"` with `FixedAt(1)`.
+    pub fn unticked_with(self, ticked: TickUnit, style: DecimalStyle)
+        -> Result<String, ConversionError>
+    {
+        let mut unticked = self.unticked(ticked)?;
+        let dot = match unticked.find('.') {
+            Some(dot) => dot,
+            None => return Ok(unticked),
+        };
+
+        let decimals = match style {
+            DecimalStyle::Fixed => return Ok(unticked),
+            DecimalStyle::Trimmed => {
+                let len = unticked.trim_end_matches('0').len();
+                unticked.truncate(len);
+                if unticked.ends_with('.') {
+                    unticked.pop();
+                }
+                return Ok(unticked);
+            }
+            DecimalStyle::FixedAt(decimals) => decimals as usize,
+        };
+
+        let current = unticked.len() - dot - 1;
+        if decimals >= current {
+            unticked.extend(std::iter::repeat('0').take(decimals - current));
+        } else if unticked[dot + 1 + decimals ..].bytes().all(|c| c == b'0') {
+            unticked.truncate(dot + 1 + decimals);
+        } else {
+            let reason = ConversionErrorReason::TooManyDecimals;
+            return Err(ConversionError::ticked(ticked, self, reason));
+        }
+
+        if decimals == 0 {
+            unticked.pop();
+        }
+        Ok(unticked)
+    }
+
     // Not optimized, don't care.
     crate fn tick_size(unticked: &str) -> Option<Tick> {
         if unticked.starts_with('1') || unticked.starts_with("1.") {
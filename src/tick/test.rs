@@ -1,6 +1,16 @@
 #![cfg(test)]
 
-use crate::tick::Tick;
+use crate::tick::{
+    Tick,
+    TickUnit,
+    Tickable,
+    Price,
+    Size,
+    RoundingMode,
+    ConversionErrorKind,
+    ConversionErrorReason,
+    DecimalStyle,
+};
 
 #[test]
 fn convert_unticked() {
@@ -81,20 +91,63 @@ fn empty_int_part() {
 
 #[test]
 fn bad_int_part() {
-    assert!(
-        Tick::new(10).ticked("abc").is_err()
-    );
+    let err = Tick::new(10).ticked("abc").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::InvalidChar('a'));
 
-    assert!(
-        Tick::new(10).ticked("abc.5").is_err()
-    );
+    let err = Tick::new(10).ticked("abc.5").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::InvalidChar('a'));
+
+    let err = Tick::new(10).ticked("-1.5").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::Negative);
+
+    let err = Tick::new(10).ticked("").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::Empty);
+    assert_eq!(err.kind(), ConversionErrorKind::Format);
 }
 
 #[test]
 fn bad_fract_part() {
-    assert!(
-        Tick::new(10).ticked("5.abc").is_err()
+    let err = Tick::new(10).ticked("5.abc").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::InvalidChar('a'));
+
+    let err = Tick::new(10).ticked("1,5").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::InvalidChar(','));
+    assert_eq!(
+        err.to_string(),
+        "failed to convert Unticked(\"1,5\") with tick 1e-1: invalid character ','"
     );
+
+    let err = Tick::new(10).ticked("1.2.3").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::InvalidChar('.'));
+}
+
+#[test]
+fn scientific_notation() {
+    let tick = Tick::new(100_000_000);
+    assert_eq!(Ok(1), tick.ticked("1e-8"));
+    assert_eq!(Ok(1), tick.ticked("1E-8"));
+    assert_eq!(Ok(15_000_000_000), tick.ticked("1.5e2"));
+    assert_eq!(Ok(15_000_000_000), tick.ticked("1.5E+2"));
+    assert_eq!(Ok(123_450_000), tick.ticked("0.012345e2"));
+    assert_eq!(Ok(1), tick.ticked("1.5e-8"));
+    assert_eq!(Ok(150), tick.ticked_exact("15e-7"));
+
+    // Values which are not multiples of the tick size still fail with `ticked_exact`.
+    let err = tick.ticked_exact("1.5e-8").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::NotTickAligned);
+
+    let err = tick.ticked("1e").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::InvalidChar('e'));
+    let err = tick.ticked("e5").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::InvalidChar('e'));
+    let err = tick.ticked("1e2.5").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::InvalidChar('.'));
+    let err = tick.ticked("-1e2").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::Negative);
+    let err = tick.ticked("1e50").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::Overflow);
+    let err = tick.ticked("1e-2147483648").unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::Overflow);
 }
 
 #[test]
@@ -106,9 +159,25 @@ fn do_not_overflow_ticked() {
 }
 
 #[test]
-#[should_panic]
 fn overflow_ticked() {
-    let _ = Tick::new(100_000_000).ticked("1000000000000.00000001");
+    let err = Tick::new(100_000_000).ticked("1000000000000.00000001").unwrap_err();
+    assert_eq!(err.kind(), ConversionErrorKind::Overflow);
+
+    let err = Tick::new(10).ticked("1".repeat(50).as_str()).unwrap_err();
+    assert_eq!(err.kind(), ConversionErrorKind::Overflow);
+
+    let err = Tick::new(10).ticked("5.abc").unwrap_err();
+    assert_eq!(err.kind(), ConversionErrorKind::Format);
+}
+
+#[test]
+fn overflow_unticked() {
+    let tick = Tick::new(12_500_000_000_000_000_000);
+    let err = tick.unticked(TickUnit::max_value()).unwrap_err();
+    assert_eq!(err.kind(), ConversionErrorKind::Overflow);
+
+    let tick = Tick::new(10_000_000_000_000_000_000);
+    assert_eq!(Ok("0.0000000000000000001".to_owned()), tick.unticked(1));
 }
 
 #[test]
@@ -124,6 +193,40 @@ fn truncate() {
     );
 }
 
+#[test]
+fn ticked_exact() {
+    assert_eq!(Ok(51), Tick::new(10).ticked_exact("5.10"));
+    assert_eq!(Ok(3), Tick::new(4).ticked_exact("0.75"));
+
+    let err = Tick::new(10).ticked_exact("5.11").unwrap_err();
+    assert_eq!(err.kind(), ConversionErrorKind::Format);
+    assert_eq!(err.reason(), ConversionErrorReason::NotTickAligned);
+
+    let err = Tick::new(4).ticked_exact("0.76").unwrap_err();
+    assert_eq!(err.kind(), ConversionErrorKind::Format);
+
+    let err = Tick::new(100_000_000).ticked_exact("1000000000000.00000001").unwrap_err();
+    assert_eq!(err.kind(), ConversionErrorKind::Overflow);
+}
+
+#[test]
+fn ticked_rounded() {
+    let tick = Tick::new(1000);
+    assert_eq!(Ok(1), tick.ticked_rounded("0.0015", RoundingMode::Down));
+    assert_eq!(Ok(2), tick.ticked_rounded("0.0015", RoundingMode::Up));
+    assert_eq!(Ok(2), tick.ticked_rounded("0.0015", RoundingMode::Nearest));
+    assert_eq!(Ok(1), tick.ticked_rounded("0.00149", RoundingMode::Nearest));
+    assert_eq!(Ok(2), tick.ticked_rounded("0.002", RoundingMode::Up));
+    assert_eq!(Ok(2), tick.ticked_rounded("0.002", RoundingMode::Exact));
+
+    let err = tick.ticked_rounded("0.0015", RoundingMode::Exact).unwrap_err();
+    assert_eq!(err.reason(), ConversionErrorReason::NotTickAligned);
+
+    let err = Tick::new(1).ticked_rounded("18446744073709551615.5", RoundingMode::Up)
+        .unwrap_err();
+    assert_eq!(err.kind(), ConversionErrorKind::Overflow);
+}
+
 #[test]
 fn convert_ticked() {
     assert_eq!(
@@ -207,3 +310,76 @@ fn tick_size() {
         Tick::tick_size("0.0025")
     );
 }
+
+#[test]
+fn decimals() {
+    assert_eq!(Tick::new(1).decimals(), Some(0));
+    assert_eq!(Tick::new(1_000_000).decimals(), Some(6));
+    assert_eq!(Tick::new(20).decimals(), None);
+
+    assert_eq!(Tick::new(1).to_string(), "1");
+    assert_eq!(Tick::new(100_000_000).to_string(), "1e-8");
+    assert_eq!(Tick::new(20).to_string(), "(20^-1)");
+}
+
+#[test]
+fn from_f64() {
+    let tick = Tick::new(1000);
+    assert_eq!(Ok(1), tick.from_f64(0.0012, RoundingMode::Down));
+    assert_eq!(Ok(2), tick.from_f64(0.0012, RoundingMode::Up));
+    assert_eq!(Ok(1), tick.from_f64(0.0012, RoundingMode::Nearest));
+    assert_eq!(Ok(2), tick.from_f64(0.0015, RoundingMode::Nearest));
+    assert!(tick.from_f64(0.0012, RoundingMode::Exact).is_err());
+
+    assert_eq!(Ok(29), Tick::new(100).from_f64(0.29, RoundingMode::Exact));
+    assert_eq!(Ok(100_027), Tick::new(1000).from_f64(100.027, RoundingMode::Exact));
+
+    assert!(tick.from_f64(-1., RoundingMode::Nearest).is_err());
+    assert!(tick.from_f64(std::f64::NAN, RoundingMode::Nearest).is_err());
+    assert!(tick.from_f64(std::f64::INFINITY, RoundingMode::Nearest).is_err());
+    assert!(tick.from_f64(1e20, RoundingMode::Nearest).is_err());
+}
+
+#[test]
+fn decimal_style() {
+    let tick = Tick::new(100);
+    assert_eq!(tick.unticked_with(150, DecimalStyle::Fixed), Ok("1.50".to_owned()));
+    assert_eq!(tick.unticked_with(150, DecimalStyle::Trimmed), Ok("1.5".to_owned()));
+    assert_eq!(tick.unticked_with(200, DecimalStyle::Trimmed), Ok("2".to_owned()));
+    assert_eq!(tick.unticked_with(0, DecimalStyle::Trimmed), Ok("0".to_owned()));
+    assert_eq!(tick.unticked_with(1000, DecimalStyle::Trimmed), Ok("10".to_owned()));
+
+    assert_eq!(tick.unticked_with(150, DecimalStyle::FixedAt(4)), Ok("1.5000".to_owned()));
+    assert_eq!(tick.unticked_with(150, DecimalStyle::FixedAt(1)), Ok("1.5".to_owned()));
+    assert_eq!(tick.unticked_with(200, DecimalStyle::FixedAt(0)), Ok("2".to_owned()));
+    assert_eq!(
+        tick.unticked_with(105, DecimalStyle::FixedAt(1)).map_err(|err| err.kind()),
+        Err(ConversionErrorKind::Format)
+    );
+
+    assert_eq!(Tick::new(10).unticked(0), Ok("0.0".to_owned()));
+    assert_eq!(Tick::new(10).unticked_with(0, DecimalStyle::Trimmed), Ok("0".to_owned()));
+}
+
+#[test]
+fn price_size() {
+    assert_eq!(serde_json::to_string(&Price(725_450)).unwrap(), "725450");
+    assert_eq!(serde_json::from_str::<Size>("1500").unwrap(), Size(1500));
+
+    assert_eq!(Size(5) + Size(3), Size(8));
+    assert_eq!(Size(5) - Size(3), Size(2));
+    assert_eq!(Size(3).saturating_sub(Size(5)), Size(0));
+    assert_eq!(Size(TickUnit::max_value()).saturating_add(Size(1)), Size(TickUnit::max_value()));
+    assert_eq!(Price(105) - Price(100), 5);
+    assert!(Price(100) < Price(105));
+
+    let mut size = Size(5);
+    size -= Size(2);
+    size += Size(4);
+    assert_eq!(size, Size(7));
+    assert!(!size.is_zero());
+
+    assert_eq!(Tickable::from(Price(100)), Tickable::Ticked(100));
+    assert_eq!(TickUnit::from(Size(7)), 7);
+    assert_eq!(Price(725_450).to_string(), "725450");
+}